@@ -1,12 +1,12 @@
-use std::fs::{read_dir, File};
+use std::fs::File;
 use std::io::{Result, Write};
 use std::path::PathBuf;
 use toml::Value;
 
 fn main() {
-    println!("cargo:rerun-if-changed=../user/c/src");
-    println!("cargo:rerun-if-changed=../user/rust/src");
     println!("cargo:rerun-if-changed=.makeargs");
+    println!("cargo:rerun-if-env-changed=CONSOLE");
+    println!("cargo:rerun-if-env-changed=LOG_TIME");
 
     let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap();
     let platform = if cfg!(feature = "platform-pc") {
@@ -24,7 +24,6 @@ fn main() {
     };
 
     parse_platform_config(&arch, platform).unwrap();
-    link_app_data(&arch).unwrap();
 }
 
 fn parse_platform_config(arch: &str, platform: &str) -> Result<()> {
@@ -43,10 +42,20 @@ fn parse_platform_config(arch: &str, platform: &str) -> Result<()> {
     for (key, value) in config.as_table().unwrap() {
         let var_name = key.to_uppercase().replace('-', "_");
         if let Value::String(s) = value {
-            writeln!(out_file, "pub const {}: usize = {};", var_name, s)?;
+            if key != "console" {
+                writeln!(out_file, "pub const {}: usize = {};", var_name, s)?;
+            }
         }
     }
 
+    // The system console, `CONSOLE` of make overrides the platform config
+    let console = std::env::var("CONSOLE")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .or_else(|| config.get("console")?.as_str().map(String::from))
+        .unwrap_or_else(|| "uart".into());
+    writeln!(out_file, "pub const CONSOLE: &str = {:?};", console)?;
+
     writeln!(out_file, "#[rustfmt::skip]")?;
     writeln!(out_file, "pub const MMIO_REGIONS: &[(usize, usize)] = &[")?;
     if let Some(regions) = config["mmio-regions"].as_array() {
@@ -75,62 +84,3 @@ fn parse_platform_config(arch: &str, platform: &str) -> Result<()> {
 
     Ok(())
 }
-
-fn link_app_data(arch: &str) -> Result<()> {
-    let app_path = PathBuf::from("../user/build/").join(arch);
-    let link_app_path = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("link_app.S");
-
-    if let Ok(dir) = read_dir(&app_path) {
-        let mut apps = dir
-            .into_iter()
-            .map(|dir_entry| dir_entry.unwrap().file_name().into_string().unwrap())
-            .collect::<Vec<_>>();
-        apps.sort();
-
-        let mut f = File::create(link_app_path)?;
-        writeln!(
-            f,
-            "
-.section .data
-.balign 8
-.global _app_count
-_app_count:
-    .quad {}",
-            apps.len()
-        )?;
-        for i in 0..apps.len() {
-            writeln!(f, "    .quad app_{}_name", i)?;
-            writeln!(f, "    .quad app_{}_start", i)?;
-        }
-        writeln!(f, "    .quad app_{}_end", apps.len() - 1)?;
-
-        for (idx, app) in apps.iter().enumerate() {
-            println!("app_{}: {}", idx, app_path.join(app).display());
-            writeln!(
-                f,
-                "
-app_{0}_name:
-    .string \"{1}\"
-.balign 8
-app_{0}_start:
-    .incbin \"{2}\"
-app_{0}_end:",
-                idx,
-                app,
-                app_path.join(app).display()
-            )?;
-        }
-    } else {
-        let mut f = File::create(link_app_path)?;
-        writeln!(
-            f,
-            "
-.section .data
-.balign 8
-.global _app_count
-_app_count:
-    .quad 0"
-        )?;
-    }
-    Ok(())
-}
@@ -48,6 +48,17 @@ impl TrapFrame {
         tf
     }
 
+    pub const fn syscall_args(&self) -> [usize; 6] {
+        let r = &self.r;
+        [
+            r[0] as _, r[1] as _, r[2] as _, r[3] as _, r[4] as _, r[5] as _,
+        ]
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.spsr & 0b1111 == 0 // EL0t
+    }
+
     pub unsafe fn exec(&self, kstack_top: VirtAddr) -> ! {
         info!(
             "user task start: entry={:#x}, ustack={:#x}, kstack={:#x}",
@@ -58,7 +58,6 @@ pub fn flush_icache_all() {
 }
 
 #[inline]
-#[allow(dead_code)]
 pub fn wait_for_ints() {
     cortex_a::asm::wfi();
 }
@@ -5,6 +5,7 @@ mod trap;
 
 pub mod config;
 pub mod instructions;
+pub mod uaccess;
 
 pub use self::context::{TaskContext, TrapFrame};
 pub use self::page_table::{PageTable, PageTableEntry};
@@ -14,4 +15,5 @@ pub fn init() {}
 
 pub fn init_percpu() {
     trap::init();
+    uaccess::init_percpu();
 }
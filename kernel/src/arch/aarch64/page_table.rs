@@ -140,6 +140,7 @@ pub struct PageTableEntry(u64);
 impl PageTableEntry {
     const PHYS_ADDR_MASK: usize = PhysAddr::MAX & !(PAGE_SIZE - 1);
 
+    #[cfg_attr(test, allow(dead_code))]
     pub const fn empty() -> Self {
         Self(0)
     }
@@ -189,3 +190,69 @@ impl fmt::Debug for PageTableEntry {
 }
 
 pub type PageTable = PageTableImpl<PageTableLevels4, PageTableEntry>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::paging::PageSize;
+    use crate::mm::VirtAddr;
+
+    #[test]
+    fn test_pte_encoding() {
+        let flags = MemFlags::READ | MemFlags::WRITE;
+        let pte = PageTableEntry::new_page(PhysAddr::new(0x4000_1000), flags, false);
+        // AF | SH | AttrIndx = 1 | NON_BLOCK | VALID, PXN | UXN
+        assert_eq!(pte.0, 0x0060_0000_4000_1707);
+        assert_eq!(pte.paddr(), PhysAddr::new(0x4000_1000));
+        assert_eq!(pte.flags(), flags);
+        assert!(pte.is_present() && !pte.is_block());
+
+        let pte = PageTableEntry::new_page(PhysAddr::new(0x4000_0000), flags, true);
+        assert_eq!(pte.0, 0x0060_0000_4000_0705);
+        assert!(pte.is_block());
+
+        let mut pte = PageTableEntry::new_table(PhysAddr::new(0x8000));
+        assert_eq!(pte.0, 0x8003);
+        assert!(pte.is_present() && !pte.is_block());
+        pte.clear();
+        assert!(pte.is_unused() && !pte.is_present());
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        for flags in [
+            MemFlags::READ,
+            MemFlags::READ | MemFlags::WRITE,
+            MemFlags::READ | MemFlags::EXECUTE,
+            MemFlags::READ | MemFlags::EXECUTE | MemFlags::USER,
+            MemFlags::READ | MemFlags::WRITE | MemFlags::USER,
+            MemFlags::READ | MemFlags::WRITE | MemFlags::DEVICE,
+        ] {
+            let pte = PageTableEntry::new_page(PhysAddr::new(0x1000), flags, false);
+            assert_eq!(pte.flags(), flags);
+        }
+    }
+
+    #[test]
+    fn test_page_table() {
+        crate::mm::init_for_test();
+        let flags = MemFlags::READ | MemFlags::WRITE;
+        let mut pt = PageTable::new();
+        pt.map_page(
+            VirtAddr::new(0x4000_0000),
+            PhysAddr::new(0x4000_0000),
+            PageSize::Size1G,
+            flags,
+        );
+        pt.unmap(VirtAddr::new(0x4000_0000));
+        assert_eq!(pt.query(VirtAddr::new(0x4000_0000)), None);
+        assert_eq!(
+            pt.query(VirtAddr::new(0x4000_1234)),
+            Some((PhysAddr::new(0x4000_1234), flags, PageSize::Size4K))
+        );
+        assert_eq!(
+            pt.query(VirtAddr::new(0x7fff_ffff)),
+            Some((PhysAddr::new(0x7fff_ffff), flags, PageSize::Size2M))
+        );
+    }
+}
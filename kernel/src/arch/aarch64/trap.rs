@@ -4,9 +4,24 @@ use cortex_a::registers::{ESR_EL1, FAR_EL1, VBAR_EL1};
 use tock_registers::interfaces::{Readable, Writeable};
 
 use super::TrapFrame;
-use crate::{syscall::syscall, task};
+use crate::config::{KERNEL_STACK_REGION_BASE, KERNEL_STACK_REGION_SIZE, KERNEL_STACK_SIZE};
+use crate::mm::{fixup_exception, is_kernel_stack_guard, MemFlags, VirtAddr};
+use crate::syscall::syscall;
+use crate::task::{self, Signal};
 
-global_asm!(include_str!("trap.S"));
+const EXCEPTION_STACK_SIZE: usize = 4096 * 2; // 8K
+const KSTACK_REGION_SHIFT: u32 = KERNEL_STACK_REGION_SIZE.trailing_zeros();
+
+/// Write not Read bit of the ISS of data aborts.
+const ISS_DA_WNR: u64 = 1 << 6;
+
+global_asm!(
+    include_str!("trap.S"),
+    kstack_region_shift = const KSTACK_REGION_SHIFT,
+    kstack_region_neg_index = const -(KERNEL_STACK_REGION_BASE as isize >> KSTACK_REGION_SHIFT),
+    kstack_size_shift = const KERNEL_STACK_SIZE.trailing_zeros(),
+    exception_stack_size = const EXCEPTION_STACK_SIZE,
+);
 
 pub fn init() {
     extern "C" {
@@ -43,6 +58,11 @@ fn invalid_exception(tf: &mut TrapFrame, kind: TrapKind, source: TrapSource) {
     );
 }
 
+#[no_mangle]
+fn kernel_stack_overflow(_tf: &mut TrapFrame) -> ! {
+    task::kernel_stack_overflow()
+}
+
 #[no_mangle]
 fn handle_sync_exception(tf: &mut TrapFrame) {
     let esr = ESR_EL1.extract();
@@ -57,31 +77,18 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
     match esr.read_as_enum(ESR_EL1::EC) {
         Some(ESR_EL1::EC::Value::Unknown) => {
             warn!("Unknown exception @ {:#x}, kernel killed it.", tf.elr);
-            task::current().exit(-1);
+            task::current().kill(Signal::SIGILL);
         }
         Some(ESR_EL1::EC::Value::SVC64) => {
-            tf.r[0] = syscall(tf, tf.r[8] as _, tf.r[0] as _, tf.r[1] as _, tf.r[2] as _) as u64
+            tf.r[0] = syscall(tf, tf.r[8] as _, tf.syscall_args()) as u64
         }
+        Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => handle_page_fault(tf, MemFlags::EXECUTE),
+        Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => handle_page_fault(tf, MemFlags::EXECUTE),
         Some(ESR_EL1::EC::Value::DataAbortLowerEL)
-        | Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => {
-            let iss = esr.read(ESR_EL1::ISS);
-            warn!(
-                "Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}, kernel killed it.",
-                tf.elr,
-                FAR_EL1.get(),
-                iss
-            );
-            task::current().exit(-1);
-        }
-        Some(ESR_EL1::EC::Value::DataAbortCurrentEL)
-        | Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => {
-            let iss = esr.read(ESR_EL1::ISS);
-            panic!(
-                "Kernel Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}, kernel killed it.",
-                tf.elr,
-                FAR_EL1.get(),
-                iss
-            );
+        | Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => {
+            let wnr = esr.read(ESR_EL1::ISS) & ISS_DA_WNR != 0;
+            let access = if wnr { MemFlags::WRITE } else { MemFlags::READ };
+            handle_page_fault(tf, access)
         }
         _ => {
             panic!(
@@ -96,6 +103,36 @@ fn handle_sync_exception(tf: &mut TrapFrame) {
 }
 
 #[no_mangle]
-fn handle_irq_exception(_tf: &mut TrapFrame) {
+fn handle_irq_exception(tf: &mut TrapFrame) {
     task::handle_irq(0);
+    if tf.is_user() {
+        task::prepare_user_return();
+    }
+}
+
+fn handle_page_fault(tf: &mut TrapFrame, access: MemFlags) {
+    let vaddr = FAR_EL1.get() as usize;
+    let iss = ESR_EL1.read(ESR_EL1::ISS);
+    if tf.is_user() {
+        if !task::handle_page_fault(VirtAddr::new(vaddr), access) {
+            warn!(
+                "Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}, kernel killed it.",
+                tf.elr, vaddr, iss
+            );
+            task::current().kill(Signal::SIGSEGV);
+        }
+    } else if let Some(fixup_pc) = fixup_exception(tf.elr as usize) {
+        // Accessing user memory, the page may be mapped on demand.
+        if !task::handle_page_fault(VirtAddr::new(vaddr), access) {
+            tf.elr = fixup_pc as u64;
+        }
+    } else {
+        if is_kernel_stack_guard(vaddr) {
+            task::kernel_stack_overflow();
+        }
+        panic!(
+            "Kernel Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}",
+            tf.elr, vaddr, iss
+        );
+    }
 }
@@ -0,0 +1,60 @@
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+global_asm!(include_str!("uaccess.S"));
+
+static PAN_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables PAN (Privileged Access Never) if supported, so that the kernel can
+/// not access user pages outside of [`user_copy`]. The kernel can never
+/// execute user pages, as they are mapped with `PXN`.
+pub(super) fn init_percpu() {
+    let mmfr1: u64;
+    unsafe { asm!("mrs {}, id_aa64mmfr1_el1", out(reg) mmfr1) };
+    let has_pan = (mmfr1 >> 20) & 0xf != 0;
+    if has_pan {
+        unsafe {
+            // Clear SCTLR_EL1.SPAN, so that PAN is set on every exception
+            // taken to EL1.
+            asm!("
+                mrs     {0}, sctlr_el1
+                bic     {0}, {0}, #(1 << 23)
+                msr     sctlr_el1, {0}
+                isb",
+                out(reg) _,
+            );
+            set_pan();
+        }
+    }
+    PAN_ENABLED.store(has_pan, Ordering::Relaxed);
+    info!("PAN enabled: {}", has_pan);
+}
+
+#[inline]
+unsafe fn set_pan() {
+    asm!(".inst 0xd500419f"); // msr pan, #1
+}
+
+#[inline]
+unsafe fn clear_pan() {
+    asm!(".inst 0xd500409f"); // msr pan, #0
+}
+
+/// Copies `len` bytes between user and kernel memory, returns the number of
+/// bytes that could not be copied due to page faults.
+///
+/// Accesses to user pages are allowed by clearing `PSTATE.PAN` if enabled.
+pub unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    extern "C" {
+        fn __user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    }
+    let pan = PAN_ENABLED.load(Ordering::Relaxed);
+    if pan {
+        clear_pan();
+    }
+    let ret = __user_copy(dst, src, len);
+    if pan {
+        set_pan();
+    }
+    ret
+}
@@ -0,0 +1,10 @@
+pub const USER_ASPACE_BASE: usize = 0;
+pub const USER_ASPACE_SIZE: usize = 0x7fff_ffff_f000;
+pub const KERNEL_ASPACE_BASE: usize = 0xffff_ff80_0000_0000;
+pub const KERNEL_ASPACE_SIZE: usize = 0x0000_007f_ffff_f000;
+
+/// The mock physical memory is mapped at this offset in the host process.
+pub const PHYS_VIRT_OFFSET: usize = 0x4000_0000_0000;
+
+pub const PA_MAX_BITS: usize = 40; // 1TB
+pub const VA_MAX_BITS: usize = 48;
@@ -0,0 +1,25 @@
+//! Privileged instructions are no-ops on the host.
+
+#[inline]
+pub fn enable_irqs() {}
+
+#[inline]
+pub fn disable_irqs() {}
+
+#[inline]
+pub fn irqs_disabled() -> bool {
+    false
+}
+
+#[allow(dead_code)]
+pub unsafe fn set_kernel_page_table_root(_root_paddr: usize) {}
+
+#[allow(dead_code)]
+pub unsafe fn set_user_page_table_root(_root_paddr: usize) {}
+
+#[inline]
+#[allow(dead_code)]
+pub fn flush_tlb_all() {}
+
+#[allow(dead_code)]
+pub fn flush_icache_all() {}
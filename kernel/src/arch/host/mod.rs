@@ -0,0 +1,54 @@
+//! Support for running unit tests on the (x86_64 Linux) host.
+//!
+//! The page table code of all architectures is included, and `PageTable` is
+//! the x86_64 one. The physical memory is mocked by an anonymous mapping at
+//! `PHYS_VIRT_OFFSET + PHYS_MEMORY_BASE` of the host process, so that
+//! `phys_to_virt()` works as in the kernel.
+
+#[path = "../aarch64/page_table.rs"]
+pub mod aarch64_page_table;
+#[path = "../riscv/page_table.rs"]
+pub mod riscv_page_table;
+#[path = "../x86_64/page_table.rs"]
+pub mod x86_64_page_table;
+
+pub mod config;
+pub mod instructions;
+pub mod uaccess;
+
+pub use self::x86_64_page_table::{PageTable, PageTableEntry};
+
+use crate::config::{PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE, PHYS_VIRT_OFFSET};
+
+// Symbols defined by the linker script of the kernel, which are referenced but
+// never used by unit tests.
+core::arch::global_asm!(
+    ".section .bss",
+    ".globl stext, etext, srodata, erodata, sdata, edata, sbss, ebss",
+    ".globl boot_stack, boot_stack_top, ekernel, __ex_table_start, __ex_table_end",
+    "stext: etext: srodata: erodata: sdata: edata: sbss: ebss:",
+    "boot_stack: boot_stack_top: ekernel: __ex_table_start: __ex_table_end:",
+    ".quad 0",
+    ".previous",
+);
+
+/// Maps the mock physical memory into the host process, and detects the paging
+/// features of the host CPU.
+pub fn init_mock_memory() {
+    extern "C" {
+        fn mmap(addr: usize, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> usize;
+    }
+    const PROT_READ: i32 = 0x1;
+    const PROT_WRITE: i32 = 0x2;
+    const MAP_PRIVATE: i32 = 0x02;
+    const MAP_ANONYMOUS: i32 = 0x20;
+    const MAP_NORESERVE: i32 = 0x4000;
+    const MAP_FIXED_NOREPLACE: i32 = 0x10_0000;
+
+    let vaddr = PHYS_VIRT_OFFSET + PHYS_MEMORY_BASE;
+    let prot = PROT_READ | PROT_WRITE;
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE | MAP_FIXED_NOREPLACE;
+    let ret = unsafe { mmap(vaddr, PHYS_MEMORY_SIZE, prot, flags, -1, 0) };
+    assert_eq!(ret, vaddr, "failed to map the mock physical memory");
+    x86_64_page_table::init();
+}
@@ -1,5 +1,8 @@
 cfg_if! {
-    if #[cfg(target_arch = "x86_64")] {
+    if #[cfg(test)] {
+        mod host;
+        pub use self::host::*;
+    } else if #[cfg(target_arch = "x86_64")] {
         mod x86_64;
         pub use self::x86_64::*;
     } else if #[cfg(target_arch = "aarch64")] {
@@ -54,7 +54,6 @@ pub struct TrapFrame {
 impl TrapFrame {
     pub fn new_user(entry: VirtAddr, ustack_top: VirtAddr, arg0: usize) -> Self {
         const SPIE: usize = 1 << 5;
-        const SUM: usize = 1 << 18;
         Self {
             regs: GeneralRegisters {
                 a0: arg0,
@@ -62,7 +61,7 @@ impl TrapFrame {
                 ..Default::default()
             },
             sepc: entry.as_usize(),
-            sstatus: SPIE | SUM,
+            sstatus: SPIE,
         }
     }
 
@@ -79,6 +78,11 @@ impl TrapFrame {
         tf
     }
 
+    pub const fn syscall_args(&self) -> [usize; 6] {
+        let r = &self.regs;
+        [r.a0, r.a1, r.a2, r.a3, r.a4, r.a5]
+    }
+
     pub unsafe fn exec(&self, kstack_top: VirtAddr) -> ! {
         info!(
             "user task start: entry={:#x}, ustack={:#x}, kstack={:#x}",
@@ -55,7 +55,6 @@ pub fn flush_icache_all() {
 }
 
 #[inline]
-#[allow(dead_code)]
 pub fn wait_for_ints() {
     unsafe { riscv::asm::wfi() }
 }
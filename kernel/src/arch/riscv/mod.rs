@@ -8,6 +8,7 @@ mod trap;
 
 pub mod config;
 pub mod instructions;
+pub mod uaccess;
 
 pub use self::context::{TaskContext, TrapFrame};
 pub use self::page_table::{PageTable, PageTableEntry};
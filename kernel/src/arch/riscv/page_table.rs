@@ -125,3 +125,71 @@ pub type PageTableEntry = Rv64PTE;
 
 /// Sv39: Page-Based 39-bit (3 levels) Virtual-Memory System.
 pub type PageTable = PageTableImpl<PageTableLevels3, PageTableEntry>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::paging::PageSize;
+    use crate::mm::VirtAddr;
+
+    #[test]
+    fn test_pte_encoding() {
+        let flags = MemFlags::READ | MemFlags::WRITE | MemFlags::EXECUTE;
+        let pte = PageTableEntry::new_page(PhysAddr::new(0x8020_0000), flags, false);
+        // D | A | X | W | R | V
+        assert_eq!(pte.0, 0x2008_00cf);
+        assert_eq!(pte.paddr(), PhysAddr::new(0x8020_0000));
+        assert_eq!(pte.flags(), flags);
+        // all leaf entries are blocks in the encoding
+        assert!(pte.is_present() && pte.is_block());
+
+        let mut pte = PageTableEntry::new_table(PhysAddr::new(0x8000_1000));
+        assert_eq!(pte.0, 0x2000_0401);
+        assert!(pte.is_present() && !pte.is_block());
+        pte.clear();
+        assert!(pte.is_unused() && !pte.is_present());
+        assert!(PageTableEntry::empty().is_unused());
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        for flags in [
+            MemFlags::READ,
+            MemFlags::READ | MemFlags::WRITE,
+            MemFlags::READ | MemFlags::EXECUTE | MemFlags::USER,
+            MemFlags::READ | MemFlags::WRITE | MemFlags::USER,
+        ] {
+            let pte = PageTableEntry::new_page(PhysAddr::new(0x1000), flags, false);
+            assert_eq!(pte.flags(), flags);
+        }
+    }
+
+    #[test]
+    fn test_page_table() {
+        crate::mm::init_for_test();
+        let flags = MemFlags::READ | MemFlags::WRITE;
+        let mut pt = PageTable::new();
+        // Sv39 has 3 levels, 1G blocks are mapped in the root table
+        pt.map_page(
+            VirtAddr::new(0x8000_0000),
+            PhysAddr::new(0x8000_0000),
+            PageSize::Size1G,
+            flags,
+        );
+        pt.map(VirtAddr::new(0x1000), PhysAddr::new(0x2000), flags);
+        assert_eq!(
+            pt.query(VirtAddr::new(0x8765_4321)),
+            Some((PhysAddr::new(0x8765_4321), flags, PageSize::Size1G))
+        );
+        assert_eq!(
+            pt.query(VirtAddr::new(0x1fff)),
+            Some((PhysAddr::new(0x2fff), flags, PageSize::Size4K))
+        );
+        pt.unmap(VirtAddr::new(0x8020_0000));
+        assert_eq!(pt.query(VirtAddr::new(0x8020_0000)), None);
+        assert_eq!(
+            pt.query(VirtAddr::new(0x8040_0000)),
+            Some((PhysAddr::new(0x8040_0000), flags, PageSize::Size2M))
+        );
+    }
+}
@@ -2,13 +2,23 @@ use riscv::register::scause::{self, Exception as E, Trap};
 use riscv::register::{mtvec::TrapMode, stval, stvec};
 
 use super::TrapFrame;
-use crate::{syscall::syscall, task};
+use crate::config::{KERNEL_STACK_REGION_BASE, KERNEL_STACK_REGION_SIZE, KERNEL_STACK_SIZE};
+use crate::mm::{fixup_exception, is_kernel_stack_guard, MemFlags, VirtAddr};
+use crate::syscall::syscall;
+use crate::task::{self, Signal};
 
 include_asm_marcos!();
 
+const EXCEPTION_STACK_SIZE: usize = 4096 * 2; // 8K
+const KSTACK_REGION_SHIFT: u32 = KERNEL_STACK_REGION_SIZE.trailing_zeros();
+
 core::arch::global_asm!(
     include_str!("trap.S"),
     trapframe_size = const core::mem::size_of::<TrapFrame>(),
+    kstack_region_shift = const KSTACK_REGION_SHIFT,
+    kstack_region_neg_index = const -(KERNEL_STACK_REGION_BASE as isize >> KSTACK_REGION_SHIFT),
+    kstack_size_shift = const KERNEL_STACK_SIZE.trailing_zeros(),
+    exception_stack_size = const EXCEPTION_STACK_SIZE,
 );
 
 pub fn init() {
@@ -18,34 +28,26 @@ pub fn init() {
     unsafe { stvec::write(trap_vector_base as usize, TrapMode::Direct) };
 }
 
+#[no_mangle]
+fn kernel_stack_overflow(_tf: &mut TrapFrame) -> ! {
+    task::kernel_stack_overflow()
+}
+
 #[no_mangle]
 fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
+    // `sstatus.SUM` is restored from the trap frame on return.
+    super::uaccess::disable_user_access();
     let scause = scause::read();
     trace!("trap {:?} @ {:#x}: {:#x?}", scause.cause(), tf.sepc, tf);
     match scause.cause() {
         Trap::Exception(E::UserEnvCall) => {
             tf.sepc += 4;
-            tf.regs.a0 = syscall(tf, tf.regs.a7, tf.regs.a0, tf.regs.a1, tf.regs.a2) as _;
+            tf.regs.a0 = syscall(tf, tf.regs.a7, tf.syscall_args()) as _;
         }
-        Trap::Exception(E::LoadPageFault)
-        | Trap::Exception(E::StorePageFault)
-        | Trap::Exception(E::InstructionPageFault) => {
-            if from_user {
-                warn!(
-                    "Page Fault @ {:#x}, stval={:#x}, scause={}, kernel killed it.",
-                    tf.sepc,
-                    stval::read(),
-                    scause.code(),
-                );
-                task::current().exit(-1);
-            } else {
-                panic!(
-                    "Kernel Page Fault @ {:#x}, stval={:#x}, scause={}",
-                    tf.sepc,
-                    stval::read(),
-                    scause.code(),
-                );
-            }
+        Trap::Exception(E::LoadPageFault) => handle_page_fault(tf, MemFlags::READ, from_user),
+        Trap::Exception(E::StorePageFault) => handle_page_fault(tf, MemFlags::WRITE, from_user),
+        Trap::Exception(E::InstructionPageFault) => {
+            handle_page_fault(tf, MemFlags::EXECUTE, from_user)
         }
         Trap::Interrupt(_) => task::handle_irq(scause.bits()),
         _ => {
@@ -57,4 +59,33 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             );
         }
     }
+    if from_user {
+        task::prepare_user_return();
+    }
+}
+
+fn handle_page_fault(tf: &mut TrapFrame, access: MemFlags, from_user: bool) {
+    let vaddr = stval::read();
+    if from_user {
+        if !task::handle_page_fault(VirtAddr::new(vaddr), access) {
+            warn!(
+                "Page Fault @ {:#x}, stval={:#x}, access={:?}, kernel killed it.",
+                tf.sepc, vaddr, access,
+            );
+            task::current().kill(Signal::SIGSEGV);
+        }
+    } else if let Some(fixup_pc) = fixup_exception(tf.sepc) {
+        // Accessing user memory, the page may be mapped on demand.
+        if !task::handle_page_fault(VirtAddr::new(vaddr), access) {
+            tf.sepc = fixup_pc;
+        }
+    } else {
+        if is_kernel_stack_guard(vaddr) {
+            task::kernel_stack_overflow();
+        }
+        panic!(
+            "Kernel Page Fault @ {:#x}, stval={:#x}, access={:?}",
+            tf.sepc, vaddr, access,
+        );
+    }
 }
@@ -0,0 +1,27 @@
+use riscv::register::sstatus;
+
+core::arch::global_asm!(
+    include_str!("uaccess.S"),
+    ptr_size = const core::mem::size_of::<usize>(),
+);
+
+/// Disallows accesses to user pages, which may be allowed by `sstatus.SUM`
+/// inherited from the interrupted context.
+#[inline]
+pub(super) fn disable_user_access() {
+    unsafe { sstatus::clear_sum() };
+}
+
+/// Copies `len` bytes between user and kernel memory, returns the number of
+/// bytes that could not be copied due to page faults.
+///
+/// Accesses to user pages are only allowed here by setting `sstatus.SUM`.
+pub unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    extern "C" {
+        fn __user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    }
+    sstatus::set_sum();
+    let ret = __user_copy(dst, src, len);
+    sstatus::clear_sum();
+    ret
+}
@@ -72,6 +72,17 @@ impl TrapFrame {
         tf
     }
 
+    pub const fn syscall_args(&self) -> [usize; 6] {
+        [
+            self.rdi as _,
+            self.rsi as _,
+            self.rdx as _,
+            self.r10 as _,
+            self.r8 as _,
+            self.r9 as _,
+        ]
+    }
+
     pub fn is_user(&self) -> bool {
         self.cs & 0b11 == 3
     }
@@ -1,6 +1,7 @@
 use alloc::boxed::Box;
 use core::arch::asm;
 
+use x86::irq::DOUBLE_FAULT_VECTOR;
 use x86_64::addr::VirtAddr;
 use x86_64::structures::idt::{Entry, HandlerFunc, InterruptDescriptorTable};
 use x86_64::structures::DescriptorTablePointer;
@@ -9,6 +10,8 @@ use crate::sync::LazyInit;
 
 const NUM_INT: usize = 256;
 
+pub(super) const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
 pub(super) static IDT: LazyInit<IdtStruct> = LazyInit::new();
 
 pub(super) struct IdtStruct {
@@ -36,6 +39,10 @@ impl IdtStruct {
                 // syscall via `int 0x80`
                 opt.set_privilege_level(x86_64::PrivilegeLevel::Ring3);
             }
+            if i == DOUBLE_FAULT_VECTOR as usize {
+                // a double fault may be caused by kernel stack overflow
+                unsafe { opt.set_stack_index(DOUBLE_FAULT_IST_INDEX) };
+            }
         }
     }
 
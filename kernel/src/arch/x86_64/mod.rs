@@ -8,16 +8,19 @@ mod trap;
 
 pub mod config;
 pub mod instructions;
+pub mod uaccess;
 
 pub use self::context::{TaskContext, TrapFrame};
 pub use self::page_table::{PageTable, PageTableEntry};
 pub use self::percpu::ArchPerCpu;
 
 pub fn init() {
+    page_table::init();
     idt::init();
 }
 
 pub fn init_percpu() {
     idt::IDT.load();
     syscall::init_percpu();
+    uaccess::init_percpu();
 }
@@ -4,6 +4,19 @@ use x86_64::structures::paging::page_table::PageTableFlags as PTF;
 
 use crate::mm::paging::{GenericPTE, PageTableImpl, PageTableLevels4};
 use crate::mm::{MemFlags, PhysAddr};
+use crate::sync::LazyInit;
+
+/// Whether the CPU supports 1 GiB pages, detected by [`init`].
+static SUPPORTS_1G_BLOCK: LazyInit<bool> = LazyInit::new();
+
+/// Detects the paging features of the CPU.
+pub fn init() {
+    SUPPORTS_1G_BLOCK.init_by(
+        raw_cpuid::CpuId::new()
+            .get_extended_processor_and_feature_identifiers()
+            .map_or(false, |f| f.has_1gib_pages()),
+    );
+}
 
 impl From<PTF> for MemFlags {
     fn from(f: PTF) -> Self {
@@ -87,6 +100,9 @@ impl GenericPTE for PageTableEntry {
     fn clear(&mut self) {
         self.0 = 0
     }
+    fn supports_1g_block() -> bool {
+        *SUPPORTS_1G_BLOCK
+    }
 }
 
 impl fmt::Debug for PageTableEntry {
@@ -100,3 +116,41 @@ impl fmt::Debug for PageTableEntry {
 }
 
 pub type PageTable = PageTableImpl<PageTableLevels4, PageTableEntry>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pte_encoding() {
+        let flags = MemFlags::READ | MemFlags::WRITE | MemFlags::USER;
+        let pte = PageTableEntry::new_page(PhysAddr::new(0x1234_5000), flags, false);
+        assert_eq!(pte.0, 0x8000_0000_1234_5007);
+        assert_eq!(pte.paddr(), PhysAddr::new(0x1234_5000));
+        assert_eq!(pte.flags(), flags);
+        assert!(pte.is_present() && !pte.is_block());
+
+        let pte = PageTableEntry::new_page(PhysAddr::new(0x4000_0000), flags, true);
+        assert_eq!(pte.0, 0x8000_0000_4000_0087);
+        assert!(pte.is_block());
+
+        let mut pte = PageTableEntry::new_table(PhysAddr::new(0x8000));
+        assert_eq!(pte.0, 0x8007);
+        assert!(pte.is_present() && !pte.is_block());
+        pte.clear();
+        assert!(pte.is_unused() && !pte.is_present());
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        for flags in [
+            MemFlags::READ,
+            MemFlags::READ | MemFlags::WRITE,
+            MemFlags::READ | MemFlags::EXECUTE | MemFlags::USER,
+            MemFlags::READ | MemFlags::WRITE | MemFlags::DEVICE,
+        ] {
+            let pte = PageTableEntry::new_page(PhysAddr::new(0x1000), flags, false);
+            assert_eq!(pte.flags(), flags);
+        }
+    }
+}
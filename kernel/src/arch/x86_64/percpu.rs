@@ -1,7 +1,10 @@
+use alloc::boxed::Box;
+
 use memoffset::offset_of;
 use x86_64::structures::tss::TaskStateSegment;
 
 use super::gdt::{GdtStruct, TSS_SELECTOR};
+use super::idt::DOUBLE_FAULT_IST_INDEX;
 use crate::mm::VirtAddr;
 use crate::percpu::PERCPU_ARCH_OFFSET;
 
@@ -14,10 +17,13 @@ pub(super) const PERCPU_KERNEL_RSP_OFFSET: usize = PERCPU_ARCH_OFFSET
     + offset_of!(ArchPerCpu, tss)
     + offset_of!(TaskStateSegment, privilege_stack_table);
 
+const EXCEPTION_STACK_SIZE: usize = 4096 * 2; // 8K
+
 pub struct ArchPerCpu {
     saved_user_rsp: u64,
     tss: TaskStateSegment,
     gdt: GdtStruct,
+    exception_stack: Box<[u8]>,
 }
 
 impl ArchPerCpu {
@@ -26,11 +32,15 @@ impl ArchPerCpu {
             saved_user_rsp: 0,
             tss: TaskStateSegment::new(),
             gdt: GdtStruct::alloc(),
+            exception_stack: Box::from(alloc::vec![0; EXCEPTION_STACK_SIZE]),
         }
     }
 
     pub fn init(&'static mut self, cpu_id: usize) {
         println!("Loading GDT for CPU {}...", cpu_id);
+        let exception_stack_top = self.exception_stack.as_ptr_range().end as u64;
+        self.tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            x86_64::VirtAddr::new(exception_stack_top);
         self.gdt.init(&self.tss);
         self.gdt.load();
         self.gdt.load_tss(TSS_SELECTOR);
@@ -17,7 +17,7 @@ global_asm!(
 
 #[no_mangle]
 fn x86_syscall_handler(tf: &mut TrapFrame) {
-    tf.rax = syscall(tf, tf.rax as _, tf.rdi as _, tf.rsi as _, tf.rdx as _) as u64;
+    tf.rax = syscall(tf, tf.rax as _, tf.syscall_args()) as u64;
 }
 
 pub fn init_percpu() {
@@ -1,9 +1,12 @@
 use core::arch::global_asm;
 
 use x86::{controlregs::cr2, irq::*};
+use x86_64::structures::idt::PageFaultErrorCode;
 
 use super::context::TrapFrame;
-use crate::{syscall::syscall, task};
+use crate::mm::{fixup_exception, is_kernel_stack_guard, MemFlags, VirtAddr};
+use crate::syscall::syscall;
+use crate::task::{self, Signal};
 
 global_asm!(include_str!("trap.S"));
 
@@ -14,36 +17,27 @@ const IRQ_VECTOR_END: u8 = 0xff;
 
 #[no_mangle]
 fn x86_trap_handler(tf: &mut TrapFrame) {
+    // The `AC` flag is restored from the trap frame on return.
+    super::uaccess::disable_user_access();
     trace!("trap {} @ {:#x}: {:#x?}", tf.vector, tf.rip, tf);
     match tf.vector as u8 {
-        PAGE_FAULT_VECTOR => {
-            if tf.is_user() {
-                warn!(
-                    "Page Fault @ {:#x}, fault_vaddr={:#x}, error_code={:#x}, kernel killed it.",
-                    tf.rip,
-                    unsafe { cr2() },
-                    tf.error_code,
-                );
-                task::current().exit(-1);
-            } else {
-                panic!(
-                    "Kernel Page Fault @ {:#x}, fault_vaddr={:#x}, error_code={:#x}",
-                    tf.rip,
-                    unsafe { cr2() },
-                    tf.error_code,
-                );
+        PAGE_FAULT_VECTOR => handle_page_fault(tf),
+        DOUBLE_FAULT_VECTOR => {
+            // Running on the IST stack. A kernel stack overflow causes a
+            // double fault, as the CPU fails to push the page fault frame.
+            if is_kernel_stack_guard(unsafe { cr2() }) {
+                task::kernel_stack_overflow();
             }
+            panic!("Double Fault @ {:#x}:\n{:#x?}", tf.rip, tf);
         }
         GENERAL_PROTECTION_FAULT_VECTOR => {
             warn!(
                 "General Protection Exception @ {:#x}, error_code = {:#x}, kernel killed it.",
                 tf.rip, tf.error_code,
             );
-            task::current().exit(-1);
-        }
-        SYSCALL_VECTOR => {
-            tf.rax = syscall(tf, tf.rax as _, tf.rdi as _, tf.rsi as _, tf.rdx as _) as u64
+            task::current().kill(Signal::SIGSEGV);
         }
+        SYSCALL_VECTOR => tf.rax = syscall(tf, tf.rax as _, tf.syscall_args()) as u64,
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
             task::handle_irq(tf.vector as usize);
         }
@@ -54,4 +48,41 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
             );
         }
     }
+    if tf.is_user() {
+        task::prepare_user_return();
+    }
+}
+
+fn handle_page_fault(tf: &mut TrapFrame) {
+    let vaddr = unsafe { cr2() };
+    let error_code = PageFaultErrorCode::from_bits_truncate(tf.error_code);
+    let access = if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+        MemFlags::EXECUTE
+    } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+        MemFlags::WRITE
+    } else {
+        MemFlags::READ
+    };
+    if tf.is_user() {
+        if !task::handle_page_fault(VirtAddr::new(vaddr), access) {
+            warn!(
+                "Page Fault @ {:#x}, fault_vaddr={:#x}, error_code={:#x}, kernel killed it.",
+                tf.rip, vaddr, tf.error_code,
+            );
+            task::current().kill(Signal::SIGSEGV);
+        }
+    } else if let Some(fixup_pc) = fixup_exception(tf.rip as usize) {
+        // Accessing user memory, the page may be mapped on demand.
+        if !task::handle_page_fault(VirtAddr::new(vaddr), access) {
+            tf.rip = fixup_pc as u64;
+        }
+    } else {
+        if is_kernel_stack_guard(vaddr) {
+            task::kernel_stack_overflow();
+        }
+        panic!(
+            "Kernel Page Fault @ {:#x}, fault_vaddr={:#x}, error_code={:#x}",
+            tf.rip, vaddr, tf.error_code,
+        );
+    }
 }
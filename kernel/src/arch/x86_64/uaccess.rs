@@ -0,0 +1,55 @@
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use raw_cpuid::CpuId;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+global_asm!(include_str!("uaccess.S"));
+
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables SMEP and SMAP if supported, so that the kernel can neither execute
+/// nor access user pages outside of [`user_copy`].
+pub(super) fn init_percpu() {
+    let features = CpuId::new().get_extended_feature_info();
+    let has_smep = features.as_ref().map_or(false, |f| f.has_smep());
+    let has_smap = features.as_ref().map_or(false, |f| f.has_smap());
+    let mut flags = Cr4Flags::empty();
+    if has_smep {
+        flags |= Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION;
+    }
+    if has_smap {
+        flags |= Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION;
+    }
+    unsafe { Cr4::update(|cr4| *cr4 |= flags) };
+    SMAP_ENABLED.store(has_smap, Ordering::Relaxed);
+    info!("SMEP enabled: {}, SMAP enabled: {}", has_smep, has_smap);
+}
+
+/// Disallows accesses to user pages, which may be allowed by the `AC` flag
+/// inherited from the interrupted context.
+#[inline]
+pub(super) fn disable_user_access() {
+    if SMAP_ENABLED.load(Ordering::Relaxed) {
+        unsafe { asm!("clac") };
+    }
+}
+
+/// Copies `len` bytes between user and kernel memory, returns the number of
+/// bytes that could not be copied due to page faults.
+///
+/// Accesses to user pages are allowed by `stac`/`clac` if SMAP is enabled.
+pub unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    extern "C" {
+        fn __user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    }
+    let smap = SMAP_ENABLED.load(Ordering::Relaxed);
+    if smap {
+        asm!("stac");
+    }
+    let ret = __user_copy(dst, src, len);
+    if smap {
+        asm!("clac");
+    }
+    ret
+}
@@ -5,16 +5,34 @@ pub use crate::platform::config::*;
 
 pub const PHYS_MEMORY_END: usize = PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE;
 
+#[cfg_attr(test, allow(dead_code))]
 pub const BOOT_KERNEL_STACK_SIZE: usize = 4096 * 4; // 16K
-pub const USER_STACK_SIZE: usize = 4096 * 4; // 16K
-pub const USER_STACK_BASE: usize = USER_ASPACE_BASE + USER_ASPACE_SIZE - USER_STACK_SIZE;
+pub const USER_STACK_TOP: usize = USER_ASPACE_BASE + USER_ASPACE_SIZE;
+pub const USER_STACK_SIZE: usize = 4096 * 4; // 16K, mapped when the program is loaded
+pub const USER_STACK_LIMIT: usize = 0x80_0000; // 8M, the default RLIMIT_STACK
+pub const USER_STACK_MAX_LIMIT: usize = 0x400_0000; // 64M, the hard limit of RLIMIT_STACK
+pub const USER_STACK_GUARD_SIZE: usize = 4096; // 4K
+/// The user stack grows downward in `[USER_STACK_REGION_BASE, USER_STACK_TOP)`,
+/// other mappings are not allowed there.
+pub const USER_STACK_REGION_BASE: usize =
+    USER_STACK_TOP - USER_STACK_MAX_LIMIT - USER_STACK_GUARD_SIZE;
+pub const USER_MMAP_BASE: usize = USER_ASPACE_BASE + 0x10_0000_0000;
+#[cfg_attr(test, allow(dead_code))]
 pub const KERNEL_STACK_SIZE: usize = 4096 * 4; // 16K
-pub const KERNEL_HEAP_SIZE: usize = 0x40_0000; // 4M
+pub const KERNEL_STACK_REGION_SIZE: usize = 0x4000_0000; // 1G
+pub const KERNEL_STACK_REGION_BASE: usize =
+    (KERNEL_ASPACE_BASE + KERNEL_ASPACE_SIZE) / KERNEL_STACK_REGION_SIZE * KERNEL_STACK_REGION_SIZE
+        - KERNEL_STACK_REGION_SIZE;
+#[cfg_attr(test, allow(dead_code))]
+pub const KERNEL_HEAP_SIZE: usize = 0x40_0000; // 4M, the early-boot heap
+pub const KERNEL_HEAP_GROW_SIZE: usize = 0x4_0000; // 256K, the minimum size to grow the heap
 
 // SMP
 
+#[cfg_attr(test, allow(dead_code))]
 pub const MAX_CPUS: usize = 1;
 
 // Scheduler
 
+#[cfg_attr(test, allow(dead_code))]
 pub const TICKS_PER_SEC: u64 = 100;
@@ -0,0 +1,255 @@
+//! ACPI tables, which describe the interrupt controllers and the HPET of PCs.
+//!
+//! Only the tables needed by the drivers are parsed, once at boot before the
+//! interrupt controllers are initialized: the MADT for the local APICs, the IO
+//! APICs and the routing of ISA IRQs, and the HPET table. If there are no ACPI
+//! tables, the legacy PC configuration is assumed.
+
+use alloc::{vec, vec::Vec};
+
+use crate::mm::{map_kernel_mmio, PhysAddr};
+use crate::sync::LazyInit;
+
+const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
+/// The size of the RSDP of ACPI 2.0 and later, ACPI 1.0 uses the first 20.
+const RSDP_SIZE: usize = 36;
+/// The size of the header of all tables.
+const SDT_HEADER_SIZE: usize = 36;
+
+/// The BIOS data area, which holds the segment of the EBDA at offset 0xe.
+const BDA_BASE: usize = 0x400;
+const EBDA_SEARCH_SIZE: usize = 0x400;
+const BIOS_AREA: (usize, usize) = (0xe_0000, 0x2_0000);
+
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INT_SRC_OVERRIDE: u8 = 2;
+const MADT_LOCAL_X2APIC: u8 = 9;
+/// The processor is enabled, in the flags of local APIC entries.
+const MADT_CPU_ENABLED: u32 = 1;
+/// There are dual 8259 PICs, in the flags of the MADT.
+const MADT_PCAT_COMPAT: u32 = 1;
+
+pub const ISA_IRQ_COUNT: usize = 16;
+
+static ACPI: LazyInit<AcpiInfo> = LazyInit::new();
+
+#[derive(Debug)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub paddr: PhysAddr,
+    /// The first global system interrupt (GSI) of the IO APIC.
+    pub gsi_base: u32,
+}
+
+/// The routing of an ISA IRQ to a GSI.
+#[derive(Debug, Clone, Copy)]
+pub struct IsaIrq {
+    pub gsi: u32,
+    pub level_triggered: bool,
+    pub active_low: bool,
+}
+
+#[derive(Debug)]
+pub struct AcpiInfo {
+    /// The APIC IDs of the enabled processors.
+    pub local_apic_ids: Vec<u32>,
+    pub io_apics: Vec<IoApicInfo>,
+    pub isa_irqs: [IsaIrq; ISA_IRQ_COUNT],
+    pub hpet_paddr: PhysAddr,
+    /// Whether there are legacy 8259 PICs, which must be masked.
+    pub has_8259: bool,
+}
+
+impl AcpiInfo {
+    /// The configuration of PCs without ACPI tables: an IO APIC and an HPET
+    /// at the standard addresses, and ISA IRQs routed to the same GSIs.
+    fn legacy() -> Self {
+        let mut isa_irqs = [IsaIrq {
+            gsi: 0,
+            level_triggered: false,
+            active_low: false,
+        }; ISA_IRQ_COUNT];
+        for (irq, isa_irq) in isa_irqs.iter_mut().enumerate() {
+            isa_irq.gsi = irq as u32;
+        }
+        Self {
+            local_apic_ids: Vec::new(),
+            io_apics: vec![IoApicInfo {
+                id: 0,
+                paddr: PhysAddr::new(0xfec0_0000),
+                gsi_base: 0,
+            }],
+            isa_irqs,
+            hpet_paddr: PhysAddr::new(0xfed0_0000),
+            has_8259: true,
+        }
+    }
+
+    fn parse_madt(&mut self, madt: &[u8]) {
+        self.has_8259 = read_u32(madt, 40) & MADT_PCAT_COMPAT != 0;
+        self.io_apics.clear();
+        let mut offset = 44;
+        while offset + 2 <= madt.len() {
+            let (ty, len) = (madt[offset], madt[offset + 1] as usize);
+            if len < 2 || offset + len > madt.len() {
+                warn!("ACPI: invalid MADT entry at offset {:#x}", offset);
+                break;
+            }
+            let entry = &madt[offset..offset + len];
+            match ty {
+                MADT_LOCAL_APIC if len >= 8 => {
+                    if read_u32(entry, 4) & MADT_CPU_ENABLED != 0 {
+                        self.local_apic_ids.push(entry[3] as u32);
+                    }
+                }
+                MADT_LOCAL_X2APIC if len >= 16 => {
+                    if read_u32(entry, 8) & MADT_CPU_ENABLED != 0 {
+                        self.local_apic_ids.push(read_u32(entry, 4));
+                    }
+                }
+                MADT_IO_APIC if len >= 12 => self.io_apics.push(IoApicInfo {
+                    id: entry[2],
+                    paddr: PhysAddr::new(read_u32(entry, 4) as usize),
+                    gsi_base: read_u32(entry, 8),
+                }),
+                MADT_INT_SRC_OVERRIDE if len >= 10 => {
+                    let (bus, source) = (entry[2], entry[3] as usize);
+                    let flags = read_u16(entry, 8);
+                    // bus 0 is ISA, the polarity and the trigger mode are 0b11
+                    // if they are not the ISA defaults
+                    if bus == 0 && source < ISA_IRQ_COUNT {
+                        self.isa_irqs[source] = IsaIrq {
+                            gsi: read_u32(entry, 4),
+                            active_low: flags & 0b11 == 0b11,
+                            level_triggered: (flags >> 2) & 0b11 == 0b11,
+                        };
+                    }
+                }
+                _ => {}
+            }
+            offset += len;
+        }
+    }
+
+    fn parse_hpet(&mut self, hpet: &[u8]) {
+        // the base address is a generic address structure at offset 40,
+        // whose address space (0 for memory) is the first byte
+        if hpet.len() >= 52 && hpet[40] == 0 {
+            self.hpet_paddr = PhysAddr::new(read_u64(hpet, 44) as usize);
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// Maps the physical memory `[paddr, paddr + len)`, which may be outside of
+/// the physical memory of the kernel.
+fn phys_bytes(paddr: usize, len: usize) -> Option<&'static [u8]> {
+    let vaddr = map_kernel_mmio(PhysAddr::new(paddr), len).ok()?;
+    Some(unsafe { core::slice::from_raw_parts(vaddr.as_ptr(), len) })
+}
+
+/// Searches the RSDP in the first KB of the EBDA, then in the BIOS area.
+fn find_rsdp() -> Option<&'static [u8]> {
+    let ebda_base = (read_u16(phys_bytes(BDA_BASE, 0x10)?, 0xe) as usize) << 4;
+    let areas = [(ebda_base, EBDA_SEARCH_SIZE), BIOS_AREA];
+    for (base, size) in areas.into_iter().filter(|&(base, _)| base != 0) {
+        let area = phys_bytes(base, size)?;
+        for offset in (0..=size - RSDP_SIZE).step_by(16) {
+            let rsdp = &area[offset..offset + RSDP_SIZE];
+            if !rsdp.starts_with(RSDP_SIGNATURE) || !checksum_ok(&rsdp[..20]) {
+                continue;
+            }
+            // the revision, and the extended checksum of ACPI 2.0
+            if rsdp[15] < 2 || checksum_ok(rsdp) {
+                return Some(rsdp);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the table at `paddr` if its checksum is valid.
+fn load_table(paddr: usize) -> Option<&'static [u8]> {
+    let len = read_u32(phys_bytes(paddr, SDT_HEADER_SIZE)?, 4) as usize;
+    if len < SDT_HEADER_SIZE {
+        return None;
+    }
+    let table = phys_bytes(paddr, len)?;
+    checksum_ok(table).then_some(table)
+}
+
+/// Returns the physical addresses of the tables listed in the XSDT, or the
+/// RSDT before ACPI 2.0.
+fn table_addrs(rsdp: &[u8]) -> Vec<usize> {
+    let xsdt_paddr = read_u64(rsdp, 24) as usize;
+    let (root, entry_size) = if rsdp[15] >= 2 && xsdt_paddr != 0 {
+        (load_table(xsdt_paddr), 8)
+    } else {
+        (load_table(read_u32(rsdp, 16) as usize), 4)
+    };
+    let root = match root {
+        Some(root) => root,
+        None => {
+            warn!("ACPI: invalid root table");
+            return Vec::new();
+        }
+    };
+    root[SDT_HEADER_SIZE..]
+        .chunks_exact(entry_size)
+        .map(|entry| match entry_size {
+            8 => read_u64(entry, 0) as usize,
+            _ => read_u32(entry, 0) as usize,
+        })
+        .collect()
+}
+
+pub fn acpi_info() -> &'static AcpiInfo {
+    &ACPI
+}
+
+pub fn init() {
+    let mut info = AcpiInfo::legacy();
+    match find_rsdp() {
+        Some(rsdp) => {
+            for paddr in table_addrs(rsdp) {
+                let table = match load_table(paddr) {
+                    Some(table) => table,
+                    None => {
+                        warn!("ACPI: invalid table at {:#x}", paddr);
+                        continue;
+                    }
+                };
+                match &table[..4] {
+                    b"APIC" => info.parse_madt(table),
+                    b"HPET" => info.parse_hpet(table),
+                    _ => {}
+                }
+            }
+        }
+        None => warn!("ACPI: RSDP not found, assuming the legacy PC configuration"),
+    }
+    println!(
+        "ACPI: {} CPUs, {} IO APICs, HPET at {:#x}",
+        info.local_apic_ids.len(),
+        info.io_apics.len(),
+        info.hpet_paddr.as_usize(),
+    );
+    debug!("{:#x?}", info);
+    ACPI.init_by(info);
+}
@@ -0,0 +1,69 @@
+//! Block devices.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::errno::SysResult;
+use crate::sync::Mutex;
+
+static BLOCK_DEVICES: Mutex<Vec<Arc<dyn BlockDevice>>> = Mutex::new(Vec::new());
+
+/// A device that is read and written in fixed-size blocks. Requests block
+/// the current task until they are completed.
+pub trait BlockDevice: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    fn num_blocks(&self) -> u64;
+
+    /// Reads `buf.len() / block_size()` blocks starting from `block_id`. The
+    /// length of `buf` must be a multiple of the block size.
+    fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> SysResult<()>;
+
+    /// Writes `buf.len() / block_size()` blocks starting from `block_id`. The
+    /// length of `buf` must be a multiple of the block size.
+    fn write_blocks(&self, block_id: u64, buf: &[u8]) -> SysResult<()>;
+
+    /// Waits until the written blocks reach the persistent storage.
+    fn flush(&self) -> SysResult<()>;
+}
+
+#[cfg_attr(test, allow(dead_code))]
+pub fn register_block_device(dev: Arc<dyn BlockDevice>) {
+    println!(
+        "Block device {}: {} blocks of {} bytes",
+        dev.name(),
+        dev.num_blocks(),
+        dev.block_size()
+    );
+    BLOCK_DEVICES.lock().push(dev);
+}
+
+/// Returns all the block devices, in the order they are found.
+pub fn block_devices() -> Vec<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES.lock().clone()
+}
+
+/// Writes the last block of each block device and reads it back, the original
+/// content is restored after the test.
+#[allow(dead_code)]
+pub fn block_device_test() {
+    use alloc::vec;
+    for dev in block_devices() {
+        let block_size = dev.block_size();
+        let last = dev.num_blocks() - 1;
+        let mut orig = vec![0; block_size * 2];
+        dev.read_blocks(last - 1, &mut orig).unwrap();
+
+        let pattern: Vec<u8> = (0..block_size * 2).map(|i| (i % 251) as u8).collect();
+        dev.write_blocks(last - 1, &pattern).unwrap();
+        dev.flush().unwrap();
+        let mut buf = vec![0; block_size];
+        dev.read_blocks(last, &mut buf).unwrap();
+        assert_eq!(buf, pattern[block_size..]);
+
+        dev.write_blocks(last - 1, &orig).unwrap();
+        println!("block_device_test passed on {}!", dev.name());
+    }
+}
@@ -0,0 +1,118 @@
+//! The system console, and the output of the kernel log.
+//!
+//! The UART is the system console unless the `console` platform config (or
+//! `CONSOLE` of make) is `virtio`, in which case the first virtio console port
+//! is used once it is found. The kernel log goes to the port named
+//! [`LOG_PORT_NAME`] if there is one, or to the system console.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use super::uart;
+use crate::config::CONSOLE;
+use crate::sync::Mutex;
+use crate::task::WaitQueue;
+
+/// The name of the port that receives the kernel log.
+pub const LOG_PORT_NAME: &str = "org.nimbos.log";
+
+static PORTS: Mutex<Vec<Arc<dyn ConsolePort>>> = Mutex::new(Vec::new());
+static SYSTEM_CONSOLE: Mutex<Option<Arc<dyn ConsolePort>>> = Mutex::new(None);
+static LOG_PORT: Mutex<Option<Arc<dyn ConsolePort>>> = Mutex::new(None);
+
+/// Serializes the output to the consoles, so that messages are not mixed.
+static OUTPUT_LOCK: Mutex<()> = Mutex::new(());
+
+/// A port that transfers a stream of bytes, such as a virtio console port.
+pub trait ConsolePort: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Writes the bytes, returns the number of bytes written, which is less
+    /// than `buf.len()` only if the device does not accept more data.
+    ///
+    /// It is called to print the kernel log, so it must not log itself.
+    fn write(&self, buf: &[u8]) -> usize;
+
+    /// Reads the bytes received, returns 0 if there are none.
+    fn read(&self, buf: &mut [u8]) -> usize;
+
+    /// Whether there are bytes received and not read yet.
+    fn can_read(&self) -> bool;
+
+    /// The tasks waiting for bytes to read, notified when they are received.
+    /// `None` if the port must be polled.
+    fn rx_waiters(&self) -> Option<&WaitQueue>;
+}
+
+/// Registers the port, which becomes the system console if it is a console
+/// port and a virtio console is selected.
+pub fn register_console_port(port: Arc<dyn ConsolePort>, is_console: bool) {
+    println!("Console port {}", port.name());
+    if port.name() == LOG_PORT_NAME {
+        LOG_PORT.lock().get_or_insert_with(|| port.clone());
+    }
+    if is_console && CONSOLE == "virtio" {
+        let mut console = SYSTEM_CONSOLE.lock();
+        if console.is_none() {
+            *console = Some(port.clone());
+            drop(console);
+            println!("Switched the system console to {}", port.name());
+        }
+    }
+    PORTS.lock().push(port);
+}
+
+/// Returns the port named `name`.
+pub fn find_console_port(name: &str) -> Option<Arc<dyn ConsolePort>> {
+    PORTS.lock().iter().find(|p| p.name() == name).cloned()
+}
+
+/// Writes to the port, or the UART if it is `None`, with each `\n` translated
+/// to `\r\n`.
+fn write_translated(port: Option<Arc<dyn ConsolePort>>, buf: &[u8]) {
+    let _locked = OUTPUT_LOCK.lock();
+    match port {
+        Some(port) => {
+            let mut lines = buf.split(|&c| c == b'\n');
+            if let Some(first) = lines.next() {
+                port.write(first);
+            }
+            for line in lines {
+                port.write(b"\r\n");
+                port.write(line);
+            }
+        }
+        None => {
+            for &c in buf {
+                if c == b'\n' {
+                    uart::console_putchar(b'\r');
+                }
+                uart::console_putchar(c);
+            }
+        }
+    }
+}
+
+/// Writes to the system console, for the standard output of user tasks.
+pub fn console_write(buf: &[u8]) {
+    let console = SYSTEM_CONSOLE.lock().clone();
+    write_translated(console, buf);
+}
+
+/// Reads a byte from the system console without blocking.
+pub fn console_getchar() -> Option<u8> {
+    let console = SYSTEM_CONSOLE.lock().clone();
+    match console {
+        Some(port) => {
+            let mut c = 0;
+            (port.read(core::slice::from_mut(&mut c)) == 1).then_some(c)
+        }
+        None => uart::console_getchar(),
+    }
+}
+
+/// Writes the kernel log.
+pub fn log_write(buf: &[u8]) {
+    let port = LOG_PORT.lock().clone();
+    let port = port.or_else(|| SYSTEM_CONSOLE.lock().clone());
+    write_translated(port, buf);
+}
@@ -2,86 +2,295 @@
 
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use raw_cpuid::CpuId;
 use x2apic::ioapic::{IoApic, IrqFlags, IrqMode};
 use x2apic::lapic::{xapic_base, LocalApic, LocalApicBuilder};
 
 use self::vectors::*;
-use crate::mm::PhysAddr;
+use super::irq::{self, IrqReturn};
+use crate::config::MAX_CPUS;
+use crate::drivers::acpi::{acpi_info, ISA_IRQ_COUNT};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{map_kernel_mmio, PhysAddr};
+use crate::percpu::PerCpu;
 use crate::sync::{LazyInit, PerCpuData, SpinNoIrqLock};
-use crate::utils::irq_handler::{IrqHandler, IrqHandlerTable};
 
 pub mod vectors {
-    pub const PIT_GSI: usize = 2; // TODO: lookup ACPI tables
-    pub const PIT_VECTOR: usize = 0x20;
+    /// ISA IRQs are delivered to vectors `ISA_VECTOR_BASE + irq`.
+    pub const ISA_VECTOR_BASE: usize = 0x20;
+    pub const PIT_IRQ: usize = 0;
+    pub const COM1_IRQ: usize = 4;
+
+    /// Vectors in `[MSI_VECTOR_START, MSI_VECTOR_END)` are allocated to MSIs.
+    pub const MSI_VECTOR_START: usize = 0x30;
+    pub const MSI_VECTOR_END: usize = 0xf0;
 
     pub const APIC_TIMER_VECTOR: usize = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: usize = 0xf1;
     pub const APIC_ERROR_VECTOR: usize = 0xf2;
 }
 
-const IRQ_COUNT: usize = 256;
-
-const IO_APIC_BASE: PhysAddr = PhysAddr::new(0xFEC0_0000);
+/// The size of the MMIO region of an IO APIC.
+const IO_APIC_SIZE: usize = 0x1000;
 
 static LOCAL_APIC: LazyInit<PerCpuData<LocalApic>> = LazyInit::new();
-static IO_APIC: LazyInit<SpinNoIrqLock<IoApic>> = LazyInit::new();
-static HANDLERS: IrqHandlerTable<IRQ_COUNT> = IrqHandlerTable::new();
+static IS_X2APIC: AtomicBool = AtomicBool::new(false);
+
+/// The APIC ID of each CPU, set when its local APIC is initialized.
+#[allow(clippy::declare_interior_mutable_const)]
+static APIC_IDS: [AtomicU32; MAX_CPUS] = {
+    const EMPTY: AtomicU32 = AtomicU32::new(u32::MAX);
+    [EMPTY; MAX_CPUS]
+};
+static IO_APICS: LazyInit<Vec<IoApicEntry>> = LazyInit::new();
+static NEXT_MSI_VECTOR: AtomicUsize = AtomicUsize::new(MSI_VECTOR_START);
+
+/// An IO APIC, which handles the GSIs in `[gsi_base, gsi_base + gsi_count)`.
+struct IoApicEntry {
+    gsi_base: usize,
+    gsi_count: usize,
+    inner: SpinNoIrqLock<IoApic>,
+}
+
+/// Returns the IO APIC of the GSI, and the index of its redirection entry.
+fn find_io_apic(gsi: usize) -> Option<(&'static SpinNoIrqLock<IoApic>, u8)> {
+    IO_APICS
+        .iter()
+        .find(|a| (a.gsi_base..a.gsi_base + a.gsi_count).contains(&gsi))
+        .map(|a| (&a.inner, (gsi - a.gsi_base) as u8))
+}
+
+fn io_apic_of(gsi: usize) -> (&'static SpinNoIrqLock<IoApic>, u8) {
+    find_io_apic(gsi).unwrap_or_else(|| panic!("no IO APIC for GSI {}", gsi))
+}
 
 fn lapic_eoi() {
     unsafe { local_apic().end_of_interrupt() };
 }
 
-pub fn set_enable(gsi: usize, enable: bool) {
+/// Returns the GSI of the vector if it is an ISA IRQ routed by an IO APIC.
+fn vector_gsi(vector: usize) -> Option<usize> {
+    let irq = vector.checked_sub(ISA_VECTOR_BASE)?;
+    if irq >= ISA_IRQ_COUNT {
+        return None;
+    }
+    let gsi = isa_irq_gsi(irq);
+    find_io_apic(gsi).map(|_| gsi)
+}
+
+/// Masks or unmasks the vector in the IO APIC if it is an ISA IRQ. MSIs are
+/// masked by the devices, and local interrupts by the local APIC.
+pub fn set_enable(vector: usize, enable: bool) {
+    let gsi = match vector_gsi(vector) {
+        Some(gsi) => gsi,
+        None => return,
+    };
+    let (io_apic, index) = io_apic_of(gsi);
     unsafe {
         if enable {
-            IO_APIC.lock().enable_irq(gsi as u8);
+            io_apic.lock().enable_irq(index);
         } else {
-            IO_APIC.lock().disable_irq(gsi as u8);
+            io_apic.lock().disable_irq(index);
         }
     }
 }
 
-#[allow(dead_code)]
-fn configure_irq(gsi: usize, vector: usize) {
-    let mut io_apic = IO_APIC.lock();
+/// Routes the vector to the CPU, only ISA IRQs can be rerouted.
+pub fn set_affinity(vector: usize, cpu_id: usize) -> SysResult<()> {
+    let gsi = vector_gsi(vector).ok_or(Errno::EINVAL)?;
+    let dest = APIC_IDS[cpu_id].load(Ordering::Acquire);
+    if dest > u8::MAX as u32 {
+        return Err(Errno::EINVAL);
+    }
+    let (io_apic, index) = io_apic_of(gsi);
+    let mut io_apic = io_apic.lock();
     unsafe {
-        let mut entry = io_apic.table_entry(gsi as u8);
-        entry.set_dest((local_apic().id() >> 24) as u8); // TODO: distinguish x2apic/x2apic
+        let mut entry = io_apic.table_entry(index);
+        entry.set_dest(dest as u8);
+        io_apic.set_table_entry(index, entry);
+    }
+    Ok(())
+}
+
+/// Returns the destination of the IRQs routed to the CPU with `apic_id`. IO
+/// APIC entries and MSIs hold 8-bit APIC IDs, larger x2APIC IDs need
+/// interrupt remapping, so the IRQs are routed to the first CPU with an ID
+/// that fits instead.
+fn irq_dest(apic_id: u32) -> SysResult<u8> {
+    if let Ok(dest) = u8::try_from(apic_id) {
+        return Ok(dest);
+    }
+    let dest = APIC_IDS
+        .iter()
+        .map(|id| id.load(Ordering::Acquire))
+        .find(|&id| id <= u8::MAX as u32)
+        .ok_or(Errno::EINVAL)?;
+    warn!(
+        "APIC ID {} is out of range for IRQs, routing them to APIC ID {}",
+        apic_id, dest
+    );
+    Ok(dest as u8)
+}
+
+/// Routes the GSI to `vector` of the current CPU, the IRQ is masked until it
+/// is enabled by [`set_enable`].
+fn configure_irq(
+    gsi: usize,
+    vector: usize,
+    level_triggered: bool,
+    active_low: bool,
+) -> SysResult<()> {
+    let (io_apic, index) = io_apic_of(gsi);
+    let dest = irq_dest(local_apic_id())?;
+    let mut flags = IrqFlags::MASKED;
+    if level_triggered {
+        flags = flags | IrqFlags::LEVEL_FREQUENCY;
+    }
+    if active_low {
+        flags = flags | IrqFlags::LOW_ACTIVE;
+    }
+    let mut io_apic = io_apic.lock();
+    unsafe {
+        let mut entry = io_apic.table_entry(index);
+        entry.set_dest(dest);
         entry.set_vector(vector as u8);
         entry.set_mode(IrqMode::Fixed);
-        entry.set_flags(IrqFlags::MASKED);
-        io_apic.set_table_entry(gsi as u8, entry);
+        entry.set_flags(flags);
+        io_apic.set_table_entry(index, entry);
     }
+    Ok(())
 }
 
-pub fn handle_irq(vector: usize) {
-    HANDLERS.handle(vector);
-    lapic_eoi();
+/// Returns the GSI that the ISA IRQ is routed to.
+pub fn isa_irq_gsi(irq: usize) -> usize {
+    acpi_info().isa_irqs[irq].gsi as usize
 }
 
-pub fn register_handler(vector: usize, handler: IrqHandler) {
-    HANDLERS.register_handler(vector, handler);
+fn init_io_apics() {
+    let mut io_apics = Vec::new();
+    for info in &acpi_info().io_apics {
+        let vaddr = match map_kernel_mmio(info.paddr, IO_APIC_SIZE) {
+            Ok(vaddr) => vaddr,
+            Err(e) => {
+                warn!("failed to map IO APIC at {:#x?}: {:?}", info.paddr, e);
+                continue;
+            }
+        };
+        let mut io_apic = unsafe { IoApic::new(vaddr.as_usize() as u64) };
+        let gsi_count = unsafe { io_apic.max_table_entry() } as usize + 1;
+        for i in 0..gsi_count {
+            unsafe { io_apic.disable_irq(i as u8) };
+        }
+        info!(
+            "IO APIC {} at {:#x?}: GSI {}..{}",
+            info.id,
+            info.paddr,
+            info.gsi_base,
+            info.gsi_base as usize + gsi_count
+        );
+        io_apics.push(IoApicEntry {
+            gsi_base: info.gsi_base as usize,
+            gsi_count,
+            inner: SpinNoIrqLock::new(io_apic),
+        });
+    }
+    IO_APICS.init_by(io_apics);
+
+    // Route the ISA IRQs with the overrides of the MADT, to the vectors
+    // following `ISA_VECTOR_BASE`.
+    for (irq, isa_irq) in acpi_info().isa_irqs.iter().enumerate() {
+        let gsi = isa_irq.gsi as usize;
+        if find_io_apic(gsi).is_some() {
+            let vector = ISA_VECTOR_BASE + irq;
+            if let Err(e) = configure_irq(gsi, vector, isa_irq.level_triggered, isa_irq.active_low)
+            {
+                warn!("failed to route ISA IRQ {}: {:?}", irq, e);
+            }
+        }
+    }
+}
+
+pub fn handle_irq(vector: usize) {
+    irq::dispatch(vector);
+    lapic_eoi();
 }
 
 pub fn init() {
     println!("Initializing Local APIC...");
-    super::i8259_pic::init();
+    if acpi_info().has_8259 {
+        super::i8259_pic::init();
+    }
 
-    let base_vaddr = PhysAddr::new(unsafe { xapic_base() } as usize).into_kvaddr();
-    let mut lapic = LocalApicBuilder::new()
+    // Use the x2APIC mode if it is supported, the xAPIC is accessed through
+    // MMIO otherwise.
+    let x2apic = CpuId::new()
+        .get_feature_info()
+        .map_or(false, |f| f.has_x2apic());
+    let mut builder = LocalApicBuilder::new();
+    builder
         .timer_vector(APIC_TIMER_VECTOR)
         .error_vector(APIC_ERROR_VECTOR)
-        .spurious_vector(APIC_SPURIOUS_VECTOR)
-        .set_xapic_base(base_vaddr.as_usize() as u64)
-        .build()
-        .unwrap();
+        .spurious_vector(APIC_SPURIOUS_VECTOR);
+    if !x2apic {
+        let base_vaddr = PhysAddr::new(unsafe { xapic_base() } as usize).into_kvaddr();
+        builder.set_xapic_base(base_vaddr.as_usize() as u64);
+    }
+    let mut lapic = builder.build().unwrap();
     unsafe { lapic.enable() };
     LOCAL_APIC.init_by(PerCpuData::new(lapic));
+    IS_X2APIC.store(x2apic, Ordering::Release);
+    APIC_IDS[PerCpu::current_cpu_id()].store(local_apic_id(), Ordering::Release);
+    println!(
+        "Local APIC {} in {} mode",
+        local_apic_id(),
+        if x2apic { "x2APIC" } else { "xAPIC" }
+    );
+
+    init_io_apics();
+
+    let handle_timer_irq = |_: &()| {
+        crate::timer::handle_timer_irq();
+        IrqReturn::Handled
+    };
+    irq::request_irq(
+        APIC_TIMER_VECTOR,
+        handle_timer_irq,
+        irq::IrqFlags::empty(),
+        "apic-timer",
+        (),
+    )
+    .expect("failed to request the APIC timer IRQ");
+}
 
-    let io_apic = unsafe { IoApic::new(IO_APIC_BASE.into_kvaddr().as_usize() as u64) };
-    IO_APIC.init_by(SpinNoIrqLock::new(io_apic));
+/// Allocates a vector for MSIs, vectors are never freed.
+pub fn alloc_msi_vector() -> Option<usize> {
+    NEXT_MSI_VECTOR
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+            (v < MSI_VECTOR_END).then_some(v + 1)
+        })
+        .ok()
+}
 
-    super::register_handler(APIC_TIMER_VECTOR, crate::timer::handle_timer_irq);
+/// Returns the address and data of the MSI message that delivers `vector` to
+/// the current CPU, with the fixed delivery mode and edge trigger.
+pub fn msi_message(vector: usize) -> SysResult<(u64, u32)> {
+    const MSI_ADDRESS_BASE: u64 = 0xfee0_0000;
+    let dest = irq_dest(local_apic_id())?;
+    Ok((MSI_ADDRESS_BASE | (dest as u64) << 12, vector as u32))
+}
+
+/// Returns the APIC ID of the current CPU.
+pub fn local_apic_id() -> u32 {
+    let id = unsafe { local_apic().id() };
+    if IS_X2APIC.load(Ordering::Acquire) {
+        id
+    } else {
+        // the ID is in bits 24..32 of the xAPIC ID register
+        id >> 24
+    }
 }
 
 pub fn local_apic() -> &'static mut LocalApic {
@@ -0,0 +1,133 @@
+//! ARM Generic Interrupt Controller, either GICv2 or GICv3.
+//!
+//! The version is set by the `gic-version` platform config, or detected from
+//! the CPU if it is 0: a GICv3 is present if the CPU implements its system
+//! register interface.
+
+use core::arch::asm;
+
+use super::{gicv2, gicv3};
+use crate::config::GIC_VERSION;
+use crate::errno::{Errno, SysResult};
+use crate::sync::LazyInit;
+
+#[allow(dead_code)]
+pub const SGI_BASE: usize = 0;
+#[allow(dead_code)]
+pub const PPI_BASE: usize = 16;
+pub const SPI_BASE: usize = 32;
+
+/// The number of SGIs, which are used as IPIs.
+pub const SGI_COUNT: usize = 16;
+
+static VERSION: LazyInit<usize> = LazyInit::new();
+
+#[allow(dead_code)]
+pub enum TriggerMode {
+    Edge = 0,
+    Level = 1,
+}
+
+#[allow(dead_code)]
+pub enum Polarity {
+    ActiveHigh = 0,
+    ActiveLow = 1,
+}
+
+/// The CPUs that receive an SGI.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum SgiTarget {
+    /// The CPU with the ID, see [`crate::percpu::PerCpu::current_cpu_id`].
+    Cpu(usize),
+    /// All CPUs except the current one.
+    AllExceptSelf,
+}
+
+fn is_v3() -> bool {
+    *VERSION == 3
+}
+
+/// Returns 3 if the CPU implements the GICv3 system register interface,
+/// otherwise 2.
+fn detect_version() -> usize {
+    let pfr0: u64;
+    unsafe { asm!("mrs {}, id_aa64pfr0_el1", out(reg) pfr0) };
+    // ID_AA64PFR0_EL1.GIC, bits [27:24]
+    if (pfr0 >> 24) & 0xf != 0 {
+        3
+    } else {
+        2
+    }
+}
+
+pub fn set_enable(vector: usize, enable: bool) {
+    if is_v3() {
+        gicv3::set_enable(vector, enable);
+    } else {
+        gicv2::set_enable(vector, enable);
+    }
+}
+
+pub fn handle_irq(_vector: usize) {
+    let pending = if is_v3() {
+        gicv3::pending_irq()
+    } else {
+        gicv2::pending_irq()
+    };
+    if let Some(vector) = pending {
+        super::irq::dispatch(vector);
+        if is_v3() {
+            gicv3::eoi(vector);
+        } else {
+            gicv2::eoi(vector);
+        }
+    }
+}
+
+/// Routes the SPI to the CPU, SGIs and PPIs are private to each CPU.
+pub fn set_affinity(vector: usize, cpu_id: usize) -> SysResult<()> {
+    if vector < SPI_BASE {
+        return Err(Errno::EINVAL);
+    }
+    if is_v3() {
+        gicv3::set_affinity(vector, cpu_id)
+    } else {
+        gicv2::set_affinity(vector, cpu_id)
+    }
+}
+
+/// Sends the software generated interrupt `sgi` to the target CPUs.
+#[allow(dead_code)]
+pub fn send_sgi(sgi: usize, target: SgiTarget) {
+    assert!(sgi < SGI_COUNT);
+    if is_v3() {
+        gicv3::send_sgi(sgi, target);
+    } else {
+        gicv2::send_sgi(sgi, target);
+    }
+}
+
+pub fn init() {
+    let version = match GIC_VERSION {
+        0 => detect_version(),
+        v => v,
+    };
+    println!("Initializing GICv{}...", version);
+    match version {
+        2 => gicv2::init(),
+        3 => gicv3::init(),
+        _ => panic!("unsupported GIC version {}", version),
+    }
+    VERSION.init_by(version);
+}
+
+/// Initializes the per-CPU state of the GIC on a secondary CPU, that of the
+/// boot CPU is initialized in [`init`].
+pub fn init_percpu() {
+    if is_v3() {
+        gicv3::init_percpu();
+    } else {
+        gicv2::init_percpu();
+    }
+}
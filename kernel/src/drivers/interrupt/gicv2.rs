@@ -2,25 +2,31 @@
 
 #![allow(dead_code)]
 
+use core::sync::atomic::{AtomicU8, Ordering};
+
 use tock_registers::interfaces::{Readable, Writeable};
 use tock_registers::register_structs;
 use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
 
+use super::gic::{Polarity, SgiTarget, TriggerMode, SPI_BASE};
+use crate::config::MAX_CPUS;
+use crate::errno::{Errno, SysResult};
 use crate::mm::{PhysAddr, VirtAddr};
+use crate::percpu::PerCpu;
 use crate::sync::LazyInit;
-use crate::utils::irq_handler::{IrqHandler, IrqHandlerTable};
 
 const GIC_BASE: usize = 0x0800_0000;
 const GICD_BASE: PhysAddr = PhysAddr::new(GIC_BASE);
 const GICC_BASE: PhysAddr = PhysAddr::new(GIC_BASE + 0x10000);
 
-const PPI_BASE: usize = 16;
-const SPI_BASE: usize = 32;
-
-const IRQ_COUNT: usize = 1024;
-
 static GIC: LazyInit<Gic> = LazyInit::new();
-static HANDLERS: IrqHandlerTable<IRQ_COUNT> = IrqHandlerTable::new();
+
+/// The CPU interface number of each CPU, as a bit mask of SGI targets.
+#[allow(clippy::declare_interior_mutable_const)]
+static CPU_TARGETS: [AtomicU8; MAX_CPUS] = {
+    const EMPTY: AtomicU8 = AtomicU8::new(0);
+    [EMPTY; MAX_CPUS]
+};
 
 register_structs! {
     #[allow(non_snake_case)]
@@ -86,16 +92,6 @@ register_structs! {
     }
 }
 
-enum TriggerMode {
-    Edge = 0,
-    Level = 1,
-}
-
-enum Polarity {
-    ActiveHigh = 0,
-    ActiveLow = 1,
-}
-
 struct Gic {
     gicd_base: VirtAddr,
     gicc_base: VirtAddr,
@@ -155,6 +151,14 @@ impl Gic {
         }
     }
 
+    /// Routes the SPI to the CPU interfaces in `mask`.
+    fn set_target(&self, vector: usize, mask: u8) {
+        assert!(vector < self.max_irqs);
+        let reg = &self.gicd().ITARGETSR[vector / 4];
+        let shift = (vector % 4) * 8;
+        reg.set((reg.get() & !(0xff << shift)) | (mask as u32) << shift);
+    }
+
     fn pending_irq(&self) -> Option<usize> {
         let iar = self.gicc().IAR.get();
         if iar >= 0x3fe {
@@ -169,11 +173,22 @@ impl Gic {
         self.gicc().EOIR.set(vector as _);
     }
 
+    fn send_sgi(&self, sgi: usize, target: SgiTarget) {
+        let val = match target {
+            SgiTarget::Cpu(cpu_id) => {
+                let mask = CPU_TARGETS[cpu_id].load(Ordering::Acquire) as u32;
+                (mask << 16) | sgi as u32
+            }
+            // TargetListFilter = 0b01
+            SgiTarget::AllExceptSelf => (1 << 24) | sgi as u32,
+        };
+        self.gicd().SGIR.set(val);
+    }
+
     fn init(&self) {
         let gicd = self.gicd();
-        let gicc = self.gicc();
 
-        for i in (0..self.max_irqs).step_by(32) {
+        for i in (SPI_BASE..self.max_irqs).step_by(32) {
             gicd.ICENABLER[i / 32].set(u32::MAX);
             gicd.ICPENDR[i / 32].set(u32::MAX);
         }
@@ -190,9 +205,23 @@ impl Gic {
 
         // enable GIC
         gicd.CTLR.set(1);
+    }
+
+    /// Initializes the banked SGIs and PPIs, and the CPU interface of the
+    /// current CPU.
+    fn init_percpu(&self) {
+        let gicd = self.gicd();
+        let gicc = self.gicc();
+
+        gicd.ICENABLER[0].set(u32::MAX);
+        gicd.ICPENDR[0].set(u32::MAX);
         gicc.CTLR.set(1);
         // unmask interrupts at all priority levels
         gicc.PMR.set(0xff);
+
+        // ITARGETSR0 is banked, the byte of SGI 0 is the current CPU
+        let target = gicd.ITARGETSR[0].get() as u8;
+        CPU_TARGETS[PerCpu::current_cpu_id()].store(target, Ordering::Release);
     }
 }
 
@@ -200,19 +229,36 @@ pub fn set_enable(vector: usize, enable: bool) {
     GIC.set_enable(vector, enable);
 }
 
-pub fn handle_irq(_vector: usize) {
-    if let Some(vector) = GIC.pending_irq() {
-        HANDLERS.handle(vector);
-        GIC.eoi(vector);
+pub fn set_affinity(vector: usize, cpu_id: usize) -> SysResult<()> {
+    // the CPU interface is unknown until the CPU is initialized
+    match CPU_TARGETS[cpu_id].load(Ordering::Acquire) {
+        0 => Err(Errno::EINVAL),
+        mask => {
+            GIC.set_target(vector, mask);
+            Ok(())
+        }
     }
 }
 
-pub fn register_handler(vector: usize, handler: IrqHandler) {
-    HANDLERS.register_handler(vector, handler);
+pub fn pending_irq() -> Option<usize> {
+    GIC.pending_irq()
+}
+
+pub fn eoi(vector: usize) {
+    GIC.eoi(vector);
+}
+
+pub fn send_sgi(sgi: usize, target: SgiTarget) {
+    GIC.send_sgi(sgi, target);
+}
+
+pub fn init_percpu() {
+    GIC.init_percpu();
 }
 
 pub fn init() {
     let gic = Gic::new(GICD_BASE.into_kvaddr(), GICC_BASE.into_kvaddr());
     gic.init();
     GIC.init_by(gic);
+    init_percpu();
 }
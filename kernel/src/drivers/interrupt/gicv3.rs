@@ -0,0 +1,431 @@
+//! ARM Generic Interrupt Controller v3.
+//!
+//! Affinity routing is enabled, so SGIs and PPIs are configured in the
+//! redistributor of each CPU, and SPIs are routed to CPUs by their affinity.
+//! The CPU interface is accessed through the `ICC_*_EL1` system registers.
+
+use core::arch::asm;
+
+use cortex_a::registers::MPIDR_EL1;
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite};
+
+use super::gic::{SgiTarget, TriggerMode, SPI_BASE};
+use crate::config::MAX_CPUS;
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysAddr, VirtAddr};
+use crate::percpu::PerCpu;
+use crate::sync::LazyInit;
+
+const GICD_BASE: PhysAddr = PhysAddr::new(0x0800_0000);
+const GICR_BASE: PhysAddr = PhysAddr::new(0x080a_0000);
+/// The size of the redistributor region of the QEMU virt machine.
+const GICR_SIZE: usize = 0xf6_0000;
+
+/// The size of the frames of a redistributor, without virtual LPI support.
+const GICR_STRIDE: usize = 0x2_0000;
+/// The offset of the SGI and PPI frame in a redistributor.
+const GICR_SGI_OFFSET: usize = 0x1_0000;
+
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
+const GICD_CTLR_ARE: u32 = 1 << 4;
+const GICD_CTLR_RWP: u32 = 1 << 31;
+
+const GICR_CTLR_RWP: u32 = 1 << 3;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+const GICR_TYPER_VLPIS: u64 = 1 << 1;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+
+/// The priority of all interrupts, 4 per `IPRIORITYR` register.
+const DEFAULT_PRIORITY: u32 = 0xa0a0_a0a0;
+
+/// INTIDs from 1020 are special, such as the spurious interrupt.
+const SPECIAL_IRQ_BASE: usize = 1020;
+
+static GIC: LazyInit<Gic> = LazyInit::new();
+static REDISTRIBUTORS: [LazyInit<Redistributor>; MAX_CPUS] = [LazyInit::new(); MAX_CPUS];
+
+register_structs! {
+    #[allow(non_snake_case)]
+    GicDistributorRegs {
+        /// Distributor Control Register.
+        (0x0000 => CTLR: ReadWrite<u32>),
+        /// Interrupt Controller Type Register.
+        (0x0004 => TYPER: ReadOnly<u32>),
+        /// Distributor Implementer Identification Register.
+        (0x0008 => IIDR: ReadOnly<u32>),
+        (0x000c => _reserved_0),
+        /// Interrupt Group Registers.
+        (0x0080 => IGROUPR: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Set-Enable Registers.
+        (0x0100 => ISENABLER: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Clear-Enable Registers.
+        (0x0180 => ICENABLER: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Set-Pending Registers.
+        (0x0200 => ISPENDR: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Clear-Pending Registers.
+        (0x0280 => ICPENDR: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Set-Active Registers.
+        (0x0300 => ISACTIVER: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Clear-Active Registers.
+        (0x0380 => ICACTIVER: [ReadWrite<u32>; 0x20]),
+        /// Interrupt Priority Registers.
+        (0x0400 => IPRIORITYR: [ReadWrite<u32>; 0x100]),
+        (0x0800 => _reserved_1),
+        /// Interrupt Configuration Registers.
+        (0x0c00 => ICFGR: [ReadWrite<u32>; 0x40]),
+        /// Interrupt Group Modifier Registers.
+        (0x0d00 => IGRPMODR: [ReadWrite<u32>; 0x20]),
+        (0x0d80 => _reserved_2),
+        /// Interrupt Routing Registers, the first 32 are reserved.
+        (0x6000 => IROUTER: [ReadWrite<u64>; 0x400]),
+        (0x8000 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    GicRedistributorRegs {
+        /// Redistributor Control Register.
+        (0x0000 => CTLR: ReadWrite<u32>),
+        /// Redistributor Implementer Identification Register.
+        (0x0004 => IIDR: ReadOnly<u32>),
+        /// Redistributor Type Register.
+        (0x0008 => TYPER: ReadOnly<u64>),
+        /// Error Reporting Status Register.
+        (0x0010 => STATUSR: ReadWrite<u32>),
+        /// Redistributor Wake Register.
+        (0x0014 => WAKER: ReadWrite<u32>),
+        (0x0018 => _reserved_0),
+        (0x1_0000 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    GicSgiRegs {
+        (0x0000 => _reserved_0),
+        /// Interrupt Group Register 0.
+        (0x0080 => IGROUPR0: ReadWrite<u32>),
+        (0x0084 => _reserved_1),
+        /// Interrupt Set-Enable Register 0.
+        (0x0100 => ISENABLER0: ReadWrite<u32>),
+        (0x0104 => _reserved_2),
+        /// Interrupt Clear-Enable Register 0.
+        (0x0180 => ICENABLER0: ReadWrite<u32>),
+        (0x0184 => _reserved_3),
+        /// Interrupt Set-Pending Register 0.
+        (0x0200 => ISPENDR0: ReadWrite<u32>),
+        (0x0204 => _reserved_4),
+        /// Interrupt Clear-Pending Register 0.
+        (0x0280 => ICPENDR0: ReadWrite<u32>),
+        (0x0284 => _reserved_5),
+        /// Interrupt Set-Active Register 0.
+        (0x0300 => ISACTIVER0: ReadWrite<u32>),
+        (0x0304 => _reserved_6),
+        /// Interrupt Clear-Active Register 0.
+        (0x0380 => ICACTIVER0: ReadWrite<u32>),
+        (0x0384 => _reserved_7),
+        /// Interrupt Priority Registers of SGIs and PPIs.
+        (0x0400 => IPRIORITYR: [ReadWrite<u32>; 8]),
+        (0x0420 => _reserved_8),
+        /// Interrupt Configuration Registers of SGIs and PPIs.
+        (0x0c00 => ICFGR: [ReadWrite<u32>; 2]),
+        (0x0c08 => _reserved_9),
+        /// Interrupt Group Modifier Register 0.
+        (0x0d00 => IGRPMODR0: ReadWrite<u32>),
+        (0x0d04 => _reserved_10),
+        (0x1_0000 => @END),
+    }
+}
+
+macro_rules! read_sysreg {
+    ($name:ident) => {{
+        let val: u64;
+        unsafe { asm!(concat!("mrs {}, ", stringify!($name)), out(reg) val) };
+        val
+    }};
+}
+
+macro_rules! write_sysreg {
+    ($name:ident, $val:expr) => {{
+        let val = $val as u64;
+        unsafe { asm!(concat!("msr ", stringify!($name), ", {}"), in(reg) val) }
+    }};
+}
+
+fn isb() {
+    unsafe { asm!("isb") };
+}
+
+/// The affinity of the current CPU, in the format of `GICR_TYPER[63:32]`
+/// (Aff3.Aff2.Aff1.Aff0).
+fn current_affinity() -> u32 {
+    let mpidr = MPIDR_EL1.get();
+    (((mpidr >> 32) & 0xff) << 24 | (mpidr & 0xff_ffff)) as u32
+}
+
+struct Gic {
+    gicd_base: VirtAddr,
+    max_irqs: usize,
+}
+
+impl Gic {
+    fn new(gicd_base: VirtAddr) -> Self {
+        let mut gic = Self {
+            gicd_base,
+            max_irqs: 0,
+        };
+        let lines = ((gic.gicd().TYPER.get() as usize & 0b11111) + 1) * 32;
+        gic.max_irqs = lines.min(SPECIAL_IRQ_BASE);
+        gic
+    }
+
+    const fn gicd(&self) -> &GicDistributorRegs {
+        unsafe { &*(self.gicd_base.as_ptr() as *const _) }
+    }
+
+    /// Waits until the writes to `CTLR` and `ICENABLER` take effect.
+    fn wait_for_rwp(&self) {
+        while self.gicd().CTLR.get() & GICD_CTLR_RWP != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn configure_interrupt(&self, vector: usize, tm: TriggerMode) {
+        // Only configurable for SPI interrupts
+        assert!(vector < self.max_irqs);
+        assert!(vector >= SPI_BASE);
+
+        // type is encoded with two bits, MSB of the two determine type
+        // 16 irqs encoded per ICFGR register
+        let reg_ndx = vector >> 4;
+        let bit_shift = ((vector & 0xf) << 1) + 1;
+        let mut reg_val = self.gicd().ICFGR[reg_ndx].get();
+        match tm {
+            TriggerMode::Edge => reg_val |= 1 << bit_shift,
+            TriggerMode::Level => reg_val &= !(1 << bit_shift),
+        }
+        self.gicd().ICFGR[reg_ndx].set(reg_val);
+    }
+
+    /// Routes the SPI to the CPU with the affinity.
+    fn set_route(&self, vector: usize, affinity: u32) {
+        assert!((SPI_BASE..self.max_irqs).contains(&vector));
+        let affinity = affinity as u64;
+        // Aff3 is at bits [39:32], and Interrupt_Routing_Mode (bit 31) is 0
+        let route = ((affinity >> 24) << 32) | (affinity & 0xff_ffff);
+        self.gicd().IROUTER[vector].set(route);
+    }
+
+    fn set_enable(&self, vector: usize, enable: bool) {
+        assert!((SPI_BASE..self.max_irqs).contains(&vector));
+        let reg = vector / 32;
+        let mask = 1 << (vector % 32);
+        if enable {
+            self.gicd().ISENABLER[reg].set(mask);
+        } else {
+            self.gicd().ICENABLER[reg].set(mask);
+            self.wait_for_rwp();
+        }
+    }
+
+    fn init(&self) {
+        let gicd = self.gicd();
+
+        gicd.CTLR.set(0);
+        self.wait_for_rwp();
+
+        for i in (SPI_BASE..self.max_irqs).step_by(32) {
+            // all interrupts are non-secure group 1
+            gicd.IGROUPR[i / 32].set(u32::MAX);
+            gicd.ICENABLER[i / 32].set(u32::MAX);
+            gicd.ICPENDR[i / 32].set(u32::MAX);
+        }
+        for i in (SPI_BASE..self.max_irqs).step_by(4) {
+            gicd.IPRIORITYR[i / 4].set(DEFAULT_PRIORITY);
+        }
+        // Initialize all the SPIs to edge triggered
+        for i in SPI_BASE..self.max_irqs {
+            self.configure_interrupt(i, TriggerMode::Edge);
+        }
+        self.wait_for_rwp();
+
+        // enable affinity routing and group 1 interrupts
+        gicd.CTLR.set(GICD_CTLR_ARE | GICD_CTLR_ENABLE_GRP1);
+        self.wait_for_rwp();
+
+        // Route external interrupts to the current CPU
+        let affinity = current_affinity();
+        for i in SPI_BASE..self.max_irqs {
+            self.set_route(i, affinity);
+        }
+    }
+}
+
+/// The redistributor of a CPU, for its SGIs and PPIs.
+struct Redistributor {
+    rd_base: VirtAddr,
+    affinity: u32,
+}
+
+impl Redistributor {
+    /// Finds the redistributor of the CPU with the affinity.
+    fn find(affinity: u32) -> Option<Self> {
+        let mut offset = 0;
+        while offset < GICR_SIZE {
+            let rd = Self {
+                rd_base: PhysAddr::new(GICR_BASE.as_usize() + offset).into_kvaddr(),
+                affinity,
+            };
+            let typer = rd.regs().TYPER.get();
+            if (typer >> 32) as u32 == affinity {
+                return Some(rd);
+            }
+            if typer & GICR_TYPER_LAST != 0 {
+                break;
+            }
+            // two more frames for virtual LPIs
+            offset += if typer & GICR_TYPER_VLPIS != 0 {
+                GICR_STRIDE * 2
+            } else {
+                GICR_STRIDE
+            };
+        }
+        None
+    }
+
+    const fn regs(&self) -> &GicRedistributorRegs {
+        unsafe { &*(self.rd_base.as_ptr() as *const _) }
+    }
+
+    const fn sgi(&self) -> &GicSgiRegs {
+        unsafe { &*(self.rd_base.as_ptr().add(GICR_SGI_OFFSET) as *const _) }
+    }
+
+    /// Waits until the writes to `ICENABLER0` take effect.
+    fn wait_for_rwp(&self) {
+        while self.regs().CTLR.get() & GICR_CTLR_RWP != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn set_enable(&self, vector: usize, enable: bool) {
+        assert!(vector < SPI_BASE);
+        let mask = 1 << vector;
+        if enable {
+            self.sgi().ISENABLER0.set(mask);
+        } else {
+            self.sgi().ICENABLER0.set(mask);
+            self.wait_for_rwp();
+        }
+    }
+
+    fn init(&self) {
+        let regs = self.regs();
+        let sgi = self.sgi();
+
+        // mark the CPU as awake
+        regs.WAKER
+            .set(regs.WAKER.get() & !GICR_WAKER_PROCESSOR_SLEEP);
+        while regs.WAKER.get() & GICR_WAKER_CHILDREN_ASLEEP != 0 {
+            core::hint::spin_loop();
+        }
+
+        sgi.IGROUPR0.set(u32::MAX);
+        sgi.ICENABLER0.set(u32::MAX);
+        sgi.ICPENDR0.set(u32::MAX);
+        for reg in &sgi.IPRIORITYR {
+            reg.set(DEFAULT_PRIORITY);
+        }
+        self.wait_for_rwp();
+    }
+}
+
+/// Enables the system register interface of the current CPU.
+fn init_cpu_interface() {
+    // ICC_SRE_EL1.SRE
+    write_sysreg!(icc_sre_el1, read_sysreg!(icc_sre_el1) | 1);
+    isb();
+    // unmask interrupts at all priority levels
+    write_sysreg!(icc_pmr_el1, 0xff);
+    write_sysreg!(icc_bpr1_el1, 0);
+    // ICC_CTLR_EL1.EOImode = 0, writing EOIR also deactivates the interrupt
+    write_sysreg!(icc_ctlr_el1, read_sysreg!(icc_ctlr_el1) & !(1 << 1));
+    write_sysreg!(icc_igrpen1_el1, 1);
+    isb();
+}
+
+fn current_redistributor() -> &'static Redistributor {
+    &REDISTRIBUTORS[PerCpu::current_cpu_id()]
+}
+
+pub fn set_enable(vector: usize, enable: bool) {
+    if vector < SPI_BASE {
+        current_redistributor().set_enable(vector, enable);
+    } else {
+        GIC.set_enable(vector, enable);
+    }
+}
+
+pub fn set_affinity(vector: usize, cpu_id: usize) -> SysResult<()> {
+    let rd = &REDISTRIBUTORS[cpu_id];
+    if !rd.is_init() {
+        return Err(Errno::EINVAL);
+    }
+    GIC.set_route(vector, rd.affinity);
+    Ok(())
+}
+
+pub fn pending_irq() -> Option<usize> {
+    let iar = read_sysreg!(icc_iar1_el1) as usize & 0xff_ffff;
+    if iar >= SPECIAL_IRQ_BASE {
+        // spurious
+        None
+    } else {
+        Some(iar)
+    }
+}
+
+pub fn eoi(vector: usize) {
+    write_sysreg!(icc_eoir1_el1, vector);
+}
+
+pub fn send_sgi(sgi: usize, target: SgiTarget) {
+    let val = match target {
+        SgiTarget::Cpu(cpu_id) => {
+            let aff = REDISTRIBUTORS[cpu_id].affinity as u64;
+            let (aff3, aff2, aff1, aff0) =
+                (aff >> 24, (aff >> 16) & 0xff, (aff >> 8) & 0xff, aff & 0xff);
+            // TargetList selects Aff0 in 16, RangeSelector (bits [47:44])
+            // selects the group of 16
+            (aff3 << 48) | ((aff0 >> 4) << 44) | (aff2 << 32) | (aff1 << 16) | (1 << (aff0 & 0xf))
+        }
+        // Interrupt_Routing_Mode = 1
+        SgiTarget::AllExceptSelf => 1 << 40,
+    };
+    // make memory writes visible to the target CPUs
+    unsafe { asm!("dsb ishst") };
+    write_sysreg!(icc_sgi1r_el1, val | ((sgi as u64) << 24));
+    isb();
+}
+
+/// Wakes up and initializes the redistributor of the current CPU, and enables
+/// its CPU interface.
+pub fn init_percpu() {
+    let affinity = current_affinity();
+    let rd = Redistributor::find(affinity)
+        .unwrap_or_else(|| panic!("no GICv3 redistributor for affinity {:#x}", affinity));
+    rd.init();
+    REDISTRIBUTORS[PerCpu::current_cpu_id()].init_by(rd);
+    init_cpu_interface();
+}
+
+pub fn init() {
+    let gic = Gic::new(GICD_BASE.into_kvaddr());
+    gic.init();
+    GIC.init_by(gic);
+    init_percpu();
+}
@@ -0,0 +1,472 @@
+//! The generic IRQ layer on top of the interrupt controllers.
+//!
+//! IRQs are numbered as the interrupt controller delivers them: vectors on
+//! x86_64, interrupt IDs of the GIC on aarch64, and `scause` interrupt causes
+//! or PLIC sources on RISC-V.
+//!
+//! Drivers request IRQs with [`request_irq`], a handler called with its data
+//! in interrupt context. An IRQ can be shared by several handlers if all of
+//! them are requested with [`IrqFlags::SHARED`], they are all called and tell
+//! whether the interrupt was raised by their device.
+//!
+//! Handlers run with IRQs disabled, so work that can be deferred should be
+//! done by a threaded handler, see [`request_threaded_irq`]: the handler only
+//! quiets the device and wakes up its IRQ thread, a high-priority kernel task
+//! that runs the thread function with IRQs enabled.
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::imp;
+use crate::config::MAX_CPUS;
+use crate::errno::{Errno, SysResult};
+use crate::sync::Mutex;
+use crate::task::{self, Task, WaitQueue};
+
+/// The mask of all CPUs, the default affinity of IRQs.
+const ALL_CPUS: usize = usize::MAX >> (usize::BITS as usize - MAX_CPUS);
+
+static IRQ_DESCS: Mutex<BTreeMap<usize, Arc<IrqDesc>>> = Mutex::new(BTreeMap::new());
+static NEXT_ACTION_ID: AtomicUsize = AtomicUsize::new(1);
+/// The number of interrupts delivered for IRQs that are never requested.
+static SPURIOUS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// IRQ threads of freed handlers, which are reused by new threaded handlers.
+static IDLE_THREADS: Mutex<Vec<Arc<IrqThread>>> = Mutex::new(Vec::new());
+/// IRQ threads created before the task manager is initialized, spawned by
+/// [`init_threads`].
+static PENDING_THREADS: Mutex<Vec<Arc<Task>>> = Mutex::new(Vec::new());
+
+bitflags::bitflags! {
+    pub struct IrqFlags: u32 {
+        /// The IRQ can be shared with other handlers requested with this flag.
+        const SHARED = 1 << 0;
+        /// The IRQ is masked until the thread functions return, for
+        /// level-triggered IRQs that the handler cannot quiet.
+        const ONESHOT = 1 << 1;
+    }
+}
+
+/// The result of an IRQ handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// The interrupt was not raised by the device.
+    None,
+    /// The interrupt was handled.
+    Handled,
+    /// The interrupt was handled, and the thread function should be run.
+    WakeThread,
+}
+
+/// A handler requested by [`request_irq`] or [`request_threaded_irq`], which
+/// is removed by [`free_irq`]. Dropping it keeps the handler.
+#[derive(Debug)]
+pub struct IrqHandle {
+    irq: usize,
+    id: usize,
+}
+
+/// The statistics of an IRQ, see [`irq_stats`].
+#[derive(Debug)]
+pub struct IrqStat {
+    pub irq: usize,
+    /// The number of interrupts delivered.
+    pub count: usize,
+    /// The number of interrupts that no handler claimed.
+    pub unhandled: usize,
+    /// The CPUs that the IRQ may be delivered to, as a bit mask.
+    pub affinity: usize,
+    /// The names of the handlers.
+    pub names: Vec<&'static str>,
+}
+
+struct IrqDesc {
+    irq: usize,
+    actions: Mutex<Vec<IrqAction>>,
+    count: AtomicUsize,
+    unhandled: AtomicUsize,
+    affinity: AtomicUsize,
+    /// The CPU that the IRQ is delivered to, the first one in `affinity`.
+    target_cpu: AtomicUsize,
+    /// The number of woken IRQ threads of a oneshot IRQ, which is unmasked
+    /// when it drops to zero.
+    threads_active: AtomicUsize,
+}
+
+struct IrqAction {
+    id: usize,
+    name: &'static str,
+    flags: IrqFlags,
+    handler: Box<dyn Fn() -> IrqReturn + Send + Sync>,
+    thread: Option<Arc<IrqThread>>,
+}
+
+/// The thread function of a handler, run by an IRQ thread.
+struct ThreadWork {
+    desc: Arc<IrqDesc>,
+    oneshot: bool,
+    func: Box<dyn Fn() + Send + Sync>,
+}
+
+struct IrqThread {
+    work: Mutex<Option<Arc<ThreadWork>>>,
+    pending: AtomicBool,
+    /// Whether the thread function is running, set when `pending` is taken.
+    running: AtomicBool,
+    waiters: WaitQueue,
+    /// Tasks waiting for the thread to be neither pending nor running.
+    idle_waiters: WaitQueue,
+}
+
+impl IrqDesc {
+    fn new(irq: usize) -> Self {
+        Self {
+            irq,
+            actions: Mutex::new(Vec::new()),
+            count: AtomicUsize::new(0),
+            unhandled: AtomicUsize::new(0),
+            affinity: AtomicUsize::new(ALL_CPUS),
+            target_cpu: AtomicUsize::new(0),
+            threads_active: AtomicUsize::new(0),
+        }
+    }
+
+    fn wake_thread(&self, action: &IrqAction) {
+        let thread = match &action.thread {
+            Some(thread) => thread,
+            None => {
+                warn!("IRQ {}: {} has no thread to wake up", self.irq, action.name);
+                return;
+            }
+        };
+        if thread.pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        if action.flags.contains(IrqFlags::ONESHOT)
+            && self.threads_active.fetch_add(1, Ordering::AcqRel) == 0
+        {
+            imp::set_enable(self.irq, false);
+        }
+        thread.waiters.notify_one();
+    }
+
+    /// Called by IRQ threads when the thread function returns.
+    fn thread_done(&self, oneshot: bool) {
+        if !oneshot {
+            return;
+        }
+        let active = self
+            .threads_active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+        if active == Ok(1) {
+            // the IRQ stays disabled if all the handlers are freed meanwhile
+            let actions = self.actions.lock();
+            if !actions.is_empty() {
+                imp::set_enable(self.irq, true);
+            }
+        }
+    }
+}
+
+impl IrqHandle {
+    #[allow(dead_code)]
+    pub fn irq(&self) -> usize {
+        self.irq
+    }
+}
+
+impl IrqThread {
+    fn new() -> Self {
+        Self {
+            work: Mutex::new(None),
+            pending: AtomicBool::new(false),
+            running: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            idle_waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Waits until the thread function is not pending or running, so that
+    /// the work can be detached without losing its [`IrqDesc::thread_done`].
+    fn wait_idle(&self) {
+        if task::is_init() {
+            self.idle_waiters.wait_until(|| {
+                !self.pending.load(Ordering::Acquire) && !self.running.load(Ordering::Acquire)
+            });
+        } else if self.pending.swap(false, Ordering::AcqRel) {
+            // IRQ threads do not run yet, the pending call is dropped
+            if let Some(work) = &*self.work.lock() {
+                work.desc.thread_done(work.oneshot);
+            }
+        }
+    }
+}
+
+fn irq_thread_entry(arg: usize) -> usize {
+    // the thread is leaked when the task is created, IRQ threads never exit
+    let thread = unsafe { &*(arg as *const IrqThread) };
+    loop {
+        // `running` is set along with taking `pending`, both with the task
+        // manager locked, so `wait_idle` never sees neither of them set
+        thread.waiters.wait_until(|| {
+            let pending = thread.pending.swap(false, Ordering::AcqRel);
+            if pending {
+                thread.running.store(true, Ordering::Release);
+            }
+            pending
+        });
+        let work = thread.work.lock().clone();
+        if let Some(work) = work {
+            (work.func)();
+            work.desc.thread_done(work.oneshot);
+        }
+        thread.running.store(false, Ordering::Release);
+        thread.idle_waiters.notify_all();
+    }
+}
+
+/// Takes an idle IRQ thread, or creates a new one.
+fn alloc_thread() -> Arc<IrqThread> {
+    if let Some(thread) = IDLE_THREADS.lock().pop() {
+        thread.pending.store(false, Ordering::Release);
+        return thread;
+    }
+    let thread = Arc::new(IrqThread::new());
+    let arg = Arc::into_raw(thread.clone()) as usize;
+    let task = Task::new_irq_thread(irq_thread_entry, arg);
+    if task::is_init() {
+        task::spawn_task(task);
+    } else {
+        PENDING_THREADS.lock().push(task);
+    }
+    thread
+}
+
+fn find_desc(irq: usize) -> Option<Arc<IrqDesc>> {
+    IRQ_DESCS.lock().get(&irq).cloned()
+}
+
+fn get_or_create_desc(irq: usize) -> Arc<IrqDesc> {
+    IRQ_DESCS
+        .lock()
+        .entry(irq)
+        .or_insert_with(|| Arc::new(IrqDesc::new(irq)))
+        .clone()
+}
+
+fn add_action(
+    irq: usize,
+    flags: IrqFlags,
+    name: &'static str,
+    handler: Box<dyn Fn() -> IrqReturn + Send + Sync>,
+    thread_fn: Option<Box<dyn Fn() + Send + Sync>>,
+) -> SysResult<IrqHandle> {
+    let desc = get_or_create_desc(irq);
+    let thread = thread_fn.map(|func| {
+        let thread = alloc_thread();
+        *thread.work.lock() = Some(Arc::new(ThreadWork {
+            desc: desc.clone(),
+            oneshot: flags.contains(IrqFlags::ONESHOT),
+            func,
+        }));
+        thread
+    });
+
+    let mut actions = desc.actions.lock();
+    if let Some(first) = actions.first() {
+        // all the handlers of a shared IRQ must agree on the flags
+        if !(first.flags & flags).contains(IrqFlags::SHARED)
+            || (first.flags ^ flags).contains(IrqFlags::ONESHOT)
+        {
+            warn!("IRQ {}: {} conflicts with {}", irq, name, first.name);
+            drop(actions);
+            if let Some(thread) = thread {
+                *thread.work.lock() = None;
+                IDLE_THREADS.lock().push(thread);
+            }
+            return Err(Errno::EBUSY);
+        }
+    }
+    let id = NEXT_ACTION_ID.fetch_add(1, Ordering::Relaxed);
+    actions.push(IrqAction {
+        id,
+        name,
+        flags,
+        handler,
+        thread,
+    });
+    if actions.len() == 1 {
+        imp::set_enable(irq, true);
+    }
+    info!("IRQ {}: requested by {}", irq, name);
+    Ok(IrqHandle { irq, id })
+}
+
+/// Requests the IRQ, `handler` is called with `data` in interrupt context.
+///
+/// Handlers must not request or free the IRQ that they handle.
+pub fn request_irq<D, H>(
+    irq: usize,
+    handler: H,
+    flags: IrqFlags,
+    name: &'static str,
+    data: D,
+) -> SysResult<IrqHandle>
+where
+    D: Send + Sync + 'static,
+    H: Fn(&D) -> IrqReturn + Send + Sync + 'static,
+{
+    add_action(irq, flags, name, Box::new(move || handler(&data)), None)
+}
+
+/// Like [`request_irq`], but `thread_fn` is also called with `data` by an IRQ
+/// thread after `handler` returns [`IrqReturn::WakeThread`].
+///
+/// Interrupts raised before the thread runs are coalesced into one call.
+pub fn request_threaded_irq<D, H, T>(
+    irq: usize,
+    handler: H,
+    thread_fn: T,
+    flags: IrqFlags,
+    name: &'static str,
+    data: D,
+) -> SysResult<IrqHandle>
+where
+    D: Send + Sync + 'static,
+    H: Fn(&D) -> IrqReturn + Send + Sync + 'static,
+    T: Fn(&D) + Send + Sync + 'static,
+{
+    let data = Arc::new(data);
+    let thread_data = data.clone();
+    add_action(
+        irq,
+        flags,
+        name,
+        Box::new(move || handler(&data)),
+        Some(Box::new(move || thread_fn(&thread_data))),
+    )
+}
+
+/// Removes the handler, the IRQ is disabled if it is the last one.
+///
+/// Blocks until the thread function of the handler returns if it is woken,
+/// so it must not be called by the thread function. Returns
+/// [`Errno::EINVAL`] if the IRQ is never requested, or [`Errno::ENOENT`] if
+/// the handler is already removed.
+#[allow(dead_code)]
+pub fn free_irq(handle: IrqHandle) -> SysResult<()> {
+    let desc = find_desc(handle.irq).ok_or(Errno::EINVAL)?;
+    let mut actions = desc.actions.lock();
+    let idx = actions
+        .iter()
+        .position(|a| a.id == handle.id)
+        .ok_or(Errno::ENOENT)?;
+    let action = actions.remove(idx);
+    if actions.is_empty() {
+        imp::set_enable(handle.irq, false);
+    }
+    drop(actions);
+    if let Some(thread) = action.thread {
+        // the handler is removed, so the thread is not woken again
+        thread.wait_idle();
+        *thread.work.lock() = None;
+        IDLE_THREADS.lock().push(thread);
+    }
+    info!("IRQ {}: freed by {}", handle.irq, action.name);
+    Ok(())
+}
+
+/// Sets the CPUs that the IRQ may be delivered to, as a bit mask. The IRQ is
+/// delivered to the first CPU in the mask.
+///
+/// Returns [`Errno::EINVAL`] if there is no CPU in the mask, or the interrupt
+/// controller cannot route the IRQ to the CPU.
+#[allow(dead_code)]
+pub fn set_irq_affinity(irq: usize, cpu_mask: usize) -> SysResult<()> {
+    let cpu_mask = cpu_mask & ALL_CPUS;
+    if cpu_mask == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let desc = get_or_create_desc(irq);
+    let cpu = cpu_mask.trailing_zeros() as usize;
+    if cpu != desc.target_cpu.load(Ordering::Acquire) {
+        imp::set_affinity(irq, cpu)?;
+        desc.target_cpu.store(cpu, Ordering::Release);
+    }
+    desc.affinity.store(cpu_mask, Ordering::Release);
+    Ok(())
+}
+
+/// Returns the statistics of all the IRQs that are requested, or whose affinity
+/// is set.
+pub fn irq_stats() -> Vec<IrqStat> {
+    let descs: Vec<_> = IRQ_DESCS.lock().values().cloned().collect();
+    descs
+        .iter()
+        .map(|desc| IrqStat {
+            irq: desc.irq,
+            count: desc.count.load(Ordering::Relaxed),
+            unhandled: desc.unhandled.load(Ordering::Relaxed),
+            affinity: desc.affinity.load(Ordering::Relaxed),
+            names: desc.actions.lock().iter().map(|a| a.name).collect(),
+        })
+        .collect()
+}
+
+/// Returns the number of interrupts delivered for IRQs that are never
+/// requested, which are not in [`irq_stats`].
+pub fn spurious_irq_count() -> usize {
+    SPURIOUS_COUNT.load(Ordering::Relaxed)
+}
+
+/// Prints the statistics of all IRQs in the log.
+pub fn dump_irq_stats() {
+    for stat in irq_stats() {
+        info!(
+            "IRQ {:#x}: {} interrupts, {} unhandled, affinity {:#x}, {:?}",
+            stat.irq, stat.count, stat.unhandled, stat.affinity, stat.names
+        );
+    }
+    info!("{} spurious interrupts", spurious_irq_count());
+}
+
+/// Calls the handlers of the IRQ, called by the interrupt controllers.
+pub(super) fn dispatch(irq: usize) {
+    trace!("IRQ {}", irq);
+    // descriptors are not allocated in interrupt context
+    let desc = match find_desc(irq) {
+        Some(desc) => desc,
+        None => {
+            SPURIOUS_COUNT.fetch_add(1, Ordering::Relaxed);
+            warn!("Spurious IRQ {}", irq);
+            return;
+        }
+    };
+    desc.count.fetch_add(1, Ordering::Relaxed);
+    let actions = desc.actions.lock();
+    if actions.is_empty() {
+        warn!("Unhandled IRQ {}", irq);
+    }
+    let mut handled = false;
+    for action in actions.iter() {
+        match (action.handler)() {
+            IrqReturn::None => {}
+            IrqReturn::Handled => handled = true,
+            IrqReturn::WakeThread => {
+                handled = true;
+                desc.wake_thread(action);
+            }
+        }
+    }
+    if !handled {
+        desc.unhandled.fetch_add(1, Ordering::Relaxed);
+        debug!("IRQ {} is not claimed by any handler", irq);
+    }
+}
+
+/// Spawns the IRQ threads created before the task manager is initialized.
+pub fn init_threads() {
+    for task in PENDING_THREADS.lock().drain(..) {
+        task::spawn_task(task);
+    }
+}
@@ -3,19 +3,31 @@ cfg_if! {
         mod apic;
         mod i8259_pic;
         use apic as imp;
-        pub use apic::local_apic;
+        pub use apic::{alloc_msi_vector, isa_irq_gsi, local_apic, local_apic_id, msi_message};
         pub use apic::vectors::*;
     } else if #[cfg(target_arch = "aarch64")] {
+        mod gic;
         mod gicv2;
-        use gicv2 as imp;
+        mod gicv3;
+        use gic as imp;
+        pub use gic::{send_sgi, SgiTarget};
+        pub(super) use gic::init_percpu;
     } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        mod plic;
         mod riscv_intc;
         use riscv_intc as imp;
         pub use riscv_intc::ScauseIntCode;
     }
 }
 
+mod irq;
+
 pub use self::imp::handle_irq;
+pub use self::irq::{
+    dump_irq_stats, free_irq, irq_stats, request_irq, request_threaded_irq, set_irq_affinity,
+    spurious_irq_count, IrqFlags, IrqHandle, IrqReturn, IrqStat,
+};
 
 #[allow(unused_imports)]
-pub(super) use self::imp::{init, register_handler, set_enable};
+pub(super) use self::imp::{init, set_enable};
+pub(super) use self::irq::init_threads;
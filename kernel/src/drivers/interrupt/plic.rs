@@ -0,0 +1,67 @@
+//! RISC-V Platform-Level Interrupt Controller, for the supervisor mode of
+//! hart 0.
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysAddr, VirtAddr};
+
+const PLIC_BASE: PhysAddr = PhysAddr::new(0x0c00_0000);
+/// The supervisor context of hart 0.
+const CONTEXT: usize = 1;
+
+const PRIORITY_BASE: usize = 0x0;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const CONTEXT_THRESHOLD: usize = 0x0;
+const CONTEXT_CLAIM: usize = 0x4;
+
+pub const IRQ_COUNT: usize = 1024;
+
+fn reg(offset: usize) -> *mut u32 {
+    let base: VirtAddr = PLIC_BASE.into_kvaddr();
+    (base.as_usize() + offset) as *mut u32
+}
+
+fn context_reg(offset: usize) -> *mut u32 {
+    reg(CONTEXT_BASE + CONTEXT * CONTEXT_STRIDE + offset)
+}
+
+pub fn set_enable(irq: usize, enable: bool) {
+    assert!(irq > 0 && irq < IRQ_COUNT);
+    let enable_reg = reg(ENABLE_BASE + CONTEXT * ENABLE_STRIDE + irq / 32 * 4);
+    unsafe {
+        if enable {
+            reg(PRIORITY_BASE + irq * 4).write_volatile(1);
+            enable_reg.write_volatile(enable_reg.read_volatile() | 1 << (irq % 32));
+        } else {
+            enable_reg.write_volatile(enable_reg.read_volatile() & !(1 << (irq % 32)));
+        }
+    }
+}
+
+/// Only the context of hart 0 is used, so IRQs cannot be routed elsewhere.
+pub fn set_affinity(irq: usize, cpu_id: usize) -> SysResult<()> {
+    assert!(irq > 0 && irq < IRQ_COUNT);
+    match cpu_id {
+        0 => Ok(()),
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// Handles all pending external interrupts.
+pub fn handle_irq() {
+    loop {
+        let irq = unsafe { context_reg(CONTEXT_CLAIM).read_volatile() } as usize;
+        if irq == 0 {
+            break;
+        }
+        super::irq::dispatch(irq);
+        unsafe { context_reg(CONTEXT_CLAIM).write_volatile(irq as u32) };
+    }
+}
+
+pub fn init() {
+    // accept interrupts of all priorities
+    unsafe { context_reg(CONTEXT_THRESHOLD).write_volatile(0) };
+}
@@ -1,17 +1,16 @@
+//! RISC-V local interrupts in `scause`, and external interrupts through the
+//! PLIC.
+
 use riscv::register::sie;
 
-use crate::sync::LazyInit;
-use crate::utils::irq_handler::IrqHandler;
+use super::plic;
+use crate::errno::{Errno, SysResult};
 
 const INT_BASE: usize = 1 << (usize::BITS - 1);
 const S_SOFT: usize = INT_BASE + 1;
 const S_TIMER: usize = INT_BASE + 5;
 const S_EXT: usize = INT_BASE + 9;
 
-static SOFT_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
-static TIMER_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
-static EXT_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
-
 #[repr(usize)]
 #[allow(dead_code)]
 #[allow(clippy::enum_clike_unportable_variant)]
@@ -35,26 +34,23 @@ where
     }
 }
 
+/// Handles the local interrupt `cause`, external interrupts are dispatched by
+/// the PLIC.
 pub fn handle_irq(cause: usize) {
-    let handler = with_cause(cause, || &SOFT_HANDLER, || &TIMER_HANDLER, || &EXT_HANDLER);
-    if handler.is_init() {
-        trace!("Trap cause {:#x}", cause);
-        handler();
+    trace!("Trap cause {:#x}", cause);
+    if cause == S_EXT {
+        plic::handle_irq();
     } else {
-        warn!("Unhandled trap cause: {:#x}", cause);
+        super::irq::dispatch(cause);
     }
 }
 
-pub fn register_handler(cause: usize, handler: IrqHandler) {
-    with_cause(
-        cause,
-        || SOFT_HANDLER.init_by(handler),
-        || TIMER_HANDLER.init_by(handler),
-        || EXT_HANDLER.init_by(handler),
-    );
-}
-
+/// Enables or disables the local interrupt `cause`, or the external interrupt
+/// `cause` of the PLIC if it is not an interrupt cause of `scause`.
 pub fn set_enable(cause: usize, enable: bool) {
+    if cause & INT_BASE == 0 {
+        return plic::set_enable(cause, enable);
+    }
     unsafe {
         if enable {
             with_cause(
@@ -74,4 +70,16 @@ pub fn set_enable(cause: usize, enable: bool) {
     }
 }
 
-pub fn init() {}
+/// Routes the external interrupt to the CPU, local interrupts are private to
+/// each CPU.
+pub fn set_affinity(cause: usize, cpu_id: usize) -> SysResult<()> {
+    if cause & INT_BASE != 0 {
+        return Err(Errno::EINVAL);
+    }
+    plic::set_affinity(cause, cpu_id)
+}
+
+pub fn init() {
+    plic::init();
+    set_enable(S_EXT, true);
+}
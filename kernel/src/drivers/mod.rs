@@ -1,7 +1,17 @@
+#[cfg(target_arch = "x86_64")]
+pub mod acpi;
+pub mod block;
+pub mod console;
 pub mod interrupt;
 pub mod misc;
+pub mod net;
+#[cfg(any(feature = "platform-pc", feature = "platform-pc-rvm"))]
+pub mod pci;
+pub mod rtc;
 pub mod timer;
 pub mod uart;
+#[cfg(not(feature = "platform-rvm-guest-x86_64"))]
+pub mod virtio;
 
 pub fn init_early() {
     uart::init_early();
@@ -9,7 +19,27 @@ pub fn init_early() {
 
 pub fn init() {
     println!("Initializing drivers...");
+    #[cfg(target_arch = "x86_64")]
+    acpi::init();
     interrupt::init();
     uart::init();
     timer::init();
+    rtc::init();
+    #[cfg(any(feature = "platform-pc", feature = "platform-pc-rvm"))]
+    pci::init();
+    #[cfg(not(feature = "platform-rvm-guest-x86_64"))]
+    virtio::init();
+}
+
+/// Initializes the per-CPU state of drivers on a secondary CPU, that of the
+/// boot CPU is initialized in [`init`].
+#[allow(dead_code)]
+pub fn init_percpu() {
+    #[cfg(target_arch = "aarch64")]
+    interrupt::init_percpu();
+}
+
+/// Initializes the parts of drivers that run in tasks, after the task manager.
+pub fn init_late() {
+    interrupt::init_threads();
 }
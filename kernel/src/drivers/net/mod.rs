@@ -0,0 +1,66 @@
+//! Network devices.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::errno::SysResult;
+use crate::sync::{LazyInit, Mutex};
+
+static NET_DEVICES: Mutex<Vec<Arc<dyn NetDevice>>> = Mutex::new(Vec::new());
+
+/// The handler called when frames are received, see [`set_rx_handler`].
+static RX_HANDLER: LazyInit<fn()> = LazyInit::new();
+
+/// A device that sends and receives ethernet frames.
+pub trait NetDevice: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn mac_address(&self) -> [u8; 6];
+
+    /// The maximum size of a frame, including the ethernet header.
+    fn max_frame_size(&self) -> usize;
+
+    /// Whether a frame can be transmitted without waiting.
+    fn can_transmit(&self) -> bool;
+
+    /// Queues the frame to be transmitted, returns [`Errno::EAGAIN`] if the
+    /// transmit queue is full.
+    ///
+    /// [`Errno::EAGAIN`]: crate::errno::Errno::EAGAIN
+    fn transmit(&self, frame: &[u8]) -> SysResult<()>;
+
+    /// Takes a received frame, returns `None` if there is none.
+    fn receive(&self) -> Option<Vec<u8>>;
+}
+
+pub fn register_net_device(dev: Arc<dyn NetDevice>) {
+    let mac = dev.mac_address();
+    println!(
+        "Network device {}: MAC {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        dev.name(),
+        mac[0],
+        mac[1],
+        mac[2],
+        mac[3],
+        mac[4],
+        mac[5]
+    );
+    NET_DEVICES.lock().push(dev);
+}
+
+/// Returns all the network devices, in the order they are found.
+pub fn net_devices() -> Vec<Arc<dyn NetDevice>> {
+    NET_DEVICES.lock().clone()
+}
+
+/// Sets the handler called in interrupt context when network devices
+/// receive frames.
+pub fn set_rx_handler(handler: fn()) {
+    RX_HANDLER.init_by(handler);
+}
+
+/// Called by the drivers when frames are received.
+pub(super) fn handle_rx() {
+    if RX_HANDLER.is_init() {
+        RX_HANDLER();
+    }
+}
@@ -0,0 +1,204 @@
+//! PCI capabilities, including MSI and MSI-X.
+
+use alloc::vec::Vec;
+
+use super::config::{ConfigSpace, PciAddress};
+use super::device::{PciDevice, REG_CAP_PTR, REG_STATUS};
+use crate::errno::{Errno, SysResult};
+use crate::mm::VirtAddr;
+
+pub const CAP_ID_MSI: u8 = 0x05;
+pub const CAP_ID_VENDOR: u8 = 0x09;
+pub const CAP_ID_PCIE: u8 = 0x10;
+pub const CAP_ID_MSIX: u8 = 0x11;
+
+const MSI_CTRL_ENABLE: u16 = 1 << 0;
+const MSI_CTRL_64BIT: u16 = 1 << 7;
+const MSI_CTRL_PER_VECTOR_MASK: u16 = 1 << 8;
+
+const MSIX_CTRL_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_CTRL_ENABLE: u16 = 1 << 15;
+const MSIX_ENTRY_SIZE: usize = 16;
+const MSIX_ENTRY_CTRL_MASKED: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Capability {
+    Msi(MsiCapability),
+    MsiX(MsixCapability),
+    Other { id: u8, offset: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MsiCapability {
+    offset: usize,
+    is_64bit: bool,
+    pub per_vector_mask: bool,
+    /// The number of vectors requested by the function.
+    pub max_vectors: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MsixCapability {
+    offset: usize,
+    /// The number of entries in the MSI-X table.
+    pub table_size: usize,
+    /// The BAR index and the offset in it of the MSI-X table.
+    pub table: (usize, usize),
+    /// The BAR index and the offset in it of the pending bit array.
+    pub pba: (usize, usize),
+}
+
+/// The MSI-X table mapped in the kernel address space.
+pub struct MsixTable {
+    base_vaddr: VirtAddr,
+    size: usize,
+}
+
+impl Capability {
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::Msi(_) => CAP_ID_MSI,
+            Self::MsiX(_) => CAP_ID_MSIX,
+            Self::Other { id, .. } => *id,
+        }
+    }
+
+    /// The offset of the capability in the configuration space.
+    pub fn offset(&self) -> usize {
+        match self {
+            Self::Msi(msi) => msi.offset,
+            Self::MsiX(msix) => msix.offset,
+            Self::Other { offset, .. } => *offset,
+        }
+    }
+
+    fn parse(config: &ConfigSpace, addr: PciAddress, id: u8, offset: usize) -> Self {
+        match id {
+            CAP_ID_MSI => {
+                let ctrl = config.read16(addr, offset + 2);
+                Self::Msi(MsiCapability {
+                    offset,
+                    is_64bit: ctrl & MSI_CTRL_64BIT != 0,
+                    per_vector_mask: ctrl & MSI_CTRL_PER_VECTOR_MASK != 0,
+                    max_vectors: 1 << ((ctrl >> 1) & 0x7).min(5),
+                })
+            }
+            CAP_ID_MSIX => {
+                let ctrl = config.read16(addr, offset + 2);
+                let table = config.read32(addr, offset + 4);
+                let pba = config.read32(addr, offset + 8);
+                Self::MsiX(MsixCapability {
+                    offset,
+                    table_size: (ctrl & 0x7ff) as usize + 1,
+                    table: ((table & 0x7) as usize, (table & !0x7) as usize),
+                    pba: ((pba & 0x7) as usize, (pba & !0x7) as usize),
+                })
+            }
+            _ => Self::Other { id, offset },
+        }
+    }
+}
+
+/// Walks the capability list of the function at `addr`.
+pub(super) fn parse_capabilities(config: &ConfigSpace, addr: PciAddress) -> Vec<Capability> {
+    const STATUS_CAP_LIST: u16 = 1 << 4;
+    // 48 capabilities at most fit in the 256-byte configuration space, avoid
+    // looping forever on a malformed list.
+    const MAX_CAPS: usize = 48;
+
+    let mut caps = Vec::new();
+    if config.read16(addr, REG_STATUS) & STATUS_CAP_LIST == 0 {
+        return caps;
+    }
+    let mut offset = (config.read8(addr, REG_CAP_PTR) & 0xfc) as usize;
+    while offset != 0 && caps.len() < MAX_CAPS {
+        let header = config.read16(addr, offset);
+        caps.push(Capability::parse(config, addr, header as u8, offset));
+        offset = ((header >> 8) & 0xfc) as usize;
+    }
+    caps
+}
+
+impl MsiCapability {
+    /// Programs the message and enables MSI with a single vector.
+    pub fn enable(&self, dev: &PciDevice, address: u64, data: u16) {
+        let off = self.offset;
+        dev.write_config32(off + 4, address as u32);
+        let data_off = if self.is_64bit {
+            dev.write_config32(off + 8, (address >> 32) as u32);
+            off + 12
+        } else {
+            off + 8
+        };
+        dev.write_config16(data_off, data);
+        if self.per_vector_mask {
+            dev.write_config32(data_off + 4, 0);
+        }
+        // one vector only, clear the multiple message enable bits
+        let ctrl = dev.read_config16(off + 2) & !(0x7 << 4);
+        dev.write_config16(off + 2, ctrl | MSI_CTRL_ENABLE);
+        dev.set_intx_enabled(false);
+    }
+
+    pub fn disable(&self, dev: &PciDevice) {
+        let ctrl = dev.read_config16(self.offset + 2);
+        dev.write_config16(self.offset + 2, ctrl & !MSI_CTRL_ENABLE);
+    }
+}
+
+impl MsixCapability {
+    /// Maps the MSI-X table. All entries are masked initially.
+    pub fn map_table(&self, dev: &PciDevice) -> SysResult<MsixTable> {
+        let size = self.table_size * MSIX_ENTRY_SIZE;
+        let base_vaddr = dev.map_bar_range(self.table.0, self.table.1, size)?;
+        let table = MsixTable { base_vaddr, size };
+        for i in 0..self.table_size {
+            table.set_masked(i, true);
+        }
+        Ok(table)
+    }
+
+    pub fn set_enabled(&self, dev: &PciDevice, enabled: bool) {
+        let ctrl = dev.read_config16(self.offset + 2) & !MSIX_CTRL_FUNCTION_MASK;
+        if enabled {
+            dev.write_config16(self.offset + 2, ctrl | MSIX_CTRL_ENABLE);
+            dev.set_intx_enabled(false);
+        } else {
+            dev.write_config16(self.offset + 2, ctrl & !MSIX_CTRL_ENABLE);
+        }
+    }
+}
+
+impl MsixTable {
+    fn entry_ptr(&self, index: usize, reg: usize) -> SysResult<*mut u32> {
+        if (index + 1) * MSIX_ENTRY_SIZE > self.size {
+            return Err(Errno::EINVAL);
+        }
+        Ok((self.base_vaddr.as_usize() + index * MSIX_ENTRY_SIZE + reg) as *mut u32)
+    }
+
+    /// Programs the message of the entry `index` and unmasks it.
+    pub fn set_entry(&self, index: usize, address: u64, data: u32) -> SysResult<()> {
+        unsafe {
+            self.entry_ptr(index, 0)?.write_volatile(address as u32);
+            self.entry_ptr(index, 4)?
+                .write_volatile((address >> 32) as u32);
+            self.entry_ptr(index, 8)?.write_volatile(data);
+        }
+        self.set_masked(index, false);
+        Ok(())
+    }
+
+    pub fn set_masked(&self, index: usize, masked: bool) {
+        if let Ok(ptr) = self.entry_ptr(index, 12) {
+            unsafe {
+                let ctrl = ptr.read_volatile() & !MSIX_ENTRY_CTRL_MASKED;
+                ptr.write_volatile(if masked {
+                    ctrl | MSIX_ENTRY_CTRL_MASKED
+                } else {
+                    ctrl
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Access to the PCI configuration space, through the PCIe Enhanced
+//! Configuration Access Mechanism (ECAM) or the legacy I/O ports.
+
+use core::fmt;
+
+use x86_64::instructions::port::Port;
+
+use crate::mm::{map_kernel_mmio, PhysAddr, VirtAddr};
+use crate::sync::Mutex;
+
+const CONFIG_ADDRESS_PORT: u16 = 0xcf8;
+const CONFIG_DATA_PORT: u16 = 0xcfc;
+
+/// Size of the configuration space of a function through the legacy ports.
+const LEGACY_CONFIG_SIZE: usize = 0x100;
+/// Size of the extended configuration space of a function through ECAM.
+const ECAM_CONFIG_SIZE: usize = 0x1000;
+
+/// The location of a function on the PCI bus.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct PciAddress {
+    pub bus: u8,
+    /// 0 to 31.
+    pub device: u8,
+    /// 0 to 7.
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            bus,
+            device,
+            function,
+        }
+    }
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+pub enum ConfigSpace {
+    /// The ports 0xcf8 and 0xcfc, only the first 256 bytes of the
+    /// configuration space are accessible.
+    Legacy(Mutex<(Port<u32>, Port<u32>)>),
+    /// The memory-mapped configuration space of buses
+    /// `[start_bus, start_bus + bus_count)`.
+    Ecam {
+        base_vaddr: VirtAddr,
+        start_bus: u8,
+        bus_count: usize,
+    },
+}
+
+impl ConfigSpace {
+    pub const fn new_legacy() -> Self {
+        Self::Legacy(Mutex::new((
+            Port::new(CONFIG_ADDRESS_PORT),
+            Port::new(CONFIG_DATA_PORT),
+        )))
+    }
+
+    /// Maps the ECAM region at `base` that covers `bus_count` buses starting
+    /// from `start_bus`.
+    pub fn new_ecam(base: PhysAddr, start_bus: u8, bus_count: usize) -> Option<Self> {
+        let size = bus_count * 32 * 8 * ECAM_CONFIG_SIZE;
+        match map_kernel_mmio(base, size) {
+            Ok(base_vaddr) => Some(Self::Ecam {
+                base_vaddr,
+                start_bus,
+                bus_count,
+            }),
+            Err(e) => {
+                warn!("failed to map the PCI ECAM region at {:#x?}: {:?}", base, e);
+                None
+            }
+        }
+    }
+
+    pub fn is_ecam(&self) -> bool {
+        matches!(self, Self::Ecam { .. })
+    }
+
+    /// Returns the range of buses that can be accessed.
+    pub fn bus_range(&self) -> core::ops::Range<usize> {
+        match self {
+            Self::Legacy(_) => 0..256,
+            Self::Ecam {
+                start_bus,
+                bus_count,
+                ..
+            } => *start_bus as usize..(*start_bus as usize + bus_count).min(256),
+        }
+    }
+
+    /// Returns the size of the accessible configuration space of a function.
+    pub fn function_config_size(&self) -> usize {
+        match self {
+            Self::Legacy(_) => LEGACY_CONFIG_SIZE,
+            Self::Ecam { .. } => ECAM_CONFIG_SIZE,
+        }
+    }
+
+    fn ecam_ptr(&self, addr: PciAddress, offset: usize) -> *mut u32 {
+        match self {
+            Self::Ecam {
+                base_vaddr,
+                start_bus,
+                ..
+            } => {
+                let bus = (addr.bus - start_bus) as usize;
+                let off = (bus << 20)
+                    | ((addr.device as usize) << 15)
+                    | ((addr.function as usize) << 12)
+                    | offset;
+                (base_vaddr.as_usize() + off) as *mut u32
+            }
+            Self::Legacy(_) => unreachable!(),
+        }
+    }
+
+    const fn legacy_address(addr: PciAddress, offset: usize) -> u32 {
+        (1 << 31)
+            | ((addr.bus as u32) << 16)
+            | ((addr.device as u32) << 11)
+            | ((addr.function as u32) << 8)
+            | offset as u32
+    }
+
+    /// Reads the 32-bit register at `offset`, which must be 4-byte aligned.
+    pub fn read32(&self, addr: PciAddress, offset: usize) -> u32 {
+        assert!(offset % 4 == 0 && offset < self.function_config_size());
+        match self {
+            Self::Legacy(ports) => {
+                let mut ports = ports.lock();
+                unsafe {
+                    ports.0.write(Self::legacy_address(addr, offset));
+                    ports.1.read()
+                }
+            }
+            Self::Ecam { .. } => unsafe { self.ecam_ptr(addr, offset).read_volatile() },
+        }
+    }
+
+    /// Writes the 32-bit register at `offset`, which must be 4-byte aligned.
+    pub fn write32(&self, addr: PciAddress, offset: usize, value: u32) {
+        assert!(offset % 4 == 0 && offset < self.function_config_size());
+        match self {
+            Self::Legacy(ports) => {
+                let mut ports = ports.lock();
+                unsafe {
+                    ports.0.write(Self::legacy_address(addr, offset));
+                    ports.1.write(value);
+                }
+            }
+            Self::Ecam { .. } => unsafe { self.ecam_ptr(addr, offset).write_volatile(value) },
+        }
+    }
+
+    pub fn read16(&self, addr: PciAddress, offset: usize) -> u16 {
+        let shift = (offset & 2) * 8;
+        (self.read32(addr, offset & !3) >> shift) as u16
+    }
+
+    pub fn read8(&self, addr: PciAddress, offset: usize) -> u8 {
+        let shift = (offset & 3) * 8;
+        (self.read32(addr, offset & !3) >> shift) as u8
+    }
+
+    /// Writes the 16-bit register at `offset`. The other half of the 32-bit
+    /// register is written back with its current value, so it must not
+    /// contain write-1-to-clear bits (e.g. the status register).
+    pub fn write16(&self, addr: PciAddress, offset: usize, value: u16) {
+        let shift = (offset & 2) * 8;
+        let old = self.read32(addr, offset & !3) & !(0xffff << shift);
+        self.write32(addr, offset & !3, old | (value as u32) << shift);
+    }
+}
@@ -0,0 +1,320 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::capability::{parse_capabilities, Capability, MsiCapability, MsixCapability};
+use super::config::{ConfigSpace, PciAddress};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{map_kernel_mmio, PhysAddr, VirtAddr};
+use crate::sync::Mutex;
+
+pub const REG_VENDOR_ID: usize = 0x00;
+pub const REG_DEVICE_ID: usize = 0x02;
+pub const REG_COMMAND: usize = 0x04;
+pub const REG_STATUS: usize = 0x06;
+pub const REG_REVISION: usize = 0x08;
+pub const REG_PROG_IF: usize = 0x09;
+pub const REG_SUBCLASS: usize = 0x0a;
+pub const REG_CLASS: usize = 0x0b;
+pub const REG_HEADER_TYPE: usize = 0x0e;
+pub const REG_BAR0: usize = 0x10;
+pub const REG_SECONDARY_BUS: usize = 0x19;
+pub const REG_SUBSYSTEM_VENDOR_ID: usize = 0x2c;
+pub const REG_SUBSYSTEM_ID: usize = 0x2e;
+pub const REG_CAP_PTR: usize = 0x34;
+pub const REG_INTERRUPT_LINE: usize = 0x3c;
+pub const REG_INTERRUPT_PIN: usize = 0x3d;
+
+pub const HEADER_TYPE_NORMAL: u8 = 0x00;
+pub const HEADER_TYPE_BRIDGE: u8 = 0x01;
+const HEADER_TYPE_MULTI_FUNCTION: u8 = 0x80;
+
+pub const MAX_BARS: usize = 6;
+
+bitflags::bitflags! {
+    pub struct Command: u16 {
+        const IO_SPACE      = 1 << 0;
+        const MEMORY_SPACE  = 1 << 1;
+        const BUS_MASTER    = 1 << 2;
+        const INTX_DISABLE  = 1 << 10;
+    }
+}
+
+/// A Base Address Register of a function.
+#[derive(Debug, Clone, Copy)]
+pub enum Bar {
+    Memory {
+        paddr: PhysAddr,
+        size: usize,
+        is_64bit: bool,
+        prefetchable: bool,
+    },
+    Io {
+        port: u16,
+        size: usize,
+    },
+}
+
+pub struct PciDevice {
+    pub addr: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub subsystem_vendor_id: u16,
+    pub subsystem_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    /// The legacy interrupt pin, 1 to 4 for INTA# to INTD#, or 0 if unused.
+    pub interrupt_pin: u8,
+    pub interrupt_line: u8,
+    pub bars: [Option<Bar>; MAX_BARS],
+    pub capabilities: Vec<Capability>,
+    /// The name of the driver bound to the device.
+    pub(super) driver: Mutex<Option<&'static str>>,
+}
+
+impl Bar {
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Memory { size, .. } | Self::Io { size, .. } => *size,
+        }
+    }
+}
+
+impl PciDevice {
+    /// Returns whether there is a function at `addr`.
+    pub(super) fn exists(config: &ConfigSpace, addr: PciAddress) -> bool {
+        config.read16(addr, REG_VENDOR_ID) != 0xffff
+    }
+
+    /// Returns whether the function 0 at `addr` is a multi-function device.
+    pub(super) fn is_multi_function(config: &ConfigSpace, addr: PciAddress) -> bool {
+        config.read8(addr, REG_HEADER_TYPE) & HEADER_TYPE_MULTI_FUNCTION != 0
+    }
+
+    /// Reads the header of the function at `addr`, returns `None` if there is
+    /// no such function.
+    pub(super) fn probe(config: &ConfigSpace, addr: PciAddress) -> Option<Self> {
+        if !Self::exists(config, addr) {
+            return None;
+        }
+        let header_type = config.read8(addr, REG_HEADER_TYPE) & !HEADER_TYPE_MULTI_FUNCTION;
+        let (subsystem_vendor_id, subsystem_id) = if header_type == HEADER_TYPE_NORMAL {
+            (
+                config.read16(addr, REG_SUBSYSTEM_VENDOR_ID),
+                config.read16(addr, REG_SUBSYSTEM_ID),
+            )
+        } else {
+            (0, 0)
+        };
+        let mut dev = Self {
+            addr,
+            vendor_id: config.read16(addr, REG_VENDOR_ID),
+            device_id: config.read16(addr, REG_DEVICE_ID),
+            subsystem_vendor_id,
+            subsystem_id,
+            class: config.read8(addr, REG_CLASS),
+            subclass: config.read8(addr, REG_SUBCLASS),
+            prog_if: config.read8(addr, REG_PROG_IF),
+            revision: config.read8(addr, REG_REVISION),
+            header_type,
+            interrupt_pin: config.read8(addr, REG_INTERRUPT_PIN),
+            interrupt_line: config.read8(addr, REG_INTERRUPT_LINE),
+            bars: [None; MAX_BARS],
+            capabilities: parse_capabilities(config, addr),
+            driver: Mutex::new(None),
+        };
+        dev.read_bars(config);
+        Some(dev)
+    }
+
+    /// Sizes the BARs by writing all ones to them, with the decoding disabled
+    /// meanwhile.
+    fn read_bars(&mut self, config: &ConfigSpace) {
+        let bar_count = match self.header_type {
+            HEADER_TYPE_NORMAL => MAX_BARS,
+            HEADER_TYPE_BRIDGE => 2,
+            _ => 0,
+        };
+        let addr = self.addr;
+        let command = config.read16(addr, REG_COMMAND);
+        let decode = (Command::IO_SPACE | Command::MEMORY_SPACE).bits();
+        config.write32(addr, REG_COMMAND, (command & !decode) as u32);
+
+        let size_reg = |reg: usize| {
+            let orig = config.read32(addr, reg);
+            config.write32(addr, reg, !0);
+            let mask = config.read32(addr, reg);
+            config.write32(addr, reg, orig);
+            (orig, mask)
+        };
+        let mut i = 0;
+        while i < bar_count {
+            let reg = REG_BAR0 + i * 4;
+            let (orig, mask) = size_reg(reg);
+            if mask == 0 {
+                i += 1;
+                continue;
+            }
+            if orig & 1 != 0 {
+                let mask = mask & 0xffff_fffc;
+                self.bars[i] = Some(Bar::Io {
+                    port: (orig & 0xfffc) as u16,
+                    size: ((!mask & 0xffff) + 1) as usize,
+                });
+                i += 1;
+                continue;
+            }
+            let is_64bit = (orig >> 1) & 0x3 == 0x2;
+            let (paddr, mask) = if is_64bit && i + 1 < bar_count {
+                let (orig_high, mask_high) = size_reg(reg + 4);
+                (
+                    (orig_high as u64) << 32 | (orig & !0xf) as u64,
+                    (mask_high as u64) << 32 | (mask & !0xf) as u64,
+                )
+            } else {
+                (
+                    (orig & !0xf) as u64,
+                    (mask & !0xf) as u64 | 0xffff_ffff_0000_0000,
+                )
+            };
+            self.bars[i] = Some(Bar::Memory {
+                paddr: PhysAddr::new(paddr as usize),
+                size: (!mask).wrapping_add(1) as usize,
+                is_64bit,
+                prefetchable: orig & 0x8 != 0,
+            });
+            i += if is_64bit { 2 } else { 1 };
+        }
+        config.write32(addr, REG_COMMAND, command as u32);
+    }
+
+    pub fn read_config8(&self, offset: usize) -> u8 {
+        super::config_space().read8(self.addr, offset)
+    }
+
+    pub fn read_config16(&self, offset: usize) -> u16 {
+        super::config_space().read16(self.addr, offset)
+    }
+
+    pub fn read_config32(&self, offset: usize) -> u32 {
+        super::config_space().read32(self.addr, offset)
+    }
+
+    /// Writes a 16-bit register with read-modify-write, use
+    /// [`set_command`](Self::set_command) for the command register.
+    pub fn write_config16(&self, offset: usize, value: u16) {
+        super::config_space().write16(self.addr, offset, value)
+    }
+
+    pub fn write_config32(&self, offset: usize, value: u32) {
+        super::config_space().write32(self.addr, offset, value)
+    }
+
+    pub fn command(&self) -> Command {
+        Command::from_bits_truncate(self.read_config16(REG_COMMAND))
+    }
+
+    /// Writes the command register, leaving the status register unchanged.
+    pub fn set_command(&self, command: Command) {
+        let reserved = self.read_config16(REG_COMMAND) & !Command::all().bits();
+        // writing zeros to the status register has no effect
+        self.write_config32(REG_COMMAND, (reserved | command.bits()) as u32);
+    }
+
+    /// Enables the decoding of the BARs and the bus mastering (DMA).
+    pub fn enable(&self) {
+        self.set_command(
+            self.command() | Command::IO_SPACE | Command::MEMORY_SPACE | Command::BUS_MASTER,
+        );
+    }
+
+    pub fn set_intx_enabled(&self, enabled: bool) {
+        let mut command = self.command();
+        command.set(Command::INTX_DISABLE, !enabled);
+        self.set_command(command);
+    }
+
+    /// Maps the whole memory BAR `index` into the kernel address space.
+    pub fn map_bar(&self, index: usize) -> SysResult<VirtAddr> {
+        let size = self
+            .bars
+            .get(index)
+            .copied()
+            .flatten()
+            .map_or(0, |bar| bar.size());
+        self.map_bar_range(index, 0, size)
+    }
+
+    /// Maps `[offset, offset + size)` of the memory BAR `index` into the
+    /// kernel address space.
+    pub fn map_bar_range(&self, index: usize, offset: usize, size: usize) -> SysResult<VirtAddr> {
+        match self.bars.get(index).copied().flatten() {
+            Some(Bar::Memory {
+                paddr,
+                size: bar_size,
+                ..
+            }) if paddr.as_usize() != 0 && offset.saturating_add(size) <= bar_size => {
+                map_kernel_mmio(PhysAddr::new(paddr.as_usize() + offset), size)
+            }
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    /// Returns the capabilities with the ID `id`.
+    pub fn find_capabilities(&self, id: u8) -> impl Iterator<Item = &Capability> {
+        self.capabilities.iter().filter(move |cap| cap.id() == id)
+    }
+
+    pub fn msi(&self) -> Option<MsiCapability> {
+        self.capabilities.iter().find_map(|cap| match cap {
+            Capability::Msi(msi) => Some(*msi),
+            _ => None,
+        })
+    }
+
+    pub fn msix(&self) -> Option<MsixCapability> {
+        self.capabilities.iter().find_map(|cap| match cap {
+            Capability::MsiX(msix) => Some(*msix),
+            _ => None,
+        })
+    }
+
+    pub fn driver(&self) -> Option<&'static str> {
+        *self.driver.lock()
+    }
+}
+
+impl fmt::Display for Bar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Memory {
+                paddr,
+                size,
+                is_64bit,
+                prefetchable,
+            } => {
+                write!(f, "mem {:#x} size {:#x}", paddr.as_usize(), size)?;
+                if *is_64bit {
+                    write!(f, " 64bit")?;
+                }
+                if *prefetchable {
+                    write!(f, " prefetchable")?;
+                }
+                Ok(())
+            }
+            Self::Io { port, size } => write!(f, "io {:#x} size {:#x}", port, size),
+        }
+    }
+}
+
+impl fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} [{:02x}{:02x}] {:04x}:{:04x} (rev {:02x})",
+            self.addr, self.class, self.subclass, self.vendor_id, self.device_id, self.revision
+        )
+    }
+}
@@ -0,0 +1,210 @@
+//! PCI(e) bus enumeration and the registry of PCI drivers.
+//!
+//! The buses are scanned once at boot, through the ECAM of the Q35 host bridge
+//! if it is enabled, otherwise through the legacy configuration ports. Drivers
+//! can be registered before or after the scan, they are probed with every
+//! unbound device that matches their ID table.
+
+#![allow(dead_code)]
+
+mod capability;
+mod config;
+mod device;
+
+use alloc::vec::Vec;
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::PhysAddr;
+use crate::sync::{LazyInit, Mutex};
+
+pub use self::capability::*;
+pub use self::config::{ConfigSpace, PciAddress};
+pub use self::device::*;
+
+/// Vendor and device IDs of the Q35 host bridge (MCH).
+const Q35_MCH_ID: (u16, u16) = (0x8086, 0x29c0);
+/// The PCIEXBAR register of the Q35 MCH, which holds the ECAM base.
+const Q35_REG_PCIEXBAR: usize = 0x60;
+
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+static CONFIG_SPACE: LazyInit<ConfigSpace> = LazyInit::new();
+static DEVICES: LazyInit<Vec<PciDevice>> = LazyInit::new();
+static DRIVERS: Mutex<Vec<&'static PciDriver>> = Mutex::new(Vec::new());
+
+/// An entry of the ID table of a driver.
+#[derive(Debug, Clone, Copy)]
+pub enum PciDeviceId {
+    /// Matches the vendor ID and the device ID.
+    Device(u16, u16),
+    /// Matches all devices of the vendor.
+    Vendor(u16),
+    /// Matches the class code and the subclass code.
+    Class(u8, u8),
+}
+
+pub struct PciDriver {
+    pub name: &'static str,
+    pub id_table: &'static [PciDeviceId],
+    /// Initializes a matched device. Returns `ENODEV` if the device is not
+    /// supported after all, so that it can be bound to other drivers.
+    pub probe: fn(&'static PciDevice) -> SysResult<()>,
+}
+
+impl PciDeviceId {
+    pub fn matches(&self, dev: &PciDevice) -> bool {
+        match *self {
+            Self::Device(vendor, device) => dev.vendor_id == vendor && dev.device_id == device,
+            Self::Vendor(vendor) => dev.vendor_id == vendor,
+            Self::Class(class, subclass) => dev.class == class && dev.subclass == subclass,
+        }
+    }
+}
+
+fn config_space() -> &'static ConfigSpace {
+    &CONFIG_SPACE
+}
+
+/// Returns all the functions found on the PCI buses.
+pub fn devices() -> &'static [PciDevice] {
+    if DEVICES.is_init() {
+        &DEVICES
+    } else {
+        &[]
+    }
+}
+
+/// Registers a driver, and probes it with the matched devices if the buses
+/// have been scanned.
+pub fn register_driver(driver: &'static PciDriver) {
+    DRIVERS.lock().push(driver);
+    if DEVICES.is_init() {
+        probe_driver(driver);
+    }
+}
+
+fn probe_driver(driver: &'static PciDriver) {
+    for dev in DEVICES.iter() {
+        if dev.driver().is_some() || !driver.id_table.iter().any(|id| id.matches(dev)) {
+            continue;
+        }
+        match (driver.probe)(dev) {
+            Ok(()) => {
+                println!("PCI {}: bound to driver {}", dev.addr, driver.name);
+                *dev.driver.lock() = Some(driver.name);
+            }
+            Err(Errno::ENODEV) => {}
+            Err(e) => warn!("PCI {}: driver {} failed: {:?}", dev.addr, driver.name, e),
+        }
+    }
+}
+
+/// Finds the ECAM region through the PCIEXBAR register of the Q35 host bridge.
+fn probe_q35_ecam(legacy: &ConfigSpace) -> Option<ConfigSpace> {
+    let host = PciAddress::new(0, 0, 0);
+    let id = (
+        legacy.read16(host, REG_VENDOR_ID),
+        legacy.read16(host, REG_DEVICE_ID),
+    );
+    if id != Q35_MCH_ID {
+        return None;
+    }
+    let pciexbar = (legacy.read32(host, Q35_REG_PCIEXBAR + 4) as u64) << 32
+        | legacy.read32(host, Q35_REG_PCIEXBAR) as u64;
+    if pciexbar & 1 == 0 {
+        return None;
+    }
+    // bits 2:1 select 256, 128 or 64 buses, the base is aligned to the size
+    let bus_count = match (pciexbar >> 1) & 0x3 {
+        0 => 256,
+        1 => 128,
+        2 => 64,
+        _ => return None,
+    };
+    let size = bus_count << 20;
+    let base = pciexbar & 0xf_ffff_ffff & !(size as u64 - 1);
+    ConfigSpace::new_ecam(PhysAddr::new(base as usize), 0, bus_count)
+}
+
+fn scan_bus(config: &ConfigSpace, bus: u8, devices: &mut Vec<PciDevice>) {
+    for device in 0..32 {
+        let addr = PciAddress::new(bus, device, 0);
+        let function_count = if !PciDevice::exists(config, addr) {
+            continue;
+        } else if PciDevice::is_multi_function(config, addr) {
+            8
+        } else {
+            1
+        };
+        for function in 0..function_count {
+            let addr = PciAddress::new(bus, device, function);
+            let dev = match PciDevice::probe(config, addr) {
+                Some(dev) => dev,
+                None => continue,
+            };
+            let secondary_bus = if dev.header_type == HEADER_TYPE_BRIDGE
+                && dev.class == CLASS_BRIDGE
+                && dev.subclass == SUBCLASS_PCI_BRIDGE
+            {
+                Some(config.read8(addr, REG_SECONDARY_BUS))
+            } else {
+                None
+            };
+            devices.push(dev);
+            // buses behind a bridge are numbered after it, which also avoids
+            // loops on misconfigured bridges
+            match secondary_bus {
+                Some(sec) if sec > bus && config.bus_range().contains(&(sec as usize)) => {
+                    scan_bus(config, sec, devices)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+pub fn init() {
+    let legacy = ConfigSpace::new_legacy();
+    let config = probe_q35_ecam(&legacy).unwrap_or(legacy);
+    println!(
+        "Scanning PCI buses through {}...",
+        if config.is_ecam() {
+            "ECAM"
+        } else {
+            "I/O ports"
+        }
+    );
+
+    let mut devices = Vec::new();
+    let host = PciAddress::new(0, 0, 0);
+    if PciDevice::is_multi_function(&config, host) {
+        // multiple host bridges, the function number is the bus number
+        for function in 0..8 {
+            if PciDevice::exists(&config, PciAddress::new(0, 0, function)) {
+                scan_bus(&config, function, &mut devices);
+            }
+        }
+    } else {
+        scan_bus(&config, 0, &mut devices);
+    }
+
+    for dev in &devices {
+        println!("PCI {}", dev);
+        for (i, bar) in dev.bars.iter().enumerate() {
+            if let Some(bar) = bar {
+                info!("    BAR{}: {}", i, bar);
+            }
+        }
+        for cap in &dev.capabilities {
+            debug!("    capability {:#04x} at {:#x}", cap.id(), cap.offset());
+        }
+    }
+    CONFIG_SPACE.init_by(config);
+    DEVICES.init_by(devices);
+
+    let drivers = DRIVERS.lock().clone();
+    for driver in drivers {
+        probe_driver(driver);
+    }
+}
@@ -0,0 +1,117 @@
+//! CMOS Real-Time Clock of the PC.
+
+use x86_64::instructions::port::{Port, PortWriteOnly};
+
+use crate::sync::Mutex;
+use crate::timer::TimeValue;
+use crate::utils::datetime::DateTime;
+
+const CMOS_ADDR_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+/// The century register, provided by QEMU and listed in the ACPI FADT of most
+/// PCs.
+const REG_CENTURY: u8 = 0x32;
+
+/// Update in progress.
+const STATUS_A_UIP: u8 = 1 << 7;
+/// Values are in binary instead of BCD.
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Hours are in the 24-hour format.
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// The PM bit of hours in the 12-hour format.
+const HOURS_PM: u8 = 1 << 7;
+
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos::new());
+
+struct Cmos {
+    addr: PortWriteOnly<u8>,
+    data: Port<u8>,
+}
+
+impl Cmos {
+    const fn new() -> Self {
+        Self {
+            addr: PortWriteOnly::new(CMOS_ADDR_PORT),
+            data: Port::new(CMOS_DATA_PORT),
+        }
+    }
+
+    fn read(&mut self, reg: u8) -> u8 {
+        unsafe {
+            self.addr.write(reg);
+            self.data.read()
+        }
+    }
+
+    /// Reads the raw date registers after the update in progress (if any)
+    /// completes.
+    fn read_raw(&mut self) -> [u8; 7] {
+        while self.read(REG_STATUS_A) & STATUS_A_UIP != 0 {
+            core::hint::spin_loop();
+        }
+        [
+            REG_SECONDS,
+            REG_MINUTES,
+            REG_HOURS,
+            REG_DAY,
+            REG_MONTH,
+            REG_YEAR,
+            REG_CENTURY,
+        ]
+        .map(|reg| self.read(reg))
+    }
+
+    fn read_datetime(&mut self) -> DateTime {
+        // Read until two consecutive values are the same, in case an update
+        // starts while reading.
+        let mut raw = self.read_raw();
+        loop {
+            let again = self.read_raw();
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+
+        let status_b = self.read(REG_STATUS_B);
+        let bcd = status_b & STATUS_B_BINARY == 0;
+        let decode = |v: u8| if bcd { (v >> 4) * 10 + (v & 0xf) } else { v };
+        let [second, minute, hours, day, month, year, century] = raw;
+
+        let mut hour = decode(hours & !HOURS_PM);
+        if status_b & STATUS_B_24_HOUR == 0 {
+            // 12 AM is 0:00, 12 PM is 12:00.
+            hour %= 12;
+            if hours & HOURS_PM != 0 {
+                hour += 12;
+            }
+        }
+        let century = match decode(century) {
+            c @ 19..=99 => c as u32,
+            _ => 20, // the century register is not available
+        };
+        DateTime {
+            year: century * 100 + decode(year) as u32,
+            month: decode(month),
+            day: decode(day),
+            hour,
+            minute: decode(minute),
+            second: decode(second),
+        }
+    }
+}
+
+/// Returns the time since the Epoch, the CMOS RTC only counts seconds.
+pub fn read_time() -> TimeValue {
+    let datetime = CMOS.lock().read_datetime();
+    TimeValue::from_secs(datetime.to_unix_secs())
+}
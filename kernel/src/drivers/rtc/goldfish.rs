@@ -0,0 +1,57 @@
+//! Goldfish RTC, emulated by QEMU on the RISC-V virt machine.
+
+use tock_registers::interfaces::Readable;
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
+
+use crate::mm::{PhysAddr, VirtAddr};
+use crate::timer::TimeValue;
+
+const RTC_BASE: PhysAddr = PhysAddr::new(0x0010_1000);
+
+static RTC: GoldfishRtc = GoldfishRtc::new(RTC_BASE.into_kvaddr());
+
+register_structs! {
+    GoldfishRtcRegs {
+        /// Low 32 bits of the time in nanoseconds, reading it latches the
+        /// high 32 bits.
+        (0x00 => time_low: ReadOnly<u32>),
+        /// High 32 bits of the time in nanoseconds.
+        (0x04 => time_high: ReadOnly<u32>),
+        /// Low 32 bits of the alarm time.
+        (0x08 => alarm_low: ReadWrite<u32>),
+        /// High 32 bits of the alarm time.
+        (0x0c => alarm_high: ReadWrite<u32>),
+        /// Interrupt Enable Register.
+        (0x10 => irq_enabled: ReadWrite<u32>),
+        /// Clears the alarm.
+        (0x14 => clear_alarm: WriteOnly<u32>),
+        /// Alarm Status Register.
+        (0x18 => alarm_status: ReadOnly<u32>),
+        /// Clears the interrupt.
+        (0x1c => clear_interrupt: WriteOnly<u32>),
+        (0x20 => @END),
+    }
+}
+
+struct GoldfishRtc {
+    base_vaddr: VirtAddr,
+}
+
+impl GoldfishRtc {
+    const fn new(base_vaddr: VirtAddr) -> Self {
+        Self { base_vaddr }
+    }
+
+    const fn regs(&self) -> &GoldfishRtcRegs {
+        unsafe { &*(self.base_vaddr.as_ptr() as *const _) }
+    }
+}
+
+/// Returns the time since the Epoch.
+pub fn read_time() -> TimeValue {
+    // The low half must be read first.
+    let low = RTC.regs().time_low.get() as u64;
+    let high = RTC.regs().time_high.get() as u64;
+    TimeValue::from_nanos(high << 32 | low)
+}
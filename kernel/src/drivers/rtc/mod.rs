@@ -0,0 +1,28 @@
+cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        mod cmos;
+        use cmos as imp;
+    } else if #[cfg(target_arch = "aarch64")] {
+        mod pl031;
+        use pl031 as imp;
+    } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        mod goldfish;
+        use goldfish as imp;
+    }
+}
+
+use crate::timer::{current_realtime, set_realtime};
+use crate::utils::datetime::DateTime;
+
+pub use self::imp::read_time;
+
+/// Seeds `CLOCK_REALTIME` from the RTC.
+pub(super) fn init() {
+    let now = read_time();
+    if set_realtime(now) {
+        let now = current_realtime();
+        println!("Real time: {} UTC", DateTime::from_unix_secs(now.as_secs()));
+    } else {
+        warn!("invalid RTC time: {:?}", now);
+    }
+}
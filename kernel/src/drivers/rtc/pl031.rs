@@ -0,0 +1,53 @@
+//! ARM PrimeCell Real Time Clock (PL031).
+
+use tock_registers::interfaces::Readable;
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
+
+use crate::mm::{PhysAddr, VirtAddr};
+use crate::timer::TimeValue;
+
+const RTC_BASE: PhysAddr = PhysAddr::new(0x0901_0000);
+
+static RTC: Pl031Rtc = Pl031Rtc::new(RTC_BASE.into_kvaddr());
+
+register_structs! {
+    Pl031RtcRegs {
+        /// Data Register.
+        (0x00 => dr: ReadOnly<u32>),
+        /// Match Register.
+        (0x04 => mr: ReadWrite<u32>),
+        /// Load Register.
+        (0x08 => lr: ReadWrite<u32>),
+        /// Control Register.
+        (0x0c => cr: ReadWrite<u32>),
+        /// Interrupt Mask Set or Clear Register.
+        (0x10 => imsc: ReadWrite<u32>),
+        /// Raw Interrupt Status Register.
+        (0x14 => ris: ReadOnly<u32>),
+        /// Masked Interrupt Status Register.
+        (0x18 => mis: ReadOnly<u32>),
+        /// Interrupt Clear Register.
+        (0x1c => icr: WriteOnly<u32>),
+        (0x20 => @END),
+    }
+}
+
+struct Pl031Rtc {
+    base_vaddr: VirtAddr,
+}
+
+impl Pl031Rtc {
+    const fn new(base_vaddr: VirtAddr) -> Self {
+        Self { base_vaddr }
+    }
+
+    const fn regs(&self) -> &Pl031RtcRegs {
+        unsafe { &*(self.base_vaddr.as_ptr() as *const _) }
+    }
+}
+
+/// Returns the time since the Epoch, the PL031 only counts seconds.
+pub fn read_time() -> TimeValue {
+    TimeValue::from_secs(RTC.regs().dr.get() as u64)
+}
@@ -3,7 +3,7 @@
 use cortex_a::registers::{CNTFRQ_EL0, CNTPCT_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0};
 use tock_registers::interfaces::{Readable, Writeable};
 
-use crate::drivers::interrupt;
+use crate::drivers::interrupt::{self, IrqFlags, IrqReturn};
 use crate::sync::LazyInit;
 use crate::timer::NANOS_PER_SEC;
 use crate::utils::ratio::Ratio;
@@ -43,6 +43,16 @@ pub fn init() {
     NANOS_TO_CNTPCT_RATIO.init_by(CNTPCT_TO_NANOS_RATIO.inverse());
 
     CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET);
-    interrupt::register_handler(PHYS_TIMER_IRQ_NUM, crate::timer::handle_timer_irq);
-    interrupt::set_enable(PHYS_TIMER_IRQ_NUM, true);
+    let handle_timer_irq = |_: &()| {
+        crate::timer::handle_timer_irq();
+        IrqReturn::Handled
+    };
+    interrupt::request_irq(
+        PHYS_TIMER_IRQ_NUM,
+        handle_timer_irq,
+        IrqFlags::empty(),
+        "timer",
+        (),
+    )
+    .expect("failed to request the timer IRQ");
 }
@@ -1,6 +1,6 @@
 use riscv::register::time;
 
-use super::super::interrupt::{self, ScauseIntCode};
+use super::super::interrupt::{self, IrqFlags, IrqReturn, ScauseIntCode};
 use super::super::misc::sbi;
 
 const NANOS_PER_TICK: u64 = crate::timer::NANOS_PER_SEC / crate::config::TIMER_FREQUENCY as u64;
@@ -22,6 +22,16 @@ pub fn set_oneshot_timer(deadline_ns: u64) {
 }
 
 pub fn init() {
-    interrupt::register_handler(ScauseIntCode::Timer as _, crate::timer::handle_timer_irq);
-    interrupt::set_enable(ScauseIntCode::Timer as _, true);
+    let handle_timer_irq = |_: &()| {
+        crate::timer::handle_timer_irq();
+        IrqReturn::Handled
+    };
+    interrupt::request_irq(
+        ScauseIntCode::Timer as _,
+        handle_timer_irq,
+        IrqFlags::empty(),
+        "timer",
+        (),
+    )
+    .expect("failed to request the timer IRQ");
 }
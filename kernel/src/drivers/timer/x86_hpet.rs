@@ -4,12 +4,14 @@ use tock_registers::interfaces::{Readable, Writeable};
 use tock_registers::register_structs;
 use tock_registers::registers::{ReadOnly, ReadWrite};
 
-use crate::mm::{PhysAddr, VirtAddr};
+use crate::drivers::acpi::acpi_info;
+use crate::mm::{map_kernel_mmio, VirtAddr};
 use crate::sync::LazyInit;
 use crate::timer::NANOS_PER_SEC;
 use crate::utils::ratio::Ratio;
 
-const HPET_BASE: PhysAddr = PhysAddr::new(0xFED0_0000);
+/// The size of the MMIO region of the HPET.
+const HPET_SIZE: usize = 0x400;
 
 static HPET: LazyInit<Hpet> = LazyInit::new();
 
@@ -192,7 +194,10 @@ pub(super) fn wait_millis(millis: u64) {
 }
 
 pub(super) fn init() {
-    let mut hpet = Hpet::new(HPET_BASE.into_kvaddr());
+    let paddr = acpi_info().hpet_paddr;
+    let vaddr = map_kernel_mmio(paddr, HPET_SIZE)
+        .unwrap_or_else(|e| panic!("failed to map HPET at {:#x?}: {:?}", paddr, e));
+    let mut hpet = Hpet::new(vaddr);
     hpet.init();
     HPET.init_by(hpet);
 }
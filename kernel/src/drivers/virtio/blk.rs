@@ -0,0 +1,285 @@
+//! Virtio block devices.
+
+use alloc::collections::BTreeSet;
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use core::mem::size_of;
+
+use super::{Transport, VirtQueue};
+use crate::drivers::block::{register_block_device, BlockDevice};
+use crate::drivers::interrupt::{request_threaded_irq, IrqFlags, IrqReturn};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysAddr, PhysFrame, PAGE_SIZE};
+use crate::sync::Mutex;
+use crate::task::{self, WaitQueue};
+
+/// The device is read-only.
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+/// The device supports the flush command.
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// The offset of `capacity` in the device configuration.
+const CONFIG_CAPACITY: usize = 0;
+
+/// Sizes are always in 512-byte sectors in virtio-blk requests.
+const SECTOR_SIZE: usize = 512;
+const QUEUE_SIZE: u16 = 16;
+/// Larger transfers are split into multiple requests.
+const MAX_REQUEST_SIZE: usize = 16 * PAGE_SIZE;
+/// The header, the data and the status.
+const MAX_DESCS_PER_REQUEST: usize = 3;
+
+static DEVICES: Mutex<Vec<Arc<VirtIOBlk>>> = Mutex::new(Vec::new());
+
+#[repr(C)]
+struct BlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+struct BlkQueue {
+    vq: VirtQueue,
+    /// Heads of the requests that are completed but not checked yet.
+    completed: BTreeSet<u16>,
+}
+
+pub struct VirtIOBlk {
+    name: String,
+    transport: Box<dyn Transport>,
+    queue: Mutex<BlkQueue>,
+    waiters: WaitQueue,
+    /// The capacity in sectors.
+    capacity: u64,
+    features: u64,
+    /// Whether the completion is reported by interrupts.
+    irq_enabled: bool,
+}
+
+/// The part of the request placed in DMA memory: the header is followed by
+/// the status, then the data in the next frames.
+struct BlkRequest {
+    dma: PhysFrame,
+}
+
+impl BlkQueue {
+    fn process_used(&mut self) {
+        while let Some((head, _)) = self.vq.pop_used() {
+            self.completed.insert(head);
+        }
+    }
+}
+
+impl BlkRequest {
+    fn new(req_type: u32, sector: u64, data_len: usize) -> SysResult<Self> {
+        let pages = 1 + (data_len + PAGE_SIZE - 1) / PAGE_SIZE;
+        let dma = PhysFrame::alloc_contiguous(pages, 1).ok_or(Errno::ENOMEM)?;
+        let req = Self { dma };
+        let header = BlkReqHeader {
+            req_type,
+            reserved: 0,
+            sector,
+        };
+        unsafe {
+            (req.paddr(0).into_kvaddr().as_mut_ptr() as *mut BlkReqHeader).write(header);
+            *req.status_paddr().into_kvaddr().as_mut_ptr() = 0xff;
+        }
+        Ok(req)
+    }
+
+    fn paddr(&self, offset: usize) -> PhysAddr {
+        PhysAddr::new(self.dma.start_paddr().as_usize() + offset)
+    }
+
+    fn status_paddr(&self) -> PhysAddr {
+        self.paddr(size_of::<BlkReqHeader>())
+    }
+
+    fn data_paddr(&self) -> PhysAddr {
+        self.paddr(PAGE_SIZE)
+    }
+
+    fn status(&self) -> u8 {
+        unsafe { *self.status_paddr().into_kvaddr().as_ptr() }
+    }
+
+    fn data(&mut self, len: usize) -> &mut [u8] {
+        &mut self.dma.as_slice_mut()[PAGE_SIZE..PAGE_SIZE + len]
+    }
+}
+
+impl VirtIOBlk {
+    /// Waits until `condition` is satisfied, after processing the used
+    /// requests.
+    fn wait_until(&self, mut condition: impl FnMut(&mut BlkQueue) -> bool) {
+        if self.irq_enabled && task::is_init() {
+            // the used requests are processed by the IRQ thread
+            self.waiters
+                .wait_until(|| condition(&mut self.queue.lock()));
+        } else {
+            loop {
+                let mut queue = self.queue.lock();
+                queue.process_used();
+                if condition(&mut queue) {
+                    break;
+                }
+                drop(queue);
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Submits a request and blocks until it is completed. The data is read by
+    /// the device if `write` is true, otherwise written by the device.
+    fn request(&self, req: &BlkRequest, data_len: usize, write: bool) -> SysResult<()> {
+        let buffers = [
+            (req.paddr(0), size_of::<BlkReqHeader>()),
+            (req.data_paddr(), data_len),
+            (req.status_paddr(), 1),
+        ];
+        let (inputs, outputs) = match (data_len, write) {
+            (0, _) => (&buffers[..1], &buffers[2..]),
+            (_, true) => (&buffers[..2], &buffers[2..]),
+            (_, false) => (&buffers[..1], &buffers[1..]),
+        };
+
+        let mut head = None;
+        while head.is_none() {
+            self.wait_until(|queue| queue.vq.num_free() >= MAX_DESCS_PER_REQUEST);
+            let mut queue = self.queue.lock();
+            if let Ok(h) = queue.vq.add(inputs, outputs) {
+                self.transport.notify(queue.vq.index());
+                head = Some(h);
+            }
+        }
+        let head = head.unwrap();
+        self.wait_until(|queue| queue.completed.remove(&head));
+
+        match req.status() {
+            VIRTIO_BLK_S_OK => Ok(()),
+            status => {
+                warn!("{}: request failed with status {}", self.name, status);
+                Err(Errno::EIO)
+            }
+        }
+    }
+
+    /// Checks the range of the blocks, returns the first sector.
+    fn check_range(&self, block_id: u64, len: usize) -> SysResult<u64> {
+        let count = (len / SECTOR_SIZE) as u64;
+        if len % SECTOR_SIZE != 0
+            || block_id
+                .checked_add(count)
+                .map_or(true, |end| end > self.capacity)
+        {
+            return Err(Errno::EINVAL);
+        }
+        Ok(block_id)
+    }
+
+    fn handle_irq(&self) -> IrqReturn {
+        if self.transport.ack_interrupt() {
+            IrqReturn::WakeThread
+        } else {
+            IrqReturn::None
+        }
+    }
+
+    fn handle_irq_thread(&self) {
+        self.queue.lock().process_used();
+        self.waiters.notify_all();
+    }
+}
+
+impl BlockDevice for VirtIOBlk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> SysResult<()> {
+        let mut sector = self.check_range(block_id, buf.len())?;
+        for chunk in buf.chunks_mut(MAX_REQUEST_SIZE) {
+            let mut req = BlkRequest::new(VIRTIO_BLK_T_IN, sector, chunk.len())?;
+            self.request(&req, chunk.len(), false)?;
+            chunk.copy_from_slice(req.data(chunk.len()));
+            sector += (chunk.len() / SECTOR_SIZE) as u64;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, block_id: u64, buf: &[u8]) -> SysResult<()> {
+        if self.features & VIRTIO_BLK_F_RO != 0 {
+            return Err(Errno::EROFS);
+        }
+        let mut sector = self.check_range(block_id, buf.len())?;
+        for chunk in buf.chunks(MAX_REQUEST_SIZE) {
+            let mut req = BlkRequest::new(VIRTIO_BLK_T_OUT, sector, chunk.len())?;
+            req.data(chunk.len()).copy_from_slice(chunk);
+            self.request(&req, chunk.len(), true)?;
+            sector += (chunk.len() / SECTOR_SIZE) as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> SysResult<()> {
+        if self.features & VIRTIO_BLK_F_FLUSH == 0 {
+            // writes are always persisted without the flush command
+            return Ok(());
+        }
+        let req = BlkRequest::new(VIRTIO_BLK_T_FLUSH, 0, 0)?;
+        self.request(&req, 0, false)
+    }
+}
+
+pub(super) fn probe(transport: Box<dyn Transport>) -> SysResult<()> {
+    let supported = VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH;
+    let (features, irq, vq) = super::init_device(&*transport, supported, |features| {
+        let irq = transport.setup_irq();
+        let vq = VirtQueue::new(&*transport, 0, QUEUE_SIZE)?;
+        Ok((features, irq, vq))
+    })?;
+    if irq.is_none() {
+        warn!("virtio-blk: interrupts are not available, polling the device");
+    }
+
+    let mut devices = DEVICES.lock();
+    let dev = Arc::new(VirtIOBlk {
+        name: format!("virtio-blk{}", devices.len()),
+        capacity: transport.read_config_u64(CONFIG_CAPACITY),
+        transport,
+        queue: Mutex::new(BlkQueue {
+            vq,
+            completed: BTreeSet::new(),
+        }),
+        waiters: WaitQueue::new(),
+        features,
+        irq_enabled: irq.is_some(),
+    });
+    devices.push(dev.clone());
+    drop(devices);
+    if let Some(irq) = irq {
+        request_threaded_irq(
+            irq,
+            |dev: &Arc<VirtIOBlk>| dev.handle_irq(),
+            |dev| dev.handle_irq_thread(),
+            IrqFlags::SHARED,
+            "virtio-blk",
+            dev.clone(),
+        )?;
+    }
+    register_block_device(dev);
+    Ok(())
+}
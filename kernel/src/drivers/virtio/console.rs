@@ -0,0 +1,466 @@
+//! Virtio console devices, with multiple ports.
+//!
+//! Received data and control messages are handled in the IRQ thread of the
+//! device, which wakes up the readers, or polled when the ports are read if
+//! interrupts are not available. Ports are registered to the console layer
+//! once the device reports them, named by the device, or `vportNpM` like
+//! Linux if they have no names.
+//!
+//! The output is written with interrupts disabled, so it is never waited for:
+//! it is dropped if the transmit virtqueue is full.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use core::mem::size_of;
+
+use super::{PageQueue, Transport, VirtQueue};
+use crate::drivers::console::{register_console_port, ConsolePort};
+use crate::drivers::interrupt::{request_threaded_irq, IrqFlags, IrqReturn};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysFrame, PAGE_SIZE};
+use crate::sync::Mutex;
+use crate::task::WaitQueue;
+use crate::timer::{current_time, TimeValue};
+
+/// The device has multiple ports, and the control virtqueues.
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+
+/// The offset of `max_nr_ports` in the device configuration.
+const CONFIG_MAX_NR_PORTS: usize = 4;
+
+const CONTROL_RX_QUEUE: u16 = 2;
+const CONTROL_TX_QUEUE: u16 = 3;
+const QUEUE_SIZE: u16 = 8;
+/// Virtqueues are allocated for at most this number of ports.
+const MAX_PORTS: u32 = 4;
+
+/// Control events.
+const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
+const VIRTIO_CONSOLE_DEVICE_ADD: u16 = 1;
+const VIRTIO_CONSOLE_DEVICE_REMOVE: u16 = 2;
+const VIRTIO_CONSOLE_PORT_READY: u16 = 3;
+const VIRTIO_CONSOLE_CONSOLE_PORT: u16 = 4;
+const VIRTIO_CONSOLE_PORT_OPEN: u16 = 6;
+const VIRTIO_CONSOLE_PORT_NAME: u16 = 7;
+
+/// The device reports the ports after it is ready, the probe waits until it
+/// is quiet for this interval.
+const PROBE_QUIET_INTERVAL: TimeValue = TimeValue::from_millis(20);
+const PROBE_TIMEOUT: TimeValue = TimeValue::from_millis(500);
+
+static DEVICES: Mutex<Vec<Arc<VirtIOConsole>>> = Mutex::new(Vec::new());
+
+/// `struct virtio_console_control`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ControlMsg {
+    id: u32,
+    event: u16,
+    value: u16,
+}
+
+/// The virtqueues of a port, each request uses a single page.
+struct PortQueues {
+    rx_index: u16,
+    tx_index: u16,
+    rx: PageQueue,
+    tx: PageQueue,
+    /// Transmit buffers that are used by the device and can be reused.
+    tx_free: Vec<PhysFrame>,
+    /// Bytes received but not read yet.
+    received: VecDeque<u8>,
+}
+
+/// A port reported by the device.
+#[derive(Default)]
+struct PortInfo {
+    name: Option<String>,
+    is_console: bool,
+    registered: bool,
+}
+
+struct Control {
+    rx: PageQueue,
+    tx: PageQueue,
+    /// Queues of the ports, taken when the ports are registered.
+    port_queues: Vec<Option<PortQueues>>,
+    ports: BTreeMap<u32, PortInfo>,
+}
+
+pub struct VirtIOConsole {
+    index: usize,
+    transport: Arc<dyn Transport>,
+    /// `None` if the device has a single port.
+    control: Option<Mutex<Control>>,
+    /// The registered ports.
+    ports: Mutex<Vec<Arc<VirtIOConsolePort>>>,
+    /// Whether received data is reported by interrupts.
+    irq_enabled: bool,
+}
+
+pub struct VirtIOConsolePort {
+    name: String,
+    transport: Arc<dyn Transport>,
+    queues: Mutex<PortQueues>,
+    /// `None` if the device is polled.
+    rx_waiters: Option<WaitQueue>,
+}
+
+impl PortQueues {
+    fn new(transport: &dyn Transport, id: u32) -> SysResult<Self> {
+        // port 0 uses virtqueues 0 and 1, the control virtqueues follow
+        let rx_index = if id == 0 { 0 } else { 2 * id as u16 + 2 };
+        let tx_index = rx_index + 1;
+        let mut rx = PageQueue::new(VirtQueue::new(transport, rx_index, QUEUE_SIZE)?);
+        let tx = PageQueue::new(VirtQueue::new(transport, tx_index, QUEUE_SIZE)?);
+        fill_rx(&mut rx)?;
+        Ok(Self {
+            rx_index,
+            tx_index,
+            rx,
+            tx,
+            tx_free: Vec::new(),
+            received: VecDeque::new(),
+        })
+    }
+
+    fn reclaim_tx(&mut self) {
+        while let Some((frame, _)) = self.tx.pop_used() {
+            self.tx_free.push(frame);
+        }
+    }
+
+    /// Queues the data, which fits in a page. Returns `EAGAIN` if there are
+    /// no free descriptors, e.g. the host side is not connected.
+    fn transmit(&mut self, transport: &dyn Transport, data: &[u8]) -> SysResult<()> {
+        self.reclaim_tx();
+        if self.tx.vq.num_free() == 0 {
+            return Err(Errno::EAGAIN);
+        }
+        let mut buf = match self.tx_free.pop() {
+            Some(buf) => buf,
+            None => PhysFrame::alloc().ok_or(Errno::ENOMEM)?,
+        };
+        buf.as_slice_mut()[..data.len()].copy_from_slice(data);
+        self.tx.add(buf, Some(data.len()))?;
+        transport.notify(self.tx_index);
+        Ok(())
+    }
+
+    /// Moves the received data to `received`, and gives the buffers back to
+    /// the device.
+    fn receive(&mut self, transport: &dyn Transport) {
+        let mut refilled = false;
+        while let Some((buf, len)) = self.rx.pop_used() {
+            self.received.extend(&buf.as_slice()[..len.min(PAGE_SIZE)]);
+            refilled |= self.rx.add(buf, None).is_ok();
+        }
+        if refilled {
+            transport.notify(self.rx_index);
+        }
+    }
+}
+
+impl Control {
+    /// Sends the message, without waiting for the device to take it.
+    fn send(&mut self, transport: &dyn Transport, id: u32, event: u16, value: u16) {
+        let msg = ControlMsg { id, event, value };
+        // the buffers of the messages taken are freed
+        while self.tx.pop_used().is_some() {}
+        let result = PhysFrame::alloc().ok_or(Errno::ENOMEM).and_then(|mut buf| {
+            unsafe { (buf.as_slice_mut().as_mut_ptr() as *mut ControlMsg).write(msg) };
+            self.tx.add(buf, Some(size_of::<ControlMsg>()))
+        });
+        if let Err(e) = result {
+            warn!("virtio-console: failed to send {:?}: {:?}", msg, e);
+            return;
+        }
+        transport.notify(CONTROL_TX_QUEUE);
+    }
+
+    fn handle_msg(&mut self, transport: &dyn Transport, msg: ControlMsg, extra: &[u8]) {
+        debug!("virtio-console: {:?}", msg);
+        match msg.event {
+            VIRTIO_CONSOLE_DEVICE_ADD => {
+                let ready = self
+                    .port_queues
+                    .get(msg.id as usize)
+                    .map_or(false, Option::is_some);
+                if ready {
+                    self.ports.entry(msg.id).or_default();
+                } else {
+                    warn!("virtio-console: port {} is not supported", msg.id);
+                }
+                self.send(transport, msg.id, VIRTIO_CONSOLE_PORT_READY, ready as u16);
+            }
+            VIRTIO_CONSOLE_DEVICE_REMOVE => {
+                warn!("virtio-console: removing port {} is not supported", msg.id);
+            }
+            VIRTIO_CONSOLE_CONSOLE_PORT => {
+                if let Some(port) = self.ports.get_mut(&msg.id) {
+                    port.is_console = true;
+                }
+            }
+            VIRTIO_CONSOLE_PORT_NAME => {
+                if let Some(port) = self.ports.get_mut(&msg.id) {
+                    let len = extra.iter().position(|&c| c == 0).unwrap_or(extra.len());
+                    port.name = Some(String::from_utf8_lossy(&extra[..len]).into());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles the messages from the device, returns whether there are any.
+    fn poll(&mut self, transport: &dyn Transport) -> bool {
+        let mut received = false;
+        while let Some((buf, len)) = self.rx.pop_used() {
+            received = true;
+            if len >= size_of::<ControlMsg>() {
+                let msg = unsafe { (buf.as_slice().as_ptr() as *const ControlMsg).read() };
+                let extra = &buf.as_slice()[size_of::<ControlMsg>()..len.min(PAGE_SIZE)];
+                self.handle_msg(transport, msg, extra);
+            }
+            if self.rx.add(buf, None).is_ok() {
+                transport.notify(CONTROL_RX_QUEUE);
+            }
+        }
+        received
+    }
+}
+
+impl VirtIOConsole {
+    /// Handles the control messages, and registers the new ports.
+    fn poll_control(&self) {
+        let mut control = match &self.control {
+            Some(control) => control.lock(),
+            None => return,
+        };
+        control.poll(&*self.transport);
+
+        let mut new_ports = Vec::new();
+        let ids: Vec<u32> = control
+            .ports
+            .iter()
+            .filter(|(_, port)| !port.registered)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            let queues = control.port_queues[id as usize].take().unwrap();
+            let port = control.ports.get_mut(&id).unwrap();
+            port.registered = true;
+            let name = port.name.take();
+            let is_console = port.is_console;
+            new_ports.push((self.new_port(id, name, queues), is_console));
+            // the port is opened by the guest
+            control.send(&*self.transport, id, VIRTIO_CONSOLE_PORT_OPEN, 1);
+        }
+        drop(control);
+        for (port, is_console) in new_ports {
+            register_console_port(port, is_console);
+        }
+    }
+
+    fn new_port(
+        &self,
+        id: u32,
+        name: Option<String>,
+        queues: PortQueues,
+    ) -> Arc<VirtIOConsolePort> {
+        let port = Arc::new(VirtIOConsolePort {
+            name: name.unwrap_or_else(|| format!("vport{}p{}", self.index, id)),
+            transport: self.transport.clone(),
+            queues: Mutex::new(queues),
+            rx_waiters: self.irq_enabled.then(WaitQueue::new),
+        });
+        self.ports.lock().push(port.clone());
+        port
+    }
+
+    fn handle_irq(&self) -> IrqReturn {
+        if self.transport.ack_interrupt() {
+            IrqReturn::WakeThread
+        } else {
+            IrqReturn::None
+        }
+    }
+
+    fn handle_irq_thread(&self) {
+        self.poll_control();
+        let ports = self.ports.lock().clone();
+        for port in ports {
+            port.poll();
+        }
+    }
+}
+
+impl VirtIOConsolePort {
+    /// Reclaims the transmit buffers and receives the data, then wakes up the
+    /// readers if there is data to read.
+    fn poll(&self) {
+        let mut queues = self.queues.lock();
+        queues.reclaim_tx();
+        queues.receive(&*self.transport);
+        let readable = !queues.received.is_empty();
+        drop(queues);
+        if let Some(waiters) = &self.rx_waiters {
+            if readable {
+                waiters.notify_all();
+            }
+        }
+    }
+}
+
+impl ConsolePort for VirtIOConsolePort {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write(&self, buf: &[u8]) -> usize {
+        let mut queues = self.queues.lock();
+        let mut written = 0;
+        for chunk in buf.chunks(PAGE_SIZE) {
+            if queues.transmit(&*self.transport, chunk).is_err() {
+                break;
+            }
+            written += chunk.len();
+        }
+        written
+    }
+
+    fn read(&self, buf: &mut [u8]) -> usize {
+        if self.rx_waiters.is_none() {
+            // new ports may be reported at any time
+            poll_control();
+        }
+        let mut queues = self.queues.lock();
+        queues.receive(&*self.transport);
+        let len = buf.len().min(queues.received.len());
+        for (dst, src) in buf.iter_mut().zip(queues.received.drain(..len)) {
+            *dst = src;
+        }
+        len
+    }
+
+    fn can_read(&self) -> bool {
+        !self.queues.lock().received.is_empty()
+    }
+
+    fn rx_waiters(&self) -> Option<&WaitQueue> {
+        self.rx_waiters.as_ref()
+    }
+}
+
+fn poll_control() {
+    let devices = DEVICES.lock().clone();
+    for dev in devices {
+        dev.poll_control();
+    }
+}
+
+fn fill_rx(rx: &mut PageQueue) -> SysResult<()> {
+    for _ in 0..rx.vq.size() {
+        let buf = PhysFrame::alloc().ok_or(Errno::ENOMEM)?;
+        rx.add(buf, None)?;
+    }
+    Ok(())
+}
+
+fn setup_queues(
+    transport: &dyn Transport,
+    multiport: bool,
+) -> SysResult<(Option<Control>, Vec<PortQueues>)> {
+    if !multiport {
+        return Ok((None, vec![PortQueues::new(transport, 0)?]));
+    }
+    let nr_ports = transport
+        .read_config_u32(CONFIG_MAX_NR_PORTS)
+        .min(MAX_PORTS);
+    let mut ports = Vec::new();
+    for id in 0..nr_ports {
+        ports.push(PortQueues::new(transport, id)?);
+    }
+    let mut rx = PageQueue::new(VirtQueue::new(transport, CONTROL_RX_QUEUE, QUEUE_SIZE)?);
+    let tx = PageQueue::new(VirtQueue::new(transport, CONTROL_TX_QUEUE, QUEUE_SIZE)?);
+    fill_rx(&mut rx)?;
+    let control = Control {
+        rx,
+        tx,
+        port_queues: Vec::new(),
+        ports: BTreeMap::new(),
+    };
+    Ok((Some(control), ports))
+}
+
+pub(super) fn probe(transport: Box<dyn Transport>) -> SysResult<()> {
+    let transport: Arc<dyn Transport> = Arc::from(transport);
+    let (irq, control, ports) =
+        super::init_device(&*transport, VIRTIO_CONSOLE_F_MULTIPORT, |features| {
+            let irq = transport.setup_irq();
+            let multiport = features & VIRTIO_CONSOLE_F_MULTIPORT != 0;
+            let (control, ports) = setup_queues(&*transport, multiport)?;
+            Ok((irq, control, ports))
+        })?;
+    if irq.is_none() {
+        warn!("virtio-console: interrupts are not available, polling the device");
+    }
+    // the device may use the receive buffers only after `DRIVER_OK`
+    for port in &ports {
+        transport.notify(port.rx_index);
+    }
+
+    let mut dev = VirtIOConsole {
+        index: DEVICES.lock().len(),
+        transport: transport.clone(),
+        control: None,
+        ports: Mutex::new(Vec::new()),
+        irq_enabled: irq.is_some(),
+    };
+    let mut control = match control {
+        Some(control) => control,
+        None => {
+            // a single console port
+            let port = dev.new_port(0, None, ports.into_iter().next().unwrap());
+            let dev = Arc::new(dev);
+            DEVICES.lock().push(dev.clone());
+            request_irq(irq, &dev)?;
+            register_console_port(port, true);
+            return Ok(());
+        }
+    };
+    transport.notify(CONTROL_RX_QUEUE);
+    control.port_queues = ports.into_iter().map(Some).collect();
+    control.send(&*transport, 0, VIRTIO_CONSOLE_DEVICE_READY, 1);
+
+    // wait for the ports to be reported
+    let start = current_time();
+    let mut last_msg = start;
+    loop {
+        let now = current_time();
+        if control.poll(&*transport) {
+            last_msg = now;
+        } else if now >= last_msg + PROBE_QUIET_INTERVAL || now >= start + PROBE_TIMEOUT {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    dev.control = Some(Mutex::new(control));
+    let dev = Arc::new(dev);
+    DEVICES.lock().push(dev.clone());
+    dev.poll_control();
+    request_irq(irq, &dev)
+}
+
+fn request_irq(irq: Option<usize>, dev: &Arc<VirtIOConsole>) -> SysResult<()> {
+    if let Some(irq) = irq {
+        request_threaded_irq(
+            irq,
+            |dev: &Arc<VirtIOConsole>| dev.handle_irq(),
+            |dev| dev.handle_irq_thread(),
+            IrqFlags::SHARED,
+            "virtio-console",
+            dev.clone(),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! The virtio-mmio transport (version 2) of the QEMU virt machines.
+
+use alloc::boxed::Box;
+
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
+
+use super::{DeviceStatus, Transport};
+use crate::errno::Errno;
+use crate::mm::{PhysAddr, VirtAddr};
+
+const MAGIC_VALUE: u32 = 0x7472_6976; // "virt"
+
+cfg_if! {
+    if #[cfg(target_arch = "aarch64")] {
+        const MMIO_BASE: usize = 0x0a00_0000;
+        const MMIO_SLOT_SIZE: usize = 0x200;
+        const MMIO_SLOT_COUNT: usize = 32;
+        /// SPI 16 is the interrupt of the first slot.
+        const MMIO_IRQ_BASE: usize = 48;
+    } else {
+        const MMIO_BASE: usize = 0x1000_1000;
+        const MMIO_SLOT_SIZE: usize = 0x1000;
+        const MMIO_SLOT_COUNT: usize = 8;
+        /// The PLIC interrupt of the first slot.
+        const MMIO_IRQ_BASE: usize = 1;
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    VirtioMmioRegs {
+        (0x000 => MagicValue: ReadOnly<u32>),
+        (0x004 => Version: ReadOnly<u32>),
+        (0x008 => DeviceID: ReadOnly<u32>),
+        (0x00c => VendorID: ReadOnly<u32>),
+        (0x010 => DeviceFeatures: ReadOnly<u32>),
+        (0x014 => DeviceFeaturesSel: WriteOnly<u32>),
+        (0x018 => _reserved0),
+        (0x020 => DriverFeatures: WriteOnly<u32>),
+        (0x024 => DriverFeaturesSel: WriteOnly<u32>),
+        (0x028 => _reserved1),
+        (0x030 => QueueSel: WriteOnly<u32>),
+        (0x034 => QueueNumMax: ReadOnly<u32>),
+        (0x038 => QueueNum: WriteOnly<u32>),
+        (0x03c => _reserved2),
+        (0x044 => QueueReady: ReadWrite<u32>),
+        (0x048 => _reserved3),
+        (0x050 => QueueNotify: WriteOnly<u32>),
+        (0x054 => _reserved4),
+        (0x060 => InterruptStatus: ReadOnly<u32>),
+        (0x064 => InterruptACK: WriteOnly<u32>),
+        (0x068 => _reserved5),
+        (0x070 => Status: ReadWrite<u32>),
+        (0x074 => _reserved6),
+        (0x080 => QueueDescLow: WriteOnly<u32>),
+        (0x084 => QueueDescHigh: WriteOnly<u32>),
+        (0x088 => _reserved7),
+        (0x090 => QueueDriverLow: WriteOnly<u32>),
+        (0x094 => QueueDriverHigh: WriteOnly<u32>),
+        (0x098 => _reserved8),
+        (0x0a0 => QueueDeviceLow: WriteOnly<u32>),
+        (0x0a4 => QueueDeviceHigh: WriteOnly<u32>),
+        (0x0a8 => _reserved9),
+        (0x0fc => ConfigGeneration: ReadOnly<u32>),
+        (0x100 => @END),
+    }
+}
+
+pub struct MmioTransport {
+    base_vaddr: VirtAddr,
+    irq: usize,
+}
+
+impl MmioTransport {
+    /// Returns `None` if there is no device in the slot.
+    fn new(base: PhysAddr, irq: usize) -> Option<Self> {
+        let transport = Self {
+            base_vaddr: base.into_kvaddr(),
+            irq,
+        };
+        let regs = transport.regs();
+        if regs.MagicValue.get() != MAGIC_VALUE || regs.DeviceID.get() == 0 {
+            return None;
+        }
+        match regs.Version.get() {
+            2 => Some(transport),
+            version => {
+                warn!(
+                    "virtio-mmio device at {:#x?} of version {} is not supported",
+                    base, version
+                );
+                None
+            }
+        }
+    }
+
+    const fn regs(&self) -> &VirtioMmioRegs {
+        unsafe { &*(self.base_vaddr.as_ptr() as *const _) }
+    }
+}
+
+impl Transport for MmioTransport {
+    fn device_id(&self) -> u32 {
+        self.regs().DeviceID.get()
+    }
+
+    fn device_features(&self) -> u64 {
+        let regs = self.regs();
+        regs.DeviceFeaturesSel.set(0);
+        let low = regs.DeviceFeatures.get() as u64;
+        regs.DeviceFeaturesSel.set(1);
+        let high = regs.DeviceFeatures.get() as u64;
+        high << 32 | low
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        let regs = self.regs();
+        regs.DriverFeaturesSel.set(0);
+        regs.DriverFeatures.set(features as u32);
+        regs.DriverFeaturesSel.set(1);
+        regs.DriverFeatures.set((features >> 32) as u32);
+    }
+
+    fn status(&self) -> DeviceStatus {
+        DeviceStatus::from_bits_truncate(self.regs().Status.get() as u8)
+    }
+
+    fn set_status(&self, status: DeviceStatus) {
+        self.regs().Status.set(status.bits() as u32);
+    }
+
+    fn max_queue_size(&self, queue: u16) -> u16 {
+        let regs = self.regs();
+        regs.QueueSel.set(queue as u32);
+        if regs.QueueReady.get() != 0 {
+            return 0;
+        }
+        regs.QueueNumMax.get() as u16
+    }
+
+    fn setup_queue(
+        &self,
+        queue: u16,
+        size: u16,
+        desc: PhysAddr,
+        driver_area: PhysAddr,
+        device_area: PhysAddr,
+    ) {
+        let regs = self.regs();
+        regs.QueueSel.set(queue as u32);
+        regs.QueueNum.set(size as u32);
+        let set_addr = |low: &WriteOnly<u32>, high: &WriteOnly<u32>, paddr: PhysAddr| {
+            low.set(paddr.as_usize() as u32);
+            high.set((paddr.as_usize() as u64 >> 32) as u32);
+        };
+        set_addr(&regs.QueueDescLow, &regs.QueueDescHigh, desc);
+        set_addr(&regs.QueueDriverLow, &regs.QueueDriverHigh, driver_area);
+        set_addr(&regs.QueueDeviceLow, &regs.QueueDeviceHigh, device_area);
+        regs.QueueReady.set(1);
+    }
+
+    fn notify(&self, queue: u16) {
+        self.regs().QueueNotify.set(queue as u32);
+    }
+
+    fn setup_irq(&self) -> Option<usize> {
+        Some(self.irq)
+    }
+
+    fn ack_interrupt(&self) -> bool {
+        let regs = self.regs();
+        let status = regs.InterruptStatus.get();
+        if status != 0 {
+            regs.InterruptACK.set(status);
+        }
+        status != 0
+    }
+
+    fn config_vaddr(&self) -> VirtAddr {
+        VirtAddr::new(self.base_vaddr.as_usize() + 0x100)
+    }
+
+    fn config_generation(&self) -> u32 {
+        self.regs().ConfigGeneration.get()
+    }
+}
+
+pub fn init() {
+    for slot in 0..MMIO_SLOT_COUNT {
+        let base = PhysAddr::new(MMIO_BASE + slot * MMIO_SLOT_SIZE);
+        if let Some(transport) = MmioTransport::new(base, MMIO_IRQ_BASE + slot) {
+            match super::probe(Box::new(transport)) {
+                Ok(()) | Err(Errno::ENODEV) => {}
+                Err(e) => warn!(
+                    "failed to probe virtio-mmio device at {:#x?}: {:?}",
+                    base, e
+                ),
+            }
+        }
+    }
+}
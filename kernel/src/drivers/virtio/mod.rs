@@ -0,0 +1,182 @@
+//! Virtio devices, over the virtio-mmio transport on ARM and RISC-V, or the
+//! virtio-pci transport on x86.
+//!
+//! Only the modern (virtio 1.0+) interface of devices is supported.
+
+mod blk;
+mod console;
+mod net;
+mod queue;
+
+cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        mod pci;
+        use pci as imp;
+    } else {
+        mod mmio;
+        use mmio as imp;
+    }
+}
+
+use alloc::boxed::Box;
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysAddr, VirtAddr};
+
+pub use self::queue::{PageQueue, VirtQueue};
+
+/// The device complies with the virtio 1.0+ specification.
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+bitflags::bitflags! {
+    pub struct DeviceStatus: u8 {
+        const ACKNOWLEDGE       = 1;
+        const DRIVER            = 2;
+        const DRIVER_OK         = 4;
+        const FEATURES_OK       = 8;
+        const DEVICE_NEEDS_RESET = 0x40;
+        const FAILED            = 0x80;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DeviceType {
+    Network = 1,
+    Block = 2,
+    Console = 3,
+}
+
+impl TryFrom<u32> for DeviceType {
+    type Error = Errno;
+    fn try_from(id: u32) -> SysResult<Self> {
+        Ok(match id {
+            1 => Self::Network,
+            2 => Self::Block,
+            3 => Self::Console,
+            _ => return Err(Errno::ENODEV),
+        })
+    }
+}
+
+/// The interface to configure a virtio device and its virtqueues.
+pub trait Transport: Send + Sync {
+    /// The raw device ID.
+    fn device_id(&self) -> u32;
+
+    fn device_features(&self) -> u64;
+
+    fn set_driver_features(&self, features: u64);
+
+    fn status(&self) -> DeviceStatus;
+
+    fn set_status(&self, status: DeviceStatus);
+
+    /// Returns the maximum size of the virtqueue `queue`, or 0 if it does not
+    /// exist.
+    fn max_queue_size(&self, queue: u16) -> u16;
+
+    /// Sets the size and the addresses of the virtqueue `queue`, and enables
+    /// it.
+    fn setup_queue(
+        &self,
+        queue: u16,
+        size: u16,
+        desc: PhysAddr,
+        driver_area: PhysAddr,
+        device_area: PhysAddr,
+    );
+
+    /// Notifies the device that new buffers are available in `queue`.
+    fn notify(&self, queue: u16);
+
+    /// Routes the interrupts of the device to an IRQ, must be called before
+    /// the virtqueues are set up. Returns the IRQ to request, or `None` if
+    /// interrupts are not available, and the device must be polled. The IRQ
+    /// may be shared with other devices.
+    fn setup_irq(&self) -> Option<usize>;
+
+    /// Acknowledges the interrupt, returns `false` if it is not raised by the
+    /// device.
+    fn ack_interrupt(&self) -> bool;
+
+    /// The virtual address of the device-specific configuration.
+    fn config_vaddr(&self) -> VirtAddr;
+
+    /// Changes whenever the device-specific configuration is changed.
+    fn config_generation(&self) -> u32;
+
+    fn read_config_u8(&self, offset: usize) -> u8 {
+        unsafe { ((self.config_vaddr().as_usize() + offset) as *const u8).read_volatile() }
+    }
+
+    fn read_config_u16(&self, offset: usize) -> u16 {
+        unsafe { ((self.config_vaddr().as_usize() + offset) as *const u16).read_volatile() }
+    }
+
+    fn read_config_u32(&self, offset: usize) -> u32 {
+        unsafe { ((self.config_vaddr().as_usize() + offset) as *const u32).read_volatile() }
+    }
+
+    /// Reads a 64-bit field with two 32-bit accesses, retrying if the
+    /// configuration is changed meanwhile.
+    fn read_config_u64(&self, offset: usize) -> u64 {
+        loop {
+            let generation = self.config_generation();
+            let low = self.read_config_u32(offset) as u64;
+            let high = self.read_config_u32(offset + 4) as u64;
+            if generation == self.config_generation() {
+                return high << 32 | low;
+            }
+        }
+    }
+}
+
+/// Initializes the device with `setup`, after resetting it and negotiating
+/// the features among `supported`. `setup` receives the accepted features,
+/// and sets up the virtqueues. The device is marked failed on errors.
+fn init_device<T>(
+    transport: &dyn Transport,
+    supported: u64,
+    setup: impl FnOnce(u64) -> SysResult<T>,
+) -> SysResult<T> {
+    let result = negotiate_features(transport, supported).and_then(setup);
+    match result {
+        Ok(_) => transport.set_status(transport.status() | DeviceStatus::DRIVER_OK),
+        Err(_) => transport.set_status(transport.status() | DeviceStatus::FAILED),
+    }
+    result
+}
+
+fn negotiate_features(transport: &dyn Transport, supported: u64) -> SysResult<u64> {
+    transport.set_status(DeviceStatus::empty());
+    let status = DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER;
+    transport.set_status(status);
+
+    let features = transport.device_features() & (supported | VIRTIO_F_VERSION_1);
+    if features & VIRTIO_F_VERSION_1 == 0 {
+        warn!("legacy virtio devices are not supported");
+        return Err(Errno::ENODEV);
+    }
+    transport.set_driver_features(features);
+    transport.set_status(status | DeviceStatus::FEATURES_OK);
+    if !transport.status().contains(DeviceStatus::FEATURES_OK) {
+        return Err(Errno::ENODEV);
+    }
+    Ok(features)
+}
+
+fn probe(transport: Box<dyn Transport>) -> SysResult<()> {
+    match DeviceType::try_from(transport.device_id()) {
+        Ok(DeviceType::Network) => net::probe(transport),
+        Ok(DeviceType::Block) => blk::probe(transport),
+        Ok(DeviceType::Console) => console::probe(transport),
+        _ => {
+            info!("unsupported virtio device {}", transport.device_id());
+            Err(Errno::ENODEV)
+        }
+    }
+}
+
+pub fn init() {
+    imp::init();
+}
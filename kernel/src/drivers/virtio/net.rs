@@ -0,0 +1,176 @@
+//! Virtio network devices.
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+
+use super::{PageQueue, Transport, VirtQueue};
+use crate::drivers::interrupt::{request_irq, IrqFlags, IrqReturn};
+use crate::drivers::net::{self, register_net_device, NetDevice};
+use crate::errno::{Errno, SysResult};
+use crate::mm::PhysFrame;
+use crate::sync::Mutex;
+
+/// The device has a given MAC address.
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+/// The offset of `mac` in the device configuration.
+const CONFIG_MAC: usize = 0;
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+const QUEUE_SIZE: u16 = 64;
+
+/// The size of `struct virtio_net_hdr`, which precedes each frame. No
+/// offloading features are negotiated, so the header is always zero when
+/// transmitting, and ignored when receiving.
+const NET_HDR_SIZE: usize = 12;
+/// The ethernet header and the maximum payload, as no large packets are
+/// negotiated.
+const MAX_FRAME_SIZE: usize = 1514;
+
+static DEVICES: Mutex<Vec<Arc<VirtIONet>>> = Mutex::new(Vec::new());
+
+/// Each request uses a single page for the header and the frame.
+struct NetQueues {
+    rx: PageQueue,
+    tx: PageQueue,
+    /// Transmit buffers that are used by the device and can be reused.
+    tx_free: Vec<PhysFrame>,
+}
+
+pub struct VirtIONet {
+    name: String,
+    transport: Box<dyn Transport>,
+    queues: Mutex<NetQueues>,
+    mac: [u8; 6],
+}
+
+impl NetQueues {
+    fn reclaim_tx(&mut self) {
+        while let Some((frame, _)) = self.tx.pop_used() {
+            self.tx_free.push(frame);
+        }
+    }
+}
+
+impl VirtIONet {
+    fn handle_irq(&self) -> IrqReturn {
+        if !self.transport.ack_interrupt() {
+            return IrqReturn::None;
+        }
+        // the frames are received by the network stack in its own task
+        if self.queues.lock().rx.vq.can_pop() {
+            net::handle_rx();
+        }
+        IrqReturn::Handled
+    }
+}
+
+impl NetDevice for VirtIONet {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn max_frame_size(&self) -> usize {
+        MAX_FRAME_SIZE
+    }
+
+    fn can_transmit(&self) -> bool {
+        let mut queues = self.queues.lock();
+        queues.reclaim_tx();
+        queues.tx.vq.num_free() > 0
+    }
+
+    fn transmit(&self, frame: &[u8]) -> SysResult<()> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(Errno::EINVAL);
+        }
+        let mut queues = self.queues.lock();
+        queues.reclaim_tx();
+        if queues.tx.vq.num_free() == 0 {
+            return Err(Errno::EAGAIN);
+        }
+        let mut buf = match queues.tx_free.pop() {
+            Some(buf) => buf,
+            None => PhysFrame::alloc().ok_or(Errno::ENOMEM)?,
+        };
+        let data = buf.as_slice_mut();
+        data[..NET_HDR_SIZE].fill(0);
+        data[NET_HDR_SIZE..NET_HDR_SIZE + frame.len()].copy_from_slice(frame);
+        queues.tx.add(buf, Some(NET_HDR_SIZE + frame.len()))?;
+        self.transport.notify(TX_QUEUE);
+        Ok(())
+    }
+
+    fn receive(&self) -> Option<Vec<u8>> {
+        let mut queues = self.queues.lock();
+        let (buf, len) = queues.rx.pop_used()?;
+        let frame = buf.as_slice()[NET_HDR_SIZE..len.max(NET_HDR_SIZE)].to_vec();
+        // give the buffer back to the device
+        if queues.rx.add(buf, None).is_ok() {
+            self.transport.notify(RX_QUEUE);
+        }
+        Some(frame)
+    }
+}
+
+fn setup_queues(transport: &dyn Transport) -> SysResult<NetQueues> {
+    let mut rx = PageQueue::new(VirtQueue::new(transport, RX_QUEUE, QUEUE_SIZE)?);
+    let tx = PageQueue::new(VirtQueue::new(transport, TX_QUEUE, QUEUE_SIZE)?);
+    for _ in 0..rx.vq.size() {
+        let buf = PhysFrame::alloc().ok_or(Errno::ENOMEM)?;
+        rx.add(buf, None)?;
+    }
+    Ok(NetQueues {
+        rx,
+        tx,
+        tx_free: Vec::new(),
+    })
+}
+
+pub(super) fn probe(transport: Box<dyn Transport>) -> SysResult<()> {
+    let (features, irq, queues) = super::init_device(&*transport, VIRTIO_NET_F_MAC, |features| {
+        let irq = transport.setup_irq();
+        let queues = setup_queues(&*transport)?;
+        Ok((features, irq, queues))
+    })?;
+    if irq.is_none() {
+        warn!("virtio-net: interrupts are not available, polling the device");
+    }
+    // the device may use the receive buffers only after `DRIVER_OK`
+    transport.notify(RX_QUEUE);
+
+    let mut mac = [0; 6];
+    if features & VIRTIO_NET_F_MAC != 0 {
+        for (i, b) in mac.iter_mut().enumerate() {
+            *b = transport.read_config_u8(CONFIG_MAC + i);
+        }
+    } else {
+        // a locally administered address
+        mac = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+    }
+
+    let mut devices = DEVICES.lock();
+    let dev = Arc::new(VirtIONet {
+        name: format!("virtio-net{}", devices.len()),
+        transport,
+        queues: Mutex::new(queues),
+        mac,
+    });
+    devices.push(dev.clone());
+    drop(devices);
+    if let Some(irq) = irq {
+        request_irq(
+            irq,
+            |dev: &Arc<VirtIONet>| dev.handle_irq(),
+            IrqFlags::SHARED,
+            "virtio-net",
+            dev.clone(),
+        )?;
+    }
+    register_net_device(dev);
+    Ok(())
+}
@@ -0,0 +1,260 @@
+//! The virtio-pci transport, using the modern interface described by the
+//! vendor-specific capabilities of the PCI function.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::register_structs;
+use tock_registers::registers::{ReadOnly, ReadWrite};
+
+use super::{DeviceStatus, Transport};
+use crate::drivers::interrupt::{alloc_msi_vector, msi_message};
+use crate::drivers::pci::{self, PciDevice, PciDeviceId, PciDriver, CAP_ID_VENDOR};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysAddr, VirtAddr};
+use crate::sync::Mutex;
+
+const VIRTIO_VENDOR_ID: u16 = 0x1af4;
+
+const CFG_TYPE_COMMON: u8 = 1;
+const CFG_TYPE_NOTIFY: u8 = 2;
+const CFG_TYPE_ISR: u8 = 3;
+const CFG_TYPE_DEVICE: u8 = 4;
+
+/// No MSI-X vector is used.
+const NO_VECTOR: u16 = 0xffff;
+
+static DRIVER: PciDriver = PciDriver {
+    name: "virtio-pci",
+    id_table: &[PciDeviceId::Vendor(VIRTIO_VENDOR_ID)],
+    probe,
+};
+
+register_structs! {
+    CommonCfgRegs {
+        (0x00 => device_feature_select: ReadWrite<u32>),
+        (0x04 => device_feature: ReadOnly<u32>),
+        (0x08 => driver_feature_select: ReadWrite<u32>),
+        (0x0c => driver_feature: ReadWrite<u32>),
+        (0x10 => msix_config: ReadWrite<u16>),
+        (0x12 => num_queues: ReadOnly<u16>),
+        (0x14 => device_status: ReadWrite<u8>),
+        (0x15 => config_generation: ReadOnly<u8>),
+        (0x16 => queue_select: ReadWrite<u16>),
+        (0x18 => queue_size: ReadWrite<u16>),
+        (0x1a => queue_msix_vector: ReadWrite<u16>),
+        (0x1c => queue_enable: ReadWrite<u16>),
+        (0x1e => queue_notify_off: ReadOnly<u16>),
+        (0x20 => queue_desc_low: ReadWrite<u32>),
+        (0x24 => queue_desc_high: ReadWrite<u32>),
+        (0x28 => queue_driver_low: ReadWrite<u32>),
+        (0x2c => queue_driver_high: ReadWrite<u32>),
+        (0x30 => queue_device_low: ReadWrite<u32>),
+        (0x34 => queue_device_high: ReadWrite<u32>),
+        (0x38 => @END),
+    }
+}
+
+pub struct PciTransport {
+    dev: &'static PciDevice,
+    device_id: u32,
+    common_cfg: VirtAddr,
+    notify_base: VirtAddr,
+    notify_off_multiplier: u32,
+    isr: Option<VirtAddr>,
+    device_cfg: VirtAddr,
+    /// The notification addresses of the virtqueues that are set up.
+    notify_addrs: Mutex<Vec<VirtAddr>>,
+    /// Whether the interrupts are delivered as the MSI-X entry 0.
+    msix_enabled: AtomicBool,
+}
+
+impl PciTransport {
+    fn new(dev: &'static PciDevice) -> SysResult<Self> {
+        // transitional devices are identified by the subsystem ID
+        let device_id = match dev.device_id {
+            0x1000..=0x103f => dev.subsystem_id as u32,
+            0x1040..=0x107f => (dev.device_id - 0x1040) as u32,
+            _ => return Err(Errno::ENODEV),
+        };
+
+        let mut common_cfg = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut device_cfg = None;
+        for cap in dev.find_capabilities(CAP_ID_VENDOR) {
+            let off = cap.offset();
+            let cfg_type = dev.read_config8(off + 3);
+            let bar = dev.read_config8(off + 4) as usize;
+            let offset = dev.read_config32(off + 8) as usize;
+            let length = dev.read_config32(off + 12) as usize;
+            // use the first capability of each type
+            let slot = match cfg_type {
+                CFG_TYPE_COMMON => &mut common_cfg,
+                CFG_TYPE_NOTIFY => &mut notify,
+                CFG_TYPE_ISR => &mut isr,
+                CFG_TYPE_DEVICE => &mut device_cfg,
+                _ => continue,
+            };
+            if slot.is_none() && length > 0 {
+                let vaddr = dev.map_bar_range(bar, offset, length)?;
+                *slot = Some((vaddr, off));
+            }
+        }
+        let (common_cfg, notify) = match (common_cfg, notify) {
+            (Some(common_cfg), Some(notify)) => (common_cfg.0, notify),
+            _ => {
+                warn!("PCI {}: no modern virtio interface", dev.addr);
+                return Err(Errno::ENODEV);
+            }
+        };
+        dev.enable();
+        Ok(Self {
+            dev,
+            device_id,
+            common_cfg,
+            notify_base: notify.0,
+            notify_off_multiplier: dev.read_config32(notify.1 + 16),
+            isr: isr.map(|isr| isr.0),
+            device_cfg: device_cfg.map_or(VirtAddr::new(0), |cfg| cfg.0),
+            notify_addrs: Mutex::new(Vec::new()),
+            msix_enabled: AtomicBool::new(false),
+        })
+    }
+
+    fn common(&self) -> &CommonCfgRegs {
+        unsafe { &*(self.common_cfg.as_ptr() as *const _) }
+    }
+}
+
+impl Transport for PciTransport {
+    fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    fn device_features(&self) -> u64 {
+        let common = self.common();
+        common.device_feature_select.set(0);
+        let low = common.device_feature.get() as u64;
+        common.device_feature_select.set(1);
+        let high = common.device_feature.get() as u64;
+        high << 32 | low
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        let common = self.common();
+        common.driver_feature_select.set(0);
+        common.driver_feature.set(features as u32);
+        common.driver_feature_select.set(1);
+        common.driver_feature.set((features >> 32) as u32);
+    }
+
+    fn status(&self) -> DeviceStatus {
+        DeviceStatus::from_bits_truncate(self.common().device_status.get())
+    }
+
+    fn set_status(&self, status: DeviceStatus) {
+        self.common().device_status.set(status.bits());
+    }
+
+    fn max_queue_size(&self, queue: u16) -> u16 {
+        let common = self.common();
+        if queue >= common.num_queues.get() {
+            return 0;
+        }
+        common.queue_select.set(queue);
+        common.queue_size.get()
+    }
+
+    fn setup_queue(
+        &self,
+        queue: u16,
+        size: u16,
+        desc: PhysAddr,
+        driver_area: PhysAddr,
+        device_area: PhysAddr,
+    ) {
+        let common = self.common();
+        common.queue_select.set(queue);
+        common.queue_size.set(size);
+        if self.msix_enabled.load(Ordering::Acquire) {
+            common.queue_msix_vector.set(0);
+            if common.queue_msix_vector.get() == NO_VECTOR {
+                warn!("PCI {}: failed to set the MSI-X vector", self.dev.addr);
+            }
+        }
+        let set_addr = |low: &ReadWrite<u32>, high: &ReadWrite<u32>, paddr: PhysAddr| {
+            low.set(paddr.as_usize() as u32);
+            high.set((paddr.as_usize() as u64 >> 32) as u32);
+        };
+        set_addr(&common.queue_desc_low, &common.queue_desc_high, desc);
+        set_addr(
+            &common.queue_driver_low,
+            &common.queue_driver_high,
+            driver_area,
+        );
+        set_addr(
+            &common.queue_device_low,
+            &common.queue_device_high,
+            device_area,
+        );
+        let notify_off = common.queue_notify_off.get() as usize;
+        common.queue_enable.set(1);
+
+        let vaddr = self.notify_base.as_usize() + notify_off * self.notify_off_multiplier as usize;
+        let mut notify_addrs = self.notify_addrs.lock();
+        if notify_addrs.len() <= queue as usize {
+            notify_addrs.resize(queue as usize + 1, VirtAddr::new(0));
+        }
+        notify_addrs[queue as usize] = VirtAddr::new(vaddr);
+    }
+
+    fn notify(&self, queue: u16) {
+        let vaddr = self.notify_addrs.lock()[queue as usize];
+        unsafe { (vaddr.as_mut_ptr() as *mut u16).write_volatile(queue) };
+    }
+
+    fn setup_irq(&self) -> Option<usize> {
+        let msix = self.dev.msix()?;
+        let (table, vector) = match (msix.map_table(self.dev), alloc_msi_vector()) {
+            (Ok(table), Some(vector)) => (table, vector),
+            _ => return None,
+        };
+        let (address, data) = msi_message(vector).ok()?;
+        table.set_entry(0, address, data).ok()?;
+        msix.set_enabled(self.dev, true);
+        // configuration changes are not reported
+        self.common().msix_config.set(NO_VECTOR);
+        self.msix_enabled.store(true, Ordering::Release);
+        Some(vector)
+    }
+
+    fn ack_interrupt(&self) -> bool {
+        if self.msix_enabled.load(Ordering::Acquire) {
+            return true;
+        }
+        // reading the ISR status clears it
+        match self.isr {
+            Some(isr) => unsafe { isr.as_ptr().read_volatile() & 1 != 0 },
+            None => false,
+        }
+    }
+
+    fn config_vaddr(&self) -> VirtAddr {
+        self.device_cfg
+    }
+
+    fn config_generation(&self) -> u32 {
+        self.common().config_generation.get() as u32
+    }
+}
+
+fn probe(dev: &'static PciDevice) -> SysResult<()> {
+    let transport = PciTransport::new(dev)?;
+    super::probe(Box::new(transport))
+}
+
+pub fn init() {
+    pci::register_driver(&DRIVER);
+}
@@ -0,0 +1,234 @@
+//! Split virtqueues.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{fence, Ordering};
+
+use super::Transport;
+use crate::errno::{Errno, SysResult};
+use crate::mm::{PhysAddr, PhysFrame, PAGE_SIZE};
+
+/// The buffer continues via the `next` field.
+const DESC_F_NEXT: u16 = 1;
+/// The buffer is write-only for the device.
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// A split virtqueue, consisting of the descriptor table, the available ring
+/// (driver area) and the used ring (device area), which are placed in
+/// physically contiguous frames.
+pub struct VirtQueue {
+    index: u16,
+    size: u16,
+    dma: PhysFrame,
+    avail_offset: usize,
+    used_offset: usize,
+    /// The head of the free descriptors, linked by the `next` field.
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    /// Allocates the virtqueue `index` with at most `max_size` entries, and
+    /// passes it to the device. `max_size` must be a power of two.
+    pub fn new(transport: &dyn Transport, index: u16, max_size: u16) -> SysResult<Self> {
+        assert!(max_size.is_power_of_two());
+        let size = transport.max_queue_size(index).min(max_size);
+        if size == 0 {
+            return Err(Errno::ENODEV);
+        }
+        // sizes of split virtqueues are always powers of two
+        let n = size as usize;
+        let avail_offset = size_of::<Descriptor>() * n;
+        // flags, idx, ring[n], used_event
+        let avail_size = 2 * (3 + n);
+        let used_offset = (avail_offset + avail_size + 3) & !3;
+        // flags, idx, ring[n], avail_event
+        let used_size = 2 * 3 + size_of::<UsedElem>() * n;
+        let pages = (used_offset + used_size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut dma = PhysFrame::alloc_contiguous(pages, 1).ok_or(Errno::ENOMEM)?;
+        dma.zero();
+
+        let mut queue = Self {
+            index,
+            size,
+            dma,
+            avail_offset,
+            used_offset,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        for i in 0..size - 1 {
+            queue.desc_mut(i).next = i + 1;
+        }
+        let base = queue.dma.start_paddr().as_usize();
+        transport.setup_queue(
+            index,
+            size,
+            PhysAddr::new(base),
+            PhysAddr::new(base + avail_offset),
+            PhysAddr::new(base + used_offset),
+        );
+        Ok(queue)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// The number of descriptors.
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+
+    /// The number of free descriptors.
+    pub fn num_free(&self) -> usize {
+        self.num_free as usize
+    }
+
+    fn ptr<T>(&self, offset: usize) -> *mut T {
+        (self.dma.start_paddr().into_kvaddr().as_usize() + offset) as *mut T
+    }
+
+    fn desc(&self, i: u16) -> &Descriptor {
+        unsafe { &*self.ptr(i as usize * size_of::<Descriptor>()) }
+    }
+
+    fn desc_mut(&mut self, i: u16) -> &mut Descriptor {
+        unsafe { &mut *self.ptr(i as usize * size_of::<Descriptor>()) }
+    }
+
+    /// Adds a descriptor chain of the buffers `inputs`, which are read by the
+    /// device, followed by `outputs`, which are written by the device. Returns
+    /// the head of the chain, which identifies the request when it is used.
+    ///
+    /// The device is not notified.
+    pub fn add(
+        &mut self,
+        inputs: &[(PhysAddr, usize)],
+        outputs: &[(PhysAddr, usize)],
+    ) -> SysResult<u16> {
+        let count = inputs.len() + outputs.len();
+        assert!(count > 0);
+        if count > self.num_free as usize {
+            return Err(Errno::EAGAIN);
+        }
+        let head = self.free_head;
+        let mut last = head;
+        let buffers = inputs
+            .iter()
+            .map(|b| (b, 0))
+            .chain(outputs.iter().map(|b| (b, DESC_F_WRITE)));
+        for (&(paddr, len), flags) in buffers {
+            last = self.free_head;
+            let desc = self.desc_mut(last);
+            desc.addr = paddr.as_usize() as u64;
+            desc.len = len as u32;
+            desc.flags = flags | DESC_F_NEXT;
+            self.free_head = desc.next;
+        }
+        self.desc_mut(last).flags &= !DESC_F_NEXT;
+        self.num_free -= count as u16;
+
+        // avail->ring[avail_idx % size] = head
+        let slot = (self.avail_idx % self.size) as usize;
+        unsafe {
+            self.ptr::<u16>(self.avail_offset + 4 + slot * 2)
+                .write_volatile(head)
+        };
+        // the device must see the ring entry before the new index
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe {
+            self.ptr::<u16>(self.avail_offset + 2)
+                .write_volatile(self.avail_idx)
+        };
+        fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    /// Whether there are used chains not popped yet.
+    pub fn can_pop(&self) -> bool {
+        fence(Ordering::SeqCst);
+        let used_idx = unsafe { self.ptr::<u16>(self.used_offset + 2).read_volatile() };
+        used_idx != self.last_used_idx
+    }
+
+    /// Pops a used descriptor chain, returns its head and the number of bytes
+    /// written by the device. The descriptors are freed.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.can_pop() {
+            return None;
+        }
+        // read the used element after the index
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used_idx % self.size) as usize;
+        let elem = unsafe {
+            self.ptr::<UsedElem>(self.used_offset + 4 + slot * size_of::<UsedElem>())
+                .read_volatile()
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        let head = elem.id as u16;
+        let mut last = head;
+        self.num_free += 1;
+        while self.desc(last).flags & DESC_F_NEXT != 0 {
+            last = self.desc(last).next;
+            self.num_free += 1;
+        }
+        self.desc_mut(last).next = self.free_head;
+        self.free_head = head;
+        Some((head, elem.len))
+    }
+}
+
+/// A virtqueue whose requests each use a single page, which is kept until the
+/// request is used. The pages are indexed by the head descriptor.
+pub struct PageQueue {
+    pub vq: VirtQueue,
+    buffers: Vec<Option<PhysFrame>>,
+}
+
+impl PageQueue {
+    pub fn new(vq: VirtQueue) -> Self {
+        let buffers = (0..vq.size()).map(|_| None).collect();
+        Self { vq, buffers }
+    }
+
+    /// Adds the page to the virtqueue, with `len` bytes to be read by the
+    /// device, or the whole page to be written by the device if `len` is
+    /// `None`.
+    pub fn add(&mut self, frame: PhysFrame, len: Option<usize>) -> SysResult<()> {
+        let buffer = [(frame.start_paddr(), len.unwrap_or(PAGE_SIZE))];
+        let head = match len {
+            Some(_) => self.vq.add(&buffer, &[])?,
+            None => self.vq.add(&[], &buffer)?,
+        };
+        self.buffers[head as usize] = Some(frame);
+        Ok(())
+    }
+
+    /// Pops a used page, and the number of bytes written by the device.
+    pub fn pop_used(&mut self) -> Option<(PhysFrame, usize)> {
+        let (head, len) = self.vq.pop_used()?;
+        let frame = self.buffers[head as usize].take().unwrap();
+        Some((frame, len as usize))
+    }
+}
@@ -0,0 +1,305 @@
+use alloc::collections::BTreeMap;
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::drivers::block::BlockDevice;
+use crate::errno::{Errno, SysResult};
+
+struct CacheEntry {
+    data: Vec<u8>,
+    dirty: bool,
+    /// The value of `BlockCache::clock` when the block was last accessed.
+    last_used: u64,
+}
+
+/// A write-back cache of the blocks of a block device, with LRU eviction.
+///
+/// Blocks of the cache may be larger than the blocks of the device. Modified
+/// blocks are written back when they are evicted, or by [`BlockCache::sync`].
+pub struct BlockCache {
+    dev: Arc<dyn BlockDevice>,
+    block_size: usize,
+    /// The number of device blocks in a cache block.
+    dev_blocks_per_block: u64,
+    capacity: usize,
+    entries: BTreeMap<u64, CacheEntry>,
+    clock: u64,
+}
+
+impl BlockCache {
+    /// Creates a cache of at most `capacity` blocks of `block_size` bytes,
+    /// which must be a multiple of the block size of `dev`.
+    pub fn new(dev: Arc<dyn BlockDevice>, block_size: usize, capacity: usize) -> SysResult<Self> {
+        let dev_block_size = dev.block_size();
+        if block_size == 0 || block_size % dev_block_size != 0 || capacity == 0 {
+            return Err(Errno::EINVAL);
+        }
+        Ok(Self {
+            dev,
+            block_size,
+            dev_blocks_per_block: (block_size / dev_block_size) as u64,
+            capacity,
+            entries: BTreeMap::new(),
+            clock: 0,
+        })
+    }
+
+    /// The number of cache blocks on the device.
+    pub fn num_blocks(&self) -> u64 {
+        self.dev.num_blocks() / self.dev_blocks_per_block
+    }
+
+    fn write_back(&self, block_id: u64, entry: &mut CacheEntry) -> SysResult<()> {
+        if entry.dirty {
+            self.dev
+                .write_blocks(block_id * self.dev_blocks_per_block, &entry.data)?;
+            entry.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the cached block, reads it from the device on a miss.
+    fn entry(&mut self, block_id: u64) -> SysResult<&mut CacheEntry> {
+        if block_id >= self.num_blocks() {
+            return Err(Errno::EIO);
+        }
+        self.clock += 1;
+        if !self.entries.contains_key(&block_id) {
+            if self.entries.len() >= self.capacity {
+                self.evict()?;
+            }
+            let mut data = vec![0; self.block_size];
+            self.dev
+                .read_blocks(block_id * self.dev_blocks_per_block, &mut data)?;
+            let entry = CacheEntry {
+                data,
+                dirty: false,
+                last_used: 0,
+            };
+            self.entries.insert(block_id, entry);
+        }
+        let entry = self.entries.get_mut(&block_id).unwrap();
+        entry.last_used = self.clock;
+        Ok(entry)
+    }
+
+    /// Removes the least recently used block.
+    fn evict(&mut self) -> SysResult<()> {
+        let victim = self
+            .entries
+            .iter()
+            .min_by_key(|(_, e)| e.last_used)
+            .map(|(&id, _)| id);
+        if let Some(id) = victim {
+            let mut entry = self.entries.remove(&id).unwrap();
+            if let Err(e) = self.write_back(id, &mut entry) {
+                // keep the data, so that it is retried later
+                self.entries.insert(id, entry);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the content of the block `block_id`.
+    pub fn read<R>(&mut self, block_id: u64, f: impl FnOnce(&[u8]) -> R) -> SysResult<R> {
+        Ok(f(&self.entry(block_id)?.data))
+    }
+
+    /// Calls `f` to modify the content of the block `block_id`, the block is
+    /// written back later.
+    pub fn modify<R>(&mut self, block_id: u64, f: impl FnOnce(&mut [u8]) -> R) -> SysResult<R> {
+        let entry = self.entry(block_id)?;
+        entry.dirty = true;
+        Ok(f(&mut entry.data))
+    }
+
+    /// Copies the bytes at `offset` of the block `block_id` to `buf`.
+    pub fn read_at(&mut self, block_id: u64, offset: usize, buf: &mut [u8]) -> SysResult<()> {
+        self.read(block_id, |data| {
+            buf.copy_from_slice(&data[offset..offset + buf.len()])
+        })
+    }
+
+    /// Copies `buf` to the bytes at `offset` of the block `block_id`.
+    pub fn write_at(&mut self, block_id: u64, offset: usize, buf: &[u8]) -> SysResult<()> {
+        self.modify(block_id, |data| {
+            data[offset..offset + buf.len()].copy_from_slice(buf)
+        })
+    }
+
+    /// Fills the block `block_id` with zeros, without reading it from the
+    /// device.
+    pub fn zero(&mut self, block_id: u64) -> SysResult<()> {
+        if let Some(entry) = self.entries.get_mut(&block_id) {
+            entry.data.fill(0);
+            entry.dirty = true;
+            return Ok(());
+        }
+        if block_id >= self.num_blocks() {
+            return Err(Errno::EIO);
+        }
+        if self.entries.len() >= self.capacity {
+            self.evict()?;
+        }
+        self.clock += 1;
+        let entry = CacheEntry {
+            data: vec![0; self.block_size],
+            dirty: true,
+            last_used: self.clock,
+        };
+        self.entries.insert(block_id, entry);
+        Ok(())
+    }
+
+    /// Writes all modified blocks back to the device, and flushes it.
+    pub fn sync(&mut self) -> SysResult<()> {
+        let mut entries = core::mem::take(&mut self.entries);
+        let result = entries
+            .iter_mut()
+            .try_for_each(|(&id, entry)| self.write_back(id, entry));
+        self.entries = entries;
+        result?;
+        self.dev.flush()
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// A block device in memory, which counts the requests.
+    pub struct MemDisk {
+        block_size: usize,
+        data: Mutex<Vec<u8>>,
+        reads: AtomicUsize,
+        writes: AtomicUsize,
+    }
+
+    impl MemDisk {
+        pub fn new(block_size: usize, num_blocks: u64) -> Arc<Self> {
+            Arc::new(Self {
+                block_size,
+                data: Mutex::new(vec![0; block_size * num_blocks as usize]),
+                reads: AtomicUsize::new(0),
+                writes: AtomicUsize::new(0),
+            })
+        }
+
+        pub fn read_raw(&self, offset: usize, len: usize) -> Vec<u8> {
+            self.data.lock().unwrap()[offset..offset + len].to_vec()
+        }
+
+        pub fn write_raw(&self, offset: usize, buf: &[u8]) {
+            self.data.lock().unwrap()[offset..offset + buf.len()].copy_from_slice(buf);
+        }
+
+        pub fn reads(&self) -> usize {
+            self.reads.load(Ordering::SeqCst)
+        }
+
+        pub fn writes(&self) -> usize {
+            self.writes.load(Ordering::SeqCst)
+        }
+    }
+
+    impl BlockDevice for MemDisk {
+        fn name(&self) -> &str {
+            "memdisk"
+        }
+
+        fn block_size(&self) -> usize {
+            self.block_size
+        }
+
+        fn num_blocks(&self) -> u64 {
+            (self.data.lock().unwrap().len() / self.block_size) as u64
+        }
+
+        fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> SysResult<()> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            buf.copy_from_slice(&self.read_raw(block_id as usize * self.block_size, buf.len()));
+            Ok(())
+        }
+
+        fn write_blocks(&self, block_id: u64, buf: &[u8]) -> SysResult<()> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            self.write_raw(block_id as usize * self.block_size, buf);
+            Ok(())
+        }
+
+        fn flush(&self) -> SysResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_read_write() {
+        let disk = MemDisk::new(512, 16);
+        disk.write_raw(1024, &[1; 1024]);
+        assert!(BlockCache::new(disk.clone(), 768, 2).is_err());
+        let mut cache = BlockCache::new(disk.clone(), 1024, 2).unwrap();
+        assert_eq!(cache.num_blocks(), 8);
+
+        // only misses read the device
+        let mut buf = [0; 4];
+        cache.read_at(1, 1020, &mut buf).unwrap();
+        assert_eq!(buf, [1; 4]);
+        assert_eq!(cache.read(1, |data| data[0]).unwrap(), 1);
+        assert_eq!(disk.reads(), 1);
+
+        // writes are cached
+        cache.write_at(1, 0, &[2; 4]).unwrap();
+        assert_eq!(
+            cache.read(1, |data| data[..5].to_vec()).unwrap(),
+            [2, 2, 2, 2, 1]
+        );
+        assert_eq!(disk.read_raw(1024, 4), [1; 4]);
+        assert_eq!(disk.writes(), 0);
+
+        // zeroed blocks are not read
+        cache.zero(3).unwrap();
+        assert!(cache.read(3, |data| data.iter().all(|&b| b == 0)).unwrap());
+        assert_eq!(disk.reads(), 1);
+
+        assert_eq!(cache.read(8, |_| ()), Err(Errno::EIO));
+        assert_eq!(cache.zero(8), Err(Errno::EIO));
+    }
+
+    #[test]
+    fn test_eviction_write_back() {
+        let disk = MemDisk::new(512, 16);
+        let mut cache = BlockCache::new(disk.clone(), 1024, 2).unwrap();
+        cache.write_at(0, 0, &[1; 4]).unwrap();
+        cache.write_at(1, 0, &[2; 4]).unwrap();
+        cache.read(0, |_| ()).unwrap();
+
+        // block 1 is the least recently used, and written back on eviction
+        cache.read(2, |_| ()).unwrap();
+        assert_eq!(disk.writes(), 1);
+        assert_eq!(disk.read_raw(1024, 4), [2; 4]);
+        assert_eq!(disk.read_raw(0, 4), [0; 4]);
+
+        // evicted blocks are read again
+        assert_eq!(cache.read(1, |data| data[0]).unwrap(), 2);
+        assert_eq!(disk.reads(), 4);
+        assert_eq!(disk.writes(), 2);
+        assert_eq!(disk.read_raw(0, 4), [1; 4]);
+
+        // clean blocks are not written back
+        cache.read(3, |_| ()).unwrap();
+        assert_eq!(disk.writes(), 2);
+
+        // `sync` writes all dirty blocks, which stay in the cache
+        cache.write_at(3, 4, &[3; 4]).unwrap();
+        cache.sync().unwrap();
+        assert_eq!(disk.writes(), 3);
+        assert_eq!(disk.read_raw(3 * 1024, 8), [0, 0, 0, 0, 3, 3, 3, 3]);
+        let reads = disk.reads();
+        cache.read(3, |_| ()).unwrap();
+        assert_eq!(disk.reads(), reads);
+        cache.sync().unwrap();
+        assert_eq!(disk.writes(), 3);
+    }
+}
@@ -0,0 +1,61 @@
+//! Device files under `/dev`, which are not in the root filesystem.
+
+use alloc::sync::Arc;
+
+use super::{File, OpenFlags};
+use crate::drivers::console::{find_console_port, ConsolePort};
+use crate::errno::{Errno, SysResult};
+use crate::task::current;
+
+/// A console port opened as `/dev/<name>`.
+struct PortFile {
+    port: Arc<dyn ConsolePort>,
+    flags: OpenFlags,
+}
+
+impl File for PortFile {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if !self.flags.readable() {
+            return Err(Errno::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Block until at least one byte is available.
+        let curr = current();
+        loop {
+            let len = self.port.read(buf);
+            if len > 0 {
+                return Ok(len);
+            }
+            match self.port.rx_waiters() {
+                Some(waiters) => {
+                    waiters.wait_until(|| self.port.can_read() || curr.is_group_exiting())
+                }
+                None => curr.yield_now(),
+            }
+            if curr.is_group_exiting() {
+                return Err(Errno::EINTR);
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        if !self.flags.writable() {
+            return Err(Errno::EBADF);
+        }
+        match self.port.write(buf) {
+            0 if !buf.is_empty() => Err(Errno::EAGAIN),
+            len => Ok(len),
+        }
+    }
+}
+
+/// Opens the device `name` in `/dev`.
+pub(super) fn open(name: &str, flags: OpenFlags) -> SysResult<Arc<dyn File>> {
+    if flags.intersects(OpenFlags::CREAT | OpenFlags::DIRECTORY) {
+        return Err(Errno::EINVAL);
+    }
+    let port = find_console_port(name).ok_or(Errno::ENOENT)?;
+    Ok(Arc::new(PortFile { port, flags }))
+}
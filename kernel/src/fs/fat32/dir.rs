@@ -0,0 +1,511 @@
+//! Directory entries, and long file name (LFN) entries that precede them.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use super::{le16, le32, FatInner, ROOT_POS};
+use crate::errno::{Errno, SysResult};
+use crate::utils::datetime::DateTime;
+
+pub(super) const ENTRY_SIZE: usize = 32;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+pub(super) const ATTR_DIRECTORY: u8 = 0x10;
+pub(super) const ATTR_ARCHIVE: u8 = 0x20;
+/// READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID
+const ATTR_LFN: u8 = 0x0f;
+
+/// The first byte of an entry marks the end of the directory.
+const ENTRY_END: u8 = 0;
+/// The first byte of a deleted entry.
+const ENTRY_FREE: u8 = 0xe5;
+/// Set in the sequence number of the last LFN entry of a name.
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+/// Offsets of the UCS-2 characters in an LFN entry.
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// Flags of the reserved byte, used by Windows NT and Linux to store short
+/// names in lowercase.
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+const MAX_NAME_LEN: usize = 255;
+/// Directories can have at most 65536 entries.
+const MAX_DIR_ENTRIES: usize = 65536;
+
+const DOT_NAME: [u8; 11] = *b".          ";
+const DOTDOT_NAME: [u8; 11] = *b"..         ";
+
+/// A directory entry with its long name.
+pub(super) struct DirEntryInfo {
+    pub name: String,
+    pub short_name: [u8; 11],
+    pub attr: u8,
+    pub first_cluster: u32,
+    pub size: u32,
+    /// Positions of the LFN entries and the short entry, which is the last.
+    pub slots: Vec<u64>,
+}
+
+impl DirEntryInfo {
+    /// The position of the short entry, which identifies the entry.
+    pub fn pos(&self) -> u64 {
+        *self.slots.last().unwrap()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// Whether it is the `.` or `..` entry.
+    pub fn is_dot(&self) -> bool {
+        self.short_name == DOT_NAME || self.short_name == DOTDOT_NAME
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || short_name_to_string(&self.short_name, 0).eq_ignore_ascii_case(name)
+    }
+}
+
+/// LFN entries being collected, before the short entry they belong to.
+struct LfnState {
+    chars: Vec<u16>,
+    /// The sequence number of the next LFN entry, 0 if the name is complete.
+    next_ord: u8,
+    checksum: u8,
+    slots: Vec<u64>,
+}
+
+fn short_name_to_string(short_name: &[u8; 11], nt_res: u8) -> String {
+    let convert = |part: &[u8], lower: bool| -> String {
+        let part = match part.iter().rposition(|&c| c != b' ') {
+            Some(end) => &part[..=end],
+            None => &[],
+        };
+        part.iter()
+            .map(|&c| {
+                let c = c as char;
+                if lower {
+                    c.to_ascii_lowercase()
+                } else {
+                    c
+                }
+            })
+            .collect()
+    };
+    let mut base = short_name[..8].to_vec();
+    // 0x05 is stored for the first character 0xe5
+    if base[0] == 0x05 {
+        base[0] = ENTRY_FREE;
+    }
+    let mut name = convert(&base, nt_res & NT_LOWER_BASE != 0);
+    let ext = convert(&short_name[8..], nt_res & NT_LOWER_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+    name
+}
+
+fn lfn_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+/// Whether `c` is allowed in short names, besides uppercase letters and
+/// digits.
+fn is_short_special_char(c: u8) -> bool {
+    b"$%'-_@~`!(){}^#&".contains(&c)
+}
+
+/// Returns the short name and the lowercase flags if `name` can be stored in
+/// a short entry only.
+fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || ext.contains('.') {
+        return None;
+    }
+    let mut nt_res = 0;
+    for (part, lower_flag) in [(base, NT_LOWER_BASE), (ext, NT_LOWER_EXT)] {
+        let bytes = part.as_bytes();
+        if !bytes
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || is_short_special_char(c))
+        {
+            return None;
+        }
+        let has_upper = bytes.iter().any(u8::is_ascii_uppercase);
+        let has_lower = bytes.iter().any(u8::is_ascii_lowercase);
+        match (has_upper, has_lower) {
+            (true, true) => return None,
+            (false, true) => nt_res |= lower_flag,
+            _ => {}
+        }
+    }
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+    short_name[8..8 + ext.len()].copy_from_slice(ext.to_ascii_uppercase().as_bytes());
+    Some((short_name, nt_res))
+}
+
+/// Generates a unique short name `BASIS~N.EXT` for the long name `name`.
+fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> SysResult<[u8; 11]> {
+    let to_short_chars = |s: &str, max_len: usize| -> Vec<u8> {
+        s.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| match c.to_ascii_uppercase() {
+                c if c.is_ascii_alphanumeric()
+                    || (c.is_ascii() && is_short_special_char(c as u8)) =>
+                {
+                    c as u8
+                }
+                _ => b'_',
+            })
+            .take(max_len)
+            .collect()
+    };
+    let name = name.trim_start_matches('.');
+    let (base, ext) = match name.rfind('.') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, ""),
+    };
+    let mut base = to_short_chars(base, 8);
+    if base.is_empty() {
+        base.push(b'_');
+    }
+    let ext = to_short_chars(ext, 3);
+
+    let mut short_name = [b' '; 11];
+    short_name[8..8 + ext.len()].copy_from_slice(&ext);
+    for n in 1..1_000_000 {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        short_name[..8].fill(b' ');
+        short_name[..keep].copy_from_slice(&base[..keep]);
+        short_name[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        if !existing.contains(&short_name) {
+            return Ok(short_name);
+        }
+    }
+    Err(Errno::EEXIST)
+}
+
+/// Checks whether `name` is a valid long name for new entries.
+pub(super) fn validate_name(name: &str) -> SysResult<()> {
+    if name.encode_utf16().count() > MAX_NAME_LEN {
+        return Err(Errno::ENAMETOOLONG);
+    }
+    let invalid_char = |c: char| c < ' ' || "\"*/:<>?\\|".contains(c);
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || name.contains(invalid_char)
+    {
+        return Err(Errno::EINVAL);
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn realtime_secs() -> u64 {
+    crate::timer::current_realtime().as_secs()
+}
+
+/// Unit tests run at the Epoch.
+#[cfg(test)]
+fn realtime_secs() -> u64 {
+    0
+}
+
+/// Returns the current time and date in the FAT format.
+fn fat_timestamp() -> (u16, u16) {
+    let now = DateTime::from_unix_secs(realtime_secs());
+    if now.year < 1980 {
+        return (0, 1 << 5 | 1); // 1980-01-01
+    }
+    let year = (now.year - 1980).min(127) as u16;
+    let date = year << 9 | (now.month as u16) << 5 | now.day as u16;
+    let time = (now.hour as u16) << 11 | (now.minute as u16) << 5 | (now.second / 2) as u16;
+    (time, date)
+}
+
+fn new_short_entry(
+    short_name: &[u8; 11],
+    nt_res: u8,
+    attr: u8,
+    first_cluster: u32,
+) -> [u8; ENTRY_SIZE] {
+    let (time, date) = fat_timestamp();
+    let mut entry = [0; ENTRY_SIZE];
+    entry[..11].copy_from_slice(short_name);
+    entry[11] = attr;
+    entry[12] = nt_res;
+    entry[14..16].copy_from_slice(&time.to_le_bytes());
+    entry[16..18].copy_from_slice(&date.to_le_bytes());
+    entry[18..20].copy_from_slice(&date.to_le_bytes());
+    entry[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+    entry[22..24].copy_from_slice(&time.to_le_bytes());
+    entry[24..26].copy_from_slice(&date.to_le_bytes());
+    entry[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+    entry
+}
+
+/// Returns the LFN entries of `name` in the order on the disk, i.e. the last
+/// part of the name first.
+fn new_lfn_entries(name: &str, checksum: u8) -> Vec<[u8; ENTRY_SIZE]> {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    let count = (chars.len() + LFN_CHARS - 1) / LFN_CHARS;
+    // terminated by NUL and padded with 0xffff
+    if chars.len() % LFN_CHARS != 0 {
+        chars.push(0);
+    }
+    chars.resize(count * LFN_CHARS, 0xffff);
+    (1..=count)
+        .rev()
+        .map(|ord| {
+            let mut entry = [0; ENTRY_SIZE];
+            entry[0] = ord as u8 | if ord == count { LFN_LAST } else { 0 };
+            entry[11] = ATTR_LFN;
+            entry[13] = checksum;
+            let part = &chars[(ord - 1) * LFN_CHARS..ord * LFN_CHARS];
+            for (&c, &off) in part.iter().zip(LFN_CHAR_OFFSETS.iter()) {
+                entry[off..off + 2].copy_from_slice(&c.to_le_bytes());
+            }
+            entry
+        })
+        .collect()
+}
+
+impl FatInner {
+    fn pos_to_sector(&self, pos: u64) -> (u64, usize) {
+        let bps = self.bytes_per_sector as u64;
+        (pos / bps, (pos % bps) as usize)
+    }
+
+    /// Calls `f` with the position and the content of each entry slot of the
+    /// directory, until it returns `false`.
+    fn for_each_slot(
+        &mut self,
+        first_cluster: u32,
+        mut f: impl FnMut(u64, &[u8]) -> bool,
+    ) -> SysResult<()> {
+        let bps = self.bytes_per_sector as u64;
+        let mut cluster = Some(first_cluster);
+        let mut visited = 0;
+        while let Some(c) = cluster {
+            let start = self.cluster_sector(c);
+            for sector in start..start + self.sectors_per_cluster as u64 {
+                let go_on = self.cache.read(sector, |data| {
+                    data.chunks(ENTRY_SIZE)
+                        .enumerate()
+                        .all(|(i, raw)| f(sector * bps + (i * ENTRY_SIZE) as u64, raw))
+                })?;
+                if !go_on {
+                    return Ok(());
+                }
+            }
+            cluster = self.walk_cluster(c, &mut visited)?;
+        }
+        Ok(())
+    }
+
+    /// Reads all entries of the directory, including `.` and `..`.
+    pub(super) fn read_dir_entries(&mut self, first_cluster: u32) -> SysResult<Vec<DirEntryInfo>> {
+        let mut entries = Vec::new();
+        let mut lfn: Option<LfnState> = None;
+        self.for_each_slot(first_cluster, |pos, raw| {
+            if raw[0] == ENTRY_END {
+                return false;
+            }
+            let attr = raw[11];
+            if raw[0] == ENTRY_FREE {
+                lfn = None;
+            } else if attr & 0x3f == ATTR_LFN {
+                let ord = raw[0] & !LFN_LAST;
+                if raw[0] & LFN_LAST != 0 {
+                    lfn = Some(LfnState {
+                        chars: vec![0xffff; ord as usize * LFN_CHARS],
+                        next_ord: ord,
+                        checksum: raw[13],
+                        slots: Vec::new(),
+                    });
+                }
+                match &mut lfn {
+                    Some(state)
+                        if ord != 0 && ord == state.next_ord && raw[13] == state.checksum =>
+                    {
+                        let start = (ord as usize - 1) * LFN_CHARS;
+                        for (i, &off) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                            state.chars[start + i] = le16(raw, off);
+                        }
+                        state.next_ord -= 1;
+                        state.slots.push(pos);
+                    }
+                    _ => lfn = None,
+                }
+            } else if attr & ATTR_VOLUME_ID != 0 {
+                lfn = None;
+            } else {
+                let short_name: [u8; 11] = raw[..11].try_into().unwrap();
+                let (name, mut slots) = match lfn.take() {
+                    Some(state)
+                        if state.next_ord == 0 && state.checksum == lfn_checksum(&short_name) =>
+                    {
+                        let len = state
+                            .chars
+                            .iter()
+                            .position(|&c| c == 0)
+                            .unwrap_or(state.chars.len());
+                        let name = char::decode_utf16(state.chars[..len].iter().copied())
+                            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                            .collect();
+                        (name, state.slots)
+                    }
+                    _ => (short_name_to_string(&short_name, raw[12]), Vec::new()),
+                };
+                slots.push(pos);
+                entries.push(DirEntryInfo {
+                    name,
+                    short_name,
+                    attr,
+                    first_cluster: (le16(raw, 20) as u32) << 16 | le16(raw, 26) as u32,
+                    size: le32(raw, 28),
+                    slots,
+                });
+            }
+            true
+        })?;
+        Ok(entries)
+    }
+
+    /// Finds the entry named `name` in the directory, ignoring the case.
+    pub(super) fn find_entry(
+        &mut self,
+        first_cluster: u32,
+        name: &str,
+    ) -> SysResult<Option<DirEntryInfo>> {
+        Ok(self
+            .read_dir_entries(first_cluster)?
+            .into_iter()
+            .find(|e| !e.is_dot() && e.matches(name)))
+    }
+
+    /// Returns the positions of `count` consecutive free slots in the
+    /// directory, which is extended if there are not enough free slots.
+    fn find_free_slots(&mut self, first_cluster: u32, count: usize) -> SysResult<Vec<u64>> {
+        loop {
+            let mut run = Vec::new();
+            let mut total = 0;
+            self.for_each_slot(first_cluster, |pos, raw| {
+                total += 1;
+                if raw[0] == ENTRY_END || raw[0] == ENTRY_FREE {
+                    run.push(pos);
+                } else {
+                    run.clear();
+                }
+                run.len() < count
+            })?;
+            if run.len() == count {
+                return Ok(run);
+            }
+            if total + self.cluster_size() / ENTRY_SIZE > MAX_DIR_ENTRIES {
+                return Err(Errno::ENOSPC);
+            }
+            let last = self.last_cluster(first_cluster)?;
+            self.alloc_cluster(Some(last), true)?;
+        }
+    }
+
+    fn write_slot(&mut self, pos: u64, entry: &[u8]) -> SysResult<()> {
+        let (sector, offset) = self.pos_to_sector(pos);
+        self.cache.write_at(sector, offset, entry)
+    }
+
+    /// Adds an entry named `name` to the directory, returns the position of
+    /// its short entry. The name must be valid and not exist.
+    pub(super) fn add_entry(
+        &mut self,
+        dir_cluster: u32,
+        name: &str,
+        attr: u8,
+        first_cluster: u32,
+    ) -> SysResult<u64> {
+        let entries = match exact_short_name(name) {
+            Some((short_name, nt_res)) => {
+                vec![new_short_entry(&short_name, nt_res, attr, first_cluster)]
+            }
+            None => {
+                let existing: Vec<_> = self
+                    .read_dir_entries(dir_cluster)?
+                    .iter()
+                    .map(|e| e.short_name)
+                    .collect();
+                let short_name = generate_short_name(name, &existing)?;
+                let mut entries = new_lfn_entries(name, lfn_checksum(&short_name));
+                entries.push(new_short_entry(&short_name, 0, attr, first_cluster));
+                entries
+            }
+        };
+        let slots = self.find_free_slots(dir_cluster, entries.len())?;
+        for (&pos, entry) in slots.iter().zip(entries.iter()) {
+            self.write_slot(pos, entry)?;
+        }
+        Ok(*slots.last().unwrap())
+    }
+
+    /// Marks the slots of the entry deleted.
+    pub(super) fn remove_entry(&mut self, entry: &DirEntryInfo) -> SysResult<()> {
+        for &pos in &entry.slots {
+            self.write_slot(pos, &[ENTRY_FREE])?;
+        }
+        Ok(())
+    }
+
+    /// Updates the first cluster and the size of the entry at `pos`, and the
+    /// modification time if `modified` is true.
+    pub(super) fn update_entry(
+        &mut self,
+        pos: u64,
+        first_cluster: u32,
+        size: u32,
+        modified: bool,
+    ) -> SysResult<()> {
+        if pos == ROOT_POS {
+            return Ok(());
+        }
+        let (sector, offset) = self.pos_to_sector(pos);
+        let (time, date) = fat_timestamp();
+        self.cache.modify(sector, |data| {
+            let entry = &mut data[offset..offset + ENTRY_SIZE];
+            entry[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+            entry[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+            entry[28..32].copy_from_slice(&size.to_le_bytes());
+            if modified {
+                entry[11] |= ATTR_ARCHIVE;
+                entry[18..20].copy_from_slice(&date.to_le_bytes());
+                entry[22..24].copy_from_slice(&time.to_le_bytes());
+                entry[24..26].copy_from_slice(&date.to_le_bytes());
+            }
+        })
+    }
+
+    /// Writes the `.` and `..` entries to the new (zeroed) directory at
+    /// `cluster`.
+    pub(super) fn init_dir(&mut self, cluster: u32, parent_cluster: u32) -> SysResult<()> {
+        // `..` refers to the root directory by cluster 0
+        let parent_cluster = if parent_cluster == self.root_cluster {
+            0
+        } else {
+            parent_cluster
+        };
+        let pos = self.cluster_sector(cluster) * self.bytes_per_sector as u64;
+        let dot = new_short_entry(&DOT_NAME, 0, ATTR_DIRECTORY, cluster);
+        let dotdot = new_short_entry(&DOTDOT_NAME, 0, ATTR_DIRECTORY, parent_cluster);
+        self.write_slot(pos, &dot)?;
+        self.write_slot(pos + ENTRY_SIZE as u64, &dotdot)
+    }
+}
@@ -0,0 +1,260 @@
+use alloc::sync::Arc;
+
+use super::dir::{validate_name, DirEntryInfo, ATTR_ARCHIVE, ATTR_DIRECTORY};
+use super::{ClusterCursor, Fat32FileSystem, FatInner};
+use crate::errno::{Errno, SysResult};
+use crate::fs::vfs::{DirEntry, Inode, InodeType};
+use crate::sync::Mutex;
+
+/// The largest file size, the size field of entries is 32 bits.
+const MAX_FILE_SIZE: u64 = u32::MAX as u64;
+
+struct InodeState {
+    /// 0 if no cluster is allocated.
+    first_cluster: u32,
+    /// Always 0 for directories.
+    size: u32,
+    /// The last cluster accessed, so that sequential reads and writes do not
+    /// walk the chain from the start.
+    cursor: ClusterCursor,
+}
+
+/// A file or a directory, identified by the position of its directory entry.
+pub struct FatInode {
+    fs: Arc<Fat32FileSystem>,
+    pos: u64,
+    kind: InodeType,
+    /// Only changed with the filesystem locked, but not held across I/O.
+    state: Mutex<InodeState>,
+}
+
+impl FatInode {
+    pub(super) fn new(
+        fs: Arc<Fat32FileSystem>,
+        pos: u64,
+        kind: InodeType,
+        first_cluster: u32,
+        size: u32,
+    ) -> Self {
+        Self {
+            fs,
+            pos,
+            kind,
+            state: Mutex::new(InodeState {
+                first_cluster,
+                size,
+                cursor: ClusterCursor::default(),
+            }),
+        }
+    }
+
+    fn first_cluster(&self) -> u32 {
+        self.state.lock().first_cluster
+    }
+
+    fn check_dir(&self) -> SysResult<()> {
+        match self.kind {
+            InodeType::Dir => Ok(()),
+            InodeType::File => Err(Errno::ENOTDIR),
+        }
+    }
+
+    fn check_file(&self) -> SysResult<()> {
+        match self.kind {
+            InodeType::File => Ok(()),
+            InodeType::Dir => Err(Errno::EISDIR),
+        }
+    }
+
+    fn entry_inode(&self, entry: &DirEntryInfo) -> Arc<FatInode> {
+        let kind = if entry.is_dir() {
+            InodeType::Dir
+        } else {
+            InodeType::File
+        };
+        let size = if entry.is_dir() { 0 } else { entry.size };
+        self.fs.inode(entry.pos(), kind, entry.first_cluster, size)
+    }
+
+    /// Changes the file size to `size`, newly added bytes are zeros.
+    fn resize(&self, inner: &mut FatInner, size: u64) -> SysResult<()> {
+        if size > MAX_FILE_SIZE {
+            return Err(Errno::EFBIG);
+        }
+        let (mut first_cluster, old_size, mut cursor) = {
+            let state = self.state.lock();
+            (state.first_cluster, state.size as u64, state.cursor)
+        };
+        let result = inner.resize_chain(&mut first_cluster, size, &mut cursor);
+        // keep the clusters allocated before the error
+        let mut state = self.state.lock();
+        state.first_cluster = first_cluster;
+        state.cursor = cursor;
+        drop(state);
+        result?;
+        // new clusters are zeroed, but the tail of the last cluster is not
+        if size > old_size {
+            let cluster_size = inner.cluster_size() as u64;
+            let tail = (cluster_size - old_size % cluster_size) % cluster_size;
+            let len = tail.min(size - old_size);
+            if len > 0 {
+                inner.zero_data(first_cluster, &mut cursor, old_size, len as usize)?;
+                self.state.lock().cursor = cursor;
+            }
+        }
+        self.state.lock().size = size as u32;
+        inner.update_entry(self.pos, first_cluster, size as u32, true)
+    }
+}
+
+impl Inode for FatInode {
+    fn kind(&self) -> InodeType {
+        self.kind
+    }
+
+    fn ino(&self) -> u64 {
+        self.pos
+    }
+
+    fn size(&self) -> u64 {
+        self.state.lock().size as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SysResult<usize> {
+        self.check_file()?;
+        let mut inner = self.fs.inner.lock();
+        let (first_cluster, size, mut cursor) = {
+            let state = self.state.lock();
+            (state.first_cluster, state.size as u64, state.cursor)
+        };
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let result = inner.read_data(first_cluster, &mut cursor, offset, &mut buf[..len]);
+        self.state.lock().cursor = cursor;
+        result?;
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> SysResult<usize> {
+        self.check_file()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = offset.checked_add(buf.len() as u64).ok_or(Errno::EFBIG)?;
+        let mut inner = self.fs.inner.lock();
+        if end > self.size() {
+            self.resize(&mut inner, end)?;
+        } else {
+            let size = self.size() as u32;
+            inner.update_entry(self.pos, self.first_cluster(), size, true)?;
+        }
+        let (first_cluster, mut cursor) = {
+            let state = self.state.lock();
+            (state.first_cluster, state.cursor)
+        };
+        let result = inner.write_data(first_cluster, &mut cursor, offset, buf);
+        self.state.lock().cursor = cursor;
+        result?;
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: u64) -> SysResult<()> {
+        self.check_file()?;
+        let mut inner = self.fs.inner.lock();
+        self.resize(&mut inner, size)
+    }
+
+    fn lookup(&self, name: &str) -> SysResult<Arc<dyn Inode>> {
+        self.check_dir()?;
+        let mut inner = self.fs.inner.lock();
+        match inner.find_entry(self.first_cluster(), name)? {
+            Some(entry) => Ok(self.entry_inode(&entry)),
+            None => Err(Errno::ENOENT),
+        }
+    }
+
+    fn create(&self, name: &str, kind: InodeType) -> SysResult<Arc<dyn Inode>> {
+        self.check_dir()?;
+        validate_name(name)?;
+        let mut inner = self.fs.inner.lock();
+        let dir_cluster = self.first_cluster();
+        if inner.find_entry(dir_cluster, name)?.is_some() {
+            return Err(Errno::EEXIST);
+        }
+        let (attr, first_cluster) = match kind {
+            InodeType::File => (ATTR_ARCHIVE, 0),
+            InodeType::Dir => {
+                let cluster = inner.alloc_cluster(None, true)?;
+                if let Err(e) = inner.init_dir(cluster, dir_cluster) {
+                    inner.free_chain(cluster)?;
+                    return Err(e);
+                }
+                (ATTR_DIRECTORY, cluster)
+            }
+        };
+        match inner.add_entry(dir_cluster, name, attr, first_cluster) {
+            Ok(pos) => Ok(self.fs.inode(pos, kind, first_cluster, 0)),
+            Err(e) => {
+                if first_cluster != 0 {
+                    inner.free_chain(first_cluster)?;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn unlink(&self, name: &str, is_dir: bool) -> SysResult<()> {
+        self.check_dir()?;
+        let mut inner = self.fs.inner.lock();
+        let entry = inner
+            .find_entry(self.first_cluster(), name)?
+            .ok_or(Errno::ENOENT)?;
+        match (entry.is_dir(), is_dir) {
+            (true, false) => return Err(Errno::EISDIR),
+            (false, true) => return Err(Errno::ENOTDIR),
+            _ => {}
+        }
+        // the clusters can not be freed while the file is still open
+        if self.fs.is_inode_busy(entry.pos()) {
+            return Err(Errno::EBUSY);
+        }
+        if entry.is_dir()
+            && inner
+                .read_dir_entries(entry.first_cluster)?
+                .iter()
+                .any(|e| !e.is_dot())
+        {
+            return Err(Errno::ENOTEMPTY);
+        }
+        inner.remove_entry(&entry)?;
+        if entry.first_cluster != 0 {
+            inner.free_chain(entry.first_cluster)?;
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, index: usize) -> SysResult<Option<DirEntry>> {
+        self.check_dir()?;
+        let mut inner = self.fs.inner.lock();
+        let entry = inner
+            .read_dir_entries(self.first_cluster())?
+            .into_iter()
+            .filter(|e| !e.is_dot())
+            .nth(index);
+        Ok(entry.map(|e| DirEntry {
+            ino: e.pos(),
+            kind: if e.is_dir() {
+                InodeType::Dir
+            } else {
+                InodeType::File
+            },
+            name: e.name,
+        }))
+    }
+
+    fn sync(&self) -> SysResult<()> {
+        self.fs.inner.lock().sync()
+    }
+}
@@ -0,0 +1,833 @@
+//! The FAT32 filesystem, with long file names.
+//!
+//! All operations are serialized by a lock of the whole filesystem, which is
+//! held across the block I/O.
+
+mod dir;
+mod inode;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+
+use self::inode::FatInode;
+use super::block_cache::BlockCache;
+use super::vfs::{FileSystem, Inode, InodeType};
+use crate::drivers::block::BlockDevice;
+use crate::errno::{Errno, SysResult};
+use crate::sync::{Mutex, SleepMutex};
+
+/// The number of sectors kept in the block cache.
+const CACHE_SECTORS: usize = 512;
+
+/// Values of FAT entries.
+const FAT_FREE: u32 = 0;
+const FAT_EOC: u32 = 0x0fff_ffff;
+/// Entries not less than it mark the end of a cluster chain.
+const FAT_EOC_MIN: u32 = 0x0fff_fff8;
+/// Only the low 28 bits of FAT entries are used.
+const FAT_ENTRY_MASK: u32 = 0x0fff_ffff;
+
+const FS_INFO_LEAD_SIG: u32 = 0x4161_5252;
+const FS_INFO_STRUC_SIG: u32 = 0x6141_7272;
+/// The free count or the next free cluster is unknown.
+const FS_INFO_UNKNOWN: u32 = 0xffff_ffff;
+
+/// The key of the root directory in the inode table, as it has no directory
+/// entry. Sector 0 is always the boot sector, so it is not a valid position of
+/// directory entries.
+const ROOT_POS: u64 = 0;
+
+/// The fields of the boot sector (BPB) used by the filesystem.
+struct BootSector {
+    bytes_per_sector: usize,
+    sectors_per_cluster: u32,
+    reserved_sectors: u32,
+    num_fats: u32,
+    total_sectors: u32,
+    fat_size: u32,
+    ext_flags: u16,
+    root_cluster: u32,
+    fs_info_sector: u32,
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+impl BootSector {
+    /// Parses the boot sector, returns `EINVAL` if it is not a FAT32 volume.
+    fn parse(data: &[u8]) -> SysResult<Self> {
+        if data[510] != 0x55 || data[511] != 0xaa {
+            return Err(Errno::EINVAL);
+        }
+        let bpb = Self {
+            bytes_per_sector: le16(data, 11) as usize,
+            sectors_per_cluster: data[13] as u32,
+            reserved_sectors: le16(data, 14) as u32,
+            num_fats: data[16] as u32,
+            total_sectors: match le16(data, 19) {
+                0 => le32(data, 32),
+                n => n as u32,
+            },
+            fat_size: le32(data, 36),
+            ext_flags: le16(data, 40),
+            root_cluster: le32(data, 44),
+            fs_info_sector: le16(data, 48) as u32,
+        };
+        // FAT12/16 volumes have fixed root directory entries and a 16-bit FAT
+        // size.
+        let root_entry_count = le16(data, 17);
+        let fat_size_16 = le16(data, 22);
+        if root_entry_count != 0 || fat_size_16 != 0 || bpb.fat_size == 0 {
+            return Err(Errno::EINVAL);
+        }
+        if !matches!(bpb.bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || !bpb.sectors_per_cluster.is_power_of_two()
+            || bpb.reserved_sectors == 0
+            || bpb.num_fats == 0
+        {
+            return Err(Errno::EINVAL);
+        }
+        Ok(bpb)
+    }
+}
+
+/// The last visited cluster of a chain, so that sequential accesses continue
+/// from it instead of walking the chain from the start.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct ClusterCursor {
+    /// The index of `cluster` in the chain.
+    index: usize,
+    /// 0 if no cluster is visited.
+    cluster: u32,
+}
+
+/// The state of the filesystem protected by the filesystem lock.
+struct FatInner {
+    cache: BlockCache,
+    bytes_per_sector: usize,
+    sectors_per_cluster: u32,
+    fat_start: u64,
+    fat_size: u32,
+    num_fats: u32,
+    /// The FAT that is read, and the only one written if mirroring is
+    /// disabled.
+    active_fat: u32,
+    mirroring: bool,
+    data_start: u64,
+    /// Valid clusters are `2..num_clusters + 2`.
+    num_clusters: u32,
+    root_cluster: u32,
+    fs_info_sector: Option<u64>,
+    free_count: Option<u32>,
+    /// Where to start searching for free clusters.
+    next_free: u32,
+}
+
+pub struct Fat32FileSystem {
+    inner: SleepMutex<FatInner>,
+    /// Inodes in use, indexed by the positions of their directory entries.
+    inodes: Mutex<BTreeMap<u64, Weak<FatInode>>>,
+    self_ref: Weak<Self>,
+}
+
+impl FatInner {
+    fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * self.sectors_per_cluster as usize
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - 2) as u64 * self.sectors_per_cluster as u64
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.num_clusters
+    }
+
+    /// Returns the sector and the offset of the FAT entry of `cluster` in the
+    /// FAT `fat_index`.
+    fn fat_entry_pos(&self, cluster: u32, fat_index: u32) -> (u64, usize) {
+        let offset = cluster as usize * 4;
+        let sector = self.fat_start
+            + fat_index as u64 * self.fat_size as u64
+            + (offset / self.bytes_per_sector) as u64;
+        (sector, offset % self.bytes_per_sector)
+    }
+
+    fn read_fat(&mut self, cluster: u32) -> SysResult<u32> {
+        let (sector, offset) = self.fat_entry_pos(cluster, self.active_fat);
+        let value = self.cache.read(sector, |data| le32(data, offset))?;
+        Ok(value & FAT_ENTRY_MASK)
+    }
+
+    fn write_fat(&mut self, cluster: u32, value: u32) -> SysResult<()> {
+        let fats = if self.mirroring {
+            0..self.num_fats
+        } else {
+            self.active_fat..self.active_fat + 1
+        };
+        for fat_index in fats {
+            let (sector, offset) = self.fat_entry_pos(cluster, fat_index);
+            self.cache.modify(sector, |data| {
+                // the high 4 bits are reserved
+                let old = le32(data, offset);
+                let new = (old & !FAT_ENTRY_MASK) | (value & FAT_ENTRY_MASK);
+                data[offset..offset + 4].copy_from_slice(&new.to_le_bytes());
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster after `cluster` in the chain, or `None` if it is
+    /// the last one.
+    fn next_cluster(&mut self, cluster: u32) -> SysResult<Option<u32>> {
+        match self.read_fat(cluster)? {
+            next if next >= FAT_EOC_MIN => Ok(None),
+            next if self.is_valid_cluster(next) => Ok(Some(next)),
+            next => {
+                warn!("FAT32: invalid cluster {:#x} after {:#x}", next, cluster);
+                Err(Errno::EIO)
+            }
+        }
+    }
+
+    /// Like [`FatInner::next_cluster`], for walking a chain. `*visited` counts
+    /// the clusters visited, the walk fails with `EIO` if the chain is longer
+    /// than the number of clusters, i.e. it is corrupted into a loop.
+    fn walk_cluster(&mut self, cluster: u32, visited: &mut u32) -> SysResult<Option<u32>> {
+        *visited += 1;
+        if *visited > self.num_clusters {
+            warn!("FAT32: the cluster chain through {:#x} loops", cluster);
+            return Err(Errno::EIO);
+        }
+        self.next_cluster(cluster)
+    }
+
+    /// Returns the `index`-th cluster of the chain starting at `start`. The
+    /// walk continues from `cursor` if it is not after the cluster, and
+    /// `cursor` is moved to the cluster.
+    fn nth_cluster(
+        &mut self,
+        start: u32,
+        index: usize,
+        cursor: &mut ClusterCursor,
+    ) -> SysResult<Option<u32>> {
+        let (mut i, mut cluster) = if cursor.cluster != 0 && cursor.index <= index {
+            (cursor.index, cursor.cluster)
+        } else {
+            (0, start)
+        };
+        let mut visited = 0;
+        while i < index {
+            match self.walk_cluster(cluster, &mut visited)? {
+                Some(next) => cluster = next,
+                None => return Ok(None),
+            }
+            i += 1;
+        }
+        *cursor = ClusterCursor { index, cluster };
+        Ok(Some(cluster))
+    }
+
+    /// Returns the last cluster of the chain starting at `start`.
+    fn last_cluster(&mut self, start: u32) -> SysResult<u32> {
+        let mut cluster = start;
+        let mut visited = 0;
+        while let Some(next) = self.walk_cluster(cluster, &mut visited)? {
+            cluster = next;
+        }
+        Ok(cluster)
+    }
+
+    /// Allocates a cluster and appends it to the chain ending at `prev`. The
+    /// cluster is filled with zeros if `zero` is true.
+    fn alloc_cluster(&mut self, prev: Option<u32>, zero: bool) -> SysResult<u32> {
+        let mut cluster = self.next_free;
+        let mut found = None;
+        for _ in 0..self.num_clusters {
+            if !self.is_valid_cluster(cluster) {
+                cluster = 2;
+            }
+            if self.read_fat(cluster)? == FAT_FREE {
+                found = Some(cluster);
+                break;
+            }
+            cluster += 1;
+        }
+        let cluster = found.ok_or(Errno::ENOSPC)?;
+        self.write_fat(cluster, FAT_EOC)?;
+        if let Some(prev) = prev {
+            self.write_fat(prev, cluster)?;
+        }
+        if zero {
+            let sector = self.cluster_sector(cluster);
+            for i in 0..self.sectors_per_cluster as u64 {
+                self.cache.zero(sector + i)?;
+            }
+        }
+        self.next_free = cluster + 1;
+        self.free_count = self.free_count.map(|n| n.saturating_sub(1));
+        Ok(cluster)
+    }
+
+    /// Frees all clusters of the chain starting at `start`.
+    fn free_chain(&mut self, start: u32) -> SysResult<()> {
+        let mut cluster = Some(start);
+        let mut visited = 0;
+        while let Some(c) = cluster {
+            cluster = self.walk_cluster(c, &mut visited)?;
+            self.write_fat(c, FAT_FREE)?;
+            self.free_count = self.free_count.map(|n| n + 1);
+        }
+        Ok(())
+    }
+
+    /// Makes the chain starting at `*first_cluster` hold `size` bytes, by
+    /// allocating or freeing clusters at the end. `*first_cluster` is 0 for
+    /// an empty chain. New clusters are filled with zeros. The walk continues
+    /// from `cursor` if it is in the new chain, and `cursor` is moved to the
+    /// last cluster.
+    fn resize_chain(
+        &mut self,
+        first_cluster: &mut u32,
+        size: u64,
+        cursor: &mut ClusterCursor,
+    ) -> SysResult<()> {
+        let cluster_size = self.cluster_size() as u64;
+        let needed = ((size + cluster_size - 1) / cluster_size) as usize;
+        if needed == 0 {
+            *cursor = ClusterCursor::default();
+            if *first_cluster != 0 {
+                self.free_chain(*first_cluster)?;
+                *first_cluster = 0;
+            }
+            return Ok(());
+        }
+        if *first_cluster == 0 {
+            *first_cluster = self.alloc_cluster(None, true)?;
+            *cursor = ClusterCursor::default();
+        }
+        let (mut index, mut cluster) = if cursor.cluster != 0 && cursor.index < needed {
+            (cursor.index, cursor.cluster)
+        } else {
+            (0, *first_cluster)
+        };
+        let mut visited = 0;
+        while index + 1 < needed {
+            cluster = match self.walk_cluster(cluster, &mut visited)? {
+                Some(next) => next,
+                None => self.alloc_cluster(Some(cluster), true)?,
+            };
+            index += 1;
+        }
+        *cursor = ClusterCursor { index, cluster };
+        if let Some(next) = self.next_cluster(cluster)? {
+            self.write_fat(cluster, FAT_EOC)?;
+            self.free_chain(next)?;
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the sector and the offset in the sector of each part of
+    /// the byte range `offset..offset + len` of the cluster chain starting at
+    /// `start`, and the range of the part. The chain must cover the range.
+    /// `cursor` is used and moved as in [`FatInner::nth_cluster`].
+    fn for_each_part(
+        &mut self,
+        start: u32,
+        cursor: &mut ClusterCursor,
+        offset: u64,
+        len: usize,
+        mut f: impl FnMut(&mut Self, u64, usize, core::ops::Range<usize>) -> SysResult<()>,
+    ) -> SysResult<()> {
+        let cluster_size = self.cluster_size();
+        let bps = self.bytes_per_sector;
+        let mut index = (offset / cluster_size as u64) as usize;
+        let mut cluster = self.nth_cluster(start, index, cursor)?.ok_or(Errno::EIO)?;
+        let mut cluster_offset = (offset % cluster_size as u64) as usize;
+        let mut done = 0;
+        while done < len {
+            let sector = self.cluster_sector(cluster) + (cluster_offset / bps) as u64;
+            let sector_offset = cluster_offset % bps;
+            let part_len = (bps - sector_offset).min(len - done);
+            f(self, sector, sector_offset, done..done + part_len)?;
+            done += part_len;
+            cluster_offset += part_len;
+            if cluster_offset == cluster_size && done < len {
+                cluster = self.next_cluster(cluster)?.ok_or(Errno::EIO)?;
+                cluster_offset = 0;
+                index += 1;
+                *cursor = ClusterCursor { index, cluster };
+            }
+        }
+        Ok(())
+    }
+
+    fn read_data(
+        &mut self,
+        start: u32,
+        cursor: &mut ClusterCursor,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> SysResult<()> {
+        self.for_each_part(
+            start,
+            cursor,
+            offset,
+            buf.len(),
+            |inner, sector, off, range| inner.cache.read_at(sector, off, &mut buf[range]),
+        )
+    }
+
+    fn write_data(
+        &mut self,
+        start: u32,
+        cursor: &mut ClusterCursor,
+        offset: u64,
+        buf: &[u8],
+    ) -> SysResult<()> {
+        self.for_each_part(
+            start,
+            cursor,
+            offset,
+            buf.len(),
+            |inner, sector, off, range| inner.cache.write_at(sector, off, &buf[range]),
+        )
+    }
+
+    fn zero_data(
+        &mut self,
+        start: u32,
+        cursor: &mut ClusterCursor,
+        offset: u64,
+        len: usize,
+    ) -> SysResult<()> {
+        let zeros = vec![0; self.bytes_per_sector];
+        self.for_each_part(start, cursor, offset, len, |inner, sector, off, range| {
+            inner.cache.write_at(sector, off, &zeros[..range.len()])
+        })
+    }
+
+    fn sync(&mut self) -> SysResult<()> {
+        if let Some(sector) = self.fs_info_sector {
+            let free_count = self.free_count.unwrap_or(FS_INFO_UNKNOWN);
+            let next_free = self.next_free;
+            self.cache.modify(sector, |data| {
+                data[488..492].copy_from_slice(&free_count.to_le_bytes());
+                data[492..496].copy_from_slice(&next_free.to_le_bytes());
+            })?;
+        }
+        self.cache.sync()
+    }
+}
+
+impl Fat32FileSystem {
+    /// Opens the FAT32 volume on `dev`, returns `EINVAL` if it is not a FAT32
+    /// volume.
+    pub fn open(dev: Arc<dyn BlockDevice>) -> SysResult<Arc<Self>> {
+        let mut sector0 = vec![0; dev.block_size().max(512)];
+        dev.read_blocks(0, &mut sector0)?;
+        let bpb = BootSector::parse(&sector0)?;
+        let device_sectors =
+            dev.num_blocks() * dev.block_size() as u64 / bpb.bytes_per_sector as u64;
+        if bpb.total_sectors as u64 > device_sectors {
+            warn!("FAT32: the volume is larger than {}", dev.name());
+            return Err(Errno::EINVAL);
+        }
+
+        let fat_start = bpb.reserved_sectors as u64;
+        let data_start = fat_start + bpb.num_fats as u64 * bpb.fat_size as u64;
+        let data_sectors = (bpb.total_sectors as u64).saturating_sub(data_start);
+        let fat_entries = bpb.fat_size as u64 * bpb.bytes_per_sector as u64 / 4;
+        let num_clusters =
+            (data_sectors / bpb.sectors_per_cluster as u64).min(fat_entries - 2) as u32;
+        let mirroring = bpb.ext_flags & 0x80 == 0;
+        let mut inner = FatInner {
+            cache: BlockCache::new(dev, bpb.bytes_per_sector, CACHE_SECTORS)?,
+            bytes_per_sector: bpb.bytes_per_sector,
+            sectors_per_cluster: bpb.sectors_per_cluster,
+            fat_start,
+            fat_size: bpb.fat_size,
+            num_fats: bpb.num_fats,
+            active_fat: if mirroring {
+                0
+            } else {
+                (bpb.ext_flags & 0xf) as u32
+            },
+            mirroring,
+            data_start,
+            num_clusters,
+            root_cluster: bpb.root_cluster,
+            fs_info_sector: None,
+            free_count: None,
+            next_free: 2,
+        };
+        if !inner.is_valid_cluster(inner.root_cluster) || inner.active_fat >= inner.num_fats {
+            return Err(Errno::EINVAL);
+        }
+
+        let fs_info_sector = bpb.fs_info_sector as u64;
+        if fs_info_sector != 0 && fs_info_sector < fat_start {
+            let (lead_sig, struc_sig, free_count, next_free) =
+                inner.cache.read(fs_info_sector, |data| {
+                    (
+                        le32(data, 0),
+                        le32(data, 484),
+                        le32(data, 488),
+                        le32(data, 492),
+                    )
+                })?;
+            if lead_sig == FS_INFO_LEAD_SIG && struc_sig == FS_INFO_STRUC_SIG {
+                inner.fs_info_sector = Some(fs_info_sector);
+                if free_count <= num_clusters {
+                    inner.free_count = Some(free_count);
+                }
+                if inner.is_valid_cluster(next_free) {
+                    inner.next_free = next_free;
+                }
+            }
+        }
+
+        Ok(Arc::new_cyclic(|self_ref| Self {
+            inner: SleepMutex::new(inner),
+            inodes: Mutex::new(BTreeMap::new()),
+            self_ref: self_ref.clone(),
+        }))
+    }
+
+    /// Returns the inode of the directory entry at `pos`, which is shared by
+    /// all users of the entry. Must be called with the filesystem locked.
+    fn inode(&self, pos: u64, kind: InodeType, first_cluster: u32, size: u32) -> Arc<FatInode> {
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.get(&pos).and_then(Weak::upgrade) {
+            return inode;
+        }
+        inodes.retain(|_, inode| inode.strong_count() > 0);
+        let fs = self.self_ref.upgrade().unwrap();
+        let inode = Arc::new(FatInode::new(fs, pos, kind, first_cluster, size));
+        inodes.insert(pos, Arc::downgrade(&inode));
+        inode
+    }
+
+    /// Whether the inode of the directory entry at `pos` is in use.
+    fn is_inode_busy(&self, pos: u64) -> bool {
+        self.inodes
+            .lock()
+            .get(&pos)
+            .map_or(false, |inode| inode.strong_count() > 0)
+    }
+}
+
+impl FileSystem for Fat32FileSystem {
+    fn name(&self) -> &str {
+        "fat32"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        let inner = self.inner.lock();
+        self.inode(ROOT_POS, InodeType::Dir, inner.root_cluster, 0)
+    }
+
+    fn sync(&self) -> SysResult<()> {
+        self.inner.lock().sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::block_cache::tests::MemDisk;
+    use alloc::vec::Vec;
+
+    const RESERVED_SECTORS: u32 = 32;
+    const FAT_SIZE: u32 = 8;
+    const NUM_CLUSTERS: u32 = 1000;
+    const TOTAL_SECTORS: u32 = RESERVED_SECTORS + 2 * FAT_SIZE + NUM_CLUSTERS;
+
+    /// Formats a volume with 512-byte clusters, 2 FATs and the root directory
+    /// at cluster 2.
+    fn mkfs() -> Arc<MemDisk> {
+        let disk = MemDisk::new(512, TOTAL_SECTORS as u64);
+        let mut boot = [0; 512];
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+        boot[16] = 2;
+        boot[32..36].copy_from_slice(&TOTAL_SECTORS.to_le_bytes());
+        boot[36..40].copy_from_slice(&FAT_SIZE.to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xaa;
+        disk.write_raw(0, &boot);
+
+        let mut fs_info = [0; 512];
+        fs_info[0..4].copy_from_slice(&FS_INFO_LEAD_SIG.to_le_bytes());
+        fs_info[484..488].copy_from_slice(&FS_INFO_STRUC_SIG.to_le_bytes());
+        fs_info[488..492].copy_from_slice(&(NUM_CLUSTERS - 1).to_le_bytes());
+        fs_info[492..496].copy_from_slice(&3u32.to_le_bytes());
+        disk.write_raw(512, &fs_info);
+
+        for fat in 0..2 {
+            let offset = (RESERVED_SECTORS + fat * FAT_SIZE) as usize * 512;
+            for (i, entry) in [0x0fff_fff8, FAT_EOC, FAT_EOC].iter().enumerate() {
+                disk.write_raw(offset + i * 4, &u32::to_le_bytes(*entry));
+            }
+        }
+        disk
+    }
+
+    fn open(disk: &Arc<MemDisk>) -> Arc<Fat32FileSystem> {
+        Fat32FileSystem::open(disk.clone()).unwrap()
+    }
+
+    fn chain(inner: &mut FatInner, start: u32) -> Vec<u32> {
+        let mut clusters = vec![start];
+        while let Some(next) = inner.next_cluster(*clusters.last().unwrap()).unwrap() {
+            clusters.push(next);
+        }
+        clusters
+    }
+
+    fn names(dir: &Arc<dyn Inode>) -> Vec<String> {
+        (0..)
+            .map_while(|i| dir.read_dir(i).unwrap())
+            .map(|e| e.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_open() {
+        let disk = mkfs();
+        disk.write_raw(510, &[0, 0]);
+        assert!(Fat32FileSystem::open(disk.clone()).is_err());
+        disk.write_raw(510, &[0x55, 0xaa]);
+        let fs = open(&disk);
+        let inner = fs.inner.lock();
+        assert_eq!(inner.num_clusters, NUM_CLUSTERS);
+        assert_eq!(inner.free_count, Some(NUM_CLUSTERS - 1));
+        assert_eq!(inner.next_free, 3);
+    }
+
+    #[test]
+    fn test_cluster_chain() {
+        let disk = mkfs();
+        let fs = open(&disk);
+        let mut inner = fs.inner.lock();
+        let mut first = 0;
+        let mut cursor = ClusterCursor::default();
+
+        // allocate and extend
+        inner.resize_chain(&mut first, 1, &mut cursor).unwrap();
+        assert_eq!(first, 3);
+        inner
+            .resize_chain(&mut first, 3 * 512 + 1, &mut cursor)
+            .unwrap();
+        assert_eq!(chain(&mut inner, first), [3, 4, 5, 6]);
+        assert_eq!((cursor.index, cursor.cluster), (3, 6));
+        assert_eq!(inner.free_count, Some(NUM_CLUSTERS - 5));
+
+        // lookups continue from the cursor, or restart before it
+        assert_eq!(inner.nth_cluster(first, 1, &mut cursor), Ok(Some(4)));
+        assert_eq!(inner.nth_cluster(first, 2, &mut cursor), Ok(Some(5)));
+        assert_eq!(inner.nth_cluster(first, 4, &mut cursor), Ok(None));
+        assert_eq!((cursor.index, cursor.cluster), (2, 5));
+
+        // shrink, and extend with the next free clusters
+        inner.resize_chain(&mut first, 1024, &mut cursor).unwrap();
+        assert_eq!(chain(&mut inner, first), [3, 4]);
+        assert_eq!(inner.read_fat(5), Ok(FAT_FREE));
+        assert_eq!(inner.read_fat(6), Ok(FAT_FREE));
+        assert_eq!(inner.free_count, Some(NUM_CLUSTERS - 3));
+        inner.resize_chain(&mut first, 1025, &mut cursor).unwrap();
+        assert_eq!(chain(&mut inner, first), [3, 4, 7]);
+
+        // both FATs are written
+        inner.sync().unwrap();
+        let fat_entry = |fat: u32, cluster: u32| {
+            let offset = (RESERVED_SECTORS + fat * FAT_SIZE) as usize * 512 + cluster as usize * 4;
+            le32(&disk.read_raw(offset, 4), 0)
+        };
+        for fat in 0..2 {
+            assert_eq!(fat_entry(fat, 4), 7);
+            assert_eq!(fat_entry(fat, 5), FAT_FREE);
+            assert_eq!(fat_entry(fat, 7), FAT_EOC);
+        }
+
+        // free
+        inner.resize_chain(&mut first, 0, &mut cursor).unwrap();
+        assert_eq!(first, 0);
+        assert_eq!(inner.free_count, Some(NUM_CLUSTERS - 1));
+        for cluster in [3, 4, 7] {
+            assert_eq!(inner.read_fat(cluster), Ok(FAT_FREE));
+        }
+
+        // the clusters are allocated until the volume is full
+        let size = (NUM_CLUSTERS - 1) as u64 * 512;
+        inner.resize_chain(&mut first, size, &mut cursor).unwrap();
+        assert_eq!(inner.free_count, Some(0));
+        assert_eq!(
+            inner.resize_chain(&mut first, size + 1, &mut cursor),
+            Err(Errno::ENOSPC)
+        );
+        inner.resize_chain(&mut first, 0, &mut cursor).unwrap();
+        assert_eq!(inner.free_count, Some(NUM_CLUSTERS - 1));
+    }
+
+    #[test]
+    fn test_cyclic_chain() {
+        let disk = mkfs();
+        let fs = open(&disk);
+        let mut inner = fs.inner.lock();
+        let mut first = 0;
+        let mut cursor = ClusterCursor::default();
+        inner
+            .resize_chain(&mut first, 3 * 512, &mut cursor)
+            .unwrap();
+        inner.write_fat(5, first).unwrap();
+
+        assert_eq!(inner.last_cluster(first), Err(Errno::EIO));
+        let mut cursor = ClusterCursor::default();
+        assert_eq!(
+            inner.nth_cluster(first, usize::MAX, &mut cursor),
+            Err(Errno::EIO)
+        );
+        assert_eq!(inner.free_chain(first), Err(Errno::EIO));
+    }
+
+    #[test]
+    fn test_dir() {
+        let disk = mkfs();
+        let fs = open(&disk);
+        let root = fs.root();
+        let file = root.create("hello.txt", InodeType::File).unwrap();
+        assert_eq!(
+            root.create("HELLO.TXT", InodeType::File).err(),
+            Some(Errno::EEXIST)
+        );
+        let dir = root
+            .create("A long directory name", InodeType::Dir)
+            .unwrap();
+        dir.create("file in the directory.dat", InodeType::File)
+            .unwrap();
+        assert_eq!(names(&root), ["hello.txt", "A long directory name"]);
+        assert_eq!(root.lookup("hello.txt").unwrap().ino(), file.ino());
+        assert_eq!(
+            root.lookup("a long directory name").unwrap().kind(),
+            InodeType::Dir
+        );
+        assert_eq!(file.lookup("x").err(), Some(Errno::ENOTDIR));
+
+        // the root directory grows beyond a cluster
+        for i in 0..40 {
+            root.create(&format!("file{}", i), InodeType::File).unwrap();
+        }
+        assert!(chain(&mut fs.inner.lock(), 2).len() > 1);
+
+        // entries are found after remounting
+        fs.sync().unwrap();
+        let fs2 = open(&disk);
+        let root2 = fs2.root();
+        assert_eq!(names(&root2).len(), 42);
+        assert_eq!(
+            names(&root2.lookup("A long directory name").unwrap()),
+            ["file in the directory.dat"]
+        );
+        assert!(root2.lookup("file39").is_ok());
+        drop(root2);
+        drop(fs2);
+
+        // remove
+        assert_eq!(root.unlink("hello.txt", true), Err(Errno::ENOTDIR));
+        assert_eq!(root.unlink("hello.txt", false), Err(Errno::EBUSY));
+        drop(file);
+        root.unlink("hello.txt", false).unwrap();
+        assert_eq!(root.lookup("hello.txt").err(), Some(Errno::ENOENT));
+        drop(dir);
+        assert_eq!(
+            root.unlink("A long directory name", true),
+            Err(Errno::ENOTEMPTY)
+        );
+        let dir = root.lookup("A long directory name").unwrap();
+        dir.unlink("file in the directory.dat", false).unwrap();
+        drop(dir);
+        root.unlink("A long directory name", true).unwrap();
+        for i in 0..40 {
+            root.unlink(&format!("file{}", i), false).unwrap();
+        }
+        assert!(names(&root).is_empty());
+        // the clusters of the directory are freed
+        let free_count = fs.inner.lock().free_count.unwrap();
+        let root_clusters = chain(&mut fs.inner.lock(), 2).len() as u32;
+        assert_eq!(free_count, NUM_CLUSTERS - root_clusters);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let disk = mkfs();
+        let fs = open(&disk);
+        let root = fs.root();
+        let file = root.create("data", InodeType::File).unwrap();
+        let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(file.write_at(300, &data), Ok(5000));
+        assert_eq!(file.size(), 5300);
+
+        let check = |file: &Arc<dyn Inode>| {
+            let mut buf = vec![0xcc; 5300];
+            assert_eq!(file.read_at(0, &mut buf), Ok(5300));
+            assert!(buf[..300].iter().all(|&b| b == 0));
+            assert_eq!(buf[300..], data[..]);
+        };
+        check(&file);
+
+        // sequential and backward reads crossing clusters
+        let mut buf = vec![0; 700];
+        for offset in (300..5300).step_by(700) {
+            let len = file.read_at(offset as u64, &mut buf).unwrap();
+            assert_eq!(buf[..len], data[offset - 300..offset - 300 + len]);
+        }
+        assert_eq!(file.read_at(100, &mut buf), Ok(700));
+        assert_eq!(buf[200..], data[..500]);
+        assert_eq!(file.read_at(5290, &mut buf), Ok(10));
+        assert_eq!(file.read_at(5300, &mut buf), Ok(0));
+
+        // overwrite across a cluster boundary
+        assert_eq!(file.write_at(1020, &[0xff; 10]), Ok(10));
+        let mut buf = [0; 12];
+        file.read_at(1019, &mut buf).unwrap();
+        assert_eq!(buf[0], data[719]);
+        assert_eq!(buf[1..11], [0xff; 10]);
+        assert_eq!(buf[11], data[730]);
+        file.write_at(1020, &data[720..730]).unwrap();
+
+        // the hole before a write past the end is zeros
+        file.write_at(6000, b"end").unwrap();
+        let mut buf = vec![0xcc; 703];
+        assert_eq!(file.read_at(5300, &mut buf), Ok(703));
+        assert!(buf[..700].iter().all(|&b| b == 0));
+        assert_eq!(&buf[700..], b"end");
+        file.truncate(5300).unwrap();
+
+        // the data is kept after remounting
+        fs.sync().unwrap();
+        let fs2 = open(&disk);
+        let file2 = fs2.root().lookup("data").unwrap();
+        assert_eq!(file2.size(), 5300);
+        check(&file2);
+
+        // truncating to a size in a cluster zeroes the tail when extending
+        file.truncate(1000).unwrap();
+        file.truncate(1500).unwrap();
+        let mut buf = vec![0xcc; 500];
+        assert_eq!(file.read_at(1000, &mut buf), Ok(500));
+        assert!(buf.iter().all(|&b| b == 0));
+        assert_eq!(root.read_dir(0).unwrap().unwrap().kind, InodeType::File);
+        assert_eq!(file.lookup("x").err(), Some(Errno::ENOTDIR));
+        assert_eq!(root.read_at(0, &mut buf), Err(Errno::EISDIR));
+    }
+}
@@ -0,0 +1,245 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use super::vfs::{DirEntry, Inode, InodeType};
+use crate::drivers::console::{console_getchar, console_write};
+use crate::errno::{Errno, SysResult};
+use crate::net::TcpSocket;
+use crate::sync::SleepMutex;
+
+/// The maximum number of open files of a process.
+const MAX_FDS: usize = 256;
+
+bitflags::bitflags! {
+    /// Flags of `open()`.
+    pub struct OpenFlags: u32 {
+        const RDONLY    = 0;
+        const WRONLY    = 1;
+        const RDWR      = 2;
+        const CREAT     = 0o100;
+        const EXCL      = 0o200;
+        const TRUNC     = 0o1000;
+        const APPEND    = 0o2000;
+        const DIRECTORY = 0o200000;
+        /// Accepted, but has no effect.
+        const CLOEXEC   = 0o2000000;
+    }
+}
+
+impl OpenFlags {
+    const ACCESS_MODE: u32 = 3;
+
+    pub fn is_valid(&self) -> bool {
+        self.bits() & Self::ACCESS_MODE != Self::ACCESS_MODE
+    }
+
+    pub fn readable(&self) -> bool {
+        self.bits() & Self::ACCESS_MODE != Self::WRONLY.bits()
+    }
+
+    pub fn writable(&self) -> bool {
+        self.bits() & Self::ACCESS_MODE != Self::RDONLY.bits()
+    }
+}
+
+/// The origin of `lseek()`.
+#[derive(Debug, Clone, Copy)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// An open file, shared by file descriptors that are duplicated or inherited.
+pub trait File: Send + Sync {
+    /// Reads from the file, returns 0 at the end of the file.
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize>;
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize>;
+
+    /// Changes the file offset, returns the new offset.
+    fn seek(&self, _pos: SeekFrom) -> SysResult<u64> {
+        Err(Errno::ESPIPE)
+    }
+
+    /// Calls `emit` with the next entries of the directory, until it returns
+    /// `false` or the end of the directory.
+    fn read_dir(&self, _emit: &mut dyn FnMut(&DirEntry) -> bool) -> SysResult<()> {
+        Err(Errno::ENOTDIR)
+    }
+
+    /// Writes the modified data of the file to the device.
+    fn sync(&self) -> SysResult<()> {
+        Err(Errno::EINVAL)
+    }
+
+    /// Returns the socket if the file is a socket.
+    fn as_socket(&self) -> Option<&TcpSocket> {
+        None
+    }
+}
+
+/// The console input.
+pub struct Stdin;
+
+/// The console output.
+pub struct Stdout;
+
+impl File for Stdin {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Block until at least one character is available.
+        let mut count = 0;
+        while count < buf.len() {
+            if let Some(c) = console_getchar() {
+                buf[count] = c;
+                count += 1;
+            } else if count > 0 {
+                break;
+            } else {
+                crate::task::current().yield_now();
+            }
+        }
+        Ok(count)
+    }
+
+    fn write(&self, _buf: &[u8]) -> SysResult<usize> {
+        Err(Errno::EBADF)
+    }
+}
+
+impl File for Stdout {
+    fn read(&self, _buf: &mut [u8]) -> SysResult<usize> {
+        Err(Errno::EBADF)
+    }
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        console_write(buf);
+        Ok(buf.len())
+    }
+}
+
+/// A file or a directory opened in a filesystem.
+pub struct InodeFile {
+    inode: Arc<dyn Inode>,
+    flags: OpenFlags,
+    /// The file offset, or the index of the next entry for directories.
+    offset: SleepMutex<u64>,
+}
+
+impl InodeFile {
+    pub fn new(inode: Arc<dyn Inode>, flags: OpenFlags) -> Self {
+        Self {
+            inode,
+            flags,
+            offset: SleepMutex::new(0),
+        }
+    }
+}
+
+impl File for InodeFile {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if !self.flags.readable() {
+            return Err(Errno::EBADF);
+        }
+        let mut offset = self.offset.lock();
+        let len = self.inode.read_at(*offset, buf)?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        if !self.flags.writable() {
+            return Err(Errno::EBADF);
+        }
+        let mut offset = self.offset.lock();
+        if self.flags.contains(OpenFlags::APPEND) {
+            *offset = self.inode.size();
+        }
+        let len = self.inode.write_at(*offset, buf)?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> SysResult<u64> {
+        if self.inode.kind() == InodeType::Dir {
+            // only rewinding is supported for directories
+            return match pos {
+                SeekFrom::Start(0) => {
+                    *self.offset.lock() = 0;
+                    Ok(0)
+                }
+                _ => Err(Errno::EINVAL),
+            };
+        }
+        let mut offset = self.offset.lock();
+        let new_offset = match pos {
+            SeekFrom::Start(off) => Some(off),
+            SeekFrom::Current(off) => offset.checked_add_signed(off),
+            SeekFrom::End(off) => self.inode.size().checked_add_signed(off),
+        };
+        *offset = new_offset.ok_or(Errno::EINVAL)?;
+        Ok(*offset)
+    }
+
+    fn read_dir(&self, emit: &mut dyn FnMut(&DirEntry) -> bool) -> SysResult<()> {
+        let mut index = self.offset.lock();
+        while let Some(entry) = self.inode.read_dir(*index as usize)? {
+            if !emit(&entry) {
+                break;
+            }
+            *index += 1;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> SysResult<()> {
+        self.inode.sync()
+    }
+}
+
+/// File descriptors of a process.
+#[derive(Clone, Default)]
+pub struct FdTable {
+    files: Vec<Option<Arc<dyn File>>>,
+}
+
+impl FdTable {
+    /// Creates a table with the standard input, output and error.
+    pub fn new_stdio() -> Self {
+        let stdout: Arc<dyn File> = Arc::new(Stdout);
+        Self {
+            files: alloc::vec![Some(Arc::new(Stdin)), Some(stdout.clone()), Some(stdout)],
+        }
+    }
+
+    pub fn get(&self, fd: usize) -> SysResult<Arc<dyn File>> {
+        match self.files.get(fd) {
+            Some(Some(file)) => Ok(file.clone()),
+            _ => Err(Errno::EBADF),
+        }
+    }
+
+    /// Adds the file with the lowest unused file descriptor.
+    pub fn add(&mut self, file: Arc<dyn File>) -> SysResult<usize> {
+        match self.files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.files[fd] = Some(file);
+                Ok(fd)
+            }
+            None if self.files.len() < MAX_FDS => {
+                self.files.push(Some(file));
+                Ok(self.files.len() - 1)
+            }
+            None => Err(Errno::EMFILE),
+        }
+    }
+
+    pub fn remove(&mut self, fd: usize) -> SysResult<Arc<dyn File>> {
+        match self.files.get_mut(fd) {
+            Some(file) if file.is_some() => Ok(file.take().unwrap()),
+            _ => Err(Errno::EBADF),
+        }
+    }
+}
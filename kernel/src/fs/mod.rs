@@ -0,0 +1,144 @@
+//! The virtual filesystem, and the FAT32 filesystem mounted at the root.
+//!
+//! There is no working directory, relative paths are resolved from the root.
+//! The console ports are opened as `/dev/<name>`.
+
+mod block_cache;
+mod dev;
+mod fat32;
+mod file;
+mod vfs;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::drivers::block::block_devices;
+use crate::errno::{Errno, SysResult};
+use crate::sync::LazyInit;
+
+pub use self::file::{FdTable, File, InodeFile, OpenFlags, SeekFrom};
+pub use self::vfs::{DirEntry, FileSystem, Inode, InodeType};
+
+static ROOT_FS: LazyInit<Arc<dyn FileSystem>> = LazyInit::new();
+
+/// Splits the path into components, with `.` and `..` resolved.
+fn path_components(path: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(name),
+        }
+    }
+    components
+}
+
+fn root_dir() -> SysResult<Arc<dyn Inode>> {
+    if ROOT_FS.is_init() {
+        Ok(ROOT_FS.root())
+    } else {
+        Err(Errno::ENOENT)
+    }
+}
+
+fn lookup_components(components: &[&str]) -> SysResult<Arc<dyn Inode>> {
+    let mut inode = root_dir()?;
+    for name in components {
+        inode = inode.lookup(name)?;
+    }
+    Ok(inode)
+}
+
+pub fn lookup(path: &str) -> SysResult<Arc<dyn Inode>> {
+    lookup_components(&path_components(path))
+}
+
+/// Returns the parent directory of `path`, and the last component of it.
+fn lookup_parent(path: &str) -> SysResult<(Arc<dyn Inode>, &str)> {
+    let components = path_components(path);
+    match components.split_last() {
+        Some((name, parent)) => Ok((lookup_components(parent)?, name)),
+        // the root directory
+        None => Err(Errno::EEXIST),
+    }
+}
+
+pub fn open(path: &str, flags: OpenFlags) -> SysResult<Arc<dyn File>> {
+    if !flags.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+    if let ["dev", name] = path_components(path)[..] {
+        return dev::open(name, flags);
+    }
+    let inode = if flags.contains(OpenFlags::CREAT) {
+        let (parent, name) = lookup_parent(path)?;
+        match parent.lookup(name) {
+            Ok(_) if flags.contains(OpenFlags::EXCL) => return Err(Errno::EEXIST),
+            Ok(inode) => inode,
+            Err(Errno::ENOENT) => parent.create(name, InodeType::File)?,
+            Err(e) => return Err(e),
+        }
+    } else {
+        lookup(path)?
+    };
+    match inode.kind() {
+        InodeType::Dir if flags.writable() => return Err(Errno::EISDIR),
+        InodeType::File if flags.contains(OpenFlags::DIRECTORY) => return Err(Errno::ENOTDIR),
+        InodeType::File if flags.contains(OpenFlags::TRUNC) && flags.writable() => {
+            inode.truncate(0)?
+        }
+        _ => {}
+    }
+    Ok(Arc::new(InodeFile::new(inode, flags)))
+}
+
+pub fn mkdir(path: &str) -> SysResult<()> {
+    let (parent, name) = lookup_parent(path)?;
+    parent.create(name, InodeType::Dir)?;
+    Ok(())
+}
+
+/// Removes the file, or the empty directory if `is_dir` is true, at `path`.
+pub fn unlink(path: &str, is_dir: bool) -> SysResult<()> {
+    let (parent, name) = lookup_parent(path).map_err(|e| match e {
+        Errno::EEXIST => Errno::EBUSY,
+        e => e,
+    })?;
+    parent.unlink(name, is_dir)
+}
+
+/// Reads the whole file at `path`.
+pub fn read_file(path: &str) -> SysResult<Vec<u8>> {
+    let inode = lookup(path)?;
+    let mut data = vec![0; inode.size() as usize];
+    let len = inode.read_at(0, &mut data)?;
+    data.truncate(len);
+    Ok(data)
+}
+
+/// Writes all modified data to the devices.
+pub fn sync() -> SysResult<()> {
+    if ROOT_FS.is_init() {
+        ROOT_FS.sync()?;
+    }
+    Ok(())
+}
+
+/// Mounts the first FAT32 volume found on the block devices at the root.
+pub fn init() {
+    for dev in block_devices() {
+        let name = dev.name();
+        match fat32::Fat32FileSystem::open(dev.clone()) {
+            Ok(fs) => {
+                println!("Mounted {} filesystem on {} at /", fs.name(), name);
+                ROOT_FS.init_by(fs);
+                return;
+            }
+            Err(Errno::EINVAL) => info!("no FAT32 filesystem on {}", name),
+            Err(e) => warn!("failed to mount {}: {:?}", name, e),
+        }
+    }
+    println!("No filesystem is mounted.");
+}
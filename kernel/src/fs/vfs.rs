@@ -0,0 +1,68 @@
+use alloc::{string::String, sync::Arc};
+
+use crate::errno::SysResult;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InodeType {
+    File,
+    Dir,
+}
+
+/// An entry returned by [`Inode::read_dir`].
+#[derive(Debug)]
+pub struct DirEntry {
+    pub ino: u64,
+    pub kind: InodeType,
+    pub name: String,
+}
+
+/// A file or a directory in a filesystem.
+///
+/// Operations on files fail with `EISDIR` for directories, and operations on
+/// directories fail with `ENOTDIR` for files.
+pub trait Inode: Send + Sync {
+    fn kind(&self) -> InodeType;
+
+    /// A number that identifies the inode in its filesystem.
+    fn ino(&self) -> u64;
+
+    /// The file size in bytes, 0 for directories.
+    fn size(&self) -> u64;
+
+    /// Reads the file at `offset`, returns the number of bytes read, which is
+    /// less than `buf.len()` only at the end of the file.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SysResult<usize>;
+
+    /// Writes the file at `offset`, the file is extended if needed.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> SysResult<usize>;
+
+    /// Changes the file size, newly added bytes are zeros.
+    fn truncate(&self, size: u64) -> SysResult<()>;
+
+    /// Finds the entry `name` in the directory.
+    fn lookup(&self, name: &str) -> SysResult<Arc<dyn Inode>>;
+
+    /// Creates an empty file or directory named `name` in the directory,
+    /// fails with `EEXIST` if it exists.
+    fn create(&self, name: &str, kind: InodeType) -> SysResult<Arc<dyn Inode>>;
+
+    /// Removes the file, or the empty directory if `is_dir` is true, named
+    /// `name` from the directory.
+    fn unlink(&self, name: &str, is_dir: bool) -> SysResult<()>;
+
+    /// Returns the `index`-th entry of the directory, excluding `.` and `..`,
+    /// or `None` at the end of the directory.
+    fn read_dir(&self, index: usize) -> SysResult<Option<DirEntry>>;
+
+    /// Writes the modified data and metadata to the device.
+    fn sync(&self) -> SysResult<()>;
+}
+
+pub trait FileSystem: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn root(&self) -> Arc<dyn Inode>;
+
+    /// Writes all modified data to the device.
+    fn sync(&self) -> SysResult<()>;
+}
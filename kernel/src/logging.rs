@@ -1,30 +1,32 @@
-use core::fmt::{self, Write};
+use core::fmt;
 
-use log::{self, Level, LevelFilter, Log, Metadata, Record};
+cfg_if! {
+    if #[cfg(not(test))] {
+        use core::fmt::Write;
+        use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
-use crate::drivers::uart::console_putchar;
-use crate::percpu::PerCpu;
-use crate::sync::Mutex;
+        use crate::drivers::console::log_write;
+        use crate::percpu::PerCpu;
+        use crate::sync::Mutex;
+        use crate::utils::datetime::DateTime;
+    }
+}
 
+#[cfg_attr(test, allow(dead_code))]
 struct Stdout;
 
+#[cfg(not(test))]
 static PRINT_LOCK: Mutex<()> = Mutex::new(());
 
+#[cfg(not(test))]
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            match c {
-                '\n' => {
-                    console_putchar(b'\r');
-                    console_putchar(b'\n');
-                }
-                _ => console_putchar(c as u8),
-            }
-        }
+        log_write(s.as_bytes());
         Ok(())
     }
 }
 
+#[cfg(not(test))]
 pub fn init() {
     static LOGGER: SimpleLogger = SimpleLogger;
     log::set_logger(&LOGGER).unwrap();
@@ -38,11 +40,17 @@ pub fn init() {
     });
 }
 
+#[cfg(not(test))]
 pub fn print(args: fmt::Arguments) {
     let _locked = PRINT_LOCK.lock();
     Stdout.write_fmt(args).unwrap();
 }
 
+#[cfg(test)]
+pub fn print(args: fmt::Arguments) {
+    std::print!("{}", args);
+}
+
 #[macro_export]
 macro_rules! print {
     ($fmt: literal $(, $($arg: tt)+)?) => {
@@ -58,6 +66,7 @@ macro_rules! println {
     }
 }
 
+#[cfg_attr(test, allow(unused_macros))]
 macro_rules! with_color {
     ($color_code:expr, $($arg:tt)*) => {{
         format_args!("\u{1B}[{}m{}\u{1B}[m", $color_code as u8, format_args!($($arg)*))
@@ -85,8 +94,29 @@ enum ColorCode {
     BrightWhite = 97,
 }
 
+#[cfg_attr(test, allow(dead_code))]
 struct SimpleLogger;
 
+/// The timestamp of log messages, the time since boot by default, or the wall
+/// time in UTC if built with `LOG_TIME=wall`.
+#[cfg(not(test))]
+struct Timestamp;
+
+#[cfg(not(test))]
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if option_env!("LOG_TIME") == Some("wall") {
+            let now = crate::timer::current_realtime();
+            let datetime = DateTime::from_unix_secs(now.as_secs());
+            write!(f, "{}.{:06}", datetime, now.subsec_micros())
+        } else {
+            let now = crate::timer::current_time();
+            write!(f, "{:>3}.{:06}", now.as_secs(), now.subsec_micros())
+        }
+    }
+}
+
+#[cfg(not(test))]
 impl Log for SimpleLogger {
     fn enabled(&self, _metadata: &Metadata) -> bool {
         true
@@ -116,12 +146,10 @@ impl Log for SimpleLogger {
         if crate::task::is_init() {
             let cpu_id = PerCpu::current_cpu_id();
             let pid = crate::task::current().pid().as_usize();
-            let now = crate::timer::current_time();
             print(with_color!(
                 ColorCode::White,
-                "[{:>3}.{:06} {} {} {}\n",
-                now.as_secs(),
-                now.subsec_micros(),
+                "[{} {} {} {}\n",
+                Timestamp,
                 with_color!(level_color, "{:<5}", level),
                 with_color!(ColorCode::White, "{}][{}:{}]", target, cpu_id, pid),
                 with_color!(args_color, "{}", record.args()),
@@ -4,7 +4,9 @@
 #![feature(panic_info_message, alloc_error_handler)]
 #![feature(const_refs_to_cell)]
 #![feature(const_maybe_uninit_zeroed)]
+#![feature(allocator_api, nonnull_slice_from_raw_parts)]
 #![feature(get_mut_unchecked)]
+#![feature(const_mut_refs)]
 
 extern crate alloc;
 #[macro_use]
@@ -17,20 +19,38 @@ mod logging;
 
 mod arch;
 mod config;
-mod drivers;
-mod loader;
+mod errno;
 mod mm;
-mod percpu;
 mod platform;
 mod sync;
-mod syscall;
-mod task;
-mod timer;
 mod utils;
 
-#[cfg(not(test))]
-mod lang_items;
+// Modules below can not run on the host, unit tests only cover the others.
+cfg_if! {
+    if #[cfg(not(test))] {
+        mod drivers;
+        mod fs;
+        mod lang_items;
+        mod loader;
+        mod net;
+        mod percpu;
+        mod syscall;
+        mod task;
+        mod timer;
+    } else {
+        // The filesystems are tested with in-memory block devices.
+        mod drivers {
+            pub mod block;
+        }
+        mod fs {
+            mod block_cache;
+            mod fat32;
+            mod vfs;
+        }
+    }
+}
 
+#[cfg(not(test))]
 fn clear_bss() {
     extern "C" {
         fn sbss();
@@ -42,6 +62,7 @@ fn clear_bss() {
     }
 }
 
+#[cfg_attr(test, allow(dead_code))]
 const LOGO: &str = r"
 NN   NN  iii               bb        OOOOO    SSSSS
 NNN  NN       mm mm mmmm   bb       OO   OO  SS
@@ -55,6 +76,7 @@ NN   NN  iii  mmm  mm  mm  bbbbbb    OOOO0    SSSSS
            /____/ \____/  /____/ /____/
 ";
 
+#[cfg(not(test))]
 #[no_mangle]
 pub fn rust_main() -> ! {
     clear_bss();
@@ -83,10 +105,13 @@ pub fn rust_main() -> ! {
 
     mm::init();
     drivers::init();
+    fs::init();
 
     percpu::init_percpu();
     timer::init();
     task::init();
+    drivers::init_late();
+    net::init();
     loader::list_apps();
     task::run();
 }
@@ -1,22 +1,67 @@
 use alloc::vec::Vec;
+use core::mem::size_of;
 
-use super::{address::virt_to_phys, PhysAddr, PAGE_SIZE};
+use super::address::{align_up, virt_to_phys};
+use super::{PhysAddr, PAGE_SIZE};
 use crate::config::PHYS_MEMORY_END;
 use crate::sync::SpinNoIrqLock;
-use crate::utils::allocator::FreeListAllocator;
+use crate::utils::allocator::{BuddyAllocator, FrameNode};
 
-static FRAME_ALLOCATOR: SpinNoIrqLock<FreeListAllocator> =
-    SpinNoIrqLock::new(FreeListAllocator::empty());
+static FRAME_ALLOCATOR: SpinNoIrqLock<BuddyAllocator> = SpinNoIrqLock::new(BuddyAllocator::empty());
 
+/// Usage statistics of physical frames.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub free_frames: usize,
+}
+
+/// One or more physically contiguous frames, freed on drop.
 #[derive(Debug)]
 pub struct PhysFrame {
     start_paddr: PhysAddr,
+    count: usize,
 }
 
 impl PhysFrame {
     pub fn alloc() -> Option<Self> {
         FRAME_ALLOCATOR.lock().alloc().map(|value| Self {
             start_paddr: PhysAddr::new(value * PAGE_SIZE),
+            count: 1,
+        })
+    }
+
+    /// Allocates `count` physically contiguous frames, whose start address is
+    /// aligned to `align` frames. `align` must be a power of two.
+    pub fn alloc_contiguous(count: usize, align: usize) -> Option<Self> {
+        FRAME_ALLOCATOR
+            .lock()
+            .alloc_contiguous(count, align)
+            .map(|value| Self {
+                start_paddr: PhysAddr::new(value * PAGE_SIZE),
+                count,
+            })
+    }
+
+    /// Takes back the ownership of frames allocated by
+    /// [`PhysFrame::alloc_contiguous`] and then forgotten.
+    ///
+    /// # Safety
+    ///
+    /// The frames must not be owned by any other `PhysFrame`.
+    pub(super) unsafe fn from_raw(start_paddr: PhysAddr, count: usize) -> Self {
+        Self { start_paddr, count }
+    }
+
+    /// Splits the frames into single frames, each of which is freed on its
+    /// own.
+    pub fn into_frames(self) -> impl Iterator<Item = Self> {
+        let start = self.start_paddr.as_usize();
+        let count = self.count;
+        core::mem::forget(self);
+        (0..count).map(move |i| Self {
+            start_paddr: PhysAddr::new(start + i * PAGE_SIZE),
+            count: 1,
         })
     }
 
@@ -30,17 +75,33 @@ impl PhysFrame {
         self.start_paddr
     }
 
+    /// The number of frames.
+    #[allow(dead_code)]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The size in bytes.
+    pub fn size(&self) -> usize {
+        self.count * PAGE_SIZE
+    }
+
     pub fn zero(&mut self) {
-        unsafe { core::ptr::write_bytes(self.start_paddr.into_kvaddr().as_mut_ptr(), 0, PAGE_SIZE) }
+        unsafe {
+            core::ptr::write_bytes(self.start_paddr.into_kvaddr().as_mut_ptr(), 0, self.size())
+        }
     }
 
     pub fn as_slice(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self.start_paddr.into_kvaddr().as_ptr(), PAGE_SIZE) }
+        unsafe { core::slice::from_raw_parts(self.start_paddr.into_kvaddr().as_ptr(), self.size()) }
     }
 
     pub fn as_slice_mut(&mut self) -> &mut [u8] {
         unsafe {
-            core::slice::from_raw_parts_mut(self.start_paddr.into_kvaddr().as_mut_ptr(), PAGE_SIZE)
+            core::slice::from_raw_parts_mut(
+                self.start_paddr.into_kvaddr().as_mut_ptr(),
+                self.size(),
+            )
         }
     }
 }
@@ -49,23 +110,53 @@ impl Drop for PhysFrame {
     fn drop(&mut self) {
         FRAME_ALLOCATOR
             .lock()
-            .dealloc(self.start_paddr.as_usize() / PAGE_SIZE);
+            .dealloc_contiguous(self.start_paddr.as_usize() / PAGE_SIZE, self.count);
+    }
+}
+
+pub fn frame_stats() -> FrameStats {
+    let allocator = FRAME_ALLOCATOR.lock();
+    FrameStats {
+        total_frames: allocator.total_frames(),
+        free_frames: allocator.free_frames(),
     }
 }
 
+#[cfg_attr(test, allow(dead_code))]
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
     let start_paddr = PhysAddr::new(virt_to_phys(ekernel as usize)).align_up();
     let end_paddr = PhysAddr::new(PHYS_MEMORY_END).align_down();
+    init_frames(start_paddr, end_paddr);
+}
+
+/// Lets the frame allocator manage the physical memory `[start_paddr, end_paddr)`.
+pub(super) fn init_frames(start_paddr: PhysAddr, end_paddr: PhysAddr) {
     println!(
         "Initializing frame allocator at: [{:#x?}, {:#x?})",
         start_paddr, end_paddr
     );
-    FRAME_ALLOCATOR
-        .lock()
-        .init(start_paddr.as_usize() / PAGE_SIZE..end_paddr.as_usize() / PAGE_SIZE);
+
+    // The metadata of frames is placed at the beginning of the free memory.
+    let frame_count = (end_paddr.as_usize() - start_paddr.as_usize()) / PAGE_SIZE;
+    let meta_size = align_up(frame_count * size_of::<FrameNode>(), PAGE_SIZE);
+    let start_frame = (start_paddr.as_usize() + meta_size) / PAGE_SIZE;
+    let end_frame = end_paddr.as_usize() / PAGE_SIZE;
+    let nodes = unsafe {
+        core::slice::from_raw_parts_mut(
+            start_paddr.into_kvaddr().as_mut_ptr() as *mut FrameNode,
+            end_frame - start_frame,
+        )
+    };
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    allocator.init(start_frame..end_frame, nodes);
+    println!(
+        "Frame allocator: {} frames available, {:#x} bytes of metadata",
+        allocator.free_frames(),
+        meta_size
+    );
 }
 
 #[allow(dead_code)]
@@ -83,5 +174,13 @@ pub fn frame_allocator_test() {
         v.push(frame);
     }
     drop(v);
+
+    let free_frames = frame_stats().free_frames;
+    let frames = PhysFrame::alloc_contiguous(3, 512).unwrap();
+    println!("{:?}", frames);
+    assert_eq!(frames.start_paddr().as_usize() % (512 * PAGE_SIZE), 0);
+    assert_eq!(frame_stats().free_frames, free_frames - 3);
+    drop(frames);
+    assert_eq!(frame_stats().free_frames, free_frames);
     println!("frame_allocator_test passed!");
 }
@@ -3,9 +3,21 @@ use core::alloc::{GlobalAlloc, Layout};
 use core::mem::size_of;
 use core::ptr::NonNull;
 
-use crate::config::KERNEL_HEAP_SIZE;
+use super::{slab, PhysFrame, PAGE_SIZE};
+use crate::config::{KERNEL_HEAP_GROW_SIZE, KERNEL_HEAP_SIZE};
 use crate::sync::SpinNoIrqLock;
 
+/// Usage statistics of the kernel heap.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Total bytes of the heap, including the memory grown from frames.
+    pub total_bytes: usize,
+    /// Bytes requested by allocations.
+    pub user_bytes: usize,
+    /// Bytes actually allocated, including the internal fragmentation.
+    pub actual_bytes: usize,
+}
+
 struct LockedHeap(SpinNoIrqLock<Heap<32>>);
 
 impl LockedHeap {
@@ -13,35 +25,105 @@ impl LockedHeap {
         LockedHeap(SpinNoIrqLock::new(Heap::<32>::new()))
     }
 
+    #[cfg_attr(test, allow(dead_code))]
     pub fn init(&self, start: usize, size: usize) {
         unsafe { self.0.lock().init(start, size) };
     }
+
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn stats(&self) -> HeapStats {
+        let heap = self.0.lock();
+        HeapStats {
+            total_bytes: heap.stats_total_bytes(),
+            user_bytes: heap.stats_alloc_user(),
+            actual_bytes: heap.stats_alloc_actual(),
+        }
+    }
+}
+
+/// Adds frames that can satisfy `layout` to the heap, returns `false` if there
+/// are no enough frames (or the frame allocator is not initialized yet).
+fn grow_heap(heap: &mut Heap<32>, layout: Layout) -> bool {
+    // A single aligned power-of-two block, so that the buddy heap can always
+    // allocate `layout` from it.
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(KERNEL_HEAP_GROW_SIZE)
+        .next_power_of_two();
+    let count = size / PAGE_SIZE;
+    if let Some(frames) = PhysFrame::alloc_contiguous(count, count) {
+        let start = frames.start_paddr().into_kvaddr().as_usize();
+        debug!(
+            "Growing kernel heap at: [{:#x}, {:#x})",
+            start,
+            start + size
+        );
+        // The frames are owned by the heap since then.
+        core::mem::forget(frames);
+        unsafe { heap.add_to_heap(start, start + size) };
+        true
+    } else {
+        false
+    }
+}
+
+impl LockedHeap {
+    fn alloc_from_heap(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let mut heap = self.0.lock();
+        let mut res = heap.alloc(layout);
+        if res.is_err() && grow_heap(&mut heap, layout) {
+            res = heap.alloc(layout);
+        }
+        res.ok()
+    }
 }
 
 unsafe impl GlobalAlloc for LockedHeap {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.0
-            .lock()
-            .alloc(layout)
-            .ok()
-            .map_or(core::ptr::null_mut(), |allocation| allocation.as_ptr())
+        let res = match slab::cache_for(&layout) {
+            Some(cache) => cache.alloc(),
+            None => self.alloc_from_heap(layout),
+        };
+        res.map_or(core::ptr::null_mut(), |allocation| allocation.as_ptr())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+        let ptr = NonNull::new_unchecked(ptr);
+        if slab::cache_for(&layout).is_some() {
+            slab::dealloc(ptr);
+        } else {
+            self.0.lock().dealloc(ptr, layout);
+        }
     }
 }
+
 #[cfg_attr(not(test), global_allocator)]
 static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
 #[cfg_attr(not(test), alloc_error_handler)]
+#[cfg_attr(test, allow(dead_code))]
 pub fn handle_alloc_error(layout: Layout) -> ! {
-    panic!("Heap allocation error, layout = {:?}", layout);
+    let heap = HEAP_ALLOCATOR.stats();
+    let frames = super::frame_stats();
+    panic!(
+        "Heap allocation error, layout = {:?}, heap used {:#x} ({:#x} requested) of {:#x} bytes, {} of {} frames free",
+        layout,
+        heap.actual_bytes,
+        heap.user_bytes,
+        heap.total_bytes,
+        frames.free_frames,
+        frames.total_frames,
+    );
 }
 
+/// The early-boot pool of the heap, more memory is allocated from the frame
+/// allocator on demand.
+#[cfg_attr(test, allow(dead_code))]
 static mut HEAP_SPACE: [u64; KERNEL_HEAP_SIZE / size_of::<u64>()] =
     [0; KERNEL_HEAP_SIZE / size_of::<u64>()];
 
+#[cfg_attr(test, allow(dead_code))]
 pub fn init_heap() {
     let heap_start = unsafe { HEAP_SPACE.as_ptr() as usize };
     println!(
@@ -52,6 +134,28 @@ pub fn init_heap() {
     HEAP_ALLOCATOR.init(heap_start, KERNEL_HEAP_SIZE);
 }
 
+/// Allocates a slab of `size` bytes aligned to its size from the heap.
+pub(super) fn alloc_slab(size: usize) -> Option<NonNull<u8>> {
+    let layout = Layout::from_size_align(size, size).ok()?;
+    HEAP_ALLOCATOR.alloc_from_heap(layout)
+}
+
+/// Returns a slab allocated by [`alloc_slab`] to the heap.
+///
+/// # Safety
+///
+/// `ptr` must be allocated by [`alloc_slab`] with the same `size`.
+pub(super) unsafe fn dealloc_slab(ptr: NonNull<u8>, size: usize) {
+    let layout = Layout::from_size_align_unchecked(size, size);
+    HEAP_ALLOCATOR.0.lock().dealloc(ptr, layout);
+}
+
+#[cfg_attr(test, allow(dead_code))]
+pub fn heap_stats() -> HeapStats {
+    HEAP_ALLOCATOR.stats()
+}
+
+#[cfg(not(test))]
 #[allow(dead_code)]
 pub fn heap_test() {
     use alloc::boxed::Box;
@@ -74,5 +178,25 @@ pub fn heap_test() {
     }
     assert!(bss_range.contains(&(v.as_ptr() as usize)));
     drop(v);
+
+    // larger than the early-boot pool, the heap must grow
+    let total_bytes = heap_stats().total_bytes;
+    let v = alloc::vec![0u8; KERNEL_HEAP_SIZE];
+    assert!(!bss_range.contains(&(v.as_ptr() as usize)));
+    assert!(heap_stats().total_bytes > total_bytes);
+    drop(v);
+
+    // small objects are allocated from slab caches
+    let boxes: Vec<Box<[u8; 100]>> = (0..100).map(|_| Box::new([0; 100])).collect();
+    for stats in slab::slab_stats() {
+        println!(
+            "{}: object size {}, {} slabs, {}/{} objects used",
+            stats.name, stats.object_size, stats.slabs, stats.used_objects, stats.total_objects
+        );
+        if stats.object_size == 128 {
+            assert!(stats.used_objects >= boxes.len());
+        }
+    }
+    drop(boxes);
     println!("heap_test passed!");
 }
@@ -0,0 +1,76 @@
+//! Kernel stacks are allocated in a dedicated region of the kernel address
+//! space. The region is divided into slots of `2 * KERNEL_STACK_SIZE` bytes,
+//! the stack occupies the upper half of the slot and the lower half is left
+//! unmapped as the guard, so a stack overflow faults instead of corrupting
+//! other memory.
+
+use alloc::vec::Vec;
+
+use super::{kernel_aspace, MapArea, MemFlags, VirtAddr};
+use crate::config::{KERNEL_STACK_REGION_BASE, KERNEL_STACK_REGION_SIZE, KERNEL_STACK_SIZE};
+use crate::sync::SpinNoIrqLock;
+
+const SLOT_SIZE: usize = KERNEL_STACK_SIZE * 2;
+const MAX_SLOTS: usize = KERNEL_STACK_REGION_SIZE / SLOT_SIZE;
+
+// The trap entries check whether the stack pointer is in the guard by testing
+// a single bit of it.
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(KERNEL_STACK_SIZE.is_power_of_two());
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(KERNEL_STACK_REGION_BASE % KERNEL_STACK_REGION_SIZE == 0);
+
+struct SlotAllocator {
+    next: usize,
+    free: Vec<usize>,
+}
+
+static SLOTS: SpinNoIrqLock<SlotAllocator> = SpinNoIrqLock::new(SlotAllocator {
+    next: 0,
+    free: Vec::new(),
+});
+
+const fn slot_base(slot: usize) -> usize {
+    KERNEL_STACK_REGION_BASE + slot * SLOT_SIZE
+}
+
+pub struct KernelStack {
+    slot: usize,
+}
+
+impl KernelStack {
+    /// Allocates a kernel stack. Freed stacks are kept mapped and reused.
+    pub fn alloc() -> Self {
+        let mut slots = SLOTS.lock();
+        let slot = if let Some(slot) = slots.free.pop() {
+            slot
+        } else {
+            let slot = slots.next;
+            assert!(slot < MAX_SLOTS, "too many kernel stacks");
+            slots.next += 1;
+            kernel_aspace().lock().insert(MapArea::new_framed(
+                VirtAddr::new(slot_base(slot) + KERNEL_STACK_SIZE),
+                KERNEL_STACK_SIZE,
+                MemFlags::READ | MemFlags::WRITE,
+            ));
+            slot
+        };
+        Self { slot }
+    }
+
+    pub fn top(&self) -> VirtAddr {
+        VirtAddr::new(slot_base(self.slot) + SLOT_SIZE)
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        SLOTS.lock().free.push(self.slot);
+    }
+}
+
+/// Whether `vaddr` is in the guard of a kernel stack.
+pub fn is_kernel_stack_guard(vaddr: usize) -> bool {
+    vaddr.wrapping_sub(KERNEL_STACK_REGION_BASE) < KERNEL_STACK_REGION_SIZE
+        && vaddr & KERNEL_STACK_SIZE == 0
+}
@@ -1,14 +1,19 @@
 use alloc::collections::btree_map::{BTreeMap, Entry};
+use alloc::vec::Vec;
 use core::fmt;
 
-use super::address::{align_down, is_aligned, phys_to_virt, virt_to_phys};
+use super::address::{align_down, align_up, is_aligned, phys_to_virt, virt_to_phys};
+use super::paging::PageSize;
 use super::{MemFlags, PhysFrame, PAGE_SIZE};
 use crate::arch::{instructions, PageTable};
-use crate::config::{KERNEL_ASPACE_BASE, KERNEL_ASPACE_SIZE, USER_STACK_BASE, USER_STACK_SIZE};
-use crate::config::{MMIO_REGIONS, PHYS_MEMORY_END};
+use crate::config::{KERNEL_ASPACE_BASE, KERNEL_ASPACE_SIZE, KERNEL_STACK_REGION_BASE};
+use crate::config::{MMIO_REGIONS, PHYS_MEMORY_END, USER_ASPACE_BASE, USER_MMAP_BASE};
+use crate::config::{USER_STACK_LIMIT, USER_STACK_REGION_BASE, USER_STACK_SIZE, USER_STACK_TOP};
+use crate::errno::{Errno, SysResult};
 use crate::mm::{PhysAddr, VirtAddr};
-use crate::sync::LazyInit;
+use crate::sync::{LazyInit, SpinNoIrqLock};
 
+#[cfg_attr(test, allow(dead_code))]
 extern "C" {
     fn stext();
     fn etext();
@@ -23,7 +28,7 @@ extern "C" {
     fn ekernel();
 }
 
-static KERNEL_ASPACE: LazyInit<MemorySet> = LazyInit::new();
+static KERNEL_ASPACE: LazyInit<SpinNoIrqLock<MemorySet>> = LazyInit::new();
 
 enum Mapper {
     Offset(usize),
@@ -40,6 +45,8 @@ pub struct MapArea {
 pub struct MemorySet {
     pt: PageTable,
     areas: BTreeMap<VirtAddr, MapArea>,
+    /// The maximum size of the user stack (RLIMIT_STACK).
+    stack_limit: usize,
 }
 
 impl MapArea {
@@ -106,12 +113,83 @@ impl MapArea {
         }
     }
 
-    pub fn unmap(&mut self, vaddr: VirtAddr) {
-        if let Mapper::Framed(frames) = &mut self.mapper {
-            frames.remove(&vaddr);
+    /// Maps all pages in `[vaddr, vaddr + size)`, and returns whether they are
+    /// physically contiguous.
+    ///
+    /// Frames of a framed area are allocated at once, aligned to `size`. If
+    /// some pages are already mapped or there are not enough contiguous
+    /// frames, no page is mapped and `false` is returned, so that the caller
+    /// can fall back to 4K pages.
+    pub fn map_contiguous(&mut self, vaddr: VirtAddr, size: usize) -> bool {
+        let frames = match &mut self.mapper {
+            Mapper::Offset(_) => return true,
+            Mapper::Framed(frames) => frames,
+        };
+        let start = vaddr.as_usize();
+        if frames
+            .range(vaddr..VirtAddr::new(start + size))
+            .next()
+            .is_some()
+        {
+            return false;
+        }
+        let count = size / PAGE_SIZE;
+        match PhysFrame::alloc_contiguous(count, count) {
+            Some(mut block) => {
+                block.zero();
+                for (i, frame) in block.into_frames().enumerate() {
+                    frames.insert(VirtAddr::new(start + i * PAGE_SIZE), frame);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Releases the frame mapped at `vaddr`, returns `false` if the page is
+    /// not mapped yet.
+    pub fn unmap(&mut self, vaddr: VirtAddr) -> bool {
+        match &mut self.mapper {
+            Mapper::Offset(_) => true,
+            Mapper::Framed(frames) => frames.remove(&vaddr).is_some(),
         }
     }
 
+    /// Whether the page at `vaddr` has been mapped.
+    pub fn is_mapped(&self, vaddr: VirtAddr) -> bool {
+        match &self.mapper {
+            Mapper::Offset(_) => true,
+            Mapper::Framed(frames) => frames.contains_key(&vaddr),
+        }
+    }
+
+    pub fn end(&self) -> VirtAddr {
+        VirtAddr::new(self.start.as_usize() + self.size)
+    }
+
+    pub fn contains(&self, vaddr: VirtAddr) -> bool {
+        self.start <= vaddr && vaddr < self.end()
+    }
+
+    /// Splits the area at `at`, `self` becomes `[start, at)`, and returns the
+    /// new area `[at, end)`.
+    pub fn split(&mut self, at: VirtAddr) -> Self {
+        assert!(at.is_aligned());
+        assert!(self.start < at && at < self.end());
+        let mapper = match &mut self.mapper {
+            Mapper::Offset(off) => Mapper::Offset(*off),
+            Mapper::Framed(frames) => Mapper::Framed(frames.split_off(&at)),
+        };
+        let right = Self {
+            start: at,
+            size: self.end().as_usize() - at.as_usize(),
+            flags: self.flags,
+            mapper,
+        };
+        self.size = at.as_usize() - self.start.as_usize();
+        right
+    }
+
     pub fn write_data(&mut self, offset: usize, data: &[u8]) {
         assert!(offset < self.size);
         assert!(offset + data.len() <= self.size);
@@ -141,16 +219,18 @@ impl MemorySet {
         Self {
             pt: PageTable::new(),
             areas: BTreeMap::new(),
+            stack_limit: 0,
         }
     }
 
     pub fn new() -> Self {
         Self {
-            pt: KERNEL_ASPACE.pt.clone_from(
+            pt: KERNEL_ASPACE.lock().pt.clone_from(
                 VirtAddr::new(KERNEL_ASPACE_BASE),
                 VirtAddr::new(KERNEL_ASPACE_BASE + KERNEL_ASPACE_SIZE),
             ),
             areas: BTreeMap::new(),
+            stack_limit: USER_STACK_LIMIT,
         }
     }
 
@@ -168,16 +248,114 @@ impl MemorySet {
         }
     }
 
-    pub fn load_user(&mut self, elf_data: &[u8]) -> (VirtAddr, VirtAddr) {
+    /// Finds a free region of `size` bytes for `mmap()`, searching upwards
+    /// from `USER_MMAP_BASE` to the region reserved for the user stack.
+    pub fn find_free_area(&self, size: usize) -> Option<VirtAddr> {
+        let mut start = USER_MMAP_BASE;
+        for area in self.areas.values() {
+            if area.end().as_usize() <= start {
+                continue;
+            }
+            if area.start.as_usize() >= start + size {
+                break;
+            }
+            start = area.end().as_usize();
+        }
+        if start + size <= USER_STACK_REGION_BASE {
+            Some(VirtAddr::new(start))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the region `[start, start + size)` overlaps with existing areas.
+    pub fn is_overlap(&self, start: VirtAddr, size: usize) -> bool {
+        let end = VirtAddr::new(start.as_usize() + size);
+        self.areas
+            .range(..end)
+            .next_back()
+            .map_or(false, |(_, area)| area.end() > start)
+    }
+
+    /// Unmaps the region `[start, start + size)`, areas partially in the
+    /// region are split.
+    pub fn unmap_range(&mut self, start: VirtAddr, size: usize) {
+        assert!(start.is_aligned());
+        assert!(is_aligned(size, PAGE_SIZE));
+        let end = VirtAddr::new(start.as_usize() + size);
+        let overlapped: Vec<_> = self
+            .areas
+            .range(..end)
+            .filter(|(_, area)| area.end() > start)
+            .map(|(&vaddr, _)| vaddr)
+            .collect();
+        for vaddr in overlapped {
+            let mut area = self.areas.remove(&vaddr).unwrap();
+            if area.start < start {
+                let right = area.split(start);
+                self.areas.insert(area.start, area);
+                area = right;
+            }
+            if area.end() > end {
+                let right = area.split(end);
+                self.areas.insert(right.start, right);
+            }
+            self.pt.unmap_area(&mut area);
+        }
+    }
+
+    /// Maps the MMIO region `[paddr, paddr + size)` to the linear mapping of
+    /// the physical memory, and returns its virtual address. Pages that are
+    /// already mapped (e.g. in `MMIO_REGIONS`) are left unchanged.
+    pub fn map_mmio(&mut self, paddr: PhysAddr, size: usize) -> SysResult<VirtAddr> {
+        let start = align_down(paddr.as_usize(), PAGE_SIZE);
+        let end = match paddr.as_usize().checked_add(size) {
+            Some(end) if size > 0 && end <= virt_to_phys(KERNEL_STACK_REGION_BASE) => {
+                align_up(end, PAGE_SIZE)
+            }
+            _ => return Err(Errno::EINVAL),
+        };
+        let end_vaddr = phys_to_virt(end);
+        let mut vaddr = phys_to_virt(start);
+        while vaddr < end_vaddr {
+            // find the next unmapped run of pages
+            if self.is_overlap(VirtAddr::new(vaddr), PAGE_SIZE) {
+                vaddr += PAGE_SIZE;
+                continue;
+            }
+            let run_start = vaddr;
+            while vaddr < end_vaddr && !self.is_overlap(VirtAddr::new(vaddr), PAGE_SIZE) {
+                vaddr += PAGE_SIZE;
+            }
+            self.insert(MapArea::new_offset(
+                VirtAddr::new(run_start),
+                PhysAddr::new(virt_to_phys(run_start)),
+                vaddr - run_start,
+                MemFlags::READ | MemFlags::WRITE | MemFlags::DEVICE,
+            ));
+        }
+        Ok(paddr.into_kvaddr())
+    }
+
+    /// Loads a user program from the ELF data, returns the entry point and the
+    /// user stack top.
+    ///
+    /// The ELF file is checked before any modification, the existing mappings
+    /// are cleared only if it is valid.
+    #[allow(clippy::absurd_extreme_comparisons)]
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn load_user(&mut self, elf_data: &[u8]) -> SysResult<(VirtAddr, VirtAddr)> {
         use xmas_elf::program::{Flags, SegmentData, Type};
         use xmas_elf::{header, ElfFile};
 
-        let elf = ElfFile::new(elf_data).expect("invalid ELF file");
-        assert_eq!(
-            elf.header.pt2.type_().as_type(),
-            header::Type::Executable,
-            "ELF is not an executable object"
-        );
+        let elf = ElfFile::new(elf_data).map_err(|e| {
+            warn!("invalid ELF file: {}", e);
+            Errno::ENOEXEC
+        })?;
+        if elf.header.pt2.type_().as_type() != header::Type::Executable {
+            warn!("ELF is not an executable object");
+            return Err(Errno::ENOEXEC);
+        }
         let expect_arch = if cfg!(target_arch = "x86_64") {
             header::Machine::X86_64
         } else if cfg!(target_arch = "aarch64") {
@@ -187,11 +365,10 @@ impl MemorySet {
         } else {
             panic!("Unsupported architecture!");
         };
-        assert_eq!(
-            elf.header.pt2.machine().as_machine(),
-            expect_arch,
-            "invalid ELF arch"
-        );
+        if elf.header.pt2.machine().as_machine() != expect_arch {
+            warn!("invalid ELF arch");
+            return Err(Errno::ENOEXEC);
+        }
 
         impl From<Flags> for MemFlags {
             fn from(f: Flags) -> Self {
@@ -209,38 +386,123 @@ impl MemorySet {
             }
         }
 
+        let mut segments: Vec<(VirtAddr, VirtAddr, usize, &[u8], Flags)> = Vec::new();
         for ph in elf.program_iter() {
-            if ph.get_type() != Ok(Type::Load) {
+            if ph.get_type() != Ok(Type::Load) || ph.mem_size() == 0 {
                 continue;
             }
-            let vaddr = VirtAddr::new(ph.virtual_addr() as usize);
-            let offset = vaddr.page_offset();
-            let area_start = vaddr.align_down();
-            let area_end = VirtAddr::new((ph.virtual_addr() + ph.mem_size()) as usize).align_up();
-            let data = match ph.get_data(&elf).unwrap() {
-                SegmentData::Undefined(data) => data,
-                _ => panic!("failed to get ELF segment data"),
+            let start = ph.virtual_addr() as usize;
+            let end = start.checked_add(ph.mem_size() as usize);
+            let data = match ph.get_data(&elf) {
+                Ok(SegmentData::Undefined(data)) => data,
+                _ => {
+                    warn!("failed to get ELF segment data");
+                    return Err(Errno::ENOEXEC);
+                }
             };
+            match end {
+                Some(end)
+                    if start >= USER_ASPACE_BASE
+                        && end <= USER_STACK_REGION_BASE
+                        && data.len() <= end - start => {}
+                _ => {
+                    warn!("invalid ELF segment @ {:#x}", start);
+                    return Err(Errno::ENOEXEC);
+                }
+            }
+            let vaddr = VirtAddr::new(start);
+            let area_start = vaddr.align_down();
+            let area_end = VirtAddr::new(end.unwrap()).align_up();
+            if segments
+                .iter()
+                .any(|seg| area_start < seg.1 && seg.0 < area_end)
+            {
+                warn!("overlapped ELF segments");
+                return Err(Errno::ENOEXEC);
+            }
+            segments.push((area_start, area_end, vaddr.page_offset(), data, ph.flags()));
+        }
 
+        self.clear();
+        for (area_start, area_end, offset, data, flags) in segments {
             let mut area = MapArea::new_framed(
                 area_start,
                 area_end.as_usize() - area_start.as_usize(),
-                ph.flags().into(),
+                flags.into(),
             );
             area.write_data(offset, data);
             self.insert(area);
             instructions::flush_icache_all();
         }
-        // user stack
+        // user stack, grows downward on page faults up to the stack limit
         self.insert(MapArea::new_framed(
-            VirtAddr::new(USER_STACK_BASE),
+            VirtAddr::new(USER_STACK_TOP - USER_STACK_SIZE),
             USER_STACK_SIZE,
             MemFlags::READ | MemFlags::WRITE | MemFlags::USER,
         ));
 
         let entry = VirtAddr::new(elf.header.pt2.entry_point() as usize);
-        let ustack_top = VirtAddr::new(USER_STACK_BASE + USER_STACK_SIZE);
-        (entry, ustack_top)
+        let ustack_top = VirtAddr::new(USER_STACK_TOP);
+        Ok((entry, ustack_top))
+    }
+
+    pub fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
+    }
+
+    /// Handles a page fault at `vaddr` caused by an `access` (`READ`, `WRITE`
+    /// or `EXECUTE`), by mapping the page on demand. The user stack is grown
+    /// if `vaddr` is below it but within the stack limit.
+    ///
+    /// Returns `false` if it is an invalid access.
+    pub fn handle_page_fault(&mut self, vaddr: VirtAddr, access: MemFlags) -> bool {
+        let page = vaddr.align_down();
+        let in_area = self
+            .areas
+            .range(..=vaddr)
+            .next_back()
+            .map_or(false, |(_, area)| area.contains(vaddr));
+        if !in_area {
+            return self.grow_stack(page) && self.handle_page_fault(vaddr, access);
+        }
+        let area = self.areas.range_mut(..=vaddr).next_back().unwrap().1;
+        if !area.flags.contains(access | MemFlags::USER) || area.is_mapped(page) {
+            return false;
+        }
+        let paddr = area.map(page);
+        self.pt.map(page, paddr, area.flags);
+        true
+    }
+
+    /// Extends the user stack downward to `page`, the new pages are mapped on
+    /// demand.
+    fn grow_stack(&mut self, page: VirtAddr) -> bool {
+        let page = page.as_usize();
+        if page < USER_STACK_TOP - self.stack_limit {
+            if page >= USER_STACK_REGION_BASE {
+                warn!(
+                    "user stack overflow @ {:#x}, exceeds the limit {:#x}",
+                    page, self.stack_limit
+                );
+            }
+            return false;
+        }
+        let stack_start = match self.areas.values().next_back() {
+            Some(area) if area.end().as_usize() == USER_STACK_TOP => area.start,
+            _ => return false,
+        };
+        if page >= stack_start.as_usize() {
+            return false;
+        }
+        let mut stack = self.areas.remove(&stack_start).unwrap();
+        stack.size += stack_start.as_usize() - page;
+        stack.start = VirtAddr::new(page);
+        self.areas.insert(stack.start, stack);
+        true
     }
 
     pub fn clear(&mut self) {
@@ -252,12 +514,14 @@ impl MemorySet {
 
     pub fn dup(&self) -> Self {
         let mut ms = Self::new();
+        ms.stack_limit = self.stack_limit;
         for area in self.areas.values() {
             ms.insert(area.dup());
         }
         ms
     }
 
+    #[cfg_attr(test, allow(dead_code))]
     pub fn page_table_root(&self) -> PhysAddr {
         self.pt.root_paddr()
     }
@@ -269,10 +533,22 @@ impl Drop for MemorySet {
     }
 }
 
-pub fn kernel_aspace<'a>() -> &'a MemorySet {
+#[cfg_attr(test, allow(dead_code))]
+pub fn kernel_aspace() -> &'static SpinNoIrqLock<MemorySet> {
     &KERNEL_ASPACE
 }
 
+/// Maps an MMIO region into the kernel address space at runtime, see
+/// [`MemorySet::map_mmio`].
+///
+/// The kernel address space lies in a single root page table entry on x86_64,
+/// so the new mapping is also visible to existing user address spaces.
+#[cfg_attr(test, allow(dead_code))]
+pub fn map_kernel_mmio(paddr: PhysAddr, size: usize) -> SysResult<VirtAddr> {
+    KERNEL_ASPACE.lock().map_mmio(paddr, size)
+}
+
+#[cfg_attr(test, allow(dead_code))]
 pub fn init_kernel_aspace() {
     let mut ms = MemorySet::new_kernel();
     let mut map_range = |start: usize, end: usize, flags: MemFlags, name: &str| {
@@ -334,11 +610,23 @@ pub fn init_kernel_aspace() {
         );
     }
 
+    // Kernel stacks are mapped later. Create the intermediate page tables of
+    // the region now, so that they are shared by all user page tables cloned
+    // from the kernel page table.
+    ms.pt
+        .create_intrm_tables(VirtAddr::new(KERNEL_STACK_REGION_BASE));
+
     let page_table_root = ms.page_table_root();
-    KERNEL_ASPACE.init_by(ms);
+    KERNEL_ASPACE.init_by(SpinNoIrqLock::new(ms));
     unsafe { instructions::set_kernel_page_table_root(page_table_root.as_usize()) };
 }
 
+/// Initializes an empty kernel address space for unit tests.
+#[cfg(test)]
+pub(super) fn init_kernel_aspace_for_test() {
+    KERNEL_ASPACE.init_by(SpinNoIrqLock::new(MemorySet::new_kernel()));
+}
+
 impl fmt::Debug for MapArea {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let start = self.start.as_usize();
@@ -364,7 +652,8 @@ impl fmt::Debug for MemorySet {
 
 #[allow(dead_code)]
 pub fn remap_test() {
-    let pt = &KERNEL_ASPACE.pt;
+    let aspace = KERNEL_ASPACE.lock();
+    let pt = &aspace.pt;
     let mid_text = VirtAddr::new(stext as usize + (etext as usize - stext as usize) / 2);
     let mid_rodata = VirtAddr::new(srodata as usize + (erodata as usize - srodata as usize) / 2);
     let _mid_data = VirtAddr::new(sdata as usize + (edata as usize - sdata as usize) / 2);
@@ -374,5 +663,214 @@ pub fn remap_test() {
         let mid_mmio = VirtAddr::new(phys_to_virt(region.0));
         assert!(pt.query(mid_mmio).unwrap().1.contains(MemFlags::DEVICE));
     }
+    // the end of the physical memory is mapped with blocks if it is aligned
+    let last_page = VirtAddr::new(phys_to_virt(PHYS_MEMORY_END) - PAGE_SIZE);
+    let (paddr, _, size) = pt.query(last_page).unwrap();
+    assert_eq!(paddr.as_usize(), PHYS_MEMORY_END - PAGE_SIZE);
+    if is_aligned(PHYS_MEMORY_END, PageSize::Size2M as usize) {
+        assert!(size.is_huge());
+    }
     println!("remap_test passed!");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URW: MemFlags = MemFlags::READ.union(MemFlags::WRITE).union(MemFlags::USER);
+
+    fn read_bytes(ms: &MemorySet, vaddr: usize, len: usize) -> &[u8] {
+        let (paddr, _, _) = ms.pt.query(VirtAddr::new(vaddr)).unwrap();
+        unsafe { core::slice::from_raw_parts(paddr.into_kvaddr().as_ptr(), len) }
+    }
+
+    #[test]
+    fn test_framed_area() {
+        crate::mm::init_for_test();
+        let start = VirtAddr::new(0x1000);
+        let mut area = MapArea::new_framed(start, 4 * PAGE_SIZE, URW);
+        assert!(!area.is_mapped(start));
+        let paddr = area.map(start);
+        assert!(area.is_mapped(start));
+        assert_eq!(area.map(start), paddr);
+        // new frames are zeroed
+        let frame = unsafe { core::slice::from_raw_parts(paddr.into_kvaddr().as_ptr(), PAGE_SIZE) };
+        assert!(frame.iter().all(|&b| b == 0));
+
+        let mut right = area.split(VirtAddr::new(0x3000));
+        assert_eq!((area.start, area.size), (start, 2 * PAGE_SIZE));
+        assert_eq!(
+            (right.start, right.size),
+            (VirtAddr::new(0x3000), 2 * PAGE_SIZE)
+        );
+        assert!(area.is_mapped(start));
+        assert!(right.contains(VirtAddr::new(0x4fff)));
+        assert!(!right.contains(VirtAddr::new(0x5000)));
+        assert!(!right.unmap(VirtAddr::new(0x3000)));
+        assert!(area.unmap(start));
+    }
+
+    #[test]
+    fn test_offset_area() {
+        crate::mm::init_for_test();
+        let mut area = MapArea::new_offset(
+            VirtAddr::new(0x20_0000),
+            PhysAddr::new(0x1000),
+            0x40_0000,
+            MemFlags::READ | MemFlags::DEVICE,
+        );
+        assert!(area.is_mapped(VirtAddr::new(0x20_0000)));
+        assert_eq!(area.map(VirtAddr::new(0x30_0000)), PhysAddr::new(0x10_1000));
+        assert!(area.map_contiguous(VirtAddr::new(0x20_0000), 0x40_0000));
+        let right = area.split(VirtAddr::new(0x40_0000));
+        assert_eq!(right.end(), VirtAddr::new(0x60_0000));
+    }
+
+    #[test]
+    fn test_map_contiguous() {
+        crate::mm::init_for_test();
+        let mut area = MapArea::new_framed(VirtAddr::new(0x20_0000), 0x40_0000, URW);
+        assert!(area.map_contiguous(VirtAddr::new(0x20_0000), 0x20_0000));
+        let paddr = area.map(VirtAddr::new(0x20_0000));
+        assert!(is_aligned(paddr.as_usize(), 0x20_0000));
+        assert_eq!(
+            area.map(VirtAddr::new(0x3f_f000)).as_usize(),
+            paddr.as_usize() + 0x1f_f000
+        );
+        // each frame can still be released on its own
+        assert!(area.unmap(VirtAddr::new(0x21_0000)));
+
+        // fails if some pages are mapped
+        area.map(VirtAddr::new(0x50_0000));
+        assert!(!area.map_contiguous(VirtAddr::new(0x40_0000), 0x20_0000));
+        assert!(!area.is_mapped(VirtAddr::new(0x40_0000)));
+    }
+
+    #[test]
+    fn test_dup() {
+        crate::mm::init_for_test();
+        let mut ms = MemorySet::new();
+        let mut area = MapArea::new_framed(VirtAddr::new(0x10_0000), 3 * PAGE_SIZE, URW);
+        area.write_data(PAGE_SIZE - 2, b"hello");
+        ms.insert(area);
+        assert_eq!(read_bytes(&ms, 0x10_0ffe, 2), b"he");
+        assert_eq!(read_bytes(&ms, 0x10_1000, 3), b"llo");
+        assert!(ms.is_overlap(VirtAddr::new(0x10_2000), PAGE_SIZE));
+        assert!(!ms.is_overlap(VirtAddr::new(0x10_3000), PAGE_SIZE));
+
+        // the new memory set has a copy of the data in different frames
+        let new_ms = ms.dup();
+        let orig_paddr = ms.pt.query(VirtAddr::new(0x10_1000)).unwrap().0;
+        let new_paddr = new_ms.pt.query(VirtAddr::new(0x10_1000)).unwrap().0;
+        assert_ne!(orig_paddr, new_paddr);
+        assert_eq!(read_bytes(&new_ms, 0x10_1000, 3), b"llo");
+        assert_eq!(new_ms.stack_limit(), ms.stack_limit());
+
+        unsafe { *(new_paddr.into_kvaddr().as_mut_ptr()) = b'L' };
+        assert_eq!(read_bytes(&ms, 0x10_1000, 3), b"llo");
+        assert_eq!(read_bytes(&new_ms, 0x10_1000, 3), b"Llo");
+    }
+
+    #[test]
+    fn test_unmap_range() {
+        crate::mm::init_for_test();
+        let mut ms = MemorySet::new();
+        ms.insert(MapArea::new_framed(
+            VirtAddr::new(0x10_0000),
+            4 * PAGE_SIZE,
+            URW,
+        ));
+        ms.insert(MapArea::new_framed(
+            VirtAddr::new(0x10_4000),
+            4 * PAGE_SIZE,
+            URW,
+        ));
+
+        // unmaps the middle of the two areas, both are split
+        ms.unmap_range(VirtAddr::new(0x10_2000), 4 * PAGE_SIZE);
+        let ranges: Vec<_> = ms
+            .areas
+            .values()
+            .map(|area| (area.start.as_usize(), area.end().as_usize()))
+            .collect();
+        assert_eq!(ranges, [(0x10_0000, 0x10_2000), (0x10_6000, 0x10_8000)]);
+        for vaddr in (0x10_0000..0x10_8000).step_by(PAGE_SIZE) {
+            let mapped = ms.pt.query(VirtAddr::new(vaddr)).is_some();
+            assert_eq!(mapped, !(0x10_2000..0x10_6000).contains(&vaddr));
+        }
+        assert_eq!(
+            ms.find_free_area(PAGE_SIZE),
+            Some(VirtAddr::new(USER_MMAP_BASE))
+        );
+    }
+
+    #[test]
+    fn test_map_mmio() {
+        crate::mm::init_for_test();
+        let mut ms = MemorySet::new_kernel();
+        let vaddr = ms.map_mmio(PhysAddr::new(0xfeb0_1010), 0x2000).unwrap();
+        assert_eq!(vaddr, PhysAddr::new(0xfeb0_1010).into_kvaddr());
+        let (paddr, flags, _) = ms
+            .pt
+            .query(VirtAddr::new(phys_to_virt(0xfeb0_2000)))
+            .unwrap();
+        assert_eq!(paddr, PhysAddr::new(0xfeb0_2000));
+        assert!(flags.contains(MemFlags::DEVICE));
+
+        // only the unmapped pages are mapped again
+        ms.map_mmio(PhysAddr::new(0xfeb0_0000), 0x5000).unwrap();
+        let ranges: Vec<_> = ms
+            .areas
+            .values()
+            .map(|area| (virt_to_phys(area.start.as_usize()), area.size))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (0xfeb0_0000, 0x1000),
+                (0xfeb0_1000, 0x3000),
+                (0xfeb0_4000, 0x1000)
+            ]
+        );
+        assert_eq!(ms.map_mmio(PhysAddr::new(0x1000), 0), Err(Errno::EINVAL));
+    }
+
+    #[test]
+    fn test_page_fault() {
+        crate::mm::init_for_test();
+        let mut ms = MemorySet::new();
+        ms.insert(MapArea::new_framed(
+            VirtAddr::new(0x10_0000),
+            PAGE_SIZE,
+            MemFlags::READ | MemFlags::USER,
+        ));
+        ms.insert(MapArea::new_framed(
+            VirtAddr::new(USER_STACK_TOP - USER_STACK_SIZE),
+            USER_STACK_SIZE,
+            URW,
+        ));
+        // faults on mapped pages or with invalid access are not handled
+        let stack_page = VirtAddr::new(USER_STACK_TOP - PAGE_SIZE);
+        assert!(!ms.handle_page_fault(stack_page, MemFlags::WRITE));
+        assert!(!ms.handle_page_fault(VirtAddr::new(0x10_0000), MemFlags::WRITE));
+
+        // the stack grows up to the limit, new pages are mapped on demand
+        ms.set_stack_limit(0x10_0000);
+        let below_stack = VirtAddr::new(USER_STACK_TOP - 0x8_0000);
+        assert!(ms.handle_page_fault(below_stack, MemFlags::WRITE));
+        assert!(ms.pt.query(below_stack).is_some());
+        assert!(ms
+            .pt
+            .query(VirtAddr::new(below_stack.as_usize() + PAGE_SIZE))
+            .is_none());
+        let stack = ms.areas.values().next_back().unwrap();
+        assert_eq!(stack.start, below_stack);
+        assert!(ms.handle_page_fault(
+            VirtAddr::new(below_stack.as_usize() + PAGE_SIZE),
+            MemFlags::READ
+        ));
+        let over_limit = VirtAddr::new(USER_STACK_TOP - 0x10_0000 - PAGE_SIZE);
+        assert!(!ms.handle_page_fault(over_limit, MemFlags::WRITE));
+        assert!(!ms.handle_page_fault(VirtAddr::new(0x20_0000), MemFlags::READ));
+    }
+}
@@ -1,15 +1,21 @@
 mod address;
 mod frame_allocator;
 mod heap_allocator;
+#[cfg_attr(test, allow(dead_code))]
+mod kernel_stack;
 mod memory_set;
+mod slab;
 mod uaccess;
 
 pub mod paging;
 
 pub use address::{PhysAddr, VirtAddr};
-pub use frame_allocator::PhysFrame;
-pub use memory_set::{kernel_aspace, MapArea, MemorySet};
-pub use uaccess::{UserInOutPtr, UserInPtr, UserOutPtr};
+pub use frame_allocator::{frame_stats, FrameStats, PhysFrame};
+pub use heap_allocator::{heap_stats, HeapStats};
+pub use kernel_stack::{is_kernel_stack_guard, KernelStack};
+pub use memory_set::{kernel_aspace, map_kernel_mmio, MapArea, MemorySet};
+pub use slab::{slab_stats, KBox, KmemCache, SlabCache, SlabStats};
+pub use uaccess::{fixup_exception, UserInOutPtr, UserInPtr, UserOutPtr};
 
 pub const PAGE_SIZE: usize = 0x1000;
 
@@ -23,11 +29,29 @@ bitflags::bitflags! {
     }
 }
 
+#[cfg_attr(test, allow(dead_code))]
 pub fn init_heap_early() {
     heap_allocator::init_heap();
 }
 
+#[cfg_attr(test, allow(dead_code))]
 pub fn init() {
     frame_allocator::init_frame_allocator();
     memory_set::init_kernel_aspace();
 }
+
+/// Initializes the memory management on the host for unit tests, using the
+/// mock physical memory of `arch`. It can be called by each test.
+#[cfg(test)]
+pub fn init_for_test() {
+    use crate::config::{PHYS_MEMORY_BASE, PHYS_MEMORY_END};
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        crate::arch::init_mock_memory();
+        frame_allocator::init_frames(
+            PhysAddr::new(PHYS_MEMORY_BASE),
+            PhysAddr::new(PHYS_MEMORY_END),
+        );
+        memory_set::init_kernel_aspace_for_test();
+    });
+}
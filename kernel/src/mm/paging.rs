@@ -1,7 +1,9 @@
 use alloc::{vec, vec::Vec};
 use core::{fmt::Debug, marker::PhantomData};
 
+use super::address::is_aligned;
 use super::{MapArea, MemFlags, PhysAddr, PhysFrame, VirtAddr, PAGE_SIZE};
+use crate::arch::instructions;
 
 pub trait PageTableLevels: Sync + Send {
     const LEVELS: usize;
@@ -37,6 +39,33 @@ pub trait GenericPTE: Debug + Clone + Copy + Sync + Send + Sized {
     fn is_block(&self) -> bool;
     /// Set this entry to zero.
     fn clear(&mut self);
+
+    /// Whether 1G blocks are supported by the hardware.
+    fn supports_1g_block() -> bool {
+        true
+    }
+}
+
+/// Sizes of pages or blocks that a leaf entry can map.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PageSize {
+    Size4K = 0x1000,
+    Size2M = 0x20_0000,
+    Size1G = 0x4000_0000,
+}
+
+impl PageSize {
+    pub const fn is_huge(self) -> bool {
+        !matches!(self, Self::Size4K)
+    }
+
+    /// The size of entries in the next level page table.
+    const fn next_level(self) -> Self {
+        match self {
+            Self::Size1G => Self::Size2M,
+            _ => Self::Size4K,
+        }
+    }
 }
 
 pub struct PageTableImpl<L: PageTableLevels, PTE: GenericPTE> {
@@ -99,37 +128,77 @@ impl<L: PageTableLevels, PTE: GenericPTE> PageTableImpl<L, PTE> {
     }
 
     pub fn map(&mut self, vaddr: VirtAddr, paddr: PhysAddr, flags: MemFlags) {
-        let entry = self.get_entry_mut_or_create(vaddr).unwrap();
+        self.map_page(vaddr, paddr, PageSize::Size4K, flags)
+    }
+
+    /// Maps a page or block of `size`, `vaddr` and `paddr` must be aligned to
+    /// `size`.
+    pub fn map_page(&mut self, vaddr: VirtAddr, paddr: PhysAddr, size: PageSize, flags: MemFlags) {
+        assert!(is_aligned(vaddr.as_usize(), size as usize));
+        assert!(is_aligned(paddr.as_usize(), size as usize));
+        let entry = self.get_entry_mut_at(vaddr, size, true).unwrap();
         if !entry.is_unused() {
             panic!("{:#x?} is mapped before mapping", vaddr);
         }
-        *entry = GenericPTE::new_page(paddr.align_down(), flags, false);
+        *entry = GenericPTE::new_page(paddr, flags, size.is_huge());
+    }
+
+    /// Creates the intermediate page tables for `vaddr` without mapping it.
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn create_intrm_tables(&mut self, vaddr: VirtAddr) {
+        self.get_entry_mut_at(vaddr, PageSize::Size4K, true)
+            .unwrap();
     }
 
+    /// Unmaps the 4K page at `vaddr`. If it is inside a block, the block is
+    /// split first.
     pub fn unmap(&mut self, vaddr: VirtAddr) {
-        let entry = self.get_entry_mut(vaddr).unwrap();
-        if entry.is_unused() {
-            panic!("{:#x?} is invalid before unmapping", vaddr);
+        match self.get_entry_mut_at(vaddr, PageSize::Size4K, false) {
+            Some(entry) if !entry.is_unused() => entry.clear(),
+            _ => panic!("{:#x?} is invalid before unmapping", vaddr),
         }
-        entry.clear();
     }
 
-    pub fn query(&self, vaddr: VirtAddr) -> Option<(PhysAddr, MemFlags)> {
-        let entry = self.get_entry_mut(vaddr)?;
+    /// Returns the mapped physical address and flags of `vaddr`, and the size
+    /// of the page or block that contains it.
+    pub fn query(&self, vaddr: VirtAddr) -> Option<(PhysAddr, MemFlags, PageSize)> {
+        let (entry, size) = self.get_entry_mut(vaddr)?;
         if entry.is_unused() {
             return None;
         }
-        let off = vaddr.page_offset();
-        Some((PhysAddr::new(entry.paddr().as_usize() + off), entry.flags()))
+        let off = vaddr.as_usize() & (size as usize - 1);
+        Some((
+            PhysAddr::new(entry.paddr().as_usize() + off),
+            entry.flags(),
+            size,
+        ))
     }
 
+    /// Maps all pages of `area`, using 2M or 1G blocks whenever both the
+    /// virtual and physical addresses are aligned and physically contiguous,
+    /// and no lower level table has been installed there. Otherwise (e.g. the
+    /// range was mapped with 4K pages before) 4K pages are used.
     pub fn map_area(&mut self, area: &mut MapArea) {
+        let mut block_sizes = [PageSize::Size1G, PageSize::Size2M].into_iter();
+        if !PTE::supports_1g_block() {
+            block_sizes.next();
+        }
         let mut vaddr = area.start.as_usize();
         let end = vaddr + area.size;
         while vaddr < end {
+            let size = block_sizes
+                .clone()
+                .find(|&size| {
+                    is_aligned(vaddr, size as usize)
+                        && end - vaddr >= size as usize
+                        && self.is_free_at(VirtAddr::new(vaddr), size)
+                        && area.map_contiguous(VirtAddr::new(vaddr), size as usize)
+                        && is_aligned(area.map(VirtAddr::new(vaddr)).as_usize(), size as usize)
+                })
+                .unwrap_or(PageSize::Size4K);
             let paddr = area.map(VirtAddr::new(vaddr));
-            self.map(VirtAddr::new(vaddr), paddr, area.flags);
-            vaddr += PAGE_SIZE;
+            self.map_page(VirtAddr::new(vaddr), paddr, size, area.flags);
+            vaddr += size as usize;
         }
     }
 
@@ -137,8 +206,25 @@ impl<L: PageTableLevels, PTE: GenericPTE> PageTableImpl<L, PTE> {
         let mut vaddr = area.start.as_usize();
         let end = vaddr + area.size;
         while vaddr < end {
-            area.unmap(VirtAddr::new(vaddr));
-            self.unmap(VirtAddr::new(vaddr));
+            // a block entirely in the area is unmapped at once
+            if let Some((entry, size)) = self.get_entry_mut(VirtAddr::new(vaddr)) {
+                if size.is_huge()
+                    && !entry.is_unused()
+                    && is_aligned(vaddr, size as usize)
+                    && end - vaddr >= size as usize
+                {
+                    entry.clear();
+                    for page in (vaddr..vaddr + size as usize).step_by(PAGE_SIZE) {
+                        area.unmap(VirtAddr::new(page));
+                    }
+                    vaddr += size as usize;
+                    continue;
+                }
+            }
+            // pages mapped on demand may not be mapped yet
+            if area.unmap(VirtAddr::new(vaddr)) {
+                self.unmap(VirtAddr::new(vaddr));
+            }
             vaddr += PAGE_SIZE;
         }
     }
@@ -173,8 +259,10 @@ impl<L: PageTableLevels, PTE: GenericPTE> PageTableImpl<L, PTE> {
         paddr
     }
 
-    fn get_entry_mut(&self, vaddr: VirtAddr) -> Option<&mut PTE> {
-        let p3 = if L::LEVELS == 3 {
+    /// Returns the leaf entry of `vaddr`, which may be a block, and the size
+    /// it maps.
+    fn get_entry_mut(&self, vaddr: VirtAddr) -> Option<(&mut PTE, PageSize)> {
+        let p3: &mut [PTE] = if L::LEVELS == 3 {
             table_of_mut(self.root_paddr())
         } else if L::LEVELS == 4 {
             let p4 = table_of_mut(self.root_paddr());
@@ -184,33 +272,133 @@ impl<L: PageTableLevels, PTE: GenericPTE> PageTableImpl<L, PTE> {
             unreachable!()
         };
         let p3e = &mut p3[p3_index(vaddr)];
+        if p3e.is_present() && p3e.is_block() {
+            return Some((p3e, PageSize::Size1G));
+        }
 
         let p2 = next_table_mut(p3e)?;
         let p2e = &mut p2[p2_index(vaddr)];
+        if p2e.is_present() && p2e.is_block() {
+            return Some((p2e, PageSize::Size2M));
+        }
 
         let p1 = next_table_mut(p2e)?;
         let p1e = &mut p1[p1_index(vaddr)];
-        Some(p1e)
+        Some((p1e, PageSize::Size4K))
     }
 
-    fn get_entry_mut_or_create(&mut self, vaddr: VirtAddr) -> Option<&mut PTE> {
-        let p3 = if L::LEVELS == 3 {
+    /// Whether the entry of `vaddr` in the page table level that maps pages of
+    /// `size` is unused, or the table containing it does not exist yet.
+    fn is_free_at(&self, vaddr: VirtAddr, size: PageSize) -> bool {
+        let mut table: &[PTE] = if L::LEVELS == 3 {
+            table_of(self.root_paddr())
+        } else if L::LEVELS == 4 {
+            let p4e = &table_of::<PTE>(self.root_paddr())[p4_index(vaddr)];
+            match next_table_mut(p4e) {
+                Some(table) => table,
+                None => return true,
+            }
+        } else {
+            unreachable!()
+        };
+        for (entry_size, index) in [
+            (PageSize::Size1G, p3_index(vaddr)),
+            (PageSize::Size2M, p2_index(vaddr)),
+            (PageSize::Size4K, p1_index(vaddr)),
+        ] {
+            let entry = &table[index];
+            if entry_size == size {
+                return entry.is_unused();
+            }
+            if entry.is_present() && entry.is_block() {
+                return false;
+            }
+            table = match next_table_mut(entry) {
+                Some(table) => table,
+                None => return true,
+            };
+        }
+        unreachable!()
+    }
+
+    /// Returns the entry of `vaddr` in the page table level that maps pages of
+    /// `size`. Blocks on the way are split. Missing intermediate tables are
+    /// created if `create` is true, otherwise `None` is returned.
+    fn get_entry_mut_at(
+        &mut self,
+        vaddr: VirtAddr,
+        size: PageSize,
+        create: bool,
+    ) -> Option<&mut PTE> {
+        let mut table: &mut [PTE] = if L::LEVELS == 3 {
             table_of_mut(self.root_paddr())
         } else if L::LEVELS == 4 {
             let p4 = table_of_mut(self.root_paddr());
             let p4e = &mut p4[p4_index(vaddr)];
-            next_table_mut_or_create(p4e, || self.alloc_intrm_table())?
+            self.next_table_mut_or_create(p4e, create)?
         } else {
             unreachable!()
         };
-        let p3e = &mut p3[p3_index(vaddr)];
+        for (entry_size, index) in [
+            (PageSize::Size1G, p3_index(vaddr)),
+            (PageSize::Size2M, p2_index(vaddr)),
+            (PageSize::Size4K, p1_index(vaddr)),
+        ] {
+            let entry = &mut table[index];
+            if entry_size == size {
+                return Some(entry);
+            }
+            if entry.is_present() && entry.is_block() {
+                self.split_block(entry, entry_size);
+            }
+            table = self.next_table_mut_or_create(entry, create)?;
+        }
+        unreachable!()
+    }
 
-        let p2 = next_table_mut_or_create(p3e, || self.alloc_intrm_table())?;
-        let p2e = &mut p2[p2_index(vaddr)];
+    fn next_table_mut_or_create<'a>(
+        &mut self,
+        entry: &mut PTE,
+        create: bool,
+    ) -> Option<&'a mut [PTE]> {
+        if entry.is_unused() && create {
+            let paddr = self.alloc_intrm_table();
+            *entry = GenericPTE::new_table(paddr);
+            Some(table_of_mut(paddr))
+        } else {
+            next_table_mut(entry)
+        }
+    }
 
-        let p1 = next_table_mut_or_create(p2e, || self.alloc_intrm_table())?;
-        let p1e = &mut p1[p1_index(vaddr)];
-        Some(p1e)
+    /// Replaces the block `entry` of `size` with a next level table, which
+    /// maps the same memory with smaller pages or blocks.
+    ///
+    /// The stale block may still be cached in the TLB, so the TLB is flushed.
+    /// A 2M or 1G block covers too many pages to be invalidated one by one.
+    fn split_block(&mut self, entry: &mut PTE, size: PageSize) {
+        let next_size = size.next_level();
+        let paddr = entry.paddr().as_usize();
+        let flags = entry.flags();
+        let table_paddr = self.alloc_intrm_table();
+        let table = table_of_mut::<PTE>(table_paddr);
+        for (i, e) in table.iter_mut().enumerate() {
+            *e = GenericPTE::new_page(
+                PhysAddr::new(paddr + i * next_size as usize),
+                flags,
+                next_size.is_huge(),
+            );
+        }
+        let table_entry = GenericPTE::new_table(table_paddr);
+        if cfg!(target_arch = "aarch64") {
+            // ARMv8 requires break-before-make: the block must be invalidated
+            // and flushed before a table is installed in its place.
+            entry.clear();
+            instructions::flush_tlb_all();
+            *entry = table_entry;
+        } else {
+            *entry = table_entry;
+            instructions::flush_tlb_all();
+        }
     }
 
     fn walk(
@@ -276,15 +464,184 @@ fn next_table_mut<'a, E: GenericPTE>(entry: &E) -> Option<&'a mut [E]> {
     }
 }
 
-fn next_table_mut_or_create<'a, E: GenericPTE>(
-    entry: &mut E,
-    mut allocator: impl FnMut() -> PhysAddr,
-) -> Option<&'a mut [E]> {
-    if entry.is_unused() {
-        let paddr = allocator();
-        *entry = GenericPTE::new_table(paddr);
-        Some(table_of_mut(paddr))
-    } else {
-        next_table_mut(entry)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::PageTable;
+    use crate::config::{KERNEL_ASPACE_BASE, KERNEL_ASPACE_SIZE};
+
+    const RW: MemFlags = MemFlags::READ.union(MemFlags::WRITE);
+
+    fn va(vaddr: usize) -> VirtAddr {
+        VirtAddr::new(vaddr)
+    }
+
+    fn pa(paddr: usize) -> PhysAddr {
+        PhysAddr::new(paddr)
+    }
+
+    #[test]
+    fn test_map_unmap() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        let flags = MemFlags::READ | MemFlags::EXECUTE | MemFlags::USER;
+        pt.map(va(0x1000), pa(0x8000), flags);
+        pt.map(va(0x40_0000), pa(0x9000), RW);
+        assert_eq!(
+            pt.query(va(0x1234)),
+            Some((pa(0x8234), flags, PageSize::Size4K))
+        );
+        assert_eq!(
+            pt.query(va(0x40_0fff)),
+            Some((pa(0x9fff), RW, PageSize::Size4K))
+        );
+        assert_eq!(pt.query(va(0x2000)), None);
+        assert_eq!(pt.query(va(0x8000_0000)), None);
+
+        pt.unmap(va(0x1000));
+        assert_eq!(pt.query(va(0x1000)), None);
+        assert!(pt.query(va(0x40_0000)).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unmap_unmapped() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        pt.map(va(0x1000), pa(0x8000), RW);
+        pt.unmap(va(0x2000));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_map_mapped() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        pt.map(va(0x1000), pa(0x8000), RW);
+        pt.map(va(0x1000), pa(0x9000), RW);
+    }
+
+    #[test]
+    fn test_split_2m_block() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        pt.map_page(va(0x20_0000), pa(0x60_0000), PageSize::Size2M, RW);
+        assert_eq!(
+            pt.query(va(0x21_2345)),
+            Some((pa(0x61_2345), RW, PageSize::Size2M))
+        );
+
+        // unmapping a page inside the block splits it into 4K pages
+        pt.unmap(va(0x30_0000));
+        assert_eq!(pt.query(va(0x30_0000)), None);
+        assert_eq!(
+            pt.query(va(0x2f_f000)),
+            Some((pa(0x6f_f000), RW, PageSize::Size4K))
+        );
+        assert_eq!(
+            pt.query(va(0x30_1000)),
+            Some((pa(0x70_1000), RW, PageSize::Size4K))
+        );
+    }
+
+    #[test]
+    fn test_split_1g_block() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        pt.map_page(va(0x4000_0000), pa(0), PageSize::Size1G, RW);
+        assert_eq!(
+            pt.query(va(0x7fff_ffff)),
+            Some((pa(0x3fff_ffff), RW, PageSize::Size1G))
+        );
+
+        // only the 2M block containing the page is split into 4K pages
+        pt.unmap(va(0x4020_0000));
+        assert_eq!(pt.query(va(0x4020_0000)), None);
+        assert_eq!(
+            pt.query(va(0x4020_1000)),
+            Some((pa(0x20_1000), RW, PageSize::Size4K))
+        );
+        assert_eq!(
+            pt.query(va(0x4000_0000)),
+            Some((pa(0), RW, PageSize::Size2M))
+        );
+        assert_eq!(
+            pt.query(va(0x7fe0_0000)),
+            Some((pa(0x3fe0_0000), RW, PageSize::Size2M))
+        );
+    }
+
+    #[test]
+    fn test_map_area() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        // [0xfff_f000, 0x1040_1000) -> [0x1f_f000, 0x60_1000): the middle is
+        // mapped with 2M blocks, both ends with 4K pages
+        let mut area = MapArea::new_offset(va(0xfff_f000), pa(0x1f_f000), 0x40_2000, RW);
+        pt.map_area(&mut area);
+        let size_of = |vaddr| pt.query(va(vaddr)).unwrap().2;
+        assert_eq!(size_of(0xfff_f000), PageSize::Size4K);
+        assert_eq!(size_of(0x1000_0000), PageSize::Size2M);
+        assert_eq!(size_of(0x103f_ffff), PageSize::Size2M);
+        assert_eq!(size_of(0x1040_0000), PageSize::Size4K);
+        assert_eq!(pt.query(va(0x1040_1000)), None);
+        assert_eq!(pt.query(va(0x1021_0000)).unwrap().0, pa(0x41_0000));
+
+        pt.unmap_area(&mut area);
+        for vaddr in (0xfff_f000..0x1040_1000).step_by(PAGE_SIZE) {
+            assert_eq!(pt.query(va(vaddr)), None);
+        }
+    }
+
+    #[test]
+    fn test_map_area_over_unmapped_pages() {
+        crate::mm::init_for_test();
+        let mut pt = PageTable::new();
+        pt.map(va(0x60_0000), pa(0x20_0000), RW);
+        pt.unmap(va(0x60_0000));
+
+        // the page table installed for the 4K page is still there, so the
+        // area is mapped with 4K pages instead of a 2M block
+        let mut area = MapArea::new_offset(va(0x60_0000), pa(0x20_0000), 0x20_0000, RW);
+        pt.map_area(&mut area);
+        assert_eq!(
+            pt.query(va(0x60_0000)),
+            Some((pa(0x20_0000), RW, PageSize::Size4K))
+        );
+        assert_eq!(
+            pt.query(va(0x7f_ffff)),
+            Some((pa(0x3f_ffff), RW, PageSize::Size4K))
+        );
+
+        pt.unmap_area(&mut area);
+        assert_eq!(pt.query(va(0x60_0000)), None);
+    }
+
+    #[test]
+    fn test_clone_from() {
+        crate::mm::init_for_test();
+        let mut kernel_pt = PageTable::new();
+        let kernel_vaddr = KERNEL_ASPACE_BASE + 0x20_0000;
+        kernel_pt.map_page(va(kernel_vaddr), pa(0x20_0000), PageSize::Size2M, RW);
+        kernel_pt.map(va(0x1000), pa(0x1000), RW);
+
+        let pt = kernel_pt.clone_from(
+            va(KERNEL_ASPACE_BASE),
+            va(KERNEL_ASPACE_BASE + KERNEL_ASPACE_SIZE),
+        );
+        assert_ne!(pt.root_paddr(), kernel_pt.root_paddr());
+        // kernel mappings are shared, user mappings are not copied
+        assert_eq!(
+            pt.query(va(kernel_vaddr + 0x1234)),
+            Some((pa(0x20_1234), RW, PageSize::Size2M))
+        );
+        assert_eq!(pt.query(va(0x1000)), None);
+
+        // the intermediate tables of the kernel space are shared
+        kernel_pt.map(va(kernel_vaddr + 0x20_0000), pa(0x40_0000), RW);
+        assert_eq!(
+            pt.query(va(kernel_vaddr + 0x20_0000)),
+            Some((pa(0x40_0000), RW, PageSize::Size4K))
+        );
     }
 }
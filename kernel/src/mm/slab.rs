@@ -0,0 +1,524 @@
+//! Slab allocator for small fixed-size kernel objects.
+//!
+//! Each [`SlabCache`] holds objects of one size, carved from slabs of
+//! [`SLAB_SIZE`] bytes allocated from the frame allocator (or from the heap in
+//! early boot). Slabs are aligned to their size and begin with a header, so
+//! the slab and the cache of any object can be found from its address. Free
+//! objects are linked in an intrusive free list per slab, and slabs with free
+//! objects are linked in a list per cache, so both allocation and deallocation
+//! are O(1), except when a slab is allocated or released. Each cache keeps at
+//! most [`MAX_EMPTY_SLABS`] empty slabs, others are released immediately.
+//!
+//! The global allocator uses the `kmalloc-*` caches for small size classes.
+//! Frequently allocated kernel objects (`Task`, `TrapFrame`, timer events) have
+//! their own typed caches ([`KmemCache`]).
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use super::address::{align_up, virt_to_phys};
+use super::heap_allocator::{alloc_slab, dealloc_slab};
+use super::{PhysAddr, PhysFrame, PAGE_SIZE};
+use crate::sync::SpinNoIrqLock;
+
+/// The size of all slabs, which are aligned to their size.
+const SLAB_SIZE: usize = 4 * PAGE_SIZE;
+
+/// The largest object size of slab caches, and the largest size class served
+/// by them.
+const MAX_OBJECT_SIZE: usize = 2048;
+
+/// The number of empty slabs kept by each cache, to avoid releasing and
+/// allocating a slab repeatedly when an object is allocated and freed.
+const MAX_EMPTY_SLABS: usize = 1;
+
+/// The maximum number of typed caches that can be registered.
+const MAX_TYPED_CACHES: usize = 8;
+
+/// Size classes of the global allocator, the size of the `i`-th cache is
+/// `16 << i`.
+static SIZE_CLASSES: [SlabCache; 8] = [
+    SlabCache::new("kmalloc-16", 16, 16),
+    SlabCache::new("kmalloc-32", 32, 32),
+    SlabCache::new("kmalloc-64", 64, 64),
+    SlabCache::new("kmalloc-128", 128, 128),
+    SlabCache::new("kmalloc-256", 256, 256),
+    SlabCache::new("kmalloc-512", 512, 512),
+    SlabCache::new("kmalloc-1024", 1024, 1024),
+    SlabCache::new("kmalloc-2048", MAX_OBJECT_SIZE, MAX_OBJECT_SIZE),
+];
+
+/// Typed caches registered by [`KmemCache::register`].
+static TYPED_CACHES: [AtomicPtr<SlabCache>; MAX_TYPED_CACHES] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NONE: AtomicPtr<SlabCache> = AtomicPtr::new(core::ptr::null_mut());
+    [NONE; MAX_TYPED_CACHES]
+};
+static TYPED_CACHE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Usage statistics of a slab cache.
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub slabs: usize,
+    pub total_objects: usize,
+    pub used_objects: usize,
+}
+
+/// The header at the beginning of each slab.
+struct SlabHeader {
+    cache: *const SlabCache,
+    /// Address of the first free object, `0` if there is none. Each free
+    /// object stores the address of the next one in its first word.
+    free_list: usize,
+    /// The number of allocated objects.
+    in_use: usize,
+    /// Links of the list of slabs with free objects, `0` if there is none.
+    prev: usize,
+    next: usize,
+    /// Whether the slab is allocated from the heap rather than from frames.
+    from_heap: bool,
+}
+
+struct SlabCacheInner {
+    /// The first slab with free objects, `0` if there is none.
+    partial: usize,
+    empty_slabs: usize,
+    slabs: usize,
+    total_objects: usize,
+    used_objects: usize,
+}
+
+/// A cache of objects of the same size (`kmem_cache`).
+pub struct SlabCache {
+    name: &'static str,
+    /// The layout of allocations served by the global allocator, if the cache
+    /// is registered.
+    layout: Layout,
+    object_size: usize,
+    object_align: usize,
+    inner: SpinNoIrqLock<SlabCacheInner>,
+}
+
+/// A typed cache of objects of `T`, with an optional constructor.
+///
+/// Objects are allocated as [`KBox`]es, which are returned to the cache on
+/// drop. A registered cache also serves global allocations of its layout, so
+/// that objects that must be allocated by `Arc::new` can be cached as well
+/// (see [`KmemCache::new_arc`]).
+pub struct KmemCache<T> {
+    cache: SlabCache,
+    ctor: Option<fn() -> T>,
+    _phantom: PhantomData<T>,
+}
+
+/// A box whose object is allocated from a [`KmemCache`].
+pub type KBox<T> = Box<T, &'static KmemCache<T>>;
+
+impl SlabHeader {
+    /// Returns the header of the slab containing `obj`.
+    ///
+    /// # Safety
+    ///
+    /// `obj` must be an object allocated from a slab.
+    unsafe fn of<'a>(obj: usize) -> &'a mut Self {
+        &mut *((obj & !(SLAB_SIZE - 1)) as *mut Self)
+    }
+
+    fn addr(&self) -> usize {
+        self as *const _ as usize
+    }
+}
+
+impl SlabCache {
+    /// Creates an empty cache of objects of `size` bytes aligned to `align`,
+    /// `align` must be a power of two.
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        let object_align = if align > align_of::<usize>() {
+            align
+        } else {
+            align_of::<usize>()
+        };
+        let object_size = if size > size_of::<usize>() {
+            align_up(size, object_align)
+        } else {
+            align_up(size_of::<usize>(), object_align)
+        };
+        assert!(object_align.is_power_of_two() && object_size <= MAX_OBJECT_SIZE);
+        Self {
+            name,
+            layout: unsafe { Layout::from_size_align_unchecked(size, align) },
+            object_size,
+            object_align,
+            inner: SpinNoIrqLock::new(SlabCacheInner {
+                partial: 0,
+                empty_slabs: 0,
+                slabs: 0,
+                total_objects: 0,
+                used_objects: 0,
+            }),
+        }
+    }
+
+    /// The offset of the first object in a slab.
+    const fn objects_offset(&self) -> usize {
+        align_up(size_of::<SlabHeader>(), self.object_align)
+    }
+
+    const fn objects_per_slab(&self) -> usize {
+        (SLAB_SIZE - self.objects_offset()) / self.object_size
+    }
+
+    /// Whether the cache can hold an object of `layout`.
+    fn fits(&self, layout: &Layout) -> bool {
+        layout.size() <= self.object_size && layout.align() <= self.object_align
+    }
+
+    pub fn alloc(&'static self) -> Option<NonNull<u8>> {
+        let mut inner = self.inner.lock();
+        if inner.partial == 0 {
+            // Do not hold the lock while allocating frames.
+            drop(inner);
+            let (slab, from_heap) = alloc_slab_memory()?;
+            inner = self.inner.lock();
+            self.add_slab(&mut inner, slab, from_heap);
+        }
+
+        let header = unsafe { &mut *(inner.partial as *mut SlabHeader) };
+        if header.in_use == 0 {
+            inner.empty_slabs -= 1;
+        }
+        let obj = header.free_list;
+        header.free_list = unsafe { *(obj as *const usize) };
+        header.in_use += 1;
+        if header.free_list == 0 {
+            Self::unlink(&mut inner, header);
+        }
+        inner.used_objects += 1;
+        NonNull::new(obj as *mut u8)
+    }
+
+    /// Returns an object to the cache, and releases its slab if it becomes
+    /// empty and the cache has enough empty slabs.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be allocated by [`SlabCache::alloc`] of the same cache.
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>) {
+        let obj = ptr.as_ptr() as usize;
+        let header = SlabHeader::of(obj);
+        debug_assert!(core::ptr::eq(header.cache, self));
+
+        let mut inner = self.inner.lock();
+        if header.free_list == 0 {
+            // The slab was full.
+            Self::push(&mut inner, header);
+        }
+        *(obj as *mut usize) = header.free_list;
+        header.free_list = obj;
+        header.in_use -= 1;
+        inner.used_objects -= 1;
+
+        if header.in_use == 0 {
+            if inner.empty_slabs < MAX_EMPTY_SLABS {
+                inner.empty_slabs += 1;
+            } else {
+                Self::unlink(&mut inner, header);
+                inner.slabs -= 1;
+                inner.total_objects -= self.objects_per_slab();
+                let (slab, from_heap) = (header.addr(), header.from_heap);
+                drop(inner);
+                release_slab_memory(slab, from_heap);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        let inner = self.inner.lock();
+        SlabStats {
+            name: self.name,
+            object_size: self.object_size,
+            slabs: inner.slabs,
+            total_objects: inner.total_objects,
+            used_objects: inner.used_objects,
+        }
+    }
+
+    /// Initializes a new empty slab and adds it to the cache.
+    fn add_slab(&'static self, inner: &mut SlabCacheInner, slab: usize, from_heap: bool) {
+        let count = self.objects_per_slab();
+        let first = slab + self.objects_offset();
+        for i in 0..count {
+            let obj = first + i * self.object_size;
+            let next = if i + 1 < count {
+                obj + self.object_size
+            } else {
+                0
+            };
+            unsafe { *(obj as *mut usize) = next };
+        }
+        let header = unsafe { &mut *(slab as *mut SlabHeader) };
+        *header = SlabHeader {
+            cache: self,
+            free_list: first,
+            in_use: 0,
+            prev: 0,
+            next: 0,
+            from_heap,
+        };
+        Self::push(inner, header);
+        inner.empty_slabs += 1;
+        inner.slabs += 1;
+        inner.total_objects += count;
+    }
+
+    /// Adds the slab to the front of the list of slabs with free objects.
+    fn push(inner: &mut SlabCacheInner, header: &mut SlabHeader) {
+        header.prev = 0;
+        header.next = inner.partial;
+        if inner.partial != 0 {
+            unsafe { (*(inner.partial as *mut SlabHeader)).prev = header.addr() };
+        }
+        inner.partial = header.addr();
+    }
+
+    /// Removes the slab from the list of slabs with free objects.
+    fn unlink(inner: &mut SlabCacheInner, header: &mut SlabHeader) {
+        if header.prev != 0 {
+            unsafe { (*(header.prev as *mut SlabHeader)).next = header.next };
+        } else {
+            inner.partial = header.next;
+        }
+        if header.next != 0 {
+            unsafe { (*(header.next as *mut SlabHeader)).prev = header.prev };
+        }
+        header.prev = 0;
+        header.next = 0;
+    }
+}
+
+impl<T> KmemCache<T> {
+    /// Creates an empty cache of objects of `T`.
+    pub const fn new(name: &'static str) -> Self {
+        Self::with_layout(name, Layout::new::<T>(), None)
+    }
+
+    /// Creates an empty cache of objects of `T`, which are initialized by
+    /// `ctor` when allocated by [`KmemCache::alloc_init`].
+    pub const fn with_ctor(name: &'static str, ctor: fn() -> T) -> Self {
+        Self::with_layout(name, Layout::new::<T>(), Some(ctor))
+    }
+
+    /// Creates an empty cache of the allocations of `Arc<T>`, i.e. `T`
+    /// preceded by the strong and weak reference counts. Once registered, it
+    /// serves all `Arc::new(T)`s.
+    pub const fn new_arc(name: &'static str) -> Self {
+        let counts = 2 * size_of::<usize>();
+        let align = if align_of::<T>() > align_of::<usize>() {
+            align_of::<T>()
+        } else {
+            align_of::<usize>()
+        };
+        let size = align_up(align_up(counts, align_of::<T>()) + size_of::<T>(), align);
+        let layout = unsafe { Layout::from_size_align_unchecked(size, align) };
+        Self::with_layout(name, layout, None)
+    }
+
+    const fn with_layout(name: &'static str, layout: Layout, ctor: Option<fn() -> T>) -> Self {
+        Self {
+            cache: SlabCache::new(name, layout.size(), layout.align()),
+            ctor,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Lists the cache in [`slab_stats`], and lets the global allocator
+    /// serve allocations of exactly its layout from it.
+    pub fn register(&'static self) {
+        let idx = TYPED_CACHE_COUNT.load(Ordering::Acquire);
+        assert!(idx < MAX_TYPED_CACHES, "too many typed slab caches");
+        TYPED_CACHES[idx].store(&self.cache as *const _ as *mut _, Ordering::Release);
+        TYPED_CACHE_COUNT.store(idx + 1, Ordering::Release);
+    }
+
+    /// Allocates an object initialized to `value`.
+    pub fn alloc(&'static self, value: T) -> KBox<T> {
+        Box::new_in(value, self)
+    }
+
+    /// Allocates an object initialized by the constructor of the cache, or
+    /// by `T::default()` if it has none.
+    pub fn alloc_init(&'static self) -> KBox<T>
+    where
+        T: Default,
+    {
+        self.alloc(self.ctor.map_or_else(T::default, |ctor| ctor()))
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        self.cache.stats()
+    }
+}
+
+unsafe impl<T> Allocator for &'static KmemCache<T> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !self.cache.fits(&layout) {
+            return Err(AllocError);
+        }
+        let ptr = self.cache.alloc().ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, self.cache.object_size))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.cache.dealloc(ptr)
+    }
+}
+
+/// Allocates the memory of a slab, returns its address and whether it is
+/// allocated from the heap.
+fn alloc_slab_memory() -> Option<(usize, bool)> {
+    let count = SLAB_SIZE / PAGE_SIZE;
+    if let Some(frames) = PhysFrame::alloc_contiguous(count, count) {
+        let slab = frames.start_paddr().into_kvaddr().as_usize();
+        // The frames are owned by the slab since then.
+        core::mem::forget(frames);
+        Some((slab, false))
+    } else {
+        // The frame allocator is not initialized in early boot.
+        alloc_slab(SLAB_SIZE).map(|ptr| (ptr.as_ptr() as usize, true))
+    }
+}
+
+fn release_slab_memory(slab: usize, from_heap: bool) {
+    if from_heap {
+        unsafe { dealloc_slab(NonNull::new_unchecked(slab as *mut u8), SLAB_SIZE) };
+    } else {
+        let paddr = PhysAddr::new(virt_to_phys(slab));
+        drop(unsafe { PhysFrame::from_raw(paddr, SLAB_SIZE / PAGE_SIZE) });
+    }
+}
+
+/// Returns the slab cache that serves global allocations of `layout`: the
+/// registered typed cache of exactly `layout`, or the cache of the size class
+/// that can hold it. Returns `None` if `layout` is too large.
+pub(super) fn cache_for(layout: &Layout) -> Option<&'static SlabCache> {
+    let size = layout.size().max(layout.align());
+    if size > MAX_OBJECT_SIZE {
+        return None;
+    }
+    let count = TYPED_CACHE_COUNT.load(Ordering::Acquire);
+    for cache in &TYPED_CACHES[..count] {
+        let cache = unsafe { &*cache.load(Ordering::Acquire) };
+        if cache.layout == *layout {
+            return Some(cache);
+        }
+    }
+    let index = size.max(16).next_power_of_two().trailing_zeros() - 4;
+    Some(&SIZE_CLASSES[index as usize])
+}
+
+/// Returns an object to the cache it is allocated from.
+///
+/// # Safety
+///
+/// `ptr` must be allocated by [`SlabCache::alloc`] of any cache.
+pub(super) unsafe fn dealloc(ptr: NonNull<u8>) {
+    let cache = &*SlabHeader::of(ptr.as_ptr() as usize).cache;
+    cache.dealloc(ptr);
+}
+
+/// Statistics of all slab caches.
+#[cfg_attr(test, allow(dead_code))]
+pub fn slab_stats() -> Vec<SlabStats> {
+    let count = TYPED_CACHE_COUNT.load(Ordering::Acquire);
+    let typed = TYPED_CACHES[..count]
+        .iter()
+        .map(|cache| unsafe { &*cache.load(Ordering::Acquire) });
+    SIZE_CLASSES
+        .iter()
+        .chain(typed)
+        .map(|cache| cache.stats())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Object {
+        id: usize,
+        data: [u8; 32],
+    }
+
+    fn new_object() -> Object {
+        Object {
+            id: 42,
+            data: [1; 32],
+        }
+    }
+
+    #[test]
+    fn test_kmem_cache() {
+        static CACHE: KmemCache<Object> = KmemCache::with_ctor("test-object", new_object);
+        crate::mm::init_for_test();
+        let per_slab = CACHE.cache.objects_per_slab();
+        assert_eq!(CACHE.stats().object_size, 40);
+
+        let obj = CACHE.alloc_init();
+        assert_eq!(*obj, new_object());
+        assert_eq!(&*obj as *const _ as usize % align_of::<Object>(), 0);
+        drop(obj);
+        let stats = CACHE.stats();
+        assert_eq!((stats.slabs, stats.used_objects), (1, 0));
+
+        // grows by whole slabs
+        let mut objs: Vec<_> = (0..per_slab * 3)
+            .map(|id| CACHE.alloc(Object { id, data: [0; 32] }))
+            .collect();
+        let stats = CACHE.stats();
+        assert_eq!((stats.slabs, stats.used_objects), (3, per_slab * 3));
+        assert_eq!(stats.total_objects, per_slab * 3);
+        for (id, obj) in objs.iter().enumerate() {
+            assert_eq!(obj.id, id);
+        }
+
+        // freed objects are reused
+        let addr = &*objs[1] as *const _ as usize;
+        objs.remove(1);
+        let obj = CACHE.alloc(Object::default());
+        assert_eq!(&*obj as *const _ as usize, addr);
+        objs.insert(1, obj);
+
+        // empty slabs are released, except one
+        objs.truncate(per_slab);
+        drop(objs.split_off(1));
+        let stats = CACHE.stats();
+        assert_eq!((stats.slabs, stats.used_objects), (2, 1));
+        drop(objs);
+        let stats = CACHE.stats();
+        assert_eq!((stats.slabs, stats.used_objects), (1, 0));
+        assert_eq!(stats.total_objects, per_slab);
+    }
+
+    #[test]
+    fn test_arc_layout() {
+        /// The layout of `alloc::sync::ArcInner`.
+        #[repr(C)]
+        struct ArcInner<T> {
+            strong: AtomicUsize,
+            weak: AtomicUsize,
+            data: T,
+        }
+        static CACHE: KmemCache<Object> = KmemCache::new_arc("test-arc");
+        assert_eq!(CACHE.cache.layout, Layout::new::<ArcInner<Object>>());
+        static CACHE_U8: KmemCache<u8> = KmemCache::new_arc("test-arc-u8");
+        assert_eq!(CACHE_U8.cache.layout, Layout::new::<ArcInner<u8>>());
+    }
+}
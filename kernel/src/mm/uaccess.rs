@@ -4,41 +4,81 @@
 use core::mem::{align_of, size_of, MaybeUninit};
 use core::{fmt, marker::PhantomData};
 
+use crate::arch::uaccess::user_copy;
 use crate::config::{USER_ASPACE_BASE, USER_ASPACE_SIZE};
+use crate::errno::{Errno, SysResult};
+use crate::mm::PAGE_SIZE;
 
 #[allow(clippy::absurd_extreme_comparisons)]
 const fn uaccess_ok(vaddr: usize, size: usize) -> bool {
-    vaddr != 0 && USER_ASPACE_BASE <= vaddr && vaddr - USER_ASPACE_BASE <= USER_ASPACE_SIZE - size
+    vaddr != 0
+        && USER_ASPACE_BASE <= vaddr
+        && size <= USER_ASPACE_SIZE
+        && vaddr - USER_ASPACE_BASE <= USER_ASPACE_SIZE - size
 }
 
-unsafe fn copy_from_user<T>(kdst: *mut T, usrc: *const T, len: usize) {
-    assert!(uaccess_ok(usrc as usize, len * size_of::<T>()));
-    kdst.copy_from_nonoverlapping(usrc, len);
+fn check_user_ptr<T>(uptr: *const T, len: usize) -> SysResult<()> {
+    let size = len.checked_mul(size_of::<T>()).ok_or(Errno::EFAULT)?;
+    if uptr as usize % align_of::<T>() == 0 && uaccess_ok(uptr as usize, size) {
+        Ok(())
+    } else {
+        Err(Errno::EFAULT)
+    }
+}
+
+unsafe fn copy_from_user<T>(kdst: *mut T, usrc: *const T, len: usize) -> SysResult<()> {
+    check_user_ptr(usrc, len)?;
+    let size = len * size_of::<T>();
+    match user_copy(kdst as *mut u8, usrc as *const u8, size) {
+        0 => Ok(()),
+        _ => Err(Errno::EFAULT),
+    }
 }
 
-unsafe fn copy_to_user<T>(udst: *mut T, ksrc: *const T, len: usize) {
-    assert!(uaccess_ok(udst as usize, len * size_of::<T>()));
-    udst.copy_from_nonoverlapping(ksrc, len);
+unsafe fn copy_to_user<T>(udst: *mut T, ksrc: *const T, len: usize) -> SysResult<()> {
+    check_user_ptr(udst, len)?;
+    let size = len * size_of::<T>();
+    match user_copy(udst as *mut u8, ksrc as *const u8, size) {
+        0 => Ok(()),
+        _ => Err(Errno::EFAULT),
+    }
 }
 
-unsafe fn copy_from_user_str(kdst: *mut u8, usrc: *const u8, max_len: usize) -> usize {
-    assert!(uaccess_ok(usrc as usize, 1));
+/// Copies the string in chunks that do not cross pages, so that no page after
+/// the NUL byte is accessed.
+unsafe fn copy_from_user_str(kdst: *mut u8, usrc: *const u8, max_len: usize) -> SysResult<usize> {
     let mut len = 0;
-    let mut kdst = kdst;
-    let mut usrc = usrc;
-    while len < max_len {
-        assert!((usrc as usize) < USER_ASPACE_BASE + USER_ASPACE_SIZE);
-        let c = usrc.read();
-        if c == b'\0' {
-            break;
+    while len <= max_len {
+        let vaddr = (usrc as usize).wrapping_add(len);
+        let chunk_len = (PAGE_SIZE - vaddr % PAGE_SIZE).min(max_len + 1 - len);
+        copy_from_user(kdst.add(len), usrc.wrapping_add(len), chunk_len)?;
+        let chunk = core::slice::from_raw_parts(kdst.add(len), chunk_len);
+        if let Some(pos) = chunk.iter().position(|&c| c == b'\0') {
+            return Ok(len + pos);
         }
-        kdst.write(c);
-        len += 1;
-        kdst = kdst.add(1);
-        usrc = usrc.add(1);
+        len += chunk_len;
+    }
+    Err(Errno::ENAMETOOLONG)
+}
+
+/// An entry of the exception table, emitted by the assembly of user copies.
+#[repr(C)]
+struct ExceptionTableEntry {
+    fault_pc: usize,
+    fixup_pc: usize,
+}
+
+/// Returns the address to resume at if a page fault at `pc` is caused by
+/// accessing user memory in kernel mode.
+pub fn fixup_exception(pc: usize) -> Option<usize> {
+    extern "C" {
+        fn __ex_table_start();
+        fn __ex_table_end();
     }
-    kdst.write(b'\0');
-    len
+    let start = __ex_table_start as usize;
+    let len = (__ex_table_end as usize - start) / size_of::<ExceptionTableEntry>();
+    let table = unsafe { core::slice::from_raw_parts(start as *const ExceptionTableEntry, len) };
+    table.iter().find(|e| e.fault_pc == pc).map(|e| e.fixup_pc)
 }
 
 pub trait Policy {}
@@ -73,7 +113,6 @@ impl<T, P: Policy> fmt::Debug for UserPtr<T, P> {
 
 impl<T, P: Policy> From<usize> for UserPtr<T, P> {
     fn from(user_vadddr: usize) -> Self {
-        assert!(user_vadddr % align_of::<T>() == 0);
         Self {
             ptr: user_vadddr as *mut T,
             _phantom: PhantomData,
@@ -96,42 +135,48 @@ impl<T, P: Policy> UserPtr<T, P> {
 
     pub unsafe fn add(&self, count: usize) -> Self {
         Self {
-            ptr: self.ptr.add(count),
+            ptr: self.ptr.wrapping_add(count),
             _phantom: PhantomData,
         }
     }
 }
 
 impl<T, P: ReadPolicy> UserPtr<T, P> {
-    pub fn read(&self) -> T {
+    pub fn read(&self) -> SysResult<T> {
         let mut value = MaybeUninit::uninit();
         unsafe {
-            copy_from_user(value.as_mut_ptr(), self.ptr, 1);
-            value.assume_init()
+            copy_from_user(value.as_mut_ptr(), self.ptr, 1)?;
+            Ok(value.assume_init())
         }
     }
 
-    pub fn read_array<const N: usize>(&self, max_len: usize) -> [T; N] {
+    pub fn read_buf(&self, buf: &mut [T]) -> SysResult<()> {
+        unsafe { copy_from_user(buf.as_mut_ptr(), self.ptr, buf.len()) }
+    }
+
+    pub fn read_array<const N: usize>(&self, max_len: usize) -> SysResult<[T; N]> {
         let mut buf: [T; N] = unsafe { MaybeUninit::uninit().assume_init() };
-        unsafe { copy_from_user(buf.as_mut_ptr(), self.ptr, max_len.min(N)) };
-        buf
+        unsafe { copy_from_user(buf.as_mut_ptr(), self.ptr, max_len.min(N))? };
+        Ok(buf)
     }
 }
 
 impl<P: ReadPolicy> UserPtr<u8, P> {
-    pub fn read_str<const N: usize>(&self) -> ([u8; N], usize) {
+    /// Reads a NUL-terminated string, fails with `ENAMETOOLONG` if it does not
+    /// fit in `N` bytes.
+    pub fn read_str<const N: usize>(&self) -> SysResult<([u8; N], usize)> {
         let mut buf: [u8; N] = unsafe { MaybeUninit::uninit().assume_init() };
-        let len = unsafe { copy_from_user_str(buf.as_mut_ptr(), self.ptr, N - 1) };
-        (buf, len)
+        let len = unsafe { copy_from_user_str(buf.as_mut_ptr(), self.ptr, N - 1)? };
+        Ok((buf, len))
     }
 }
 
 impl<T, P: WritePolicy> UserPtr<T, P> {
-    pub fn write(&mut self, value: T) {
+    pub fn write(&mut self, value: T) -> SysResult<()> {
         unsafe { copy_to_user(self.ptr, &value as *const T, 1) }
     }
 
-    pub fn write_buf(&mut self, buf: &[T]) {
-        unsafe { copy_to_user(self.ptr, buf.as_ptr(), buf.len()) };
+    pub fn write_buf(&mut self, buf: &[T]) -> SysResult<()> {
+        unsafe { copy_to_user(self.ptr, buf.as_ptr(), buf.len()) }
     }
 }
@@ -0,0 +1,71 @@
+//! Adapts network devices to the `smoltcp` device interface.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::time::Instant;
+
+use crate::drivers::net::NetDevice;
+
+pub struct EthernetDevice {
+    dev: Arc<dyn NetDevice>,
+}
+
+pub struct EthernetRxToken(Vec<u8>);
+
+pub struct EthernetTxToken<'a>(&'a dyn NetDevice);
+
+impl EthernetDevice {
+    pub fn new(dev: Arc<dyn NetDevice>) -> Self {
+        Self { dev }
+    }
+}
+
+impl Device for EthernetDevice {
+    type RxToken<'a> = EthernetRxToken;
+    type TxToken<'a> = EthernetTxToken<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let frame = self.dev.receive()?;
+        Some((EthernetRxToken(frame), EthernetTxToken(&*self.dev)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        if self.dev.can_transmit() {
+            Some(EthernetTxToken(&*self.dev))
+        } else {
+            None
+        }
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = self.dev.max_frame_size();
+        caps
+    }
+}
+
+impl RxToken for EthernetRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl<'a> TxToken for EthernetTxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut frame = vec![0; len];
+        let ret = f(&mut frame);
+        if let Err(e) = self.0.transmit(&frame) {
+            // dropped, as if it is lost on the wire
+            warn!("{}: failed to transmit: {:?}", self.0.name(), e);
+        }
+        ret
+    }
+}
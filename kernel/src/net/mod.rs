@@ -0,0 +1,377 @@
+//! The network subsystem, built on the `smoltcp` TCP/IP stack.
+//!
+//! There are two interfaces: the loopback interface, and an ethernet
+//! interface on the first network device, with the static configuration of
+//! QEMU user-mode networking. A socket is placed on the loopback interface if
+//! it talks to a local address. The stack is polled by a kernel task when
+//! frames are received or timers expire, and by the tasks using sockets.
+//!
+//! The stack is protected by a sleeping lock, as it is only used by tasks:
+//! the interrupt handlers of the devices only wake up the network task, and
+//! the device queues accessed by the polls have their own IRQ-safe locks.
+
+mod device;
+mod tcp;
+
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{Device, Loopback, Medium};
+use smoltcp::socket::tcp::{Socket, SocketBuffer, State};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint};
+
+use self::device::EthernetDevice;
+use crate::drivers::net::{net_devices, set_rx_handler};
+use crate::errno::{Errno, SysResult};
+use crate::sync::{LazyInit, SleepMutex};
+use crate::task::{self, current, Task, WaitQueue};
+use crate::timer::{current_time, TimeValue};
+
+pub use self::tcp::TcpSocket;
+pub use smoltcp::wire::Ipv4Address;
+
+/// The address of the guest in QEMU user-mode networking.
+const ETH_IP: Ipv4Address = Ipv4Address::new(10, 0, 2, 15);
+const ETH_PREFIX_LEN: u8 = 24;
+const GATEWAY_IP: Ipv4Address = Ipv4Address::new(10, 0, 2, 2);
+const LOOPBACK_IP: Ipv4Address = Ipv4Address::new(127, 0, 0, 1);
+
+const TCP_BUF_SIZE: usize = 16 * 1024;
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+/// The network task polls the stack at least at this interval.
+const MAX_POLL_INTERVAL: TimeValue = TimeValue::from_millis(100);
+
+static NET: LazyInit<SleepMutex<NetStack>> = LazyInit::new();
+
+/// Tasks blocked on sockets, woken up when the readiness of sockets may have
+/// changed, i.e. when `SOCKET_EVENTS` is increased.
+static SOCKET_WAITERS: LazyInit<WaitQueue> = LazyInit::new();
+static SOCKET_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The network task, woken up when `POLL_PENDING` is set.
+static POLL_WAITERS: LazyInit<WaitQueue> = LazyInit::new();
+static POLL_PENDING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum IfaceId {
+    Loopback,
+    Ethernet,
+}
+
+/// A TCP socket in the socket set of an interface.
+#[derive(Debug, Clone, Copy)]
+struct SocketRef {
+    iface: IfaceId,
+    handle: SocketHandle,
+}
+
+/// An IPv4 address and a port.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SocketAddrV4 {
+    pub addr: Ipv4Address,
+    pub port: u16,
+}
+
+struct Iface<D: Device> {
+    iface: Interface,
+    dev: D,
+    sockets: SocketSet<'static>,
+}
+
+pub(crate) struct NetStack {
+    lo: Iface<Loopback>,
+    eth: Option<Iface<EthernetDevice>>,
+    /// Local ports that are bound.
+    ports: BTreeSet<u16>,
+    next_ephemeral_port: u16,
+    /// Sockets closed by users, which are removed when the connections are
+    /// closed, and the local ports they own.
+    orphans: Vec<(SocketRef, Option<u16>)>,
+}
+
+fn now() -> Instant {
+    Instant::from_micros(current_time().as_micros() as i64)
+}
+
+impl SocketAddrV4 {
+    pub const fn new(addr: Ipv4Address, port: u16) -> Self {
+        Self { addr, port }
+    }
+}
+
+impl From<SocketAddrV4> for IpEndpoint {
+    fn from(addr: SocketAddrV4) -> Self {
+        IpEndpoint::new(addr.addr.into(), addr.port)
+    }
+}
+
+impl From<IpEndpoint> for SocketAddrV4 {
+    fn from(endpoint: IpEndpoint) -> Self {
+        // only IPv4 is enabled
+        let IpAddress::Ipv4(addr) = endpoint.addr;
+        Self::new(addr, endpoint.port)
+    }
+}
+
+impl<D: Device> Iface<D> {
+    fn new(mut dev: D, hardware_addr: HardwareAddress, addrs: &[IpCidr]) -> Self {
+        let config = Config::new(hardware_addr);
+        let mut iface = Interface::new(config, &mut dev, now());
+        iface.update_ip_addrs(|ip_addrs| {
+            for &addr in addrs {
+                ip_addrs.push(addr).unwrap();
+            }
+        });
+        Self {
+            iface,
+            dev,
+            sockets: SocketSet::new(vec![]),
+        }
+    }
+
+    fn poll(&mut self, timestamp: Instant) -> bool {
+        self.iface.poll(timestamp, &mut self.dev, &mut self.sockets)
+    }
+
+    fn poll_delay(&mut self, timestamp: Instant) -> Option<Duration> {
+        self.iface.poll_delay(timestamp, &self.sockets)
+    }
+}
+
+impl NetStack {
+    fn new() -> Self {
+        let lo_addrs = [
+            IpCidr::new(LOOPBACK_IP.into(), 8),
+            IpCidr::new(ETH_IP.into(), 32),
+        ];
+        let lo = Iface::new(Loopback::new(Medium::Ip), HardwareAddress::Ip, &lo_addrs);
+        let eth = net_devices().first().map(|dev| {
+            let mac = EthernetAddress(dev.mac_address());
+            let eth_addrs = [IpCidr::new(ETH_IP.into(), ETH_PREFIX_LEN)];
+            let mut eth = Iface::new(EthernetDevice::new(dev.clone()), mac.into(), &eth_addrs);
+            eth.iface
+                .routes_mut()
+                .add_default_ipv4_route(GATEWAY_IP)
+                .unwrap();
+            println!(
+                "Network interface on {}: {}/{}",
+                dev.name(),
+                ETH_IP,
+                ETH_PREFIX_LEN
+            );
+            eth
+        });
+        Self {
+            lo,
+            eth,
+            ports: BTreeSet::new(),
+            next_ephemeral_port: *EPHEMERAL_PORTS.start(),
+            orphans: Vec::new(),
+        }
+    }
+
+    fn sockets(&mut self, iface: IfaceId) -> &mut SocketSet<'static> {
+        match iface {
+            IfaceId::Loopback => &mut self.lo.sockets,
+            IfaceId::Ethernet => &mut self.eth.as_mut().unwrap().sockets,
+        }
+    }
+
+    fn tcp(&mut self, sock: SocketRef) -> &mut Socket<'static> {
+        self.sockets(sock.iface).get_mut(sock.handle)
+    }
+
+    fn add_tcp(&mut self, iface: IfaceId) -> SocketRef {
+        let socket = Socket::new(
+            SocketBuffer::new(vec![0; TCP_BUF_SIZE]),
+            SocketBuffer::new(vec![0; TCP_BUF_SIZE]),
+        );
+        let handle = self.sockets(iface).add(socket);
+        SocketRef { iface, handle }
+    }
+
+    fn remove(&mut self, sock: SocketRef) {
+        self.sockets(sock.iface).remove(sock.handle);
+    }
+
+    /// Returns the interface to reach `addr`.
+    fn route(&self, addr: Ipv4Address) -> SysResult<IfaceId> {
+        if addr.is_unspecified() {
+            Err(Errno::EINVAL)
+        } else if addr.is_loopback() || addr == ETH_IP {
+            Ok(IfaceId::Loopback)
+        } else if self.eth.is_some() {
+            Ok(IfaceId::Ethernet)
+        } else {
+            Err(Errno::ENETUNREACH)
+        }
+    }
+
+    /// Returns the interfaces where a socket bound to `addr` receives
+    /// connections.
+    fn listen_ifaces(&self, addr: Ipv4Address) -> Vec<IfaceId> {
+        let mut ifaces = vec![IfaceId::Loopback];
+        if self.eth.is_some() && !addr.is_loopback() {
+            ifaces.push(IfaceId::Ethernet);
+        }
+        ifaces
+    }
+
+    fn is_local_addr(&self, addr: Ipv4Address) -> bool {
+        addr.is_unspecified() || addr.is_loopback() || (addr == ETH_IP && self.eth.is_some())
+    }
+
+    /// Binds the local port, or an unused ephemeral port if `port` is 0.
+    fn alloc_port(&mut self, port: u16) -> SysResult<u16> {
+        if port != 0 {
+            return if self.ports.insert(port) {
+                Ok(port)
+            } else {
+                Err(Errno::EADDRINUSE)
+            };
+        }
+        for _ in EPHEMERAL_PORTS {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = if port == *EPHEMERAL_PORTS.end() {
+                *EPHEMERAL_PORTS.start()
+            } else {
+                port + 1
+            };
+            if self.ports.insert(port) {
+                return Ok(port);
+            }
+        }
+        Err(Errno::EADDRINUSE)
+    }
+
+    fn free_port(&mut self, port: u16) {
+        self.ports.remove(&port);
+    }
+
+    /// Starts connecting `sock` to `remote`.
+    fn connect(&mut self, sock: SocketRef, remote: IpEndpoint, local_port: u16) -> SysResult<()> {
+        let (iface, sockets) = match sock.iface {
+            IfaceId::Loopback => (&mut self.lo.iface, &mut self.lo.sockets),
+            IfaceId::Ethernet => {
+                let eth = self.eth.as_mut().unwrap();
+                (&mut eth.iface, &mut eth.sockets)
+            }
+        };
+        sockets
+            .get_mut::<Socket>(sock.handle)
+            .connect(iface.context(), remote, local_port)
+            .map_err(|_| Errno::EINVAL)
+    }
+
+    /// Closes the connection gracefully, the socket is removed later.
+    fn close_orphan(&mut self, sock: SocketRef, port: Option<u16>) {
+        self.tcp(sock).close();
+        self.orphans.push((sock, port));
+    }
+
+    fn poll(&mut self) -> bool {
+        let timestamp = now();
+        let mut changed = self.lo.poll(timestamp);
+        if let Some(eth) = &mut self.eth {
+            changed |= eth.poll(timestamp);
+        }
+
+        let mut i = 0;
+        while i < self.orphans.len() {
+            let (sock, port) = self.orphans[i];
+            // TIME-WAIT is skipped, as the port is not reused immediately
+            if matches!(self.tcp(sock).state(), State::Closed | State::TimeWait) {
+                self.remove(sock);
+                if let Some(port) = port {
+                    self.free_port(port);
+                }
+                self.orphans.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        changed
+    }
+
+    fn poll_delay(&mut self) -> Option<Duration> {
+        let timestamp = now();
+        let lo_delay = self.lo.poll_delay(timestamp);
+        let eth_delay = self.eth.as_mut().and_then(|eth| eth.poll_delay(timestamp));
+        match (lo_delay, eth_delay) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Polls the stack, and wakes up the tasks blocked on sockets if anything
+/// is changed.
+fn poll() {
+    if NET.lock().poll() {
+        SOCKET_EVENTS.fetch_add(1, Ordering::SeqCst);
+        SOCKET_WAITERS.notify_all();
+    }
+}
+
+/// Wakes up the network task, to poll the stack and recompute the time of the
+/// next poll.
+fn kick() {
+    POLL_PENDING.store(true, Ordering::SeqCst);
+    POLL_WAITERS.notify_one();
+}
+
+/// Calls `f` with the stack until it does not return [`Errno::EAGAIN`], or
+/// once if `nonblocking` is true. The stack is polled before `f`, and after
+/// `f` to send what it queues.
+fn block_on<T>(
+    nonblocking: bool,
+    mut f: impl FnMut(&mut NetStack) -> SysResult<T>,
+) -> SysResult<T> {
+    let curr = current();
+    poll();
+    loop {
+        let events = SOCKET_EVENTS.load(Ordering::SeqCst);
+        let ret = f(&mut NET.lock());
+        poll();
+        kick();
+        match ret {
+            Err(Errno::EAGAIN) if !nonblocking => {}
+            _ => return ret,
+        }
+        SOCKET_WAITERS.wait_until(|| {
+            SOCKET_EVENTS.load(Ordering::SeqCst) != events || curr.is_group_exiting()
+        });
+        if curr.is_group_exiting() {
+            return Err(Errno::EINTR);
+        }
+    }
+}
+
+fn handle_rx() {
+    kick();
+}
+
+fn net_task(_arg: usize) -> usize {
+    loop {
+        poll();
+        let delay = NET.lock().poll_delay().map_or(MAX_POLL_INTERVAL, |d| {
+            TimeValue::from_micros(d.total_micros()).min(MAX_POLL_INTERVAL)
+        });
+        POLL_WAITERS.wait_until_timeout(
+            || POLL_PENDING.swap(false, Ordering::SeqCst),
+            current_time() + delay,
+        );
+    }
+}
+
+/// Initializes the network stack, and starts the network task.
+pub fn init() {
+    println!("Initializing network stack...");
+    SOCKET_WAITERS.init_by(WaitQueue::new());
+    POLL_WAITERS.init_by(WaitQueue::new());
+    NET.init_by(SleepMutex::new(NetStack::new()));
+    set_rx_handler(handle_rx);
+    task::spawn_task(Task::new_daemon(net_task, 0));
+}
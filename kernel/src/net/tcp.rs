@@ -0,0 +1,317 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use smoltcp::socket::tcp::State as TcpState;
+use smoltcp::wire::{IpListenEndpoint, Ipv4Address};
+
+use super::{block_on, IfaceId, NetStack, SocketAddrV4, SocketRef, NET};
+use crate::errno::{Errno, SysResult};
+use crate::fs::File;
+use crate::sync::Mutex;
+
+/// The maximum number of pending connections on each interface.
+const MAX_BACKLOG: usize = 8;
+
+enum State {
+    /// Not connected, with the local address if it is bound.
+    Closed { local: Option<SocketAddrV4> },
+    /// Listening on the local port, with sockets in the LISTEN state that
+    /// receive connections.
+    Listening {
+        local: SocketAddrV4,
+        backlog: Vec<SocketRef>,
+    },
+    /// Connecting or connected, the local port is released when the socket
+    /// is removed if it is owned.
+    Connected {
+        sock: SocketRef,
+        owned_port: Option<u16>,
+    },
+}
+
+/// A TCP socket, which is an open file.
+///
+/// The state is only accessed with the network stack locked.
+pub struct TcpSocket {
+    state: Mutex<State>,
+    nonblocking: AtomicBool,
+}
+
+impl TcpSocket {
+    pub fn new(nonblocking: bool) -> Self {
+        Self {
+            state: Mutex::new(State::Closed { local: None }),
+            nonblocking: AtomicBool::new(nonblocking),
+        }
+    }
+
+    fn connected(sock: SocketRef) -> Self {
+        Self {
+            state: Mutex::new(State::Connected {
+                sock,
+                owned_port: None,
+            }),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+
+    /// Binds the socket to the local address, or an ephemeral port if `port`
+    /// is 0.
+    pub fn bind(&self, local: SocketAddrV4) -> SysResult<()> {
+        let mut net = NET.lock();
+        let mut state = self.state.lock();
+        match &mut *state {
+            State::Closed {
+                local: bound @ None,
+            } => {
+                if !net.is_local_addr(local.addr) {
+                    return Err(Errno::EADDRNOTAVAIL);
+                }
+                let port = net.alloc_port(local.port)?;
+                *bound = Some(SocketAddrV4::new(local.addr, port));
+                Ok(())
+            }
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    pub fn listen(&self, backlog: usize) -> SysResult<()> {
+        let mut net = NET.lock();
+        let mut state = self.state.lock();
+        let (local, bound) = match &*state {
+            State::Closed { local: Some(local) } => (*local, true),
+            State::Closed { local: None } => {
+                let port = net.alloc_port(0)?;
+                (SocketAddrV4::new(Ipv4Address::UNSPECIFIED, port), false)
+            }
+            // changing the backlog is not supported
+            State::Listening { .. } => return Ok(()),
+            State::Connected { .. } => return Err(Errno::EINVAL),
+        };
+        let backlog = backlog.clamp(1, MAX_BACKLOG);
+        let mut socks = Vec::new();
+        for iface in net.listen_ifaces(local.addr) {
+            for _ in 0..backlog {
+                match listen_on(&mut net, iface, local) {
+                    Ok(sock) => socks.push(sock),
+                    Err(e) => {
+                        for sock in socks {
+                            net.remove(sock);
+                        }
+                        if !bound {
+                            net.free_port(local.port);
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        *state = State::Listening {
+            local,
+            backlog: socks,
+        };
+        Ok(())
+    }
+
+    /// Waits for a connection, returns the new socket. `f` is called with the
+    /// remote address before the connection is taken from the backlog, and
+    /// the connection stays there if `f` fails.
+    pub fn accept(&self, f: impl FnOnce(SocketAddrV4) -> SysResult<()>) -> SysResult<TcpSocket> {
+        let mut f = Some(f);
+        block_on(self.is_nonblocking(), |net| {
+            let (local, i, sock) = match &*self.state.lock() {
+                State::Listening { local, backlog } => {
+                    let ready = backlog.iter().position(|&sock| {
+                        matches!(
+                            net.tcp(sock).state(),
+                            TcpState::Established | TcpState::CloseWait
+                        )
+                    });
+                    let i = ready.ok_or(Errno::EAGAIN)?;
+                    (*local, i, backlog[i])
+                }
+                _ => return Err(Errno::EINVAL),
+            };
+            let remote = net.tcp(sock).remote_endpoint().unwrap();
+            // `f` may access the user memory, so the state is not locked, but
+            // it is not changed as the stack is locked
+            (f.take().unwrap())(remote.into())?;
+            // replace it with a new listening socket
+            let new = listen_on(net, sock.iface, local)?;
+            if let State::Listening { backlog, .. } = &mut *self.state.lock() {
+                backlog[i] = new;
+            }
+            Ok(TcpSocket::connected(sock))
+        })
+    }
+
+    /// Connects to the remote address, returns [`Errno::EINPROGRESS`] if the
+    /// socket is non-blocking and the connection is not established yet.
+    pub fn connect(&self, remote: SocketAddrV4) -> SysResult<()> {
+        let mut connecting = false;
+        block_on(self.is_nonblocking(), |net| {
+            let mut state = self.state.lock();
+            match &*state {
+                State::Closed { local } => {
+                    let iface = net.route(remote.addr)?;
+                    let local = *local;
+                    let port = match local {
+                        Some(local) => local.port,
+                        None => net.alloc_port(0)?,
+                    };
+                    let sock = net.add_tcp(iface);
+                    if let Err(e) = net.connect(sock, remote.into(), port) {
+                        net.remove(sock);
+                        if local.is_none() {
+                            net.free_port(port);
+                        }
+                        return Err(e);
+                    }
+                    *state = State::Connected {
+                        sock,
+                        owned_port: Some(port),
+                    };
+                    connecting = true;
+                    Err(Errno::EAGAIN)
+                }
+                State::Listening { .. } => Err(Errno::EINVAL),
+                State::Connected { sock, .. } => match net.tcp(*sock).state() {
+                    TcpState::SynSent | TcpState::SynReceived if connecting => Err(Errno::EAGAIN),
+                    TcpState::SynSent | TcpState::SynReceived => Err(Errno::EALREADY),
+                    TcpState::Closed if connecting => Err(Errno::ECONNREFUSED),
+                    _ if connecting => Ok(()),
+                    _ => Err(Errno::EISCONN),
+                },
+            }
+        })
+        .map_err(|e| match e {
+            Errno::EAGAIN => Errno::EINPROGRESS,
+            e => e,
+        })
+    }
+
+    /// Sends data, returns the number of bytes queued.
+    pub fn send(&self, buf: &[u8], nonblocking: bool) -> SysResult<usize> {
+        block_on(nonblocking || self.is_nonblocking(), |net| {
+            let socket = net.tcp(self.connected_sock()?);
+            match socket.state() {
+                TcpState::SynSent | TcpState::SynReceived => return Err(Errno::EAGAIN),
+                _ if !socket.may_send() => return Err(Errno::EPIPE),
+                _ => {}
+            }
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            match socket.send_slice(buf) {
+                Ok(0) => Err(Errno::EAGAIN),
+                Ok(len) => Ok(len),
+                Err(_) => Err(Errno::EPIPE),
+            }
+        })
+    }
+
+    /// Receives data, returns 0 if the connection is closed by the peer.
+    pub fn recv(&self, buf: &mut [u8], nonblocking: bool) -> SysResult<usize> {
+        block_on(nonblocking || self.is_nonblocking(), |net| {
+            let socket = net.tcp(self.connected_sock()?);
+            match socket.state() {
+                TcpState::SynSent | TcpState::SynReceived => return Err(Errno::EAGAIN),
+                _ if !socket.may_recv() => return Ok(0),
+                _ => {}
+            }
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            match socket.recv_slice(buf) {
+                Ok(0) => Err(Errno::EAGAIN),
+                Ok(len) => Ok(len),
+                Err(_) => Ok(0),
+            }
+        })
+    }
+
+    fn connected_sock(&self) -> SysResult<SocketRef> {
+        match &*self.state.lock() {
+            State::Connected { sock, .. } => Ok(*sock),
+            _ => Err(Errno::ENOTCONN),
+        }
+    }
+
+    /// The local address, which is unspecified if the socket is not bound.
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        let mut net = NET.lock();
+        let unspecified = SocketAddrV4::new(Ipv4Address::UNSPECIFIED, 0);
+        match &*self.state.lock() {
+            State::Closed { local } => local.unwrap_or(unspecified),
+            State::Listening { local, .. } => *local,
+            State::Connected { sock, .. } => net
+                .tcp(*sock)
+                .local_endpoint()
+                .map_or(unspecified, Into::into),
+        }
+    }
+
+    /// The remote address of a connected socket.
+    pub fn peer_addr(&self) -> SysResult<SocketAddrV4> {
+        let mut net = NET.lock();
+        let sock = self.connected_sock()?;
+        let remote = net.tcp(sock).remote_endpoint().ok_or(Errno::ENOTCONN)?;
+        Ok(remote.into())
+    }
+}
+
+/// Adds a socket listening on `local` to `iface`.
+fn listen_on(net: &mut NetStack, iface: IfaceId, local: SocketAddrV4) -> SysResult<SocketRef> {
+    let sock = net.add_tcp(iface);
+    let endpoint = IpListenEndpoint {
+        addr: (!local.addr.is_unspecified()).then(|| local.addr.into()),
+        port: local.port,
+    };
+    if net.tcp(sock).listen(endpoint).is_err() {
+        net.remove(sock);
+        return Err(Errno::EINVAL);
+    }
+    Ok(sock)
+}
+
+impl File for TcpSocket {
+    fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        self.recv(buf, false)
+    }
+
+    fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        self.send(buf, false)
+    }
+
+    fn as_socket(&self) -> Option<&TcpSocket> {
+        Some(self)
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        let mut net = NET.lock();
+        match &*self.state.lock() {
+            State::Closed { local } => {
+                if let Some(local) = local {
+                    net.free_port(local.port);
+                }
+            }
+            State::Listening { local, backlog } => {
+                for &sock in backlog {
+                    // reset pending connections
+                    net.tcp(sock).abort();
+                    net.orphans.push((sock, None));
+                }
+                net.free_port(local.port);
+            }
+            State::Connected { sock, owned_port } => net.close_orphan(*sock, *owned_port),
+        }
+        drop(net);
+        super::kick();
+    }
+}
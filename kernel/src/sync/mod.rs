@@ -1,9 +1,16 @@
 mod lazy_init;
 mod mutex;
 mod percpu;
+#[cfg(not(test))]
+mod sleep_mutex;
 mod spin;
 
 pub use lazy_init::LazyInit;
 pub use mutex::Mutex;
 pub use percpu::PerCpuData;
+#[cfg(not(test))]
+pub use sleep_mutex::{SleepMutex, SleepMutexGuard};
+/// Tasks can not sleep on the host.
+#[cfg(test)]
+pub use spin::SpinNoIrqLock as SleepMutex;
 pub use spin::{spin_lock_irqsave, spin_trylock_irqsave, spin_unlock_irqrestore, SpinNoIrqLock};
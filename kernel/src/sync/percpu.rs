@@ -3,12 +3,14 @@ use core::cell::UnsafeCell;
 /// A wrapper contains the data owned by each CPU and can only be accessed by
 /// that CPU.
 #[repr(transparent)]
+#[cfg_attr(test, allow(dead_code))]
 pub struct PerCpuData<T> {
     data: UnsafeCell<T>,
 }
 
 unsafe impl<T: Send> Sync for PerCpuData<T> {}
 
+#[cfg_attr(test, allow(dead_code))]
 impl<T> PerCpuData<T> {
     pub const fn new(data: T) -> Self {
         Self {
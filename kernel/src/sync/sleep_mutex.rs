@@ -0,0 +1,77 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::task::{self, WaitQueue};
+
+/// A mutex that blocks the current task instead of spinning, so it can be
+/// held across operations that sleep, such as block device I/O.
+///
+/// Before the task manager is initialized, it spins like a spin lock.
+pub struct SleepMutex<T: ?Sized> {
+    locked: AtomicBool,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+pub struct SleepMutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a SleepMutex<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for SleepMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for SleepMutex<T> {}
+
+impl<T> SleepMutex<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T: ?Sized> SleepMutex<T> {
+    fn try_acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn lock(&self) -> SleepMutexGuard<T> {
+        if task::is_init() {
+            self.waiters.wait_until(|| self.try_acquire());
+        } else {
+            while !self.try_acquire() {
+                core::hint::spin_loop();
+            }
+        }
+        SleepMutexGuard { mutex: self }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for SleepMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SleepMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SleepMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        // Tasks only wait after the task manager is initialized. A waiter
+        // checks the flag and enqueues itself with the task manager locked,
+        // so the queue is not checked here without that lock, or a waiter
+        // that has just seen the mutex locked would miss the wakeup.
+        if task::is_init() {
+            self.mutex.waiters.notify_one();
+        }
+    }
+}
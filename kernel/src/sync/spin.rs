@@ -64,10 +64,12 @@ impl<T> SpinNoIrqLock<T> {
         }
     }
 
+    #[cfg_attr(test, allow(dead_code))]
     pub fn is_locked(&self) -> bool {
         self.lock.load(Ordering::Relaxed)
     }
 
+    #[cfg_attr(test, allow(dead_code))]
     pub unsafe fn force_unlock(&self) {
         self.lock.store(false, Ordering::Release);
     }
@@ -1,44 +1,172 @@
-use crate::drivers::uart::console_getchar;
+use alloc::{vec, vec::Vec};
+
+use crate::errno::{Errno, SysResult};
+use crate::fs::{self, DirEntry, InodeType, OpenFlags, SeekFrom};
 use crate::mm::{UserInPtr, UserOutPtr};
+use crate::task::current;
+
+const MAX_PATH_LEN: usize = 256;
+const CHUNK_SIZE: usize = 4096;
+
+const SEEK_SET: u32 = 0;
+const SEEK_CUR: u32 = 1;
+const SEEK_END: u32 = 2;
+
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
+/// The offset of `d_name` in `struct linux_dirent64`, after `d_ino: u64`,
+/// `d_off: i64`, `d_reclen: u16` and `d_type: u8`.
+const DIRENT64_NAME_OFFSET: usize = 19;
+
+fn read_path(path: UserInPtr<u8>) -> SysResult<([u8; MAX_PATH_LEN], usize)> {
+    let (buf, len) = path.read_str::<MAX_PATH_LEN>()?;
+    if len == 0 {
+        return Err(Errno::ENOENT);
+    }
+    Ok((buf, len))
+}
 
-const FD_STDIN: usize = 0;
-const FD_STDOUT: usize = 1;
-const FD_STDERR: usize = 2;
-const CHUNK_SIZE: usize = 256;
-
-pub fn sys_write(fd: usize, buf: UserInPtr<u8>, len: usize) -> isize {
-    match fd {
-        FD_STDOUT | FD_STDERR => {
-            let mut count = 0;
-            while count < len {
-                let chunk_len = CHUNK_SIZE.min(len);
-                let chunk: [u8; CHUNK_SIZE] = unsafe { buf.add(count).read_array(chunk_len) };
-                print!("{}", core::str::from_utf8(&chunk[..chunk_len]).unwrap());
-                count += chunk_len;
-            }
-            count as isize
+macro_rules! path_str {
+    ($buf: expr) => {
+        core::str::from_utf8(&$buf.0[..$buf.1]).map_err(|_| Errno::EINVAL)?
+    };
+}
+
+pub fn sys_write(fd: usize, buf: UserInPtr<u8>, len: usize) -> SysResult {
+    let file = current().files().lock().get(fd)?;
+    let mut chunk = vec![0; CHUNK_SIZE.min(len)];
+    let mut count = 0;
+    while count < len {
+        let chunk_len = CHUNK_SIZE.min(len - count);
+        unsafe { buf.add(count).read_buf(&mut chunk[..chunk_len])? };
+        let written = match file.write(&chunk[..chunk_len]) {
+            Ok(written) => written,
+            Err(_) if count > 0 => break,
+            Err(e) => return Err(e),
+        };
+        count += written;
+        if written < chunk_len {
+            break;
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+    }
+    Ok(count)
+}
+
+pub fn sys_read(fd: usize, buf: UserOutPtr<u8>, len: usize) -> SysResult {
+    let file = current().files().lock().get(fd)?;
+    let mut chunk = vec![0; CHUNK_SIZE.min(len)];
+    let mut count = 0;
+    while count < len {
+        let chunk_len = CHUNK_SIZE.min(len - count);
+        let read = match file.read(&mut chunk[..chunk_len]) {
+            Ok(read) => read,
+            Err(_) if count > 0 => break,
+            Err(e) => return Err(e),
+        };
+        unsafe { buf.add(count).write_buf(&chunk[..read])? };
+        count += read;
+        if read < chunk_len {
+            break;
         }
     }
+    Ok(count)
+}
+
+pub fn sys_open(path: UserInPtr<u8>, flags: u32, _mode: u32) -> SysResult {
+    let path = read_path(path)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let file = fs::open(path_str!(path), flags)?;
+    current().files().lock().add(file)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
+    let file = current().files().lock().remove(fd)?;
+    // release the file outside the lock
+    drop(file);
+    Ok(0)
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: u32) -> SysResult {
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+        SEEK_CUR => SeekFrom::Current(offset as i64),
+        SEEK_END => SeekFrom::End(offset as i64),
+        _ => return Err(Errno::EINVAL),
+    };
+    let file = current().files().lock().get(fd)?;
+    let offset = file.seek(pos)?;
+    if offset > isize::MAX as u64 {
+        return Err(Errno::EINVAL);
+    }
+    Ok(offset as usize)
+}
+
+pub fn sys_fsync(fd: usize) -> SysResult {
+    let file = current().files().lock().get(fd)?;
+    file.sync()?;
+    Ok(0)
+}
+
+pub fn sys_sync() -> SysResult {
+    fs::sync()?;
+    Ok(0)
+}
+
+pub fn sys_mkdir(path: UserInPtr<u8>, _mode: u32) -> SysResult {
+    let path = read_path(path)?;
+    fs::mkdir(path_str!(path))?;
+    Ok(0)
+}
+
+pub fn sys_rmdir(path: UserInPtr<u8>) -> SysResult {
+    let path = read_path(path)?;
+    fs::unlink(path_str!(path), true)?;
+    Ok(0)
+}
+
+pub fn sys_unlink(path: UserInPtr<u8>) -> SysResult {
+    let path = read_path(path)?;
+    fs::unlink(path_str!(path), false)?;
+    Ok(0)
 }
 
-pub fn sys_read(fd: usize, mut buf: UserOutPtr<u8>, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            loop {
-                if let Some(c) = console_getchar() {
-                    buf.write(c);
-                    return 1;
-                } else {
-                    crate::task::current().yield_now();
-                }
-            }
+/// Reads entries of the directory `fd` as `struct linux_dirent64`.
+pub fn sys_getdents64(fd: usize, mut dirp: UserOutPtr<u8>, count: usize) -> SysResult {
+    let file = current().files().lock().get(fd)?;
+    let mut buf = Vec::new();
+    let mut emit = |entry: &DirEntry| {
+        let name_len = entry.name.len();
+        // the record is 8-byte aligned, and the name is NUL-terminated
+        let reclen = (DIRENT64_NAME_OFFSET + name_len + 1 + 7) & !7;
+        if buf.len() + reclen > count {
+            return false;
         }
-        _ => {
-            panic!("Unsupported fd in sys_read!");
+        let start = buf.len();
+        buf.resize(start + reclen, 0);
+        let record = &mut buf[start..];
+        record[0..8].copy_from_slice(&entry.ino.to_ne_bytes());
+        record[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+        record[18] = match entry.kind {
+            InodeType::Dir => DT_DIR,
+            InodeType::File => DT_REG,
+        };
+        record[DIRENT64_NAME_OFFSET..DIRENT64_NAME_OFFSET + name_len]
+            .copy_from_slice(entry.name.as_bytes());
+        true
+    };
+    file.read_dir(&mut emit)?;
+    if buf.is_empty() {
+        // check whether the next entry does not fit in the buffer
+        let mut is_end = true;
+        file.read_dir(&mut |_| {
+            is_end = false;
+            false
+        })?;
+        if !is_end {
+            return Err(Errno::EINVAL);
         }
     }
+    dirp.write_buf(&buf)?;
+    Ok(buf.len())
 }
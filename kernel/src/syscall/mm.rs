@@ -0,0 +1,111 @@
+use crate::arch::instructions;
+use crate::config::{USER_ASPACE_BASE, USER_ASPACE_SIZE, USER_STACK_REGION_BASE};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{MapArea, MemFlags, VirtAddr};
+use crate::task::current;
+
+const PROT_READ: u32 = 1 << 0;
+const PROT_WRITE: u32 = 1 << 1;
+const PROT_EXEC: u32 = 1 << 2;
+
+const MAP_SHARED: u32 = 0x01;
+const MAP_PRIVATE: u32 = 0x02;
+const MAP_FIXED: u32 = 0x10;
+const MAP_ANONYMOUS: u32 = 0x20;
+
+#[allow(clippy::absurd_extreme_comparisons)]
+fn is_user_range(start: usize, size: usize) -> bool {
+    start >= USER_ASPACE_BASE
+        && size <= USER_ASPACE_SIZE
+        && start - USER_ASPACE_BASE <= USER_ASPACE_SIZE - size
+}
+
+/// The region reserved for the user stack to grow can not be mapped.
+fn is_stack_region_overlap(start: usize, size: usize) -> bool {
+    start + size > USER_STACK_REGION_BASE
+}
+
+fn prot_to_flags(prot: u32) -> MemFlags {
+    let mut flags = MemFlags::USER;
+    if prot & PROT_READ != 0 {
+        flags |= MemFlags::READ;
+    }
+    if prot & PROT_WRITE != 0 {
+        flags |= MemFlags::WRITE;
+    }
+    if prot & PROT_EXEC != 0 {
+        flags |= MemFlags::EXECUTE;
+    }
+    flags
+}
+
+/// Maps anonymous memory into the address space of the current task. File
+/// mappings and shared mappings are not supported.
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: u32,
+    flags: u32,
+    _fd: i32,
+    _offset: usize,
+) -> SysResult {
+    if len == 0 || flags & MAP_ANONYMOUS == 0 || flags & (MAP_SHARED | MAP_PRIVATE) != MAP_PRIVATE {
+        warn!("Unsupported mmap: len={:#x}, flags={:#x}", len, flags);
+        return Err(Errno::EINVAL);
+    }
+    if len > USER_ASPACE_SIZE {
+        return Err(Errno::ENOMEM);
+    }
+    let size = VirtAddr::new(len).align_up().as_usize();
+    let curr = current();
+    let mut vm = match curr.vm() {
+        Some(vm) => vm.lock(),
+        None => return Err(Errno::EINVAL),
+    };
+
+    let start = if flags & MAP_FIXED != 0 {
+        let start = VirtAddr::new(addr);
+        if !start.is_aligned() || !is_user_range(addr, size) {
+            return Err(Errno::EINVAL);
+        }
+        if is_stack_region_overlap(addr, size) {
+            return Err(Errno::ENOMEM);
+        }
+        vm.unmap_range(start, size);
+        instructions::flush_tlb_all();
+        start
+    } else {
+        let hint = VirtAddr::new(addr).align_down();
+        if hint.as_usize() != 0
+            && is_user_range(hint.as_usize(), size)
+            && !is_stack_region_overlap(hint.as_usize(), size)
+            && !vm.is_overlap(hint, size)
+        {
+            hint
+        } else if let Some(start) = vm.find_free_area(size) {
+            start
+        } else {
+            return Err(Errno::ENOMEM);
+        }
+    };
+    vm.insert(MapArea::new_framed(start, size, prot_to_flags(prot)));
+    Ok(start.as_usize())
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> SysResult {
+    if len > USER_ASPACE_SIZE {
+        return Err(Errno::EINVAL);
+    }
+    let start = VirtAddr::new(addr);
+    let size = VirtAddr::new(len).align_up().as_usize();
+    if !start.is_aligned() || size == 0 || !is_user_range(addr, size) {
+        return Err(Errno::EINVAL);
+    }
+    if let Some(vm) = current().vm() {
+        vm.lock().unmap_range(start, size);
+        instructions::flush_tlb_all();
+        Ok(0)
+    } else {
+        Err(Errno::EINVAL)
+    }
+}
@@ -1,56 +1,142 @@
 const SYSCALL_READ: usize = 0;
 const SYSCALL_WRITE: usize = 1;
+const SYSCALL_OPEN: usize = 2;
+const SYSCALL_CLOSE: usize = 3;
+const SYSCALL_LSEEK: usize = 8;
+const SYSCALL_MMAP: usize = 9;
+const SYSCALL_MUNMAP: usize = 11;
+const SYSCALL_RT_SIGPROCMASK: usize = 14;
 const SYSCALL_YIELD: usize = 24;
+const SYSCALL_GETITIMER: usize = 36;
+const SYSCALL_ALARM: usize = 37;
+const SYSCALL_SETITIMER: usize = 38;
 const SYSCALL_GETPID: usize = 39;
+const SYSCALL_SOCKET: usize = 41;
+const SYSCALL_CONNECT: usize = 42;
+const SYSCALL_ACCEPT: usize = 43;
+const SYSCALL_SENDTO: usize = 44;
+const SYSCALL_RECVFROM: usize = 45;
+const SYSCALL_BIND: usize = 49;
+const SYSCALL_LISTEN: usize = 50;
+const SYSCALL_GETSOCKNAME: usize = 51;
+const SYSCALL_GETPEERNAME: usize = 52;
 const SYSCALL_CLONE: usize = 56;
 const SYSCALL_FORK: usize = 57;
 const SYSCALL_EXEC: usize = 59;
 const SYSCALL_EXIT: usize = 60;
 const SYSCALL_WAITPID: usize = 61;
-const SYSCALL_GET_TIME_MS: usize = 96;
+const SYSCALL_FSYNC: usize = 74;
+const SYSCALL_MKDIR: usize = 83;
+const SYSCALL_RMDIR: usize = 84;
+const SYSCALL_UNLINK: usize = 87;
+const SYSCALL_GETTIMEOFDAY: usize = 96;
+const SYSCALL_GETRLIMIT: usize = 97;
+const SYSCALL_GETPPID: usize = 110;
+const SYSCALL_RT_SIGTIMEDWAIT: usize = 128;
+const SYSCALL_SETRLIMIT: usize = 160;
+const SYSCALL_SYNC: usize = 162;
+const SYSCALL_GETTID: usize = 186;
+const SYSCALL_TIME: usize = 201;
+const SYSCALL_FUTEX: usize = 202;
+const SYSCALL_GETDENTS64: usize = 217;
+const SYSCALL_SET_TID_ADDRESS: usize = 218;
+const SYSCALL_TIMER_CREATE: usize = 222;
+const SYSCALL_TIMER_SETTIME: usize = 223;
+const SYSCALL_TIMER_GETTIME: usize = 224;
+const SYSCALL_TIMER_GETOVERRUN: usize = 225;
+const SYSCALL_TIMER_DELETE: usize = 226;
+const SYSCALL_CLOCK_SETTIME: usize = 227;
 const SYSCALL_CLOCK_GETTIME: usize = 228;
+const SYSCALL_CLOCK_GETRES: usize = 229;
 const SYSCALL_CLOCK_NANOSLEEP: usize = 230;
+const SYSCALL_EXIT_GROUP: usize = 231;
 
 mod fs;
+mod mm;
+mod net;
+mod signal;
 mod task;
 mod time;
 
 use self::fs::*;
+use self::mm::*;
+use self::net::*;
+use self::signal::*;
 use self::task::*;
 use self::time::*;
 use crate::arch::{instructions, TrapFrame};
 
-pub fn syscall(
-    tf: &mut TrapFrame,
-    syscall_id: usize,
-    arg0: usize,
-    arg1: usize,
-    arg2: usize,
-) -> isize {
+pub fn syscall(tf: &mut TrapFrame, syscall_id: usize, args: [usize; 6]) -> isize {
     instructions::enable_irqs();
-    debug!(
-        "syscall {} enter <= ({:#x}, {:#x}, {:#x})",
-        syscall_id, arg0, arg1, arg2
-    );
+    debug!("syscall {} enter <= {:#x?}", syscall_id, args);
+    let [arg0, arg1, arg2, arg3, arg4, arg5] = args;
     let ret = match syscall_id {
         SYSCALL_READ => sys_read(arg0, arg1.into(), arg2),
         SYSCALL_WRITE => sys_write(arg0, arg1.into(), arg2),
+        SYSCALL_OPEN => sys_open(arg0.into(), arg1 as _, arg2 as _),
+        SYSCALL_CLOSE => sys_close(arg0),
+        SYSCALL_LSEEK => sys_lseek(arg0, arg1 as _, arg2 as _),
+        SYSCALL_MMAP => sys_mmap(arg0, arg1, arg2 as _, arg3 as _, arg4 as _, arg5),
+        SYSCALL_MUNMAP => sys_munmap(arg0, arg1),
+        SYSCALL_RT_SIGPROCMASK => sys_rt_sigprocmask(arg0 as _, arg1.into(), arg2.into(), arg3),
         SYSCALL_YIELD => sys_yield(),
+        SYSCALL_GETITIMER => sys_getitimer(arg0 as _, arg1.into()),
+        SYSCALL_ALARM => sys_alarm(arg0 as _),
+        SYSCALL_SETITIMER => sys_setitimer(arg0 as _, arg1.into(), arg2.into()),
         SYSCALL_GETPID => sys_getpid(),
-        SYSCALL_CLONE => sys_clone(arg0, tf),
+        SYSCALL_SOCKET => sys_socket(arg0 as _, arg1 as _, arg2 as _),
+        SYSCALL_CONNECT => sys_connect(arg0, arg1.into(), arg2 as _),
+        SYSCALL_ACCEPT => sys_accept(arg0, arg1.into(), arg2.into()),
+        SYSCALL_SENDTO => sys_sendto(arg0, arg1.into(), arg2, arg3 as _),
+        SYSCALL_RECVFROM => sys_recvfrom(arg0, arg1.into(), arg2, arg3 as _),
+        SYSCALL_BIND => sys_bind(arg0, arg1.into(), arg2 as _),
+        SYSCALL_LISTEN => sys_listen(arg0, arg1),
+        SYSCALL_GETSOCKNAME => sys_getsockname(arg0, arg1.into(), arg2.into()),
+        SYSCALL_GETPEERNAME => sys_getpeername(arg0, arg1.into(), arg2.into()),
+        SYSCALL_CLONE => sys_clone(arg0 as _, arg1, arg2.into(), arg3, arg4, tf),
         SYSCALL_FORK => sys_fork(tf),
         SYSCALL_EXEC => sys_exec(arg0.into(), tf),
         SYSCALL_EXIT => sys_exit(arg0 as i32),
         SYSCALL_WAITPID => sys_waitpid(arg0 as _, arg1.into(), arg2 as _),
-        SYSCALL_GET_TIME_MS => sys_get_time_ms(),
+        SYSCALL_FSYNC => sys_fsync(arg0),
+        SYSCALL_MKDIR => sys_mkdir(arg0.into(), arg1 as _),
+        SYSCALL_RMDIR => sys_rmdir(arg0.into()),
+        SYSCALL_UNLINK => sys_unlink(arg0.into()),
+        SYSCALL_GETTIMEOFDAY => sys_gettimeofday(arg0.into(), arg1.into()),
+        SYSCALL_GETRLIMIT => sys_getrlimit(arg0 as _, arg1.into()),
+        SYSCALL_GETPPID => sys_getppid(),
+        SYSCALL_RT_SIGTIMEDWAIT => sys_rt_sigtimedwait(arg0.into(), arg1.into(), arg2.into(), arg3),
+        SYSCALL_SETRLIMIT => sys_setrlimit(arg0 as _, arg1.into()),
+        SYSCALL_SYNC => sys_sync(),
+        SYSCALL_GETTID => sys_gettid(),
+        SYSCALL_TIME => sys_time(arg0.into()),
+        SYSCALL_FUTEX => sys_futex(arg0, arg1 as _, arg2 as _),
+        SYSCALL_GETDENTS64 => sys_getdents64(arg0, arg1.into(), arg2),
+        SYSCALL_SET_TID_ADDRESS => sys_set_tid_address(arg0),
+        SYSCALL_TIMER_CREATE => sys_timer_create(arg0 as _, arg1.into(), arg2.into()),
+        SYSCALL_TIMER_SETTIME => sys_timer_settime(arg0 as _, arg1 as _, arg2.into(), arg3.into()),
+        SYSCALL_TIMER_GETTIME => sys_timer_gettime(arg0 as _, arg1.into()),
+        SYSCALL_TIMER_GETOVERRUN => sys_timer_getoverrun(arg0 as _),
+        SYSCALL_TIMER_DELETE => sys_timer_delete(arg0 as _),
+        SYSCALL_CLOCK_SETTIME => sys_clock_settime(arg0 as _, arg1.into()),
         SYSCALL_CLOCK_GETTIME => sys_clock_gettime(arg0 as _, arg1.into()),
+        SYSCALL_CLOCK_GETRES => sys_clock_getres(arg0 as _, arg1.into()),
         SYSCALL_CLOCK_NANOSLEEP => sys_clock_nanosleep(arg0 as _, arg1 as _, arg2.into()),
+        SYSCALL_EXIT_GROUP => sys_exit_group(arg0 as i32),
         _ => {
             warn!("Unsupported syscall_id: {}", syscall_id);
-            crate::task::current().exit(-1);
+            crate::task::current().kill(crate::task::Signal::SIGSYS);
+        }
+    };
+    let ret = match ret {
+        Ok(ret) => ret as isize,
+        Err(e) => {
+            debug!("syscall {} failed: {:?}", syscall_id, e);
+            e.as_isize()
         }
     };
     debug!("syscall {} ret => {:#x}", syscall_id, ret);
+    crate::task::prepare_user_return();
     instructions::disable_irqs();
     ret
 }
@@ -0,0 +1,176 @@
+use alloc::{sync::Arc, vec};
+use core::mem::size_of;
+
+use crate::errno::{Errno, SysResult};
+use crate::fs::File;
+use crate::mm::{UserInOutPtr, UserInPtr, UserOutPtr};
+use crate::net::{Ipv4Address, SocketAddrV4, TcpSocket};
+use crate::task::current;
+
+const AF_INET: u16 = 2;
+const SOCK_STREAM: u32 = 1;
+const SOCK_TYPE_MASK: u32 = 0xf;
+const SOCK_NONBLOCK: u32 = 0o4000;
+/// Accepted, but has no effect.
+const SOCK_CLOEXEC: u32 = 0o2000000;
+const IPPROTO_TCP: u32 = 6;
+
+const MSG_DONTWAIT: u32 = 0x40;
+
+/// The maximum number of bytes sent or received by a call.
+const MAX_TRANSFER_SIZE: usize = 64 * 1024;
+
+/// `struct sockaddr_in`, the port and the address are in network byte order.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockAddrIn {
+    family: u16,
+    port: u16,
+    addr: [u8; 4],
+    zero: [u8; 8],
+}
+
+impl From<SocketAddrV4> for SockAddrIn {
+    fn from(addr: SocketAddrV4) -> Self {
+        Self {
+            family: AF_INET,
+            port: addr.port.to_be(),
+            addr: addr.addr.0,
+            zero: [0; 8],
+        }
+    }
+}
+
+fn read_sockaddr(addr: UserInPtr<SockAddrIn>, addrlen: u32) -> SysResult<SocketAddrV4> {
+    if addr.is_null() {
+        return Err(Errno::EFAULT);
+    }
+    if (addrlen as usize) < size_of::<SockAddrIn>() {
+        return Err(Errno::EINVAL);
+    }
+    let addr = addr.read()?;
+    if addr.family != AF_INET {
+        return Err(Errno::EAFNOSUPPORT);
+    }
+    Ok(SocketAddrV4::new(
+        Ipv4Address(addr.addr),
+        u16::from_be(addr.port),
+    ))
+}
+
+/// Writes the address to `addr`, truncated to the buffer size in `addrlen`,
+/// and the size of the address to `addrlen`. Nothing is written if `addr` is
+/// null.
+fn write_sockaddr(
+    sockaddr: SocketAddrV4,
+    mut addr: UserOutPtr<u8>,
+    mut addrlen: UserInOutPtr<u32>,
+) -> SysResult<()> {
+    if addr.is_null() {
+        return Ok(());
+    }
+    let len = addrlen.read()? as usize;
+    let sockaddr = SockAddrIn::from(sockaddr);
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&sockaddr as *const _ as *const u8, size_of::<SockAddrIn>())
+    };
+    addr.write_buf(&bytes[..len.min(bytes.len())])?;
+    addrlen.write(bytes.len() as u32)
+}
+
+/// Adds the socket to the file table. Closing a socket locks the network
+/// stack, which may sleep, so it is not closed with the table locked if the
+/// table is full.
+fn add_socket(socket: TcpSocket) -> SysResult {
+    let socket: Arc<dyn File> = Arc::new(socket);
+    let ret = current().files().lock().add(socket.clone());
+    drop(socket);
+    ret
+}
+
+fn get_socket(fd: usize) -> SysResult<Arc<dyn File>> {
+    let file = current().files().lock().get(fd)?;
+    file.as_socket().ok_or(Errno::ENOTSOCK)?;
+    Ok(file)
+}
+
+pub fn sys_socket(domain: u32, ty: u32, protocol: u32) -> SysResult {
+    if domain != AF_INET as u32 {
+        return Err(Errno::EAFNOSUPPORT);
+    }
+    if ty & !(SOCK_TYPE_MASK | SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+        return Err(Errno::EINVAL);
+    }
+    if ty & SOCK_TYPE_MASK != SOCK_STREAM || (protocol != 0 && protocol != IPPROTO_TCP) {
+        return Err(Errno::EPROTONOSUPPORT);
+    }
+    add_socket(TcpSocket::new(ty & SOCK_NONBLOCK != 0))
+}
+
+pub fn sys_bind(fd: usize, addr: UserInPtr<SockAddrIn>, addrlen: u32) -> SysResult {
+    let file = get_socket(fd)?;
+    let addr = read_sockaddr(addr, addrlen)?;
+    file.as_socket().unwrap().bind(addr)?;
+    Ok(0)
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> SysResult {
+    let file = get_socket(fd)?;
+    file.as_socket().unwrap().listen(backlog)?;
+    Ok(0)
+}
+
+pub fn sys_accept(fd: usize, addr: UserOutPtr<u8>, addrlen: UserInOutPtr<u32>) -> SysResult {
+    let file = get_socket(fd)?;
+    // the address is written before the connection is taken, so that it is
+    // not lost if the address is invalid
+    let socket = file
+        .as_socket()
+        .unwrap()
+        .accept(|remote| write_sockaddr(remote, addr, addrlen))?;
+    add_socket(socket)
+}
+
+pub fn sys_connect(fd: usize, addr: UserInPtr<SockAddrIn>, addrlen: u32) -> SysResult {
+    let file = get_socket(fd)?;
+    let addr = read_sockaddr(addr, addrlen)?;
+    file.as_socket().unwrap().connect(addr)?;
+    Ok(0)
+}
+
+/// Sends data on a connected socket, the destination address is ignored.
+pub fn sys_sendto(fd: usize, buf: UserInPtr<u8>, len: usize, flags: u32) -> SysResult {
+    let file = get_socket(fd)?;
+    let mut data = vec![0; len.min(MAX_TRANSFER_SIZE)];
+    buf.read_buf(&mut data)?;
+    file.as_socket()
+        .unwrap()
+        .send(&data, flags & MSG_DONTWAIT != 0)
+}
+
+/// Receives data from a connected socket, the source address is not
+/// returned, as for TCP sockets on Linux.
+pub fn sys_recvfrom(fd: usize, mut buf: UserOutPtr<u8>, len: usize, flags: u32) -> SysResult {
+    let file = get_socket(fd)?;
+    let mut data = vec![0; len.min(MAX_TRANSFER_SIZE)];
+    let read = file
+        .as_socket()
+        .unwrap()
+        .recv(&mut data, flags & MSG_DONTWAIT != 0)?;
+    buf.write_buf(&data[..read])?;
+    Ok(read)
+}
+
+pub fn sys_getsockname(fd: usize, addr: UserOutPtr<u8>, addrlen: UserInOutPtr<u32>) -> SysResult {
+    let file = get_socket(fd)?;
+    let local = file.as_socket().unwrap().local_addr();
+    write_sockaddr(local, addr, addrlen)?;
+    Ok(0)
+}
+
+pub fn sys_getpeername(fd: usize, addr: UserOutPtr<u8>, addrlen: UserInOutPtr<u32>) -> SysResult {
+    let file = get_socket(fd)?;
+    let peer = file.as_socket().unwrap().peer_addr()?;
+    write_sockaddr(peer, addr, addrlen)?;
+    Ok(0)
+}
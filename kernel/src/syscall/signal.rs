@@ -0,0 +1,98 @@
+use super::time::TimeSpec;
+use crate::errno::{Errno, SysResult};
+use crate::mm::{UserInPtr, UserOutPtr};
+use crate::task::{current, SigInfo, SigMaskHow, SigSet};
+use crate::timer::current_time;
+
+const SIG_BLOCK: u32 = 0;
+const SIG_UNBLOCK: u32 = 1;
+const SIG_SETMASK: u32 = 2;
+
+/// The size of `sigset_t` in the kernel, in bytes.
+const SIGSET_SIZE: usize = 8;
+
+/// `siginfo_t`, only the fields used by timer signals are defined.
+#[repr(C)]
+pub struct UserSigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    _pad: i32,
+    pub timer_id: i32,
+    pub overrun: i32,
+    pub value: usize,
+    _rest: [u64; 12],
+}
+
+impl From<SigInfo> for UserSigInfo {
+    fn from(info: SigInfo) -> Self {
+        Self {
+            signo: info.signo as _,
+            errno: 0,
+            code: info.code,
+            _pad: 0,
+            timer_id: info.timer_id,
+            overrun: info.overrun,
+            value: info.value,
+            _rest: [0; 12],
+        }
+    }
+}
+
+pub fn sys_rt_sigprocmask(
+    how: u32,
+    set: UserInPtr<u64>,
+    mut oldset: UserOutPtr<u64>,
+    sigsetsize: usize,
+) -> SysResult {
+    if sigsetsize != SIGSET_SIZE {
+        return Err(Errno::EINVAL);
+    }
+    let how = match how {
+        SIG_BLOCK => SigMaskHow::Block,
+        SIG_UNBLOCK => SigMaskHow::Unblock,
+        SIG_SETMASK => SigMaskHow::SetMask,
+        _ => return Err(Errno::EINVAL),
+    };
+    let set = if set.is_null() {
+        None
+    } else {
+        Some(SigSet::from_bits(set.read()?))
+    };
+    let old = current().sigprocmask(how, set);
+    if !oldset.is_null() {
+        oldset.write(old.bits())?;
+    }
+    Ok(0)
+}
+
+/// Waits for a signal in `set`, returns the signal number.
+pub fn sys_rt_sigtimedwait(
+    set: UserInPtr<u64>,
+    mut info: UserOutPtr<UserSigInfo>,
+    timeout: UserInPtr<TimeSpec>,
+    sigsetsize: usize,
+) -> SysResult {
+    if sigsetsize != SIGSET_SIZE {
+        return Err(Errno::EINVAL);
+    }
+    let set = SigSet::from_bits(set.read()?);
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        let timeout = timeout.read()?;
+        if !timeout.is_valid() {
+            return Err(Errno::EINVAL);
+        }
+        Some(
+            current_time()
+                .checked_add(timeout.into())
+                .ok_or(Errno::EINVAL)?,
+        )
+    };
+    let si = current().sigtimedwait(set, deadline)?;
+    if !info.is_null() {
+        info.write(si.into())?;
+    }
+    Ok(si.signo as usize)
+}
@@ -1,49 +1,167 @@
 use crate::arch::TrapFrame;
+use crate::config::USER_STACK_MAX_LIMIT;
+use crate::errno::{Errno, SysResult};
 use crate::mm::{UserInPtr, UserOutPtr};
-use crate::task::{current, spawn_task};
+use crate::task::{current, futex_wait, futex_wake, spawn_task, WaitOptions};
 
 const MAX_STR_LEN: usize = 256;
 
+const CLONE_PARENT_SETTID: u32 = 0x0010_0000;
+const CLONE_CHILD_CLEARTID: u32 = 0x0020_0000;
+const CLONE_CHILD_SETTID: u32 = 0x0100_0000;
+
+const FUTEX_WAIT: u32 = 0;
+const FUTEX_WAKE: u32 = 1;
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+
+const RLIMIT_STACK: u32 = 3;
+
+#[repr(C)]
+pub struct RLimit {
+    /// soft limit
+    pub cur: usize,
+    /// hard limit
+    pub max: usize,
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     current().exit(exit_code);
 }
 
-pub fn sys_yield() -> isize {
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    current().exit_group(exit_code);
+}
+
+pub fn sys_yield() -> SysResult {
     current().yield_now();
-    0
+    Ok(0)
+}
+
+pub fn sys_getpid() -> SysResult {
+    Ok(current().tgid().as_usize())
+}
+
+pub fn sys_getppid() -> SysResult {
+    let parent = current().group_leader().parent();
+    Ok(parent.map_or(0, |p| p.tgid().as_usize()))
+}
+
+pub fn sys_gettid() -> SysResult {
+    Ok(current().pid().as_usize())
 }
 
-pub fn sys_getpid() -> isize {
-    current().pid().as_usize() as isize
+pub fn sys_set_tid_address(tidptr: usize) -> SysResult {
+    let curr = current();
+    curr.set_clear_child_tid(tidptr);
+    Ok(curr.pid().as_usize())
 }
 
-pub fn sys_clone(newsp: usize, tf: &TrapFrame) -> isize {
+/// Creates a new thread in the thread group of the current task.
+///
+/// The arguments are `(flags, newsp, ptid, ctid, tls)` on all architectures.
+/// A new process can only be created by `fork()` currently, and TLS is not
+/// supported.
+pub fn sys_clone(
+    flags: u32,
+    newsp: usize,
+    mut ptid: UserOutPtr<i32>,
+    ctid: usize,
+    _tls: usize,
+    tf: &TrapFrame,
+) -> SysResult {
+    if current().is_kernel_task() {
+        return Err(Errno::EPERM);
+    }
     let new_task = current().new_clone(newsp, tf);
-    let pid = new_task.pid().as_usize() as isize;
+    let tid = new_task.pid().as_usize() as i32;
+    if flags & CLONE_PARENT_SETTID != 0 {
+        ptid.write(tid)?;
+    }
+    if flags & CLONE_CHILD_SETTID != 0 {
+        // The child shares the address space with the parent.
+        UserOutPtr::<i32>::from(ctid).write(tid)?;
+    }
+    if flags & CLONE_CHILD_CLEARTID != 0 {
+        new_task.set_clear_child_tid(ctid);
+    }
     spawn_task(new_task);
-    pid
+    Ok(tid as usize)
 }
 
-pub fn sys_fork(tf: &TrapFrame) -> isize {
+pub fn sys_fork(tf: &TrapFrame) -> SysResult {
+    if current().is_kernel_task() {
+        return Err(Errno::EPERM);
+    }
     let new_task = current().new_fork(tf);
-    let pid = new_task.pid().as_usize() as isize;
+    let pid = new_task.pid().as_usize();
     spawn_task(new_task);
-    pid
+    Ok(pid)
 }
 
-pub fn sys_exec(path: UserInPtr<u8>, tf: &mut TrapFrame) -> isize {
-    let (path_buf, len) = path.read_str::<MAX_STR_LEN>();
-    let path = core::str::from_utf8(&path_buf[..len]).unwrap();
-    current().exec(path, tf)
+pub fn sys_exec(path: UserInPtr<u8>, tf: &mut TrapFrame) -> SysResult {
+    let (path_buf, len) = path.read_str::<MAX_STR_LEN>()?;
+    let path = core::str::from_utf8(&path_buf[..len]).map_err(|_| Errno::EINVAL)?;
+    current().exec(path, tf)?;
+    Ok(0)
 }
 
-pub fn sys_waitpid(pid: isize, mut exit_code_ptr: UserOutPtr<i32>, options: u32) -> isize {
-    if let Some((pid, exit_code)) = current().waitpid(pid, options) {
-        if !exit_code_ptr.is_null() {
-            exit_code_ptr.write(exit_code);
+pub fn sys_waitpid(pid: isize, mut wstatus: UserOutPtr<i32>, options: u32) -> SysResult {
+    let options = WaitOptions::from_bits(options).ok_or(Errno::EINVAL)?;
+    if let Some((pid, status)) = current().waitpid(pid, options)? {
+        if !wstatus.is_null() {
+            wstatus.write(status)?;
         }
-        pid.as_usize() as _
+        Ok(pid.as_usize())
     } else {
-        -1
+        Ok(0)
+    }
+}
+
+pub fn sys_futex(uaddr: usize, op: u32, val: u32) -> SysResult {
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            futex_wait(uaddr.into(), val as i32)?;
+            Ok(0)
+        }
+        FUTEX_WAKE => Ok(futex_wake(uaddr, val as usize)),
+        _ => {
+            warn!("Unsupported futex op: {}", op);
+            Err(Errno::ENOSYS)
+        }
+    }
+}
+
+/// Gets the resource limits of the current process. Only `RLIMIT_STACK` is
+/// supported, whose hard limit is fixed.
+pub fn sys_getrlimit(resource: u32, mut rlim: UserOutPtr<RLimit>) -> SysResult {
+    if resource != RLIMIT_STACK {
+        return Err(Errno::EINVAL);
+    }
+    let curr = current();
+    let vm = curr.vm().ok_or(Errno::EINVAL)?;
+    let cur = vm.lock().stack_limit();
+    rlim.write(RLimit {
+        cur,
+        max: USER_STACK_MAX_LIMIT,
+    })?;
+    Ok(0)
+}
+
+/// Sets the resource limits of the current process. Only the soft limit of
+/// `RLIMIT_STACK` can be changed, it takes effect when the stack grows.
+pub fn sys_setrlimit(resource: u32, rlim: UserInPtr<RLimit>) -> SysResult {
+    if resource != RLIMIT_STACK {
+        return Err(Errno::EINVAL);
+    }
+    let rlim = rlim.read()?;
+    if rlim.cur > rlim.max {
+        return Err(Errno::EINVAL);
+    }
+    if rlim.max > USER_STACK_MAX_LIMIT {
+        return Err(Errno::EPERM);
     }
+    let curr = current();
+    let vm = curr.vm().ok_or(Errno::EINVAL)?;
+    vm.lock().set_stack_limit(rlim.cur);
+    Ok(0)
 }
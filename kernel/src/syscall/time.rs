@@ -1,8 +1,61 @@
+use crate::errno::{Errno, SysResult};
 use crate::mm::{UserInPtr, UserOutPtr};
-use crate::timer::{current_time, TimeValue};
+use crate::task::{current, is_valid_signo, SigEvent, SigNotify, TimerSetting};
+use crate::timer::{self, current_time, Clock, TimeValue};
 
 const TIMER_ABSTIME: u32 = 1;
 
+const CLOCK_REALTIME: u32 = 0;
+const CLOCK_MONOTONIC: u32 = 1;
+const CLOCK_PROCESS_CPUTIME_ID: u32 = 2;
+const CLOCK_THREAD_CPUTIME_ID: u32 = 3;
+const CLOCK_BOOTTIME: u32 = 7;
+
+const ITIMER_REAL: u32 = 0;
+
+const SIGEV_SIGNAL: i32 = 0;
+const SIGEV_NONE: i32 = 1;
+const SIGEV_THREAD_ID: i32 = 4;
+
+enum ClockId {
+    System(Clock),
+    ProcessCpuTime,
+    ThreadCpuTime,
+}
+
+impl TryFrom<u32> for ClockId {
+    type Error = Errno;
+    fn try_from(clock_id: u32) -> SysResult<Self> {
+        Ok(match clock_id {
+            CLOCK_REALTIME => Self::System(Clock::Realtime),
+            CLOCK_MONOTONIC => Self::System(Clock::Monotonic),
+            CLOCK_BOOTTIME => Self::System(Clock::Boottime),
+            CLOCK_PROCESS_CPUTIME_ID => Self::ProcessCpuTime,
+            CLOCK_THREAD_CPUTIME_ID => Self::ThreadCpuTime,
+            _ => return Err(Errno::EINVAL),
+        })
+    }
+}
+
+impl ClockId {
+    fn now(&self) -> TimeValue {
+        match self {
+            Self::System(clock) => clock.now(),
+            Self::ProcessCpuTime => current().process_cpu_time(),
+            Self::ThreadCpuTime => current().thread_cpu_time(),
+        }
+    }
+
+    /// Returns the clock for sleeps and timers, only clocks based on the
+    /// system timer are supported.
+    fn system_clock(&self) -> SysResult<Clock> {
+        match self {
+            Self::System(clock) => Ok(*clock),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+}
+
 #[repr(C)]
 pub struct TimeSpec {
     /// seconds
@@ -26,21 +79,269 @@ impl From<TimeValue> for TimeSpec {
     }
 }
 
-pub fn sys_get_time_ms() -> isize {
-    current_time().as_millis() as isize
+impl TimeSpec {
+    pub fn is_valid(&self) -> bool {
+        self.nsec < 1_000_000_000
+    }
+}
+
+/// `struct timezone`, which is obsolete and always zero.
+#[repr(C)]
+#[derive(Default)]
+pub struct TimeZone {
+    pub minuteswest: i32,
+    pub dsttime: i32,
+}
+
+pub fn sys_gettimeofday(mut tv: UserOutPtr<TimeVal>, mut tz: UserOutPtr<TimeZone>) -> SysResult {
+    if !tv.is_null() {
+        tv.write(TimeVal::from(timer::current_realtime()))?;
+    }
+    if !tz.is_null() {
+        tz.write(TimeZone::default())?;
+    }
+    Ok(0)
+}
+
+/// Returns the seconds since the Epoch.
+pub fn sys_time(mut tloc: UserOutPtr<usize>) -> SysResult {
+    let secs = timer::current_realtime().as_secs() as usize;
+    if !tloc.is_null() {
+        tloc.write(secs)?;
+    }
+    Ok(secs)
+}
+
+pub fn sys_clock_gettime(clock_id: u32, mut ts: UserOutPtr<TimeSpec>) -> SysResult {
+    let now = ClockId::try_from(clock_id)?.now();
+    ts.write(TimeSpec::from(now))?;
+    Ok(0)
 }
 
-pub fn sys_clock_gettime(_clock_id: u32, mut ts: UserOutPtr<TimeSpec>) -> isize {
-    ts.write(TimeSpec::from(current_time()));
-    0
+/// Only `CLOCK_REALTIME` can be set.
+pub fn sys_clock_settime(clock_id: u32, ts: UserInPtr<TimeSpec>) -> SysResult {
+    let ts = ts.read()?;
+    if clock_id != CLOCK_REALTIME || !ts.is_valid() || !timer::set_realtime(ts.into()) {
+        return Err(Errno::EINVAL);
+    }
+    Ok(0)
+}
+
+/// All clocks are based on the same hardware counter, so they have the same
+/// resolution.
+pub fn sys_clock_getres(clock_id: u32, mut res: UserOutPtr<TimeSpec>) -> SysResult {
+    ClockId::try_from(clock_id)?;
+    if !res.is_null() {
+        res.write(TimeSpec::from(timer::clock_resolution()))?;
+    }
+    Ok(0)
 }
 
-pub fn sys_clock_nanosleep(_clock_id: u32, flags: u32, req: UserInPtr<TimeSpec>) -> isize {
+pub fn sys_clock_nanosleep(clock_id: u32, flags: u32, req: UserInPtr<TimeSpec>) -> SysResult {
+    let clock = ClockId::try_from(clock_id)?.system_clock()?;
+    let req = req.read()?;
+    if !req.is_valid() {
+        return Err(Errno::EINVAL);
+    }
     let deadline = if (flags & TIMER_ABSTIME) != 0 {
-        req.read().into()
+        // not adjusted if the real time is set during the sleep
+        clock.to_monotonic(req.into())
     } else {
-        current_time() + req.read().into()
+        current_time()
+            .checked_add(req.into())
+            .ok_or(Errno::EINVAL)?
     };
     crate::task::current().sleep(deadline);
-    0
+    Ok(0)
+}
+
+#[repr(C)]
+pub struct TimeVal {
+    /// seconds
+    pub sec: usize,
+    /// micro seconds
+    pub usec: usize,
+}
+
+impl From<TimeVal> for TimeValue {
+    fn from(tv: TimeVal) -> Self {
+        Self::new(tv.sec as _, tv.usec as u32 * 1000)
+    }
+}
+
+impl From<TimeValue> for TimeVal {
+    fn from(tv: TimeValue) -> Self {
+        Self {
+            sec: tv.as_secs() as _,
+            usec: tv.subsec_micros() as _,
+        }
+    }
+}
+
+impl TimeVal {
+    fn is_valid(&self) -> bool {
+        self.usec < 1_000_000
+    }
+}
+
+/// `struct itimerspec`
+#[repr(C)]
+pub struct ITimerSpec {
+    pub interval: TimeSpec,
+    pub value: TimeSpec,
+}
+
+impl From<TimerSetting> for ITimerSpec {
+    fn from(setting: TimerSetting) -> Self {
+        Self {
+            interval: setting.interval.into(),
+            value: setting.value.into(),
+        }
+    }
+}
+
+/// `struct itimerval`
+#[repr(C)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+impl From<TimerSetting> for ITimerVal {
+    fn from(setting: TimerSetting) -> Self {
+        Self {
+            interval: setting.interval.into(),
+            value: setting.value.into(),
+        }
+    }
+}
+
+/// The leading fields of `struct sigevent`.
+#[repr(C)]
+pub struct UserSigEvent {
+    pub value: usize,
+    pub signo: i32,
+    pub notify: i32,
+    pub tid: i32,
+}
+
+impl TryFrom<UserSigEvent> for SigEvent {
+    type Error = Errno;
+    fn try_from(ev: UserSigEvent) -> SysResult<Self> {
+        let notify = match ev.notify {
+            SIGEV_NONE => SigNotify::None,
+            SIGEV_SIGNAL => SigNotify::Signal,
+            SIGEV_THREAD_ID => SigNotify::ThreadId((ev.tid as usize).into()),
+            _ => return Err(Errno::EINVAL),
+        };
+        if !matches!(notify, SigNotify::None) && !is_valid_signo(ev.signo as usize) {
+            return Err(Errno::EINVAL);
+        }
+        Ok(Self {
+            notify,
+            signo: ev.signo as u8,
+            value: ev.value,
+        })
+    }
+}
+
+pub fn sys_timer_create(
+    clock_id: u32,
+    sevp: UserInPtr<UserSigEvent>,
+    mut timerid: UserOutPtr<i32>,
+) -> SysResult {
+    let clock = ClockId::try_from(clock_id)?.system_clock()?;
+    let event = if sevp.is_null() {
+        None
+    } else {
+        Some(sevp.read()?.try_into()?)
+    };
+    let curr = current();
+    let id = curr.timer_create(clock, event)?;
+    if let Err(e) = timerid.write(id) {
+        curr.timer_delete(id)?;
+        return Err(e);
+    }
+    Ok(0)
+}
+
+pub fn sys_timer_settime(
+    timerid: i32,
+    flags: u32,
+    new_value: UserInPtr<ITimerSpec>,
+    mut old_value: UserOutPtr<ITimerSpec>,
+) -> SysResult {
+    let new_value = new_value.read()?;
+    if !new_value.value.is_valid() || !new_value.interval.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+    let abs = flags & TIMER_ABSTIME != 0;
+    let old = current().timer_settime(
+        timerid,
+        new_value.value.into(),
+        abs,
+        new_value.interval.into(),
+    )?;
+    if !old_value.is_null() {
+        old_value.write(old.into())?;
+    }
+    Ok(0)
+}
+
+pub fn sys_timer_gettime(timerid: i32, mut curr_value: UserOutPtr<ITimerSpec>) -> SysResult {
+    let setting = current().timer_gettime(timerid)?;
+    curr_value.write(setting.into())?;
+    Ok(0)
+}
+
+pub fn sys_timer_getoverrun(timerid: i32) -> SysResult {
+    Ok(current().timer_getoverrun(timerid)? as usize)
+}
+
+pub fn sys_timer_delete(timerid: i32) -> SysResult {
+    current().timer_delete(timerid)?;
+    Ok(0)
+}
+
+/// Only `ITIMER_REAL` is supported.
+pub fn sys_setitimer(
+    which: u32,
+    new_value: UserInPtr<ITimerVal>,
+    mut old_value: UserOutPtr<ITimerVal>,
+) -> SysResult {
+    if which != ITIMER_REAL {
+        return Err(Errno::EINVAL);
+    }
+    let new_value = new_value.read()?;
+    if !new_value.value.is_valid() || !new_value.interval.is_valid() {
+        return Err(Errno::EINVAL);
+    }
+    let old = current().setitimer_real(new_value.value.into(), new_value.interval.into())?;
+    if !old_value.is_null() {
+        old_value.write(old.into())?;
+    }
+    Ok(0)
+}
+
+/// Only `ITIMER_REAL` is supported.
+pub fn sys_getitimer(which: u32, mut curr_value: UserOutPtr<ITimerVal>) -> SysResult {
+    if which != ITIMER_REAL {
+        return Err(Errno::EINVAL);
+    }
+    curr_value.write(current().getitimer_real().into())?;
+    Ok(0)
+}
+
+/// Sends `SIGALRM` after `seconds`, returns the remaining seconds of the
+/// previous alarm.
+pub fn sys_alarm(seconds: u32) -> SysResult {
+    let value = TimeValue::from_secs(seconds as _);
+    let old = current().setitimer_real(value, TimeValue::ZERO)?.value;
+    // Round to the nearest second, but do not report a pending alarm as 0.
+    let remaining = (old + TimeValue::from_millis(500)).as_secs();
+    Ok(if remaining == 0 && !old.is_zero() {
+        1
+    } else {
+        remaining as usize
+    })
 }
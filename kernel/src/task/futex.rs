@@ -0,0 +1,116 @@
+//! A minimal futex implementation, only `FUTEX_WAIT` and `FUTEX_WAKE` are
+//! supported. It is mainly used to join threads (`CLONE_CHILD_CLEARTID`).
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::manager::TASK_MANAGER;
+use super::wait_queue::WaitQueue;
+use super::{current, CurrentTask};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{UserInPtr, UserOutPtr};
+use crate::sync::SpinNoIrqLock;
+
+/// Futexes are identified by the address space and the user address.
+type FutexKey = (usize, usize);
+
+/// The tasks waiting on a futex.
+struct FutexQueue {
+    waiters: WaitQueue,
+    /// Increased by each wake, so a task that reads the value before a wake
+    /// but blocks after it does not sleep.
+    wakes: AtomicUsize,
+}
+
+/// The queues of futexes, each referenced by the map and the tasks in
+/// [`futex_wait`], and removed when no task references it.
+static FUTEX_QUEUES: SpinNoIrqLock<BTreeMap<FutexKey, Arc<FutexQueue>>> =
+    SpinNoIrqLock::new(BTreeMap::new());
+
+fn futex_key(curr: &CurrentTask, uaddr: usize) -> FutexKey {
+    let vm = curr.vm().map_or(0, |vm| Arc::as_ptr(vm) as usize);
+    (vm, uaddr)
+}
+
+/// Removes the queue of `key` if it is only referenced by the map and `fq`.
+fn release_queue(
+    queues: &mut BTreeMap<FutexKey, Arc<FutexQueue>>,
+    key: FutexKey,
+    fq: Arc<FutexQueue>,
+) {
+    if fq.waiters.is_empty() && Arc::strong_count(&fq) == 2 {
+        queues.remove(&key);
+    }
+}
+
+/// Blocks the current task if the value at `uaddr` is still `val`, until
+/// other tasks call [`futex_wake`] on the same address. It may also return
+/// early if the address is woken while the value is read.
+///
+/// Returns [`Errno::EAGAIN`] if the value has been changed.
+pub fn futex_wait(uaddr: UserInPtr<i32>, val: i32) -> SysResult<()> {
+    let curr = current();
+    let key = futex_key(&curr, uaddr.as_ptr() as usize);
+    let (fq, wakes) = {
+        let mut queues = FUTEX_QUEUES.lock();
+        let fq = queues
+            .entry(key)
+            .or_insert_with(|| {
+                Arc::new(FutexQueue {
+                    waiters: WaitQueue::new(),
+                    wakes: AtomicUsize::new(0),
+                })
+            })
+            .clone();
+        let wakes = fq.wakes.load(Ordering::SeqCst);
+        (fq, wakes)
+    };
+
+    // The value is read without locks, as it may fault. A `futex_wake()`
+    // after the value is changed increases `wakes`, which is checked while
+    // holding the lock of `TASK_MANAGER`, so it can not be missed.
+    let ret = match uaddr.read() {
+        Ok(v) if v == val => {
+            let mut m = TASK_MANAGER.lock();
+            if fq.wakes.load(Ordering::SeqCst) == wakes {
+                fq.waiters.wait_locked(&mut m);
+            }
+            Ok(())
+        }
+        Ok(_) => Err(Errno::EAGAIN),
+        Err(e) => Err(e),
+    };
+    release_queue(&mut FUTEX_QUEUES.lock(), key, fq);
+    ret
+}
+
+/// Wakes up at most `count` tasks waiting on `uaddr`, returns the number of
+/// tasks woken up.
+pub fn futex_wake(uaddr: usize, count: usize) -> usize {
+    let key = futex_key(&current(), uaddr);
+    let mut queues = FUTEX_QUEUES.lock();
+    let fq = match queues.get(&key) {
+        Some(fq) => fq.clone(),
+        None => return 0,
+    };
+    fq.wakes.fetch_add(1, Ordering::SeqCst);
+
+    let mut m = TASK_MANAGER.lock();
+    let mut woken = 0;
+    while woken < count && fq.waiters.notify_one_locked(&mut m) {
+        woken += 1;
+    }
+    drop(m);
+    release_queue(&mut queues, key, fq);
+    woken
+}
+
+/// Clears the TID at `clear_child_tid` and wakes up a joining thread, called
+/// when a thread created with `CLONE_CHILD_CLEARTID` exits.
+pub(super) fn clear_child_tid(tidptr: usize) {
+    let mut ptr = UserOutPtr::<i32>::from(tidptr);
+    // The thread is exiting, nothing to report if the address is invalid.
+    if ptr.write(0).is_ok() {
+        futex_wake(tidptr, 1);
+    }
+}
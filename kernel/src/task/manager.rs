@@ -5,7 +5,7 @@ use super::schedule::{Scheduler, SchedulerTrait};
 use super::structs::{CurrentTask, Task, TaskState, ROOT_TASK};
 use crate::percpu::PerCpu;
 use crate::sync::{LazyInit, SpinNoIrqLock};
-use crate::timer::{current_time, TimeValue};
+use crate::timer::{current_time, current_time_nanos, TimeValue};
 
 pub struct TaskManager {
     scheduler: Scheduler,
@@ -18,13 +18,27 @@ impl TaskManager {
         }
     }
 
-    pub fn scheduler_timer_tick(&mut self) {
-        self.scheduler.timer_tick();
+    pub fn scheduler_timer_tick(&mut self, ticks: usize) {
+        self.scheduler.timer_tick(ticks);
     }
 
     pub fn spawn(&mut self, t: Arc<Task>) {
         assert!(t.state() == TaskState::Ready);
         self.scheduler.push_ready_task_back(t);
+        self.update_tick();
+    }
+
+    /// In NO_HZ mode, stops the periodic tick if no task is waiting for the
+    /// CPU, as there is nothing to preempt the running task for. Restarts it
+    /// otherwise.
+    fn update_tick(&self) {
+        if cfg!(feature = "nohz") {
+            if self.scheduler.has_ready_tasks() {
+                crate::timer::restart_tick();
+            } else {
+                crate::timer::stop_tick();
+            }
+        }
     }
 
     fn switch_to(&self, curr_task: &Arc<Task>, next_task: Arc<Task>) {
@@ -33,6 +47,9 @@ impl TaskManager {
             curr_task.pid(),
             next_task.pid()
         );
+        let now_ns = current_time_nanos();
+        curr_task.account_run_end(now_ns);
+        next_task.account_run_start(now_ns);
         next_task.set_state(TaskState::Running);
         if Arc::ptr_eq(curr_task, &next_task) {
             return;
@@ -54,7 +71,9 @@ impl TaskManager {
 
     fn resched(&mut self, curr_task: &CurrentTask) {
         assert!(curr_task.state() != TaskState::Running);
-        if let Some(next_task) = self.scheduler.pick_next_task() {
+        let next_task = self.scheduler.pick_next_task();
+        self.update_tick();
+        if let Some(next_task) = next_task {
             // let `next_task` hold its ownership to avoid clone
             self.switch_to(curr_task, next_task);
         } else {
@@ -74,7 +93,15 @@ impl TaskManager {
     pub fn unblock_task(&mut self, task: Arc<Task>) -> bool {
         if task.state() == TaskState::Sleeping {
             task.set_state(TaskState::Ready);
+            let preempt = task.is_high_priority();
             self.scheduler.push_ready_task_front(task);
+            self.update_tick();
+            // Leave the idle task as soon as possible, it may be halted. High
+            // priority tasks preempt the current task as well.
+            let curr_task = CurrentTask::get();
+            if curr_task.is_idle() || (preempt && !curr_task.is_high_priority()) {
+                curr_task.set_need_resched();
+            }
             true
         } else {
             false
@@ -94,45 +121,52 @@ impl TaskManager {
         assert!(!curr_task.is_idle());
         if current_time() < deadline {
             let curr_task_clone = curr_task.clone_task();
-            crate::timer::set_timer(deadline, move |_| {
+            let timer = crate::timer::set_timer(deadline, move |_| {
                 TASK_MANAGER.lock().unblock_task(curr_task_clone);
                 CurrentTask::get().set_need_resched();
             });
             self.block_current(curr_task);
+            // The task may be woken up before the deadline, e.g. by the exit of
+            // the thread group. Do not let the timer wake it up again.
+            timer.cancel();
         }
     }
 
-    pub fn exit_current(&mut self, curr_task: &CurrentTask, exit_code: i32) -> ! {
+    pub fn exit_current(&mut self, curr_task: &CurrentTask, exit_status: i32) -> ! {
         assert!(!curr_task.is_idle());
         assert!(!curr_task.is_root());
         assert!(curr_task.state() == TaskState::Running);
 
-        // Make all child tasks as the children of the root task
-        {
-            let mut notify = false;
-            let mut children = curr_task.children.lock();
-            for c in children.iter() {
-                ROOT_TASK.add_child(c);
-                if c.state() == TaskState::Zombie {
-                    notify = true;
+        curr_task.set_state(TaskState::Zombie);
+        curr_task.set_exit_status(exit_status);
+
+        // The process exits only after all threads in the group have exited.
+        let leader = curr_task.group_leader();
+        if leader.is_group_dead() {
+            // Make all child tasks as the children of the root task
+            {
+                let mut notify = false;
+                let mut children = leader.children.lock();
+                for c in children.iter() {
+                    ROOT_TASK.add_child(c);
+                    if c.is_group_dead() {
+                        notify = true;
+                    }
+                }
+                children.clear();
+                if notify {
+                    ROOT_TASK.wait_children_exit.notify_all_locked(self);
                 }
             }
-            children.clear();
-            if notify {
-                ROOT_TASK.wait_children_exit.notify_locked(self);
-            }
-        }
 
-        curr_task.set_state(TaskState::Zombie);
-        curr_task.set_exit_code(exit_code);
-
-        curr_task
-            .parent
-            .lock()
-            .upgrade()
-            .unwrap()
-            .wait_children_exit
-            .notify_locked(self);
+            leader
+                .parent
+                .lock()
+                .upgrade()
+                .unwrap()
+                .wait_children_exit
+                .notify_all_locked(self);
+        }
 
         self.resched(curr_task);
         unreachable!("task exited!");
@@ -144,25 +178,25 @@ impl TaskManager {
             return;
         }
         println!(
-            "{:>4} {:>4} {:>6} {:>4}  STATE",
-            "PID", "PPID", "#CHILD", "#REF",
+            "{:>4} {:>4} {:>4} {:>6} {:>4}  STATE",
+            "PID", "TGID", "PPID", "#CHILD", "#REF",
         );
         ROOT_TASK.traverse(&|t: &Arc<Task>| {
             let pid = t.pid().as_usize();
+            let tgid = t.tgid().as_usize();
             let ref_count = Arc::strong_count(t);
-            let children_count = t.children.lock().len();
+            let children_count = t.group_leader().children.lock().len();
             let state = t.state();
-            let shared = if t.is_shared_with_parent() { 'S' } else { ' ' };
-            if let Some(p) = t.parent.lock().upgrade() {
+            if let Some(p) = t.group_leader().parent.lock().upgrade() {
                 let ppid = p.pid().as_usize();
                 println!(
-                    "{:>4}{}{:>4} {:>6} {:>4}  {:?}",
-                    pid, shared, ppid, children_count, ref_count, state
+                    "{:>4} {:>4} {:>4} {:>6} {:>4}  {:?}",
+                    pid, tgid, ppid, children_count, ref_count, state
                 );
             } else {
                 println!(
-                    "{:>4} {:>4} {:>6} {:>4}  {:?}",
-                    pid, '-', children_count, ref_count, state
+                    "{:>4} {:>4} {:>4} {:>6} {:>4}  {:?}",
+                    pid, tgid, '-', children_count, ref_count, state
                 );
             }
         });
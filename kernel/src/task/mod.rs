@@ -1,9 +1,16 @@
+mod futex;
 mod manager;
+mod posix_timer;
 mod schedule;
+mod signal;
 mod structs;
 mod wait_queue;
 
-pub use structs::{CurrentTask, Task, TaskId};
+pub use futex::{futex_wait, futex_wake};
+pub use posix_timer::{SigEvent, SigNotify, TimerSetting};
+pub use signal::{is_valid_signo, SigInfo, SigMaskHow, SigSet, Signal};
+pub use structs::{CurrentTask, Task, TaskId, WaitOptions};
+pub use wait_queue::WaitQueue;
 
 use alloc::sync::Arc;
 use core::sync::atomic::{AtomicBool, Ordering};
@@ -11,6 +18,7 @@ use core::sync::atomic::{AtomicBool, Ordering};
 use self::manager::TASK_MANAGER;
 use self::structs::ROOT_TASK;
 use crate::arch::instructions;
+use crate::mm::{MemFlags, VirtAddr};
 
 static TASK_INITED: AtomicBool = AtomicBool::new(false);
 
@@ -20,14 +28,18 @@ pub fn is_init() -> bool {
 
 pub fn init() {
     println!("Initializing task manager...");
+    structs::init_caches();
     manager::init();
 
     ROOT_TASK.init_by(Task::new_kernel(
         |_| loop {
             let curr_task = current();
-            while curr_task.waitpid(-1, 0).is_some() {}
-            // instructions::wait_for_ints();
+            while curr_task.waitpid(-1, WaitOptions::empty()).is_ok() {}
             info!("No more tasks to run, shutdown!");
+            crate::drivers::interrupt::dump_irq_stats();
+            if let Err(e) = crate::fs::sync() {
+                warn!("failed to sync filesystems: {:?}", e);
+            }
             crate::drivers::misc::shutdown();
         },
         0,
@@ -42,11 +54,15 @@ pub fn init() {
         0
     };
 
+    let shell = Task::new_user("user_shell");
     let mut m = TASK_MANAGER.lock();
     m.spawn(ROOT_TASK.clone());
     m.spawn(Task::new_kernel(test_kernel_task, 0xdead));
     m.spawn(Task::new_kernel(test_kernel_task, 0xbeef));
-    m.spawn(Task::new_user("user_shell"));
+    match shell {
+        Ok(shell) => m.spawn(shell),
+        Err(e) => warn!("failed to load user_shell: {:?}", e),
+    }
 
     TASK_INITED.store(true, Ordering::SeqCst);
 }
@@ -64,8 +80,35 @@ pub fn handle_irq(vector: usize) {
     }
 }
 
-pub fn timer_tick_periodic() {
-    TASK_MANAGER.lock().scheduler_timer_tick();
+/// Handles pending works of the current task before returning to user space.
+pub fn prepare_user_return() {
+    let curr = current();
+    if curr.is_group_exiting() {
+        curr.do_exit(curr.group_leader().exit_status());
+    }
+    curr.handle_pending_signals();
+}
+
+/// Handles a page fault at the user address `vaddr`, returns `false` if it is
+/// an invalid access.
+pub fn handle_page_fault(vaddr: VirtAddr, access: MemFlags) -> bool {
+    match current().vm() {
+        Some(vm) => vm.lock().handle_page_fault(vaddr, access),
+        None => false,
+    }
+}
+
+/// Reports a kernel stack overflow of the current task, called by the trap
+/// handlers on a separate exception stack.
+pub fn kernel_stack_overflow() -> ! {
+    panic!(
+        "kernel stack overflow in task {}",
+        current().pid().as_usize()
+    );
+}
+
+pub fn timer_tick_periodic(ticks: usize) {
+    TASK_MANAGER.lock().scheduler_timer_tick(ticks);
 }
 
 pub fn spawn_task(task: Arc<Task>) {
@@ -78,6 +121,10 @@ pub fn run() -> ! {
     current().yield_now(); // current task is idle at this time
     loop {
         current().yield_now();
-        // instructions::wait_for_ints(); // the `HLT` instruction has poor performance
+        if cfg!(feature = "nohz") {
+            // Halt until the next interrupt, tasks woken up by the interrupt
+            // handler are scheduled before it returns.
+            instructions::wait_for_ints();
+        }
     }
 }
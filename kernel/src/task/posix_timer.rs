@@ -0,0 +1,326 @@
+//! POSIX interval timers (`timer_create()` and friends), and the `ITIMER_REAL`
+//! timer of `setitimer()` which is a special POSIX timer sending `SIGALRM`.
+//!
+//! Timers are shared by the thread group, and are deleted on `exec()` (except
+//! the `ITIMER_REAL` timer) or when the thread group exits. They are not
+//! inherited by `fork()`.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+
+use super::signal::{SigInfo, Signal, SI_TIMER};
+use super::structs::TaskState;
+use super::{CurrentTask, Task, TaskId};
+use crate::errno::{Errno, SysResult};
+use crate::sync::Mutex;
+use crate::timer::{current_time, set_periodic_timer, set_timer, Clock, TimeValue, TimerHandle};
+
+/// The maximum number of POSIX timers of a thread group.
+const MAX_POSIX_TIMERS: usize = 32;
+
+/// How to notify the expiration of a timer.
+#[derive(Debug, Clone, Copy)]
+pub enum SigNotify {
+    /// Do nothing, the timer can only be examined by `timer_gettime()`.
+    None,
+    /// Send a signal to the thread group.
+    Signal,
+    /// Send a signal to the thread in the thread group.
+    ThreadId(TaskId),
+}
+
+/// Describes the notification of a timer, as `struct sigevent`.
+#[derive(Debug, Clone, Copy)]
+pub struct SigEvent {
+    pub notify: SigNotify,
+    pub signo: u8,
+    pub value: usize,
+}
+
+/// The setting of a timer. `value` is the time until the next expiration,
+/// or zero if the timer is disarmed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimerSetting {
+    pub interval: TimeValue,
+    pub value: TimeValue,
+}
+
+struct PosixTimer {
+    /// The timer ID, `None` for the `ITIMER_REAL` timer.
+    id: Option<i32>,
+    /// The clock of absolute expiration times.
+    clock: Clock,
+    /// The receiver of signals, the group leader or a thread in the group.
+    target: Weak<Task>,
+    event: SigEvent,
+    state: Mutex<TimerState>,
+}
+
+#[derive(Default)]
+struct TimerState {
+    handle: Option<TimerHandle>,
+    interval: TimeValue,
+    /// Incremented when the timer is set, so that expirations of the old
+    /// setting are ignored.
+    generation: u64,
+    /// Whether the signal of the last expiration is still pending.
+    sig_pending: bool,
+    /// Expirations not notified since the pending signal was sent.
+    overrun: i32,
+    /// The overrun count of the last delivered signal.
+    last_overrun: i32,
+}
+
+/// Timers of a thread group, only used by the group leader.
+#[derive(Default)]
+pub(super) struct PosixTimers {
+    timers: BTreeMap<i32, Arc<PosixTimer>>,
+    itimer_real: Option<Arc<PosixTimer>>,
+}
+
+impl PosixTimer {
+    fn new(id: Option<i32>, clock: Clock, target: &Arc<Task>, event: SigEvent) -> Arc<Self> {
+        Arc::new(Self {
+            id,
+            clock,
+            target: Arc::downgrade(target),
+            event,
+            state: Mutex::new(TimerState::default()),
+        })
+    }
+
+    fn gettime(&self) -> TimerSetting {
+        let state = self.state.lock();
+        let value = match state.handle.and_then(|h| h.deadline()) {
+            // The timer is about to expire, do not report it as disarmed.
+            Some(deadline) => deadline
+                .saturating_sub(current_time())
+                .max(TimeValue::from_nanos(1)),
+            None => TimeValue::ZERO,
+        };
+        TimerSetting {
+            interval: state.interval,
+            value,
+        }
+    }
+
+    /// Arms the timer to expire after `value` (or at `value` of its clock if
+    /// `abs` is true) and then every `interval` (if it is not zero), or
+    /// disarms it if `value` is zero. Returns the old setting.
+    fn settime(
+        self: &Arc<Self>,
+        value: TimeValue,
+        abs: bool,
+        interval: TimeValue,
+    ) -> SysResult<TimerSetting> {
+        let deadline = if value.is_zero() {
+            None
+        } else if abs {
+            // not adjusted if the real time is set later
+            Some(self.clock.to_monotonic(value))
+        } else {
+            Some(current_time().checked_add(value).ok_or(Errno::EINVAL)?)
+        };
+        let old = self.gettime();
+        let mut state = self.state.lock();
+        if let Some(handle) = state.handle.take() {
+            handle.cancel();
+        }
+        state.generation += 1;
+        state.overrun = 0;
+        state.last_overrun = 0;
+        state.interval = TimeValue::ZERO;
+        if let Some(deadline) = deadline {
+            let timer = Arc::downgrade(self);
+            let generation = state.generation;
+            let expire = move |expirations| {
+                if let Some(timer) = timer.upgrade() {
+                    timer.expire(generation, expirations);
+                }
+                // Let the receiver run as soon as possible.
+                CurrentTask::get().set_need_resched();
+            };
+            state.handle = Some(if interval.is_zero() {
+                set_timer(deadline, move |_| expire(1))
+            } else {
+                state.interval = interval;
+                set_periodic_timer(deadline, interval, move |_, n| expire(n))
+            });
+        }
+        Ok(old)
+    }
+
+    /// Notifies `expirations` expirations of the timer, called in the timer
+    /// interrupt handler.
+    fn expire(&self, generation: u64, expirations: u64) {
+        let mut state = self.state.lock();
+        if state.generation != generation || matches!(self.event.notify, SigNotify::None) {
+            return;
+        }
+        let info = match self.id {
+            // Signals of the `ITIMER_REAL` timer are not counted.
+            None => SigInfo::kernel(self.event.signo),
+            Some(_) if state.sig_pending => {
+                let expirations = expirations.min(i32::MAX as u64) as i32;
+                state.overrun = state.overrun.saturating_add(expirations);
+                return;
+            }
+            Some(id) => {
+                let expirations = expirations.min(i32::MAX as u64) as i32;
+                state.overrun = state.overrun.saturating_add(expirations - 1);
+                state.sig_pending = true;
+                SigInfo {
+                    signo: self.event.signo,
+                    code: SI_TIMER,
+                    timer_id: id,
+                    overrun: 0,
+                    value: self.event.value,
+                }
+            }
+        };
+        drop(state);
+
+        let thread_directed = matches!(self.event.notify, SigNotify::ThreadId(_));
+        let sent = match self.target.upgrade() {
+            Some(target) => target.send_signal(info, thread_directed),
+            None => false,
+        };
+        if !sent && self.id.is_some() {
+            self.state.lock().sig_pending = false;
+        }
+    }
+}
+
+impl Drop for PosixTimer {
+    fn drop(&mut self) {
+        if let Some(handle) = self.state.lock().handle.take() {
+            handle.cancel();
+        }
+    }
+}
+
+impl PosixTimers {
+    /// Deletes all timers except the `ITIMER_REAL` timer, called on `exec()`.
+    pub(super) fn remove_posix_timers(&mut self) {
+        self.timers.clear();
+    }
+
+    /// Deletes all timers, called when the thread group exits.
+    pub(super) fn remove_all(&mut self) {
+        self.timers.clear();
+        self.itimer_real = None;
+    }
+}
+
+/// Updates the overrun count of the POSIX timer `id` when its signal is
+/// dequeued, returns the overrun count to report in the signal information.
+pub(super) fn signal_dequeued(curr: &CurrentTask, id: i32) -> i32 {
+    let timer = curr
+        .group_leader()
+        .posix_timers
+        .lock()
+        .timers
+        .get(&id)
+        .cloned();
+    timer.map_or(0, |timer| {
+        let mut state = timer.state.lock();
+        state.last_overrun = state.overrun;
+        state.overrun = 0;
+        state.sig_pending = false;
+        state.last_overrun
+    })
+}
+
+impl<'a> CurrentTask<'a> {
+    fn posix_timer(&self, id: i32) -> SysResult<Arc<PosixTimer>> {
+        let timers = self.group_leader().posix_timers.lock();
+        timers.timers.get(&id).cloned().ok_or(Errno::EINVAL)
+    }
+
+    /// Creates a disarmed POSIX timer based on `clock`, returns the timer ID.
+    /// If `event` is `None`, `SIGALRM` is sent to the thread group with the
+    /// timer ID as the value.
+    pub fn timer_create(&self, clock: Clock, event: Option<SigEvent>) -> SysResult<i32> {
+        let leader = self.group_leader();
+        let target = match event.map(|e| e.notify) {
+            Some(SigNotify::ThreadId(tid)) => core::iter::once(leader)
+                .chain(leader.threads.lock().iter())
+                .find(|t| t.pid() == tid && t.state() != TaskState::Zombie)
+                .cloned()
+                .ok_or(Errno::EINVAL)?,
+            _ => leader.clone(),
+        };
+        let mut timers = leader.posix_timers.lock();
+        if timers.timers.len() >= MAX_POSIX_TIMERS {
+            return Err(Errno::EAGAIN);
+        }
+        let id = (0..).find(|id| !timers.timers.contains_key(id)).unwrap();
+        let event = event.unwrap_or(SigEvent {
+            notify: SigNotify::Signal,
+            signo: Signal::SIGALRM.signo(),
+            value: id as usize,
+        });
+        timers
+            .timers
+            .insert(id, PosixTimer::new(Some(id), clock, &target, event));
+        Ok(id)
+    }
+
+    /// Arms the POSIX timer `id` to expire after `value` (or at `value` of its
+    /// clock if `abs` is true) and then every `interval` (if it is not zero),
+    /// or disarms it if `value` is zero. Returns the old setting.
+    pub fn timer_settime(
+        &self,
+        id: i32,
+        value: TimeValue,
+        abs: bool,
+        interval: TimeValue,
+    ) -> SysResult<TimerSetting> {
+        self.posix_timer(id)?.settime(value, abs, interval)
+    }
+
+    pub fn timer_gettime(&self, id: i32) -> SysResult<TimerSetting> {
+        Ok(self.posix_timer(id)?.gettime())
+    }
+
+    /// Returns the overrun count of the last signal sent by the POSIX timer
+    /// `id`, i.e. the number of expirations missed before it was delivered.
+    pub fn timer_getoverrun(&self, id: i32) -> SysResult<i32> {
+        Ok(self.posix_timer(id)?.state.lock().last_overrun)
+    }
+
+    pub fn timer_delete(&self, id: i32) -> SysResult<()> {
+        let mut timers = self.group_leader().posix_timers.lock();
+        timers.timers.remove(&id).ok_or(Errno::EINVAL)?;
+        Ok(())
+    }
+
+    /// Arms the `ITIMER_REAL` timer to send `SIGALRM` after `value` and then
+    /// every `interval` (if it is not zero), or disarms it if `value` is
+    /// zero. Returns the old setting.
+    pub fn setitimer_real(&self, value: TimeValue, interval: TimeValue) -> SysResult<TimerSetting> {
+        let leader = self.group_leader();
+        let timer = leader
+            .posix_timers
+            .lock()
+            .itimer_real
+            .get_or_insert_with(|| {
+                let event = SigEvent {
+                    notify: SigNotify::Signal,
+                    signo: Signal::SIGALRM.signo(),
+                    value: 0,
+                };
+                PosixTimer::new(None, Clock::Monotonic, leader, event)
+            })
+            .clone();
+        timer.settime(value, false, interval)
+    }
+
+    pub fn getitimer_real(&self) -> TimerSetting {
+        let timers = self.group_leader().posix_timers.lock();
+        timers
+            .itimer_real
+            .as_ref()
+            .map_or_else(TimerSetting::default, |t| t.gettime())
+    }
+}
@@ -12,7 +12,11 @@ pub trait SchedulerTrait {
     fn push_ready_task_front(&mut self, t: Arc<Task>);
     fn push_ready_task_back(&mut self, t: Arc<Task>);
     fn pick_next_task(&mut self) -> Option<Arc<Task>>;
-    fn timer_tick(&mut self);
+    /// Called on the periodic tick, `ticks` is more than 1 if some ticks were
+    /// missed.
+    fn timer_tick(&mut self, ticks: usize);
+    /// Whether some tasks are waiting for the CPU.
+    fn has_ready_tasks(&self) -> bool;
 }
 
 pub type SchedulerState = RRSchedulerState;
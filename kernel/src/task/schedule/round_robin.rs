@@ -21,10 +21,15 @@ impl RRSchedulerState {
         self.quantum.store(MAX_QUANTUM, Ordering::Release);
     }
 
-    fn decrease(&self) -> usize {
-        let quantum = self.quantum.fetch_sub(1, Ordering::Release);
-        assert!(quantum > 0);
-        quantum - 1
+    /// Consumes `ticks` of the quantum, returns the remaining ticks.
+    fn decrease(&self, ticks: usize) -> usize {
+        let quantum = self
+            .quantum
+            .fetch_update(Ordering::Release, Ordering::Acquire, |q| {
+                Some(q.saturating_sub(ticks))
+            })
+            .unwrap();
+        quantum.saturating_sub(ticks)
     }
 }
 
@@ -37,7 +42,11 @@ impl SchedulerTrait for RRScheduler {
 
     fn push_ready_task_back(&mut self, t: Arc<Task>) {
         t.sched_state().reset();
-        self.ready_queue.push_back(t);
+        if t.is_high_priority() {
+            self.ready_queue.push_front(t);
+        } else {
+            self.ready_queue.push_back(t);
+        }
     }
 
     fn push_ready_task_front(&mut self, t: Arc<Task>) {
@@ -49,9 +58,13 @@ impl SchedulerTrait for RRScheduler {
         self.ready_queue.pop_front()
     }
 
-    fn timer_tick(&mut self) {
+    fn has_ready_tasks(&self) -> bool {
+        !self.ready_queue.is_empty()
+    }
+
+    fn timer_tick(&mut self, ticks: usize) {
         let curr_task = current();
-        if !curr_task.is_idle() && curr_task.sched_state().decrease() == 0 {
+        if !curr_task.is_idle() && curr_task.sched_state().decrease(ticks) == 0 {
             curr_task.set_need_resched();
         }
     }
@@ -0,0 +1,286 @@
+//! A minimal signal implementation. User handlers are not supported: signals
+//! either take the default action when returning to user space (terminate
+//! the thread group or ignore the signal), or are blocked and consumed by
+//! `sigtimedwait()`.
+
+use alloc::collections::{btree_map::Entry, BTreeMap};
+use alloc::sync::Arc;
+use core::sync::atomic::Ordering;
+
+use super::manager::TASK_MANAGER;
+use super::structs::{signaled_status, CurrentTask, Task, TaskState};
+use crate::errno::{Errno, SysResult};
+use crate::timer::TimeValue;
+
+/// The number of signals, including real-time signals.
+pub const NSIG: usize = 64;
+
+/// The signal was sent by the kernel.
+pub const SI_KERNEL: i32 = 0x80;
+/// The signal was sent by the expiration of a POSIX timer.
+pub const SI_TIMER: i32 = -2;
+
+/// Standard signal numbers.
+#[repr(u8)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Signal {
+    SIGHUP = 1,
+    SIGINT = 2,
+    SIGQUIT = 3,
+    SIGILL = 4,
+    SIGTRAP = 5,
+    SIGABRT = 6,
+    SIGBUS = 7,
+    SIGFPE = 8,
+    SIGKILL = 9,
+    SIGUSR1 = 10,
+    SIGSEGV = 11,
+    SIGUSR2 = 12,
+    SIGPIPE = 13,
+    SIGALRM = 14,
+    SIGTERM = 15,
+    SIGCHLD = 17,
+    SIGCONT = 18,
+    SIGSTOP = 19,
+    SIGTSTP = 20,
+    SIGTTIN = 21,
+    SIGTTOU = 22,
+    SIGURG = 23,
+    SIGWINCH = 28,
+    SIGSYS = 31,
+}
+
+/// A set of signals, bit `n - 1` stands for the signal `n`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct SigSet(u64);
+
+/// Information about a pending signal.
+#[derive(Debug, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: u8,
+    pub code: i32,
+    /// The ID of the POSIX timer, only valid if `code` is [`SI_TIMER`].
+    pub timer_id: i32,
+    /// The timer overrun count, only valid if `code` is [`SI_TIMER`].
+    pub overrun: i32,
+    /// The value given in `sigevent` when the timer was created.
+    pub value: usize,
+}
+
+/// Pending signals of a thread or a thread group. Standard signals are not
+/// queued, a signal is discarded if it is already pending.
+#[derive(Default)]
+pub(super) struct SigPending(BTreeMap<u8, SigInfo>);
+
+#[derive(Debug, Clone, Copy)]
+pub enum SigMaskHow {
+    Block,
+    Unblock,
+    SetMask,
+}
+
+impl Signal {
+    pub const fn signo(self) -> u8 {
+        self as u8
+    }
+}
+
+impl SigSet {
+    /// Signals that can not be blocked.
+    const UNBLOCKABLE: Self =
+        Self::from_signal(Signal::SIGKILL).union(Self::from_signal(Signal::SIGSTOP));
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    const fn from_signal(sig: Signal) -> Self {
+        Self(1 << (sig as u8 - 1))
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(&self, signo: u8) -> bool {
+        self.0 & (1 << (signo - 1)) != 0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl SigInfo {
+    /// Creates the information of a signal sent by the kernel.
+    pub const fn kernel(signo: u8) -> Self {
+        Self {
+            signo,
+            code: SI_KERNEL,
+            timer_id: 0,
+            overrun: 0,
+            value: 0,
+        }
+    }
+}
+
+impl SigPending {
+    /// Adds a signal, returns `false` if it is already pending.
+    fn add(&mut self, info: SigInfo) -> bool {
+        match self.0.entry(info.signo) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(e) => {
+                e.insert(info);
+                true
+            }
+        }
+    }
+
+    /// Removes the lowest-numbered pending signal in `mask`.
+    fn take(&mut self, mask: SigSet) -> Option<SigInfo> {
+        let signo = *self.0.keys().find(|&&signo| mask.contains(signo))?;
+        self.0.remove(&signo)
+    }
+}
+
+pub const fn is_valid_signo(signo: usize) -> bool {
+    signo >= 1 && signo <= NSIG
+}
+
+/// Whether the default action of the signal is to ignore it. Tasks can not be
+/// stopped currently, so the stop signals are also ignored.
+const fn is_default_ignored(signo: u8) -> bool {
+    const IGNORED: SigSet = SigSet::empty()
+        .union(SigSet::from_signal(Signal::SIGCHLD))
+        .union(SigSet::from_signal(Signal::SIGCONT))
+        .union(SigSet::from_signal(Signal::SIGSTOP))
+        .union(SigSet::from_signal(Signal::SIGTSTP))
+        .union(SigSet::from_signal(Signal::SIGTTIN))
+        .union(SigSet::from_signal(Signal::SIGTTOU))
+        .union(SigSet::from_signal(Signal::SIGURG))
+        .union(SigSet::from_signal(Signal::SIGWINCH));
+    IGNORED.contains(signo)
+}
+
+impl Task {
+    pub(super) fn sig_blocked(&self) -> SigSet {
+        SigSet(self.sig_blocked.load(Ordering::SeqCst))
+    }
+
+    fn set_sig_blocked(&self, set: SigSet) {
+        let set = set.difference(SigSet::UNBLOCKABLE);
+        self.sig_blocked.store(set.bits(), Ordering::SeqCst);
+    }
+
+    /// Sends a signal to the thread group of `self`, or to the thread `self`
+    /// only if `thread_directed` is true. Returns `false` if the signal is
+    /// discarded.
+    ///
+    /// If the signal is not blocked by the receiver and its default action is
+    /// to terminate, the thread group starts exiting immediately. Otherwise
+    /// threads waiting in `sigtimedwait()` are woken up.
+    pub fn send_signal(self: &Arc<Self>, info: SigInfo, thread_directed: bool) -> bool {
+        let leader = self.group_leader();
+        if self.is_group_exiting() || leader.is_group_dead() {
+            return false;
+        }
+        let queued = if thread_directed {
+            self.sig_pending.lock().add(info)
+        } else {
+            leader.shared_sig_pending.lock().add(info)
+        };
+        if !queued {
+            return false;
+        }
+
+        let signo = info.signo;
+        let accepts =
+            |t: &Arc<Task>| t.state() != TaskState::Zombie && !t.sig_blocked().contains(signo);
+        let fatal = !is_default_ignored(signo)
+            && if thread_directed {
+                accepts(self)
+            } else {
+                accepts(leader) || leader.threads.lock().iter().any(accepts)
+            };
+        if fatal {
+            leader.start_group_exit(signaled_status(signo));
+        } else {
+            leader.sig_waiters.notify_all();
+        }
+        true
+    }
+}
+
+impl<'a> CurrentTask<'a> {
+    /// Removes a pending signal in `mask`, thread-directed signals are taken
+    /// first.
+    fn dequeue_signal(&self, mask: SigSet) -> Option<SigInfo> {
+        let info = self.sig_pending.lock().take(mask);
+        let mut info = info.or_else(|| self.group_leader().shared_sig_pending.lock().take(mask))?;
+        if info.code == SI_TIMER {
+            info.overrun = super::posix_timer::signal_dequeued(self, info.timer_id);
+        }
+        Some(info)
+    }
+
+    /// Examines and changes the blocked signals of the current thread,
+    /// returns the old set.
+    pub fn sigprocmask(&self, how: SigMaskHow, set: Option<SigSet>) -> SigSet {
+        let old = self.sig_blocked();
+        if let Some(set) = set {
+            self.set_sig_blocked(match how {
+                SigMaskHow::Block => old.union(set),
+                SigMaskHow::Unblock => old.difference(set),
+                SigMaskHow::SetMask => set,
+            });
+        }
+        old
+    }
+
+    /// Waits for a signal in `mask` to become pending and removes it, until
+    /// `deadline` if it is not `None`.
+    ///
+    /// Returns [`Errno::EAGAIN`] on timeout.
+    pub fn sigtimedwait(&self, mask: SigSet, deadline: Option<TimeValue>) -> SysResult<SigInfo> {
+        let leader = self.group_leader();
+        loop {
+            // Check pending signals while holding the lock of `TASK_MANAGER`,
+            // to avoid missing notifications from `send_signal()`.
+            let mut m = TASK_MANAGER.lock();
+            if let Some(info) = self.dequeue_signal(mask) {
+                return Ok(info);
+            }
+            if self.is_group_exiting() {
+                return Err(Errno::EINTR);
+            }
+            match deadline {
+                Some(deadline) if crate::timer::current_time() >= deadline => {
+                    return Err(Errno::EAGAIN)
+                }
+                Some(deadline) => leader.sig_waiters.wait_timeout_locked(&mut m, deadline),
+                None => leader.sig_waiters.wait_locked(&mut m),
+            }
+        }
+    }
+
+    /// Takes the default actions of pending signals that are not blocked,
+    /// called before returning to user space.
+    pub(super) fn handle_pending_signals(&self) {
+        let mask = SigSet::from_bits(!0).difference(self.sig_blocked());
+        while let Some(info) = self.dequeue_signal(mask) {
+            if !is_default_ignored(info.signo) {
+                warn!("task killed by signal {}", info.signo);
+                self.do_group_exit(signaled_status(info.signo));
+            }
+        }
+    }
+}
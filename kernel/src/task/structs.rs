@@ -1,29 +1,47 @@
 use alloc::sync::{Arc, Weak};
-use alloc::{boxed::Box, vec::Vec};
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicUsize, Ordering};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 
 use super::manager::{TaskLockedCell, TASK_MANAGER};
+use super::posix_timer::PosixTimers;
 use super::schedule::SchedulerState;
-use super::wait_queue::WaitCurrent;
+use super::signal::{SigPending, Signal};
+use super::wait_queue::WaitQueue;
 use crate::arch::{instructions, TaskContext, TrapFrame};
-use crate::config::KERNEL_STACK_SIZE;
+use crate::errno::{Errno, SysResult};
+use crate::fs::FdTable;
 use crate::loader;
-use crate::mm::{kernel_aspace, MemorySet, VirtAddr};
+use crate::mm::{kernel_aspace, KBox, KernelStack, KmemCache, MemorySet, VirtAddr};
 use crate::percpu::PerCpu;
 use crate::sync::{LazyInit, Mutex};
-use crate::timer::TimeValue;
+use crate::timer::{current_time_nanos, TimeValue};
 
 pub(super) static ROOT_TASK: LazyInit<Arc<Task>> = LazyInit::new();
 
+/// Serves all `Arc<Task>`s once registered.
+static TASK_CACHE: KmemCache<Task> = KmemCache::new_arc("task");
+static TRAP_FRAME_CACHE: KmemCache<TrapFrame> = KmemCache::new("trap_frame");
+
 #[derive(Debug)]
 enum EntryState {
     Kernel { pc: usize, arg: usize },
-    User(Box<TrapFrame>),
+    User(KBox<TrapFrame>),
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(usize);
 
+bitflags::bitflags! {
+    /// Options of `waitpid()`.
+    pub struct WaitOptions: u32 {
+        /// Return immediately if no child has exited.
+        const WNOHANG = 1;
+        /// Also return if a child has stopped. Tasks can not be stopped
+        /// currently, so it has no effect.
+        const WUNTRACED = 2;
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum TaskState {
@@ -36,22 +54,64 @@ pub enum TaskState {
 pub struct Task {
     id: TaskId,
     is_kernel: bool,
-    is_shared: bool,
+    /// High-priority tasks are scheduled before others, and preempt the
+    /// running task when they are woken up.
+    high_priority: bool,
     entry: EntryState,
 
     state: AtomicU8,
-    exit_code: AtomicI32,
+    /// The wait status word, see `exited_status()` and `signaled_status()`.
+    exit_status: AtomicI32,
     need_resched: AtomicBool,
     sched_state: SchedulerState,
+    /// CPU time consumed before the task was last scheduled in, in
+    /// nanoseconds.
+    cpu_time_ns: AtomicU64,
+    /// The time when the task was last scheduled in, in nanoseconds.
+    run_start_ns: AtomicU64,
 
-    kstack: Stack<KERNEL_STACK_SIZE>,
+    kstack: KernelStack,
     ctx: TaskLockedCell<TaskContext>,
 
-    pub(super) wait_children_exit: WaitCurrent,
+    pub(super) wait_children_exit: WaitQueue,
+    clear_child_tid: AtomicUsize,
+
+    /// Blocked signals, see [`super::signal::SigSet`].
+    pub(super) sig_blocked: AtomicU64,
+    /// Pending signals sent to this thread only.
+    pub(super) sig_pending: Mutex<SigPending>,
 
     vm: Option<Arc<Mutex<MemorySet>>>,
+    /// Open files, shared by all threads in the thread group.
+    files: Arc<Mutex<FdTable>>,
     pub(super) parent: Mutex<Weak<Task>>,
     pub(super) children: Mutex<Vec<Arc<Task>>>,
+
+    /// The thread group leader, `None` if the task is the leader itself.
+    leader: Option<Arc<Task>>,
+    /// Other threads in the thread group, only used by the group leader.
+    pub(super) threads: Mutex<Vec<Arc<Task>>>,
+    /// Whether the whole thread group is exiting, only used by the group leader.
+    group_exiting: AtomicBool,
+    /// The number of threads in the group that have not started exiting, only
+    /// used by the group leader.
+    live_threads: AtomicUsize,
+    /// Pending signals sent to the thread group, only used by the group leader.
+    pub(super) shared_sig_pending: Mutex<SigPending>,
+    /// Threads waiting in `sigtimedwait()`, only used by the group leader.
+    pub(super) sig_waiters: WaitQueue,
+    /// POSIX timers of the thread group, only used by the group leader.
+    pub(super) posix_timers: Mutex<PosixTimers>,
+    /// CPU time of released threads in nanoseconds, only used by the group
+    /// leader.
+    released_cpu_time_ns: AtomicU64,
+}
+
+/// Registers the slab caches of tasks, so that `Arc<Task>`s are allocated from
+/// the task cache.
+pub(super) fn init_caches() {
+    TASK_CACHE.register();
+    TRAP_FRAME_CACHE.register();
 }
 
 impl TaskId {
@@ -90,22 +150,38 @@ impl Task {
         Self {
             id,
             is_kernel: false,
-            is_shared: false,
+            high_priority: false,
             entry: EntryState::Kernel { pc: 0, arg: 0 },
 
             state: AtomicU8::new(TaskState::Ready as u8),
-            exit_code: AtomicI32::new(0),
+            exit_status: AtomicI32::new(0),
             need_resched: AtomicBool::new(false),
             sched_state: SchedulerState::default(),
+            cpu_time_ns: AtomicU64::new(0),
+            run_start_ns: AtomicU64::new(0),
 
-            kstack: Stack::default(),
+            kstack: KernelStack::alloc(),
             ctx: TaskLockedCell::new(TaskContext::default()),
 
-            wait_children_exit: WaitCurrent::new(),
+            wait_children_exit: WaitQueue::new(),
+            clear_child_tid: AtomicUsize::new(0),
+
+            sig_blocked: AtomicU64::new(0),
+            sig_pending: Mutex::new(SigPending::default()),
 
             vm: None,
+            files: Arc::new(Mutex::new(FdTable::new_stdio())),
             parent: Mutex::new(Weak::default()),
             children: Mutex::new(Vec::new()),
+
+            leader: None,
+            threads: Mutex::new(Vec::new()),
+            group_exiting: AtomicBool::new(false),
+            live_threads: AtomicUsize::new(1),
+            shared_sig_pending: Mutex::new(SigPending::default()),
+            sig_waiters: WaitQueue::new(),
+            posix_timers: Mutex::new(PosixTimers::default()),
+            released_cpu_time_ns: AtomicU64::new(0),
         }
     }
 
@@ -125,12 +201,12 @@ impl Task {
         self.ctx.get_mut().init(
             0,
             self.kstack.top(),
-            kernel_aspace().page_table_root(),
+            kernel_aspace().lock().page_table_root(),
             true,
         );
     }
 
-    pub fn new_kernel(entry: fn(usize) -> usize, arg: usize) -> Arc<Self> {
+    fn new_kernel_common(entry: fn(usize) -> usize, arg: usize) -> Self {
         let mut t = Self::new_common(TaskId::alloc());
         t.is_kernel = true;
         t.entry = EntryState::Kernel {
@@ -140,24 +216,42 @@ impl Task {
         t.ctx.get_mut().init(
             task_entry as _,
             t.kstack.top(),
-            kernel_aspace().page_table_root(),
+            kernel_aspace().lock().page_table_root(),
             true,
         );
+        t
+    }
 
-        let t = Arc::new(t);
+    pub fn new_kernel(entry: fn(usize) -> usize, arg: usize) -> Arc<Self> {
+        let t = Arc::new(Self::new_kernel_common(entry, arg));
         if !t.is_root() {
             ROOT_TASK.add_child(&t);
         }
         t
     }
 
-    pub fn new_user(path: &str) -> Arc<Self> {
-        let elf_data = loader::get_app_data_by_name(path).expect("new_user: no such app");
+    /// Creates a kernel task running in the background, which is not a child
+    /// of the root task, so it does not keep the system from shutting down.
+    pub fn new_daemon(entry: fn(usize) -> usize, arg: usize) -> Arc<Self> {
+        Arc::new(Self::new_kernel_common(entry, arg))
+    }
+
+    /// Creates a high-priority daemon, which runs the threaded handlers of
+    /// IRQs.
+    pub fn new_irq_thread(entry: fn(usize) -> usize, arg: usize) -> Arc<Self> {
+        let mut t = Self::new_kernel_common(entry, arg);
+        t.high_priority = true;
+        Arc::new(t)
+    }
+
+    pub fn new_user(path: &str) -> SysResult<Arc<Self>> {
+        let elf_data = loader::load_app(path)?;
         let mut vm = MemorySet::new();
-        let (entry, ustack_top) = vm.load_user(elf_data);
+        let (entry, ustack_top) = vm.load_user(&elf_data)?;
 
         let mut t = Self::new_common(TaskId::alloc());
-        t.entry = EntryState::User(Box::new(TrapFrame::new_user(entry, ustack_top, 0)));
+        t.entry =
+            EntryState::User(TRAP_FRAME_CACHE.alloc(TrapFrame::new_user(entry, ustack_top, 0)));
         t.ctx
             .get_mut()
             .init(task_entry as _, t.kstack.top(), vm.page_table_root(), false);
@@ -165,15 +259,16 @@ impl Task {
 
         let t = Arc::new(t);
         ROOT_TASK.add_child(&t);
-        t
+        Ok(t)
     }
 
+    /// Creates a new thread in the same thread group as `self`.
     pub fn new_clone(self: &Arc<Self>, newsp: usize, tf: &TrapFrame) -> Arc<Self> {
         assert!(!self.is_kernel_task());
+        let leader = self.group_leader().clone();
         let mut t = Self::new_common(TaskId::alloc());
-        t.is_shared = true;
         let vm = self.vm.as_ref().unwrap().clone();
-        t.entry = EntryState::User(Box::new(tf.new_clone(VirtAddr::new(newsp))));
+        t.entry = EntryState::User(TRAP_FRAME_CACHE.alloc(tf.new_clone(VirtAddr::new(newsp))));
         t.ctx.get_mut().init(
             task_entry as _,
             t.kstack.top(),
@@ -181,9 +276,25 @@ impl Task {
             false,
         );
         t.vm = Some(vm);
+        t.files = self.files.clone();
+        t.leader = Some(leader.clone());
+        t.sig_blocked = AtomicU64::new(self.sig_blocked().bits());
 
         let t = Arc::new(t);
-        self.add_child(&t);
+        let mut threads = leader.threads.lock();
+        // Exited threads are only kept until here or the group is released.
+        threads.retain(|t| {
+            let alive = t.state() != TaskState::Zombie;
+            if !alive {
+                let cpu_time = t.cpu_time_ns.load(Ordering::SeqCst);
+                leader
+                    .released_cpu_time_ns
+                    .fetch_add(cpu_time, Ordering::SeqCst);
+            }
+            alive
+        });
+        threads.push(t.clone());
+        leader.live_threads.fetch_add(1, Ordering::SeqCst);
         t
     }
 
@@ -191,17 +302,20 @@ impl Task {
         assert!(!self.is_kernel_task());
         let mut t = Self::new_common(TaskId::alloc());
         let vm = self.vm.as_ref().unwrap().lock().dup();
-        t.entry = EntryState::User(Box::new(tf.new_fork()));
+        t.entry = EntryState::User(TRAP_FRAME_CACHE.alloc(tf.new_fork()));
         t.ctx
             .get_mut()
             .init(task_entry as _, t.kstack.top(), vm.page_table_root(), false);
         t.vm = Some(Arc::new(Mutex::new(vm)));
+        t.files = Arc::new(Mutex::new(self.files.lock().clone()));
+        t.sig_blocked = AtomicU64::new(self.sig_blocked().bits());
 
         let t = Arc::new(t);
-        self.add_child(&t);
+        self.group_leader().add_child(&t);
         t
     }
 
+    /// Returns the task ID, it is also the thread ID for user tasks.
     pub const fn pid(&self) -> TaskId {
         self.id
     }
@@ -218,8 +332,66 @@ impl Task {
         self.id.as_usize() == 0
     }
 
-    pub const fn is_shared_with_parent(&self) -> bool {
-        self.is_shared
+    pub const fn is_high_priority(&self) -> bool {
+        self.high_priority
+    }
+
+    /// Returns the thread group ID, i.e. the process ID in user space.
+    pub fn tgid(&self) -> TaskId {
+        self.leader.as_ref().map_or(self.id, |l| l.id)
+    }
+
+    pub fn group_leader<'a>(self: &'a Arc<Self>) -> &'a Arc<Task> {
+        self.leader.as_ref().unwrap_or(self)
+    }
+
+    pub fn is_group_exiting(self: &Arc<Self>) -> bool {
+        self.group_leader().group_exiting.load(Ordering::SeqCst)
+    }
+
+    /// Starts terminating all threads in the thread group with `exit_status`,
+    /// only valid for the group leader. Returns `false` if the group is
+    /// already exiting.
+    pub(super) fn start_group_exit(self: &Arc<Self>, exit_status: i32) -> bool {
+        assert!(self.leader.is_none());
+        if self.group_exiting.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.set_exit_status(exit_status);
+        // Wake up all threads, they will exit before returning to user space.
+        let mut m = TASK_MANAGER.lock();
+        m.unblock_task(self.clone());
+        for t in self.threads.lock().iter() {
+            m.unblock_task(t.clone());
+        }
+        true
+    }
+
+    /// Whether all threads in the group have exited, only valid for the group
+    /// leader.
+    pub(super) fn is_group_dead(&self) -> bool {
+        self.state() == TaskState::Zombie
+            && self
+                .threads
+                .lock()
+                .iter()
+                .all(|t| t.state() == TaskState::Zombie)
+    }
+
+    pub fn parent(&self) -> Option<Arc<Task>> {
+        self.parent.lock().upgrade()
+    }
+
+    pub fn vm(&self) -> Option<&Arc<Mutex<MemorySet>>> {
+        self.vm.as_ref()
+    }
+
+    pub fn files(&self) -> &Mutex<FdTable> {
+        &self.files
+    }
+
+    pub fn set_clear_child_tid(&self, tidptr: usize) {
+        self.clear_child_tid.store(tidptr, Ordering::SeqCst);
     }
 
     pub fn state(&self) -> TaskState {
@@ -230,12 +402,12 @@ impl Task {
         self.state.store(state as u8, Ordering::SeqCst)
     }
 
-    pub fn exit_code(&self) -> i32 {
-        self.exit_code.load(Ordering::SeqCst)
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status.load(Ordering::SeqCst)
     }
 
-    pub(super) fn set_exit_code(&self, exit_code: i32) {
-        self.exit_code.store(exit_code, Ordering::SeqCst)
+    pub(super) fn set_exit_status(&self, exit_status: i32) {
+        self.exit_status.store(exit_status, Ordering::SeqCst)
     }
 
     pub fn need_resched(&self) -> bool {
@@ -250,8 +422,37 @@ impl Task {
         &self.sched_state
     }
 
+    /// Updates the CPU time when the task is scheduled out at `now_ns`.
+    pub(super) fn account_run_end(&self, now_ns: u64) {
+        let start_ns = self.run_start_ns.load(Ordering::SeqCst);
+        self.cpu_time_ns
+            .fetch_add(now_ns.saturating_sub(start_ns), Ordering::SeqCst);
+    }
+
+    /// Records the time when the task is scheduled in.
+    pub(super) fn account_run_start(&self, now_ns: u64) {
+        self.run_start_ns.store(now_ns, Ordering::SeqCst);
+    }
+
+    /// Returns the CPU time consumed by the thread in nanoseconds, including
+    /// the current run. `TASK_MANAGER` must be locked so that the task is not
+    /// scheduled meanwhile.
+    fn cpu_time_locked(&self, now_ns: u64) -> u64 {
+        assert!(TASK_MANAGER.is_locked());
+        let cpu_time = self.cpu_time_ns.load(Ordering::SeqCst);
+        if self.state() == TaskState::Running {
+            let start_ns = self.run_start_ns.load(Ordering::SeqCst);
+            cpu_time + now_ns.saturating_sub(start_ns)
+        } else {
+            cpu_time
+        }
+    }
+
     pub(super) fn traverse(self: &Arc<Self>, func: &impl Fn(&Arc<Task>)) {
         func(self);
+        for t in self.threads.lock().iter() {
+            func(t);
+        }
         for c in self.children.lock().iter() {
             c.traverse(func);
         }
@@ -292,6 +493,31 @@ impl<'a> CurrentTask<'a> {
         self.0.clone()
     }
 
+    /// Returns the CPU time consumed by the current thread.
+    pub fn thread_cpu_time(&self) -> TimeValue {
+        let _m = TASK_MANAGER.lock();
+        TimeValue::from_nanos(self.cpu_time_locked(current_time_nanos()))
+    }
+
+    /// Returns the CPU time consumed by all threads in the thread group of the
+    /// current task, including exited threads.
+    pub fn process_cpu_time(&self) -> TimeValue {
+        let _m = TASK_MANAGER.lock();
+        let now_ns = current_time_nanos();
+        let leader = self.group_leader();
+        let threads_ns: u64 = leader
+            .threads
+            .lock()
+            .iter()
+            .map(|t| t.cpu_time_locked(now_ns))
+            .sum();
+        TimeValue::from_nanos(
+            leader.cpu_time_locked(now_ns)
+                + threads_ns
+                + leader.released_cpu_time_ns.load(Ordering::SeqCst),
+        )
+    }
+
     pub fn clear_need_resched(&self) {
         self.0.need_resched.store(false, Ordering::SeqCst);
     }
@@ -308,76 +534,143 @@ impl<'a> CurrentTask<'a> {
         TASK_MANAGER.lock().sleep_current(self, deadline);
     }
 
+    /// Terminates the current thread, other threads in the thread group are
+    /// not affected.
     pub fn exit(&self, exit_code: i32) -> ! {
         info!("task exit with code {}", exit_code);
+        self.do_exit(exited_status(exit_code))
+    }
+
+    pub(super) fn do_exit(&self, exit_status: i32) -> ! {
+        let tidptr = self.clear_child_tid.swap(0, Ordering::SeqCst);
+        if tidptr != 0 {
+            super::futex::clear_child_tid(tidptr);
+        }
         if let Some(vm) = self.vm.as_ref() {
-            if Arc::strong_count(vm) == 1 {
+            if self.leave_group() {
                 vm.lock().clear(); // drop memory set before lock
+                self.group_leader().posix_timers.lock().remove_all();
+                // close files outside the lock
+                let files = core::mem::take(&mut *self.files.lock());
+                drop(files);
             }
         }
-        TASK_MANAGER.lock().exit_current(self, exit_code)
+        TASK_MANAGER.lock().exit_current(self, exit_status)
     }
 
-    pub fn exec(&self, path: &str, tf: &mut TrapFrame) -> isize {
-        assert!(!self.is_kernel_task());
-        assert_eq!(Arc::strong_count(self.vm.as_ref().unwrap()), 1);
-        if let Some(elf_data) = loader::get_app_data_by_name(path) {
-            let mut vm = self.vm.as_ref().unwrap().lock();
-            vm.clear();
-            let (entry, ustack_top) = vm.load_user(elf_data);
-            *tf = TrapFrame::new_user(entry, ustack_top, 0);
-            instructions::flush_tlb_all();
-            0
-        } else {
-            -1
-        }
+    /// Terminates all threads in the thread group of the current task.
+    pub fn exit_group(&self, exit_code: i32) -> ! {
+        info!("task group exit with code {}", exit_code);
+        self.do_group_exit(exited_status(exit_code))
     }
 
-    pub fn waitpid(&self, pid: isize, _options: u32) -> Option<(TaskId, i32)> {
-        let mut found_pid = false;
-        for t in self.children.lock().iter() {
-            if pid == -1 || t.pid().as_usize() == pid as usize {
-                found_pid = true;
-                break;
-            }
+    /// Terminates all threads in the thread group of the current task, as if
+    /// it was killed by the signal `sig`.
+    pub fn kill(&self, sig: Signal) -> ! {
+        warn!("task killed by {:?}", sig);
+        self.do_group_exit(signaled_status(sig.signo()))
+    }
+
+    pub(super) fn do_group_exit(&self, exit_status: i32) -> ! {
+        let leader = self.group_leader();
+        leader.start_group_exit(exit_status);
+        self.do_exit(leader.exit_status())
+    }
+
+    fn is_last_thread(&self) -> bool {
+        self.group_leader().live_threads.load(Ordering::SeqCst) == 1
+    }
+
+    /// Removes the current thread from the live threads of the group, returns
+    /// whether it is the last one. The check and the removal are one atomic
+    /// step, so exactly one of the threads exiting at once sees it is the last.
+    fn leave_group(&self) -> bool {
+        self.group_leader()
+            .live_threads
+            .fetch_sub(1, Ordering::SeqCst)
+            == 1
+    }
+
+    /// Replaces the address space of the current task with a new program.
+    ///
+    /// The old address space is kept if the program can not be loaded.
+    /// Calling it while other threads in the group are still running is not
+    /// supported and returns [`Errno::EBUSY`].
+    pub fn exec(&self, path: &str, tf: &mut TrapFrame) -> SysResult<()> {
+        assert!(!self.is_kernel_task());
+        if !self.is_last_thread() {
+            return Err(Errno::EBUSY);
+        }
+        let elf_data = loader::load_app(path)?;
+        let mut vm = self.vm.as_ref().unwrap().lock();
+        let (entry, ustack_top) = vm.load_user(&elf_data)?;
+        *tf = TrapFrame::new_user(entry, ustack_top, 0);
+        self.group_leader()
+            .posix_timers
+            .lock()
+            .remove_posix_timers();
+        instructions::flush_tlb_all();
+        Ok(())
+    }
+
+    /// Waits for a child process to exit, returns its process ID and wait
+    /// status, or `None` if `WNOHANG` is specified and no child has exited.
+    ///
+    /// `pid` is either a process ID, or -1 for any child. Process groups are
+    /// not supported, so `pid` 0 or less than -1 (a process group) matches
+    /// no child.
+    pub fn waitpid(&self, pid: isize, options: WaitOptions) -> SysResult<Option<(TaskId, i32)>> {
+        if pid == 0 || pid < -1 {
+            return Err(Errno::ECHILD);
         }
-        if !found_pid {
-            return None;
+        // Children are shared by all threads in the thread group.
+        let leader = self.group_leader();
+        let is_target = |t: &Arc<Task>| pid == -1 || t.pid().as_usize() == pid as usize;
+        if !leader.children.lock().iter().any(is_target) {
+            return Err(Errno::ECHILD);
         }
 
         loop {
+            // Check children while holding the lock of `TASK_MANAGER`, to
+            // avoid missing notifications from `exit_current()`.
+            let mut m = TASK_MANAGER.lock();
+            let mut children = leader.children.lock();
+            if let Some(idx) = children
+                .iter()
+                .position(|t| is_target(t) && t.is_group_dead())
             {
-                let mut children = self.children.lock();
-                for (idx, t) in children.iter().enumerate() {
-                    if (pid == -1 || t.pid().as_usize() == pid as usize)
-                        && t.state() == TaskState::Zombie
-                    {
-                        let child = children.remove(idx);
-                        assert_eq!(Arc::strong_count(&child), 1);
-                        return Some((child.pid(), child.exit_code()));
-                    }
-                }
+                let child = children.remove(idx);
+                drop(children);
+                drop(m);
+                // Release exited threads, which hold the leader.
+                child.threads.lock().clear();
+                return Ok(Some((child.pid(), child.exit_status())));
+            }
+            drop(children);
+            if options.contains(WaitOptions::WNOHANG) {
+                return Ok(None);
+            }
+            if self.is_group_exiting() {
+                return Err(Errno::EINTR);
             }
-            self.wait_children_exit.wait();
+            leader.wait_children_exit.wait_locked(&mut m);
         }
     }
 }
 
+/// Encodes the wait status of a task that exited normally.
+const fn exited_status(exit_code: i32) -> i32 {
+    (exit_code & 0xff) << 8
+}
+
+/// Encodes the wait status of a task that was killed by the signal `signo`.
+pub(super) const fn signaled_status(signo: u8) -> i32 {
+    signo as i32 & 0x7f
+}
+
 impl<'a> core::ops::Deref for CurrentTask<'a> {
     type Target = Arc<Task>;
     fn deref(&self) -> &Self::Target {
         self.0
     }
 }
-
-struct Stack<const N: usize>(Box<[u8]>);
-
-impl<const N: usize> Stack<N> {
-    pub fn default() -> Self {
-        Self(Box::from(alloc::vec![0; N]))
-    }
-
-    pub fn top(&self) -> VirtAddr {
-        VirtAddr::new(self.0.as_ptr_range().end as usize)
-    }
-}
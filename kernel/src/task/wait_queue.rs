@@ -1,44 +1,110 @@
-use alloc::sync::Arc;
+use alloc::{collections::VecDeque, sync::Arc};
 
 use super::manager::{TaskManager, TASK_MANAGER};
 use super::{current, Task};
 use crate::sync::SpinNoIrqLock;
+use crate::timer::{current_time, TimeValue};
 
-pub struct WaitCurrent {
-    current: SpinNoIrqLock<Option<Arc<Task>>>,
+pub struct WaitQueue {
+    queue: SpinNoIrqLock<VecDeque<Arc<Task>>>,
 }
 
-impl WaitCurrent {
-    pub const fn new() -> Self {
+impl WaitQueue {
+    pub fn new() -> Self {
         Self {
-            current: SpinNoIrqLock::new(None),
+            queue: SpinNoIrqLock::new(VecDeque::new()),
         }
     }
 
+    /// Blocks the current task and put it into the wait queue, until other
+    /// tasks notify it.
+    #[allow(dead_code)]
     pub fn wait(&self) {
+        assert!(!TASK_MANAGER.is_locked());
+        self.wait_locked(&mut TASK_MANAGER.lock());
+    }
+
+    /// Blocks the current task until `condition` returns `true`. The condition
+    /// is checked with interrupts disabled, so a wakeup from an interrupt
+    /// handler between the check and the sleep is not lost.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        assert!(!TASK_MANAGER.is_locked());
+        let mut m = TASK_MANAGER.lock();
+        while !condition() {
+            self.wait_locked(&mut m);
+        }
+    }
+
+    /// Like [`WaitQueue::wait_until`], but gives up at `deadline`. Returns
+    /// whether the condition is satisfied.
+    pub fn wait_until_timeout(
+        &self,
+        mut condition: impl FnMut() -> bool,
+        deadline: TimeValue,
+    ) -> bool {
         assert!(!TASK_MANAGER.is_locked());
         let mut m = TASK_MANAGER.lock();
+        while !condition() {
+            if current_time() >= deadline {
+                return false;
+            }
+            self.wait_timeout_locked(&mut m, deadline);
+        }
+        true
+    }
+
+    pub(super) fn wait_locked(&self, m: &mut TaskManager) {
+        assert!(TASK_MANAGER.is_locked());
         let curr_task = current();
-        assert!(self
-            .current
-            .lock()
-            .replace(curr_task.clone_task())
-            .is_none());
+        self.queue.lock().push_back(curr_task.clone_task());
         m.block_current(&curr_task);
+        // The task may be woken up by others (e.g. `exit_group()`), remove it
+        // from the queue to avoid spurious wakeups later.
+        self.queue.lock().retain(|t| !Arc::ptr_eq(t, curr_task.0));
     }
 
-    #[allow(dead_code)]
-    pub fn notify(&self) -> bool {
+    /// Like [`WaitQueue::wait_locked`], but also wakes up the current task at
+    /// `deadline`.
+    pub(super) fn wait_timeout_locked(&self, m: &mut TaskManager, deadline: TimeValue) {
+        assert!(TASK_MANAGER.is_locked());
+        let curr_task = current();
+        self.queue.lock().push_back(curr_task.clone_task());
+        m.sleep_current(&curr_task, deadline);
+        self.queue.lock().retain(|t| !Arc::ptr_eq(t, curr_task.0));
+    }
+
+    pub fn notify_one(&self) -> bool {
+        assert!(!TASK_MANAGER.is_locked());
+        self.notify_one_locked(&mut TASK_MANAGER.lock())
+    }
+
+    pub fn notify_all(&self) -> usize {
         assert!(!TASK_MANAGER.is_locked());
-        self.notify_locked(&mut TASK_MANAGER.lock())
+        self.notify_all_locked(&mut TASK_MANAGER.lock())
     }
 
-    pub(super) fn notify_locked(&self, m: &mut TaskManager) -> bool {
+    pub(super) fn notify_one_locked(&self, m: &mut TaskManager) -> bool {
         assert!(TASK_MANAGER.is_locked());
-        if let Some(t) = self.current.lock().take() {
-            m.unblock_task(t)
-        } else {
-            false
+        while let Some(t) = self.queue.lock().pop_front() {
+            if m.unblock_task(t) {
+                return true;
+            }
         }
+        false
+    }
+
+    pub(super) fn notify_all_locked(&self, m: &mut TaskManager) -> usize {
+        assert!(TASK_MANAGER.is_locked());
+        let mut count = 0;
+        while let Some(t) = self.queue.lock().pop_front() {
+            if m.unblock_task(t) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().is_empty()
     }
 }
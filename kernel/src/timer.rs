@@ -1,9 +1,7 @@
-#![allow(dead_code)]
-
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 
 use crate::sync::{LazyInit, SpinNoIrqLock};
-use crate::utils::timer_list::TimerList;
+use crate::utils::timer_list::{self, TimerId, TimerList};
 
 pub use crate::drivers::timer::{current_ticks, nanos_to_ticks, set_oneshot_timer, ticks_to_nanos};
 pub use crate::utils::timer_list::TimeValue;
@@ -12,9 +10,19 @@ pub const NANOS_PER_SEC: u64 = 1_000_000_000;
 pub const MICROS_PER_SEC: u64 = 1_000_000;
 
 const PERIODIC_INTERVAL_NANOS: u64 = NANOS_PER_SEC / crate::config::TICKS_PER_SEC;
+/// The maximum interval of the one-shot timer when the periodic tick is
+/// stopped and no timer is pending, it must fit in the hardware timers.
+const MAX_IDLE_INTERVAL_NANOS: u64 = NANOS_PER_SEC;
 
 static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(0);
 static NEXT_PERIODIC_DEADLINE: AtomicU64 = AtomicU64::new(0);
+/// Whether the periodic scheduler tick is stopped (NO_HZ mode).
+static TICK_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// The offset of the real time from the monotonic time, i.e. the real time
+/// at boot. It is zero (the Epoch) until the real time is set, and negative
+/// if the real time is set to a time earlier than the time since boot.
+static REALTIME_OFFSET_NANOS: AtomicI64 = AtomicI64::new(0);
 
 static TIMER_LIST: LazyInit<SpinNoIrqLock<TimerList>> = LazyInit::new();
 
@@ -23,6 +31,27 @@ fn update_deadline(deadline_ns: u64) {
     set_oneshot_timer(deadline_ns);
 }
 
+fn update_deadline_if_earlier(deadline: TimeValue) {
+    let deadline_ns = deadline.as_nanos() as u64;
+    if deadline_ns < NEXT_DEADLINE.load(Ordering::Acquire) {
+        update_deadline(deadline_ns);
+    }
+}
+
+/// Returns the next deadline of the timer interrupt, i.e. the earliest one of
+/// the periodic tick (if it is not stopped) and the timers in `TIMER_LIST`.
+fn next_deadline_nanos() -> u64 {
+    let mut deadline = if TICK_STOPPED.load(Ordering::Acquire) {
+        current_time_nanos() + MAX_IDLE_INTERVAL_NANOS
+    } else {
+        NEXT_PERIODIC_DEADLINE.load(Ordering::Acquire)
+    };
+    if let Some(d) = TIMER_LIST.lock().next_deadline() {
+        deadline = deadline.min(d.as_nanos() as u64);
+    }
+    deadline
+}
+
 pub fn current_time_nanos() -> u64 {
     ticks_to_nanos(current_ticks())
 }
@@ -31,38 +60,185 @@ pub fn current_time() -> TimeValue {
     TimeValue::from_nanos(current_time_nanos())
 }
 
+/// Stops the periodic scheduler tick, the timer interrupt then only fires for
+/// expired timers. It takes effect when the timer interrupt is reprogrammed.
+pub fn stop_tick() {
+    TICK_STOPPED.store(true, Ordering::Release);
+}
+
+/// Restarts the periodic scheduler tick if it is stopped, the next tick is one
+/// period later.
+pub fn restart_tick() {
+    if TICK_STOPPED.swap(false, Ordering::AcqRel) {
+        let deadline = current_time_nanos() + PERIODIC_INTERVAL_NANOS;
+        NEXT_PERIODIC_DEADLINE.store(deadline, Ordering::Release);
+        update_deadline_if_earlier(TimeValue::from_nanos(deadline));
+    }
+}
+
+/// Returns the resolution of the system clocks, i.e. the duration of a tick
+/// of the hardware counter, rounded up to nanoseconds.
+pub fn clock_resolution() -> TimeValue {
+    let freq = nanos_to_ticks(NANOS_PER_SEC).max(1);
+    TimeValue::from_nanos(((NANOS_PER_SEC + freq - 1) / freq).max(1))
+}
+
+/// Adds `offset` to the time in nanoseconds, a result before zero (the Epoch
+/// or boot) becomes zero.
+fn add_offset(time_ns: u64, offset: i64) -> TimeValue {
+    let time_ns = (time_ns as i64).saturating_add(offset);
+    TimeValue::from_nanos(time_ns.max(0) as u64)
+}
+
+/// Returns the real (wall-clock) time since the Epoch.
+pub fn current_realtime() -> TimeValue {
+    add_offset(
+        current_time_nanos(),
+        REALTIME_OFFSET_NANOS.load(Ordering::Acquire),
+    )
+}
+
+/// Sets the real time, returns `false` if `now` is too large to be
+/// represented in nanoseconds.
+pub fn set_realtime(now: TimeValue) -> bool {
+    match i64::try_from(now.as_nanos()) {
+        Ok(now_ns) => {
+            let offset = now_ns - current_time_nanos() as i64;
+            REALTIME_OFFSET_NANOS.store(offset, Ordering::Release);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Clocks based on the system timer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Clock {
+    /// The settable wall-clock time.
+    Realtime,
+    /// The time since boot.
+    Monotonic,
+    /// Like [`Clock::Monotonic`], the system can not be suspended.
+    Boottime,
+}
+
+impl Clock {
+    pub fn now(self) -> TimeValue {
+        match self {
+            Self::Realtime => current_realtime(),
+            Self::Monotonic | Self::Boottime => current_time(),
+        }
+    }
+
+    /// Converts a time of the clock to the monotonic time, which is used by
+    /// timer deadlines. A real time is converted with the current offset.
+    ///
+    /// Timers and sleeps are not re-armed when the real time is set: unlike
+    /// Linux, a converted deadline of [`Clock::Realtime`] keeps its monotonic
+    /// time, so it expires earlier or later than the requested real time if
+    /// the real time is changed before it.
+    pub fn to_monotonic(self, time: TimeValue) -> TimeValue {
+        match self {
+            Self::Realtime => {
+                let time_ns = time.as_nanos().min(i64::MAX as u128) as u64;
+                add_offset(time_ns, -REALTIME_OFFSET_NANOS.load(Ordering::Acquire))
+            }
+            Self::Monotonic | Self::Boottime => time,
+        }
+    }
+}
+
 pub fn init() {
+    timer_list::init_cache();
     TIMER_LIST.init_by(SpinNoIrqLock::new(TimerList::new()));
     let deadline = current_time_nanos() + PERIODIC_INTERVAL_NANOS;
     NEXT_PERIODIC_DEADLINE.store(deadline, Ordering::Release);
     update_deadline(deadline);
 }
 
-pub fn set_timer(deadline: TimeValue, callback: impl FnOnce(TimeValue) + Send + Sync + 'static) {
-    TIMER_LIST.lock().set(deadline, callback);
-    let deadline_ns = deadline.as_nanos() as u64;
-    if deadline_ns < NEXT_DEADLINE.load(Ordering::Acquire) {
-        update_deadline(deadline_ns);
+/// A handle to a timer set by [`set_timer`] or [`set_periodic_timer`].
+///
+/// Dropping the handle does not cancel the timer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TimerHandle(TimerId);
+
+impl TimerHandle {
+    /// Cancels the timer, returns `false` if it has expired (for one-shot
+    /// timers) or has been cancelled.
+    pub fn cancel(&self) -> bool {
+        TIMER_LIST.lock().cancel(self.0)
     }
+
+    /// Changes the (next) deadline of the timer, returns `false` if it has
+    /// expired (for one-shot timers) or has been cancelled.
+    #[allow(dead_code)]
+    pub fn reset(&self, deadline: TimeValue) -> bool {
+        let ok = TIMER_LIST.lock().reset(self.0, deadline);
+        if ok {
+            update_deadline_if_earlier(deadline);
+        }
+        ok
+    }
+
+    /// Returns the (next) deadline of the timer, or `None` if it is not
+    /// pending.
+    pub fn deadline(&self) -> Option<TimeValue> {
+        TIMER_LIST.lock().deadline(self.0)
+    }
+}
+
+/// Sets a one-shot timer, `callback` is called in the timer interrupt handler
+/// at `deadline`.
+pub fn set_timer(
+    deadline: TimeValue,
+    callback: impl FnOnce(TimeValue) + Send + Sync + 'static,
+) -> TimerHandle {
+    let id = TIMER_LIST.lock().set(deadline, callback);
+    update_deadline_if_earlier(deadline);
+    TimerHandle(id)
+}
+
+/// Sets a periodic timer, `callback` is called in the timer interrupt handler
+/// at `deadline`, and then every `interval`, with the number of expirations
+/// since the last call.
+pub fn set_periodic_timer(
+    deadline: TimeValue,
+    interval: TimeValue,
+    callback: impl Fn(TimeValue, u64) + Send + Sync + 'static,
+) -> TimerHandle {
+    let id = TIMER_LIST.lock().set_periodic(deadline, interval, callback);
+    update_deadline_if_earlier(deadline);
+    TimerHandle(id)
 }
 
 pub fn handle_timer_irq() {
     assert!(crate::arch::instructions::irqs_disabled());
 
     let now_ns = current_time_nanos();
-    let mut next_deadline = NEXT_PERIODIC_DEADLINE.load(Ordering::Acquire);
-
-    if now_ns >= next_deadline {
-        crate::task::timer_tick_periodic();
-        NEXT_PERIODIC_DEADLINE.fetch_add(PERIODIC_INTERVAL_NANOS, Ordering::Release);
-        next_deadline = NEXT_PERIODIC_DEADLINE.load(Ordering::Acquire);
+    let tick_deadline = NEXT_PERIODIC_DEADLINE.load(Ordering::Acquire);
+    if !TICK_STOPPED.load(Ordering::Acquire) && now_ns >= tick_deadline {
+        // Ticks missed by a late interrupt are accounted at once, and the next
+        // tick is the first one after `now`, so that they do not fire in a
+        // burst.
+        let ticks = (now_ns - tick_deadline) / PERIODIC_INTERVAL_NANOS + 1;
+        crate::task::timer_tick_periodic(ticks as usize);
+        NEXT_PERIODIC_DEADLINE.store(
+            tick_deadline + ticks * PERIODIC_INTERVAL_NANOS,
+            Ordering::Release,
+        );
     }
 
-    let mut timers = TIMER_LIST.lock();
-    while timers.expire_one(current_time()).is_some() {}
-
-    if let Some(d) = timers.next_deadline() {
-        next_deadline = next_deadline.min(d.as_nanos() as u64);
+    // Callbacks are called without holding the list, so that they can set or
+    // cancel timers.
+    loop {
+        let now = current_time();
+        let expired = TIMER_LIST.lock().expire_one(now);
+        match expired {
+            Some(timer) => timer.fire(now),
+            None => break,
+        }
     }
-    update_deadline(next_deadline);
+
+    // The tick may be stopped or restarted by the scheduler meanwhile.
+    update_deadline(next_deadline_nanos());
 }
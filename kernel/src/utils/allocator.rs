@@ -1,47 +1,308 @@
-use alloc::vec::Vec;
 use core::ops::Range;
 
-pub struct FreeListAllocator {
-    range: Range<usize>,
-    next_available: usize,
-    free_list: Vec<usize>,
+/// The maximum order of blocks is `MAX_ORDER - 1`, i.e. 2^19 frames (2G).
+pub const MAX_ORDER: usize = 20;
+
+const NIL: u32 = u32::MAX;
+
+/// Per-frame metadata of [`BuddyAllocator`]. Only the first frame (head) of a
+/// free block is meaningful, it links the block into the free list of its
+/// order.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameNode {
+    prev: u32,
+    next: u32,
+    order: u8,
+    free: bool,
+}
+
+impl FrameNode {
+    const EMPTY: Self = Self {
+        prev: NIL,
+        next: NIL,
+        order: 0,
+        free: false,
+    };
 }
 
-impl FreeListAllocator {
+/// A buddy allocator of frame numbers.
+///
+/// Blocks of order `k` contain 2^k frames, and are aligned to 2^k by the frame
+/// number, so that an aligned block is also physically aligned. Free blocks
+/// are kept in intrusive doubly linked lists through the `nodes` array, both
+/// allocation and deallocation are O(`MAX_ORDER`), without using the heap.
+pub struct BuddyAllocator {
+    base: usize,
+    nodes: &'static mut [FrameNode],
+    free_lists: [u32; MAX_ORDER],
+    total: usize,
+    free: usize,
+}
+
+impl BuddyAllocator {
     pub const fn empty() -> Self {
         Self {
-            range: 0..0,
-            next_available: 0,
-            free_list: Vec::new(),
+            base: 0,
+            nodes: &mut [],
+            free_lists: [NIL; MAX_ORDER],
+            total: 0,
+            free: 0,
         }
     }
 
-    pub fn init(&mut self, range: Range<usize>) {
-        self.next_available = range.start;
-        self.range = range;
+    /// Initializes the allocator to manage frames in `range`, all of them are
+    /// free. `nodes` is the metadata of frames, one for each frame in `range`.
+    pub fn init(&mut self, range: Range<usize>, nodes: &'static mut [FrameNode]) {
+        assert!(nodes.len() >= range.len() && range.len() < NIL as usize);
+        nodes.fill(FrameNode::EMPTY);
+        self.base = range.start;
+        self.nodes = nodes;
+        self.free_lists = [NIL; MAX_ORDER];
+        self.total = range.len();
+        self.free = 0;
+        self.free_range(range.start, range.end);
+    }
+
+    /// The number of frames managed by the allocator.
+    pub fn total_frames(&self) -> usize {
+        self.total
     }
 
-    pub fn available_space(&self) -> usize {
-        self.free_list.len() + self.range.end - self.next_available
+    /// The number of free frames.
+    pub fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    /// The number of free blocks of each order.
+    pub fn free_blocks(&self) -> [usize; MAX_ORDER] {
+        let mut counts = [0; MAX_ORDER];
+        for (order, count) in counts.iter_mut().enumerate() {
+            let mut idx = self.free_lists[order];
+            while idx != NIL {
+                *count += 1;
+                idx = self.nodes[idx as usize].next;
+            }
+        }
+        counts
     }
 
     pub fn alloc(&mut self) -> Option<usize> {
-        if let Some(value) = self.free_list.pop() {
-            Some(value)
-        } else if self.next_available >= self.range.end {
-            None
-        } else {
-            self.next_available += 1;
-            Some(self.next_available - 1)
+        self.alloc_block(0)
+    }
+
+    /// Allocates `count` contiguous frames, the first frame number is a
+    /// multiple of `align`, which must be a power of two.
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<usize> {
+        if count == 0 || !align.is_power_of_two() {
+            return None;
         }
+        let block_size = count.checked_next_power_of_two()?.max(align);
+        let order = block_size.trailing_zeros() as usize;
+        if order >= MAX_ORDER {
+            return None;
+        }
+        let start = self.alloc_block(order)?;
+        // return the unused tail of the block
+        self.free_range(start + count, start + block_size);
+        Some(start)
     }
 
     pub fn dealloc(&mut self, value: usize) {
+        self.dealloc_contiguous(value, 1)
+    }
+
+    /// Deallocates `count` contiguous frames starting at `start`, they need
+    /// not to be allocated by a single `alloc_contiguous()`.
+    pub fn dealloc_contiguous(&mut self, start: usize, count: usize) {
         // validity check
-        assert!(value >= self.range.start);
-        assert!(value < self.next_available);
-        assert!(!self.free_list.contains(&value));
-        // recycle
-        self.free_list.push(value);
+        assert!(start >= self.base && start + count <= self.base + self.total);
+        // a free block overlapping the range either contains the first frame,
+        // or starts in the range
+        assert!(
+            !self.is_free_frame(start) && !(start..start + count).any(|v| self.node(v).free),
+            "double free of frames [{:#x}, {:#x})",
+            start,
+            start + count
+        );
+        self.free_range(start, start + count);
+    }
+
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let mut cur_order = (order..MAX_ORDER).find(|&o| self.free_lists[o] != NIL)?;
+        let start = self.base + self.free_lists[cur_order] as usize;
+        self.list_remove(start, cur_order);
+        // split the block, and put the upper halves back
+        while cur_order > order {
+            cur_order -= 1;
+            self.list_push(start + (1 << cur_order), cur_order);
+        }
+        Some(start)
+    }
+
+    /// Splits `[start, end)` into maximal aligned blocks and frees them.
+    fn free_range(&mut self, start: usize, end: usize) {
+        let mut value = start;
+        while value < end {
+            let mut order = (value.trailing_zeros() as usize).min(MAX_ORDER - 1);
+            while value + (1 << order) > end {
+                order -= 1;
+            }
+            self.free_block(value, order);
+            value += 1 << order;
+        }
+    }
+
+    /// Frees a block and merges it with its buddies as far as possible.
+    fn free_block(&mut self, mut start: usize, mut order: usize) {
+        assert!(!self.node(start).free, "double free of frame {:#x}", start);
+        while order < MAX_ORDER - 1 {
+            let buddy = start ^ (1 << order);
+            if !self.is_free_block(buddy, order) {
+                break;
+            }
+            self.list_remove(buddy, order);
+            start = start.min(buddy);
+            order += 1;
+        }
+        self.list_push(start, order);
+    }
+
+    /// Whether the frame is in a free block. The block may have been merged
+    /// with its buddies, so each aligned block that contains it is checked.
+    fn is_free_frame(&self, value: usize) -> bool {
+        (0..MAX_ORDER).any(|order| self.is_free_block(value & !((1 << order) - 1), order))
+    }
+
+    fn is_free_block(&self, start: usize, order: usize) -> bool {
+        if start < self.base || start >= self.base + self.total {
+            return false;
+        }
+        let node = self.node(start);
+        node.free && node.order as usize == order
+    }
+
+    fn node(&self, value: usize) -> &FrameNode {
+        &self.nodes[value - self.base]
+    }
+
+    fn list_push(&mut self, start: usize, order: usize) {
+        let idx = (start - self.base) as u32;
+        let head = self.free_lists[order];
+        if head != NIL {
+            self.nodes[head as usize].prev = idx;
+        }
+        self.nodes[idx as usize] = FrameNode {
+            prev: NIL,
+            next: head,
+            order: order as u8,
+            free: true,
+        };
+        self.free_lists[order] = idx;
+        self.free += 1 << order;
+    }
+
+    fn list_remove(&mut self, start: usize, order: usize) {
+        let idx = start - self.base;
+        let FrameNode { prev, next, .. } = self.nodes[idx];
+        if prev != NIL {
+            self.nodes[prev as usize].next = next;
+        } else {
+            self.free_lists[order] = next;
+        }
+        if next != NIL {
+            self.nodes[next as usize].prev = prev;
+        }
+        self.nodes[idx] = FrameNode::EMPTY;
+        self.free -= 1 << order;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_allocator(range: Range<usize>) -> BuddyAllocator {
+        let nodes = Box::leak(vec![FrameNode::EMPTY; range.len()].into_boxed_slice());
+        let mut allocator = BuddyAllocator::empty();
+        allocator.init(range, nodes);
+        allocator
+    }
+
+    #[test]
+    fn test_init() {
+        let allocator = new_allocator(3..64);
+        assert_eq!(allocator.total_frames(), 61);
+        assert_eq!(allocator.free_frames(), 61);
+        // [3, 4), [4, 8), [8, 16), [16, 32), [32, 64)
+        assert_eq!(&allocator.free_blocks()[..6], &[1, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_alloc_dealloc() {
+        let mut allocator = new_allocator(0..16);
+        let frames: Vec<_> = (0..16).map(|_| allocator.alloc().unwrap()).collect();
+        assert_eq!(allocator.free_frames(), 0);
+        assert_eq!(allocator.alloc(), None);
+        let mut sorted = frames.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..16).collect::<Vec<_>>());
+
+        for frame in frames {
+            allocator.dealloc(frame);
+        }
+        // all buddies are merged back
+        assert_eq!(allocator.free_frames(), 16);
+        assert_eq!(&allocator.free_blocks()[..5], &[0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_alloc_contiguous() {
+        let mut allocator = new_allocator(1..1024);
+        let start = allocator.alloc_contiguous(3, 64).unwrap();
+        assert_eq!(start % 64, 0);
+        assert_eq!(allocator.free_frames(), 1023 - 3);
+
+        let start2 = allocator.alloc_contiguous(100, 1).unwrap();
+        assert!(start2 + 100 <= start || start + 3 <= start2);
+        assert_eq!(allocator.free_frames(), 1023 - 103);
+
+        // frames of different allocations can be freed at once
+        allocator.dealloc_contiguous(start, 3);
+        allocator.dealloc_contiguous(start2, 100);
+        assert_eq!(allocator.free_frames(), 1023);
+        assert_eq!(allocator.alloc_contiguous(512, 512), Some(512));
+
+        assert_eq!(allocator.alloc_contiguous(0, 1), None);
+        assert_eq!(allocator.alloc_contiguous(1, 3), None);
+        assert_eq!(allocator.alloc_contiguous(1024, 1), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_double_free() {
+        let mut allocator = new_allocator(0..16);
+        let frame = allocator.alloc().unwrap();
+        allocator.dealloc(frame);
+        allocator.dealloc(frame);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_double_free_in_merged_block() {
+        let mut allocator = new_allocator(0..16);
+        let frames = allocator.alloc_contiguous(4, 4).unwrap();
+        allocator.dealloc_contiguous(frames, 4);
+        // the frames are merged into a free block of all 16 frames
+        assert_eq!(&allocator.free_blocks()[..5], &[0, 0, 0, 0, 1]);
+        allocator.dealloc(frames + 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_double_free_in_range() {
+        let mut allocator = new_allocator(0..16);
+        let frames = allocator.alloc_contiguous(4, 4).unwrap();
+        allocator.dealloc(frames + 2);
+        allocator.dealloc_contiguous(frames, 4);
     }
 }
@@ -0,0 +1,123 @@
+use core::fmt;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A calendar date and time in UTC.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DateTime {
+    pub year: u32,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Converts the seconds since the Epoch (1970-01-01 00:00:00 UTC).
+    pub const fn from_unix_secs(secs: u64) -> Self {
+        // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = secs / SECS_PER_DAY;
+        let secs_of_day = secs % SECS_PER_DAY;
+        // Shift the Epoch to 0000-03-01, so that leap days are at the end of
+        // years, and years are grouped into 400-year eras.
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z % 146_097; // [0, 146096]
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365; // [0, 399]
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // [0, 365]
+        let mp = (5 * doy + 2) / 153; // [0, 11], from March
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as u64;
+        Self {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+        }
+    }
+
+    /// Converts to the seconds since the Epoch, dates earlier than the Epoch
+    /// are converted to 0.
+    pub const fn to_unix_secs(self) -> u64 {
+        let month = self.month as i64;
+        let year = self.year as i64 - (month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        let secs = days * SECS_PER_DAY as i64
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64;
+        if secs < 0 {
+            0
+        } else {
+            secs as u64
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    const fn dt(year: u32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn test_conversion() {
+        let cases = [
+            (0, dt(1970, 1, 1, 0, 0, 0)),
+            (951_782_400, dt(2000, 2, 29, 0, 0, 0)),
+            (951_868_799, dt(2000, 2, 29, 23, 59, 59)),
+            (951_868_800, dt(2000, 3, 1, 0, 0, 0)),
+            (1_234_567_890, dt(2009, 2, 13, 23, 31, 30)),
+            (4_107_542_400, dt(2100, 3, 1, 0, 0, 0)),
+        ];
+        for (secs, datetime) in cases {
+            assert_eq!(DateTime::from_unix_secs(secs), datetime);
+            assert_eq!(datetime.to_unix_secs(), secs);
+        }
+        assert_eq!(dt(1969, 12, 31, 23, 59, 59).to_unix_secs(), 0);
+        // every day of some years, including leap and non-leap centuries
+        for day in 0..(366 * 4 + 365 * 2) {
+            for base in [0, 946_684_800, 4_102_444_800] {
+                let secs = base + day * SECS_PER_DAY + 12345;
+                assert_eq!(DateTime::from_unix_secs(secs).to_unix_secs(), secs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let datetime = DateTime::from_unix_secs(1_234_567_890);
+        assert_eq!(datetime.to_string(), "2009-02-13 23:31:30");
+    }
+}
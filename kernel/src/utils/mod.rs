@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
 pub mod allocator;
-pub mod irq_handler;
+pub mod datetime;
 pub mod ratio;
 pub mod timer_list;
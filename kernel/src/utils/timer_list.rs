@@ -1,70 +1,266 @@
-use alloc::{boxed::Box, collections::BinaryHeap};
-use core::cmp::{Ord, Ordering, PartialOrd};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::{boxed::Box, sync::Arc};
 use core::time::Duration;
 
+use crate::mm::{KBox, KmemCache};
+
 pub type TimeValue = Duration;
 
-type TimerCallback = Box<dyn FnOnce(TimeValue) + Send + Sync + 'static>;
+type OneshotCallback = Box<dyn FnOnce(TimeValue) + Send + Sync + 'static>;
+type PeriodicCallback = Arc<dyn Fn(TimeValue, u64) + Send + Sync + 'static>;
+
+enum TimerCallback {
+    Oneshot(OneshotCallback),
+    Periodic(TimeValue, PeriodicCallback),
+}
 
+/// A timer in a [`TimerList`].
 struct TimerEvent {
+    /// The (next) deadline.
     deadline: TimeValue,
     callback: TimerCallback,
 }
 
-pub struct TimerList {
-    events: BinaryHeap<TimerEvent>,
-}
+static TIMER_EVENT_CACHE: KmemCache<TimerEvent> = KmemCache::new("timer_event");
 
-impl PartialOrd for TimerEvent {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.deadline.partial_cmp(&self.deadline) // reverse ordering for Min-heap
-    }
+/// Identifies a timer in a [`TimerList`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct TimerId(u64);
+
+/// A timer removed from the list by [`TimerList::expire_one`], whose callback
+/// is called by [`ExpiredTimer::fire`].
+pub struct ExpiredTimer {
+    pub id: TimerId,
+    pub deadline: TimeValue,
+    /// The number of periods elapsed since `deadline`, including the expiry
+    /// at `deadline`. Always 1 for one-shot timers.
+    pub expirations: u64,
+    callback: TimerCallback,
 }
 
-impl Ord for TimerEvent {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.deadline.cmp(&self.deadline) // reverse ordering for Min-heap
-    }
+/// A list of timers ordered by their deadlines.
+///
+/// Timers are identified by [`TimerId`]s, so that they can be cancelled or
+/// reset before they expire. Periodic timers are re-armed on expiry.
+pub struct TimerList {
+    /// Timers ordered by the deadline, timers with the same deadline are
+    /// ordered by the ID (i.e. the creation order).
+    queue: BTreeSet<(TimeValue, TimerId)>,
+    /// All timers in `queue`.
+    events: BTreeMap<TimerId, KBox<TimerEvent>>,
+    next_id: u64,
 }
 
-impl PartialEq for TimerEvent {
-    fn eq(&self, other: &Self) -> bool {
-        self.deadline.eq(&other.deadline)
+impl ExpiredTimer {
+    /// Calls the callback of the timer.
+    pub fn fire(self, now: TimeValue) {
+        match self.callback {
+            TimerCallback::Oneshot(callback) => callback(now),
+            TimerCallback::Periodic(_, callback) => callback(now, self.expirations),
+        }
     }
 }
 
-impl Eq for TimerEvent {}
+/// Registers the slab cache of timer events.
+pub fn init_cache() {
+    TIMER_EVENT_CACHE.register();
+}
 
 impl TimerList {
     pub fn new() -> Self {
         Self {
-            events: BinaryHeap::new(),
+            queue: BTreeSet::new(),
+            events: BTreeMap::new(),
+            next_id: 0,
         }
     }
 
+    /// Adds a one-shot timer that expires at `deadline`.
     pub fn set(
         &mut self,
         deadline: TimeValue,
         callback: impl FnOnce(TimeValue) + Send + Sync + 'static,
-    ) {
-        self.events.push(TimerEvent {
+    ) -> TimerId {
+        self.insert(deadline, TimerCallback::Oneshot(Box::new(callback)))
+    }
+
+    /// Adds a periodic timer that first expires at `deadline`, and then every
+    /// `interval`. The callback is also given the number of expirations since
+    /// it was last called, which is more than 1 if some periods were missed.
+    pub fn set_periodic(
+        &mut self,
+        deadline: TimeValue,
+        interval: TimeValue,
+        callback: impl Fn(TimeValue, u64) + Send + Sync + 'static,
+    ) -> TimerId {
+        assert!(!interval.is_zero());
+        self.insert(
             deadline,
-            callback: Box::new(callback),
-        });
+            TimerCallback::Periodic(interval, Arc::new(callback)),
+        )
+    }
+
+    /// Removes the timer, returns `false` if it is not in the list (expired
+    /// or cancelled).
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        if let Some(event) = self.events.remove(&id) {
+            self.queue.remove(&(event.deadline, id));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Changes the (next) deadline of the timer, returns `false` if it is not
+    /// in the list.
+    pub fn reset(&mut self, id: TimerId, deadline: TimeValue) -> bool {
+        if let Some(event) = self.events.get_mut(&id) {
+            self.queue.remove(&(event.deadline, id));
+            event.deadline = deadline;
+            self.queue.insert((deadline, id));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the (next) deadline of the timer, or `None` if it is not in
+    /// the list.
+    pub fn deadline(&self, id: TimerId) -> Option<TimeValue> {
+        self.events.get(&id).map(|event| event.deadline)
     }
 
     pub fn next_deadline(&self) -> Option<TimeValue> {
-        self.events.peek().map(|e| e.deadline)
+        self.queue.iter().next().map(|&(deadline, _)| deadline)
     }
 
-    pub fn expire_one(&mut self, now: TimeValue) -> Option<TimeValue> {
-        if let Some(e) = self.events.peek() {
-            if e.deadline <= now {
-                let e = self.events.pop().unwrap();
-                (e.callback)(now);
-                return Some(e.deadline);
+    /// Removes the earliest timer if it has expired at `now`. A periodic
+    /// timer is re-armed at the first multiple of its interval after `now`,
+    /// counted from its original deadline so that it does not drift.
+    ///
+    /// The callback is not called, so that the caller can call it without
+    /// holding the list.
+    pub fn expire_one(&mut self, now: TimeValue) -> Option<ExpiredTimer> {
+        let &(deadline, id) = self.queue.iter().next()?;
+        if deadline > now {
+            return None;
+        }
+        self.queue.remove(&(deadline, id));
+        let event = self.events.get_mut(&id).unwrap();
+        let (callback, expirations) = match &event.callback {
+            TimerCallback::Periodic(interval, callback) => {
+                let periods = (now - deadline).as_nanos() / interval.as_nanos() + 1;
+                event.deadline =
+                    deadline + Duration::from_nanos((interval.as_nanos() * periods) as u64);
+                self.queue.insert((event.deadline, id));
+                (
+                    TimerCallback::Periodic(*interval, callback.clone()),
+                    periods as u64,
+                )
             }
+            TimerCallback::Oneshot(_) => (self.events.remove(&id).unwrap().callback, 1),
+        };
+        Some(ExpiredTimer {
+            id,
+            deadline,
+            expirations,
+            callback,
+        })
+    }
+
+    fn insert(&mut self, deadline: TimeValue, callback: TimerCallback) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        let event = TIMER_EVENT_CACHE.alloc(TimerEvent { deadline, callback });
+        self.queue.insert((deadline, id));
+        self.events.insert(id, event);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use std::sync::Mutex;
+
+    fn ms(millis: u64) -> TimeValue {
+        TimeValue::from_millis(millis)
+    }
+
+    fn expire_all(timers: &mut TimerList, now: TimeValue) -> Vec<TimeValue> {
+        let mut deadlines = Vec::new();
+        while let Some(timer) = timers.expire_one(now) {
+            deadlines.push(timer.deadline);
+            timer.fire(now);
         }
-        None
+        deadlines
+    }
+
+    #[test]
+    fn test_oneshot() {
+        crate::mm::init_for_test();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut timers = TimerList::new();
+        for (i, deadline) in [30, 10, 20, 10].into_iter().enumerate() {
+            let fired = fired.clone();
+            timers.set(ms(deadline), move |_| fired.lock().unwrap().push(i));
+        }
+        assert_eq!(timers.next_deadline(), Some(ms(10)));
+        assert_eq!(expire_all(&mut timers, ms(5)), []);
+        assert_eq!(expire_all(&mut timers, ms(20)), [ms(10), ms(10), ms(20)]);
+        assert_eq!(*fired.lock().unwrap(), [1, 3, 2]);
+        assert_eq!(expire_all(&mut timers, ms(100)), [ms(30)]);
+        assert_eq!(timers.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_cancel_reset() {
+        crate::mm::init_for_test();
+        let mut timers = TimerList::new();
+        let t1 = timers.set(ms(10), |_| {});
+        let t2 = timers.set(ms(20), |_| {});
+        let t3 = timers.set(ms(30), |_| {});
+
+        assert!(timers.cancel(t1));
+        assert!(!timers.cancel(t1));
+        assert_eq!(timers.deadline(t1), None);
+        assert_eq!(timers.next_deadline(), Some(ms(20)));
+
+        assert!(timers.reset(t3, ms(5)));
+        assert_eq!(timers.deadline(t3), Some(ms(5)));
+        assert_eq!(expire_all(&mut timers, ms(10)), [ms(5)]);
+        assert!(!timers.reset(t3, ms(50)));
+        assert!(!timers.cancel(t3));
+
+        assert!(timers.reset(t2, ms(40)));
+        assert_eq!(expire_all(&mut timers, ms(30)), []);
+        assert_eq!(expire_all(&mut timers, ms(40)), [ms(40)]);
+    }
+
+    #[test]
+    fn test_periodic() {
+        crate::mm::init_for_test();
+        let count = Arc::new(Mutex::new(0));
+        let mut timers = TimerList::new();
+        let c = count.clone();
+        let id = timers.set_periodic(ms(10), ms(10), move |_, n| *c.lock().unwrap() += n);
+
+        // late expiries do not delay the following deadlines
+        assert_eq!(expire_all(&mut timers, ms(13)), [ms(10)]);
+        assert_eq!(timers.deadline(id), Some(ms(20)));
+        assert_eq!(expire_all(&mut timers, ms(21)), [ms(20)]);
+        assert_eq!(timers.deadline(id), Some(ms(30)));
+
+        // missed periods are skipped, but counted
+        assert_eq!(expire_all(&mut timers, ms(55)), [ms(30)]);
+        assert_eq!(timers.deadline(id), Some(ms(60)));
+        assert_eq!(*count.lock().unwrap(), 5);
+
+        assert!(timers.reset(id, ms(100)));
+        assert_eq!(expire_all(&mut timers, ms(100)), [ms(100)]);
+        assert_eq!(timers.deadline(id), Some(ms(110)));
+        assert!(timers.cancel(id));
+        assert_eq!(timers.next_deadline(), None);
     }
 }
@@ -0,0 +1,19 @@
+#ifndef __DIRENT_H__
+#define __DIRENT_H__
+
+#include <stdint.h>
+
+#define DT_DIR 4
+#define DT_REG 8
+
+struct dirent64 {
+    ino_t d_ino;
+    off_t d_off;
+    unsigned short d_reclen;
+    unsigned char d_type;
+    char d_name[];
+};
+
+ssize_t getdents64(int fd, void *buf, size_t count);
+
+#endif // __DIRENT_H__
@@ -0,0 +1,40 @@
+#ifndef __NETINET_IN_H__
+#define __NETINET_IN_H__
+
+#include <stdint.h>
+#include <sys/socket.h>
+
+#define IPPROTO_TCP 6
+
+#define INADDR_ANY       ((in_addr_t)0x00000000)
+#define INADDR_LOOPBACK  ((in_addr_t)0x7f000001)
+
+typedef uint16_t in_port_t;
+typedef uint32_t in_addr_t;
+
+struct in_addr {
+    in_addr_t s_addr;
+};
+
+struct sockaddr_in {
+    sa_family_t sin_family;
+    in_port_t sin_port;
+    struct in_addr sin_addr;
+    uint8_t sin_zero[8];
+};
+
+/* All supported architectures are little-endian */
+static inline uint16_t htons(uint16_t x)
+{
+    return (x << 8) | (x >> 8);
+}
+
+static inline uint32_t htonl(uint32_t x)
+{
+    return (x << 24) | ((x << 8) & 0xff0000) | ((x >> 8) & 0xff00) | (x >> 24);
+}
+
+#define ntohs(x) htons(x)
+#define ntohl(x) htonl(x)
+
+#endif // __NETINET_IN_H__
@@ -4,5 +4,6 @@
 typedef unsigned long pthread_t;
 
 int pthread_create(pthread_t *res, const void *attrp, void *(*entry)(void *), void *arg);
+int pthread_join(pthread_t t, void **res);
 
 #endif // __PTHREAD_H__
@@ -0,0 +1,65 @@
+#ifndef __SIGNAL_H__
+#define __SIGNAL_H__
+
+#include <stdint.h>
+#include <time.h>
+
+#define SIGKILL  9
+#define SIGUSR1  10
+#define SIGUSR2  12
+#define SIGALRM  14
+#define SIGTERM  15
+#define SIGCHLD  17
+#define SIGRTMIN 34
+#define SIGRTMAX 64
+
+#define SIG_BLOCK   0
+#define SIG_UNBLOCK 1
+#define SIG_SETMASK 2
+
+#define SI_KERNEL 0x80
+#define SI_TIMER  (-2)
+
+#define SIGEV_SIGNAL    0
+#define SIGEV_NONE      1
+#define SIGEV_THREAD_ID 4
+
+typedef struct {
+    unsigned long long sig;
+} sigset_t;
+
+union sigval {
+    int sival_int;
+    void *sival_ptr;
+};
+
+typedef struct {
+    int si_signo;
+    int si_errno;
+    int si_code;
+    int __pad;
+    int si_timerid;
+    int si_overrun;
+    union sigval si_value;
+    char __rest[128 - 6 * sizeof(int) - sizeof(union sigval)];
+} siginfo_t;
+
+struct sigevent {
+    union sigval sigev_value;
+    int sigev_signo;
+    int sigev_notify;
+    int sigev_notify_thread_id;
+    int __pad[11];
+};
+
+int sigemptyset(sigset_t *set);
+int sigfillset(sigset_t *set);
+int sigaddset(sigset_t *set, int signo);
+int sigdelset(sigset_t *set, int signo);
+int sigismember(const sigset_t *set, int signo);
+
+int sigprocmask(int how, const sigset_t *set, sigset_t *oldset);
+int sigtimedwait(const sigset_t *set, siginfo_t *info, const struct timespec *timeout);
+int sigwaitinfo(const sigset_t *set, siginfo_t *info);
+
+#endif // __SIGNAL_H__
@@ -36,6 +36,9 @@ typedef uintptr_t size_t;
 typedef intptr_t ssize_t;
 
 typedef int pid_t;
+typedef long off_t;
+typedef unsigned int mode_t;
+typedef uint64_t ino_t;
 
 #define NULL ((void *)0)
 
@@ -0,0 +1,32 @@
+#ifndef __SYS_SOCKET_H__
+#define __SYS_SOCKET_H__
+
+#include <stdint.h>
+
+#define AF_INET 2
+
+#define SOCK_STREAM   1
+#define SOCK_NONBLOCK 04000
+#define SOCK_CLOEXEC  02000000
+
+#define MSG_DONTWAIT 0x40
+
+typedef uint32_t socklen_t;
+typedef uint16_t sa_family_t;
+
+struct sockaddr {
+    sa_family_t sa_family;
+    char sa_data[14];
+};
+
+int socket(int domain, int type, int protocol);
+int bind(int fd, const struct sockaddr *addr, socklen_t addrlen);
+int listen(int fd, int backlog);
+int accept(int fd, struct sockaddr *addr, socklen_t *addrlen);
+int connect(int fd, const struct sockaddr *addr, socklen_t addrlen);
+ssize_t send(int fd, const void *buf, size_t len, int flags);
+ssize_t recv(int fd, void *buf, size_t len, int flags);
+int getsockname(int fd, struct sockaddr *addr, socklen_t *addrlen);
+int getpeername(int fd, struct sockaddr *addr, socklen_t *addrlen);
+
+#endif // __SYS_SOCKET_H__
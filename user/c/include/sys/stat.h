@@ -0,0 +1,8 @@
+#ifndef __SYS_STAT_H__
+#define __SYS_STAT_H__
+
+#include <stdint.h>
+
+int mkdir(const char *path, mode_t mode);
+
+#endif // __SYS_STAT_H__
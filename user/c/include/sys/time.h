@@ -0,0 +1,16 @@
+#ifndef __SYS_TIME_H__
+#define __SYS_TIME_H__
+
+#include <time.h>
+
+#define ITIMER_REAL 0
+
+struct itimerval {
+    struct timeval it_interval;
+    struct timeval it_value;
+};
+
+int setitimer(int which, const struct itimerval *new_value, struct itimerval *old_value);
+int getitimer(int which, struct itimerval *curr_value);
+
+#endif // __SYS_TIME_H__
@@ -0,0 +1,14 @@
+#ifndef __SYS_WAIT_H__
+#define __SYS_WAIT_H__
+
+#include <unistd.h>
+
+#define WNOHANG   1
+#define WUNTRACED 2
+
+#define WEXITSTATUS(s) (((s) & 0xff00) >> 8)
+#define WTERMSIG(s)    ((s) & 0x7f)
+#define WIFEXITED(s)   (!WTERMSIG(s))
+#define WIFSIGNALED(s) (((s) & 0xffff) - 1U < 0xffu)
+
+#endif // __SYS_WAIT_H__
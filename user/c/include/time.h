@@ -3,6 +3,7 @@
 
 typedef int clockid_t;
 typedef long time_t;
+typedef int timer_t;
 
 struct timeval {
     time_t tv_sec;
@@ -14,8 +15,18 @@ struct timespec {
     long tv_nsec;
 };
 
-#define CLOCK_REALTIME  0
-#define CLOCK_MONOTONIC 1
+struct itimerspec {
+    struct timespec it_interval;
+    struct timespec it_value;
+};
+
+struct sigevent;
+
+#define CLOCK_REALTIME           0
+#define CLOCK_MONOTONIC          1
+#define CLOCK_PROCESS_CPUTIME_ID 2
+#define CLOCK_THREAD_CPUTIME_ID  3
+#define CLOCK_BOOTTIME           7
 
 #define TIMER_ABSTIME 1
 
@@ -23,6 +34,16 @@ int clock_nanosleep(clockid_t clk, int flags, const struct timespec *req,
                     struct timespec *rem);
 int nanosleep(const struct timespec *req, struct timespec *rem);
 int clock_gettime(clockid_t clk, struct timespec *ts);
+int clock_settime(clockid_t clk, const struct timespec *ts);
+int clock_getres(clockid_t clk, struct timespec *res);
 int gettimeofday(struct timeval *tv, void *tz);
+time_t time(time_t *tloc);
+
+int timer_create(clockid_t clk, struct sigevent *sevp, timer_t *timerid);
+int timer_settime(timer_t timerid, int flags, const struct itimerspec *new_value,
+                  struct itimerspec *old_value);
+int timer_gettime(timer_t timerid, struct itimerspec *curr_value);
+int timer_getoverrun(timer_t timerid);
+int timer_delete(timer_t timerid);
 
 #endif // __TIME_H__
@@ -3,10 +3,22 @@
 
 #include <stdint.h>
 
+#define SEEK_SET 0
+#define SEEK_CUR 1
+#define SEEK_END 2
+
 ssize_t read(int, void *, size_t);
 ssize_t write(int, const void *, size_t);
+int close(int fd);
+off_t lseek(int fd, off_t offset, int whence);
+int fsync(int fd);
+void sync(void);
+int unlink(const char *path);
+int rmdir(const char *path);
 
 pid_t getpid(void);
+pid_t getppid(void);
+pid_t gettid(void);
 int sched_yield(void);
 
 pid_t fork(void);
@@ -16,5 +28,6 @@ pid_t waitpid(pid_t pid, int *exitcode, int options);
 
 int usleep(unsigned useconds);
 unsigned sleep(unsigned seconds);
+unsigned alarm(unsigned seconds);
 
 #endif // __UNISTD_H__
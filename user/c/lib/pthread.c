@@ -1,24 +1,79 @@
 #include <pthread.h>
 #include <stdint.h>
 #include <stdio.h>
+#include <sys/mman.h>
 
-#define __MAX_THREADS       16
-#define __THREAD_STACK_SIZE (4096 * 4)
+#include "syscall.h"
 
-static char THREAD_STACKS[__MAX_THREADS][__THREAD_STACK_SIZE] __attribute__((__aligned__(16)));
-static int THREAD_COUNT = 0;
+#define __THREAD_STACK_SIZE (4096 * 16)
 
-extern int __clone(void *(*entry)(void *), void *stack, void *arg);
+#define CLONE_VM             0x00000100
+#define CLONE_FS             0x00000200
+#define CLONE_FILES          0x00000400
+#define CLONE_SIGHAND        0x00000800
+#define CLONE_THREAD         0x00010000
+#define CLONE_SYSVSEM        0x00040000
+#define CLONE_CHILD_CLEARTID 0x00200000
+#define CLONE_CHILD_SETTID   0x01000000
+
+#define FUTEX_WAIT 0
+
+// Placed at the top of the thread stack.
+struct pthread {
+    // Set by the kernel on creation, and cleared on exit (CLONE_CHILD_CLEARTID).
+    volatile int tid;
+    void *(*entry)(void *);
+    void *arg;
+    void *result;
+    void *stack_base;
+};
+
+extern int __clone(int (*func)(void *), void *arg, void *stack, int flags, volatile int *ctid);
+
+static int start(void *p)
+{
+    struct pthread *self = p;
+    self->result = self->entry(self->arg);
+    return 0;
+}
 
 int pthread_create(pthread_t *restrict res, const void *restrict attrp, void *(*entry)(void *),
                    void *restrict arg)
 {
-    int thread_id = THREAD_COUNT++;
-    void *newsp = THREAD_STACKS[thread_id] + __THREAD_STACK_SIZE;
-    int tid = __clone(entry, arg, newsp);
+    char *stack = mmap(NULL, __THREAD_STACK_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS,
+                       -1, 0);
+    if (stack == MAP_FAILED) {
+        return -1;
+    }
+    uintptr_t top = (uintptr_t)stack + __THREAD_STACK_SIZE;
+    struct pthread *t = (struct pthread *)((top - sizeof(struct pthread)) & -16);
+    t->tid = 0;
+    t->entry = entry;
+    t->arg = arg;
+    t->result = NULL;
+    t->stack_base = stack;
+
+    int flags = CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD | CLONE_SYSVSEM |
+                CLONE_CHILD_SETTID | CLONE_CHILD_CLEARTID;
+    int tid = __clone(start, t, t, flags, &t->tid);
     if (tid < 0) {
+        munmap(stack, __THREAD_STACK_SIZE);
         return tid;
     }
-    *res = tid;
+    *res = (pthread_t)t;
+    return 0;
+}
+
+int pthread_join(pthread_t thread, void **res)
+{
+    struct pthread *t = (struct pthread *)thread;
+    int tid;
+    while ((tid = t->tid) != 0) {
+        syscall(SYS_futex, &t->tid, FUTEX_WAIT, tid);
+    }
+    if (res) {
+        *res = t->result;
+    }
+    munmap(t->stack_base, __THREAD_STACK_SIZE);
     return 0;
 }
@@ -0,0 +1,53 @@
+#include <signal.h>
+
+#include "syscall.h"
+
+int sigemptyset(sigset_t *set)
+{
+    set->sig = 0;
+    return 0;
+}
+
+int sigfillset(sigset_t *set)
+{
+    set->sig = ~0ULL;
+    return 0;
+}
+
+int sigaddset(sigset_t *set, int signo)
+{
+    if (signo < 1 || signo > SIGRTMAX)
+        return -22;
+    set->sig |= 1ULL << (signo - 1);
+    return 0;
+}
+
+int sigdelset(sigset_t *set, int signo)
+{
+    if (signo < 1 || signo > SIGRTMAX)
+        return -22;
+    set->sig &= ~(1ULL << (signo - 1));
+    return 0;
+}
+
+int sigismember(const sigset_t *set, int signo)
+{
+    if (signo < 1 || signo > SIGRTMAX)
+        return 0;
+    return (set->sig >> (signo - 1)) & 1;
+}
+
+int sigprocmask(int how, const sigset_t *set, sigset_t *oldset)
+{
+    return syscall(SYS_rt_sigprocmask, how, set, oldset, sizeof(sigset_t));
+}
+
+int sigtimedwait(const sigset_t *set, siginfo_t *info, const struct timespec *timeout)
+{
+    return syscall(SYS_rt_sigtimedwait, set, info, timeout, sizeof(sigset_t));
+}
+
+int sigwaitinfo(const sigset_t *set, siginfo_t *info)
+{
+    return sigtimedwait(set, info, 0);
+}
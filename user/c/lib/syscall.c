@@ -1,4 +1,11 @@
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdarg.h>
 #include <stdint.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
 #include <unistd.h>
 
 #include "syscall.h"
@@ -13,11 +20,118 @@ ssize_t write(int fd, const void *buf, size_t count)
     return syscall(SYS_write, fd, buf, count);
 }
 
+int open(const char *path, int flags, ...)
+{
+    mode_t mode = 0;
+    if (flags & O_CREAT) {
+        va_list ap;
+        va_start(ap, flags);
+        mode = va_arg(ap, mode_t);
+        va_end(ap);
+    }
+    return syscall(SYS_open, path, flags, mode);
+}
+
+int close(int fd)
+{
+    return syscall(SYS_close, fd);
+}
+
+off_t lseek(int fd, off_t offset, int whence)
+{
+    return syscall(SYS_lseek, fd, offset, whence);
+}
+
+int fsync(int fd)
+{
+    return syscall(SYS_fsync, fd);
+}
+
+void sync(void)
+{
+    syscall(SYS_sync);
+}
+
+int mkdir(const char *path, mode_t mode)
+{
+    return syscall(SYS_mkdir, path, mode);
+}
+
+int rmdir(const char *path)
+{
+    return syscall(SYS_rmdir, path);
+}
+
+int unlink(const char *path)
+{
+    return syscall(SYS_unlink, path);
+}
+
+ssize_t getdents64(int fd, void *buf, size_t count)
+{
+    return syscall(SYS_getdents64, fd, buf, count);
+}
+
+int socket(int domain, int type, int protocol)
+{
+    return syscall(SYS_socket, domain, type, protocol);
+}
+
+int bind(int fd, const struct sockaddr *addr, socklen_t addrlen)
+{
+    return syscall(SYS_bind, fd, addr, addrlen);
+}
+
+int listen(int fd, int backlog)
+{
+    return syscall(SYS_listen, fd, backlog);
+}
+
+int accept(int fd, struct sockaddr *addr, socklen_t *addrlen)
+{
+    return syscall(SYS_accept, fd, addr, addrlen);
+}
+
+int connect(int fd, const struct sockaddr *addr, socklen_t addrlen)
+{
+    return syscall(SYS_connect, fd, addr, addrlen);
+}
+
+ssize_t send(int fd, const void *buf, size_t len, int flags)
+{
+    return syscall(SYS_sendto, fd, buf, len, flags, NULL, 0);
+}
+
+ssize_t recv(int fd, void *buf, size_t len, int flags)
+{
+    return syscall(SYS_recvfrom, fd, buf, len, flags, NULL, NULL);
+}
+
+int getsockname(int fd, struct sockaddr *addr, socklen_t *addrlen)
+{
+    return syscall(SYS_getsockname, fd, addr, addrlen);
+}
+
+int getpeername(int fd, struct sockaddr *addr, socklen_t *addrlen)
+{
+    return syscall(SYS_getpeername, fd, addr, addrlen);
+}
+
 pid_t getpid(void)
 {
     return syscall(SYS_getpid);
 }
 
+pid_t getppid(void)
+{
+    return syscall(SYS_getppid);
+}
+
+pid_t gettid(void)
+{
+    return syscall(SYS_gettid);
+}
+
 int sched_yield(void)
 {
     return syscall(SYS_yield);
@@ -25,7 +139,27 @@ int sched_yield(void)
 
 _Noreturn void exit(int code)
 {
-    for (;;) syscall(SYS_exit, code);
+    for (;;) syscall(SYS_exit_group, code);
+}
+
+void *mmap(void *addr, size_t len, int prot, int flags, int fd, off_t off)
+{
+    return (void *)syscall(SYS_mmap, addr, len, prot, flags, fd, off);
+}
+
+int munmap(void *addr, size_t len)
+{
+    return syscall(SYS_munmap, addr, len);
+}
+
+int getrlimit(int resource, struct rlimit *rlim)
+{
+    return syscall(SYS_getrlimit, resource, rlim);
+}
+
+int setrlimit(int resource, const struct rlimit *rlim)
+{
+    return syscall(SYS_setrlimit, resource, rlim);
 }
 
 pid_t fork(void)
@@ -1,4 +1,6 @@
+#include <sys/time.h>
 #include <time.h>
+#include <unistd.h>
 
 #include "syscall.h"
 
@@ -10,7 +12,7 @@ int clock_nanosleep(clockid_t clk, int flags, const struct timespec *req,
 
 int nanosleep(const struct timespec *req, struct timespec *rem)
 {
-    return clock_nanosleep(CLOCK_REALTIME, 0, req, rem);
+    return clock_nanosleep(CLOCK_MONOTONIC, 0, req, rem);
 }
 
 int usleep(unsigned useconds)
@@ -33,13 +35,63 @@ int clock_gettime(clockid_t clk, struct timespec *ts)
     return syscall(SYS_clock_gettime, clk, ts);
 }
 
+int clock_settime(clockid_t clk, const struct timespec *ts)
+{
+    return syscall(SYS_clock_settime, clk, ts);
+}
+
+int clock_getres(clockid_t clk, struct timespec *res)
+{
+    return syscall(SYS_clock_getres, clk, res);
+}
+
 int gettimeofday(struct timeval *restrict tv, void *restrict tz)
 {
-    struct timespec ts;
-    if (!tv)
-        return 0;
-    clock_gettime(CLOCK_REALTIME, &ts);
-    tv->tv_sec = ts.tv_sec;
-    tv->tv_usec = (int)ts.tv_nsec / 1000;
-    return 0;
+    return syscall(SYS_gettimeofday, tv, tz);
+}
+
+time_t time(time_t *tloc)
+{
+    return syscall(SYS_time, tloc);
+}
+
+int timer_create(clockid_t clk, struct sigevent *sevp, timer_t *timerid)
+{
+    return syscall(SYS_timer_create, clk, sevp, timerid);
+}
+
+int timer_settime(timer_t timerid, int flags, const struct itimerspec *new_value,
+                  struct itimerspec *old_value)
+{
+    return syscall(SYS_timer_settime, timerid, flags, new_value, old_value);
+}
+
+int timer_gettime(timer_t timerid, struct itimerspec *curr_value)
+{
+    return syscall(SYS_timer_gettime, timerid, curr_value);
+}
+
+int timer_getoverrun(timer_t timerid)
+{
+    return syscall(SYS_timer_getoverrun, timerid);
+}
+
+int timer_delete(timer_t timerid)
+{
+    return syscall(SYS_timer_delete, timerid);
+}
+
+int setitimer(int which, const struct itimerval *new_value, struct itimerval *old_value)
+{
+    return syscall(SYS_setitimer, which, new_value, old_value);
+}
+
+int getitimer(int which, struct itimerval *curr_value)
+{
+    return syscall(SYS_getitimer, which, curr_value);
+}
+
+unsigned alarm(unsigned seconds)
+{
+    return syscall(SYS_alarm, seconds);
 }
@@ -207,7 +207,7 @@ int main()
         print_stat(&thrpar[i], &thrstat[i]);
     }
 
-    // for (int i = 0; i < NUM_THREADS; i++) {
-    //  pthread_join(thrpar[i].thread, NULL);
-    // }
+    for (int i = 0; i < NUM_THREADS; i++) {
+        pthread_join(thrpar[i].thread, NULL);
+    }
 }
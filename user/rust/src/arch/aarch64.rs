@@ -16,21 +16,48 @@ pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        asm!(
+            "svc #0",
+            inlateout("x0") args[0] => ret,
+            in("x1") args[1],
+            in("x2") args[2],
+            in("x3") args[3],
+            in("x4") args[4],
+            in("x5") args[5],
+            in("x8") id,
+        );
+    }
+    ret
+}
+
 #[naked]
 #[allow(improper_ctypes_definitions)]
-pub extern "C" fn sys_clone(_entry: fn(usize) -> i32, _arg: usize, _newsp: usize) -> isize {
-    // sys_clone(entry, arg, newsp)
-    //             x0,   x1,    x2
-    // syscall(SYSCALL_CLONE, newsp)
-    //                   x8,     x0
+pub extern "C" fn sys_clone(
+    _entry: fn(usize) -> i32,
+    _arg: usize,
+    _newsp: usize,
+    _flags: usize,
+    _ctid: *mut i32,
+) -> isize {
+    // sys_clone(entry, arg, newsp, flags, ctid)
+    //             x0,   x1,    x2,    x3,   x4
+    // syscall(SYSCALL_CLONE, flags, newsp, ptid, ctid, tls)
+    //                   x8,     x0,    x1,   x2,   x3,  x4
     unsafe {
         asm!("
             // align stack and save entry,arg to the new stack
             and x2, x2, #-16
             stp x0, x1, [x2, #-16]!
 
-            // syscall(SYSCALL_CLONE, newsp)
-            mov x0, x2
+            // syscall(SYSCALL_CLONE, flags, newsp, 0, ctid, 0)
+            mov x0, x3
+            mov x1, x2
+            mov x2, xzr
+            mov x3, x4
+            mov x4, xzr
             mov x8, {sys_clone}
             svc #0
 
@@ -16,13 +16,36 @@ pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => ret,
+            in("a1") args[1],
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a7") id,
+        );
+    }
+    ret
+}
+
 #[naked]
 #[allow(improper_ctypes_definitions)]
-pub extern "C" fn sys_clone(_entry: fn(usize) -> i32, _arg: usize, _newsp: usize) -> isize {
-    // sys_clone(entry, arg, newsp)
-    //             a0,   a1,    a2
-    // syscall(SYSCALL_CLONE, newsp)
-    //                   a7,     x0
+pub extern "C" fn sys_clone(
+    _entry: fn(usize) -> i32,
+    _arg: usize,
+    _newsp: usize,
+    _flags: usize,
+    _ctid: *mut i32,
+) -> isize {
+    // sys_clone(entry, arg, newsp, flags, ctid)
+    //             a0,   a1,    a2,    a3,   a4
+    // syscall(SYSCALL_CLONE, flags, newsp, ptid, ctid, tls)
+    //                   a7,     a0,    a1,   a2,   a3,  a4
     unsafe {
         asm!("
             // align stack and save entry,arg to the new stack
@@ -31,8 +54,12 @@ pub extern "C" fn sys_clone(_entry: fn(usize) -> i32, _arg: usize, _newsp: usize
             sd      a0, 0(a2)
             sd      a1, 8(a2)
 
-            // syscall(SYSCALL_CLONE, newsp)
-            mv      a0, a2
+            // syscall(SYSCALL_CLONE, flags, newsp, 0, ctid, 0)
+            mv      a0, a3
+            mv      a1, a2
+            li      a2, 0
+            mv      a3, a4
+            li      a4, 0
             li      a7, {sys_clone}
             ecall
 
@@ -18,13 +18,38 @@ pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let ret;
+    unsafe {
+        asm!(
+            "syscall",
+            inlateout("rax") id => ret,
+            in("rdi") args[0],
+            in("rsi") args[1],
+            in("rdx") args[2],
+            in("r10") args[3],
+            in("r8") args[4],
+            in("r9") args[5],
+            out("rcx") _,
+            out("r11") _,
+        );
+    }
+    ret
+}
+
 #[naked]
 #[allow(improper_ctypes_definitions)]
-pub extern "C" fn sys_clone(_entry: fn(usize) -> i32, _arg: usize, _newsp: usize) -> isize {
-    // sys_clone(entry, arg, newsp)
-    //             rdi, rsi,   rdx
-    // syscall(SYSCALL_CLONE, newsp)
-    //                   rax,   rdi
+pub extern "C" fn sys_clone(
+    _entry: fn(usize) -> i32,
+    _arg: usize,
+    _newsp: usize,
+    _flags: usize,
+    _ctid: *mut i32,
+) -> isize {
+    // sys_clone(entry, arg, newsp, flags, ctid)
+    //             rdi, rsi,   rdx,   rcx,   r8
+    // syscall(SYSCALL_CLONE, flags, newsp, ptid, ctid, tls)
+    //                   rax,   rdi,   rsi,  rdx,  r10,  r8
     unsafe {
         asm!("
             // push arg (rsi) to stack, set func (rdi) to r9
@@ -33,8 +58,12 @@ pub extern "C" fn sys_clone(_entry: fn(usize) -> i32, _arg: usize, _newsp: usize
             mov [rdx], rsi
             mov r9, rdi
 
-            // syscall(SYSCALL_CLONE, newsp)
-            mov rdi, rdx
+            // syscall(SYSCALL_CLONE, flags, newsp, 0, ctid, 0)
+            mov rdi, rcx
+            mov rsi, rdx
+            xor edx, edx
+            mov r10, r8
+            xor r8d, r8d
             mov rax, {sys_clone}
             syscall
 
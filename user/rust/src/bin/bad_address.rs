@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+const PAGE_SIZE: usize = 0x1000;
+const EFAULT: isize = 14;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let page = mmap(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(page > 0);
+    let ro_buf = unsafe { core::slice::from_raw_parts_mut(page as *mut u8, PAGE_SIZE) };
+
+    // The kernel can not write to a read-only page on behalf of the user.
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    let status = unsafe { &mut *(page as *mut i32) };
+    assert_eq!(waitpid(pid, Some(status), 0), -EFAULT);
+
+    // Neither can it read from an unmapped page.
+    assert_eq!(munmap(page as usize, PAGE_SIZE), 0);
+    assert_eq!(write(1, ro_buf), -EFAULT);
+
+    println!("bad_address passed!");
+    0
+}
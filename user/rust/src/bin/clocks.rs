@@ -0,0 +1,145 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_getres, clock_gettime, clock_nanosleep, clock_settime, get_time_us, gettimeofday,
+    thread_spawn, time, usleep, TimeSpec, TimeVal, CLOCK_BOOTTIME, CLOCK_MONOTONIC,
+    CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID, TIMER_ABSTIME,
+};
+
+const EINVAL: isize = -22;
+
+fn to_us(ts: &TimeSpec) -> usize {
+    ts.sec * 1_000_000 + ts.nsec / 1000
+}
+
+fn from_us(us: usize) -> TimeSpec {
+    TimeSpec {
+        sec: us / 1_000_000,
+        nsec: us % 1_000_000 * 1000,
+    }
+}
+
+fn now_us(clk: u32) -> usize {
+    let mut ts = TimeSpec::default();
+    assert_eq!(clock_gettime(clk, &mut ts), 0);
+    to_us(&ts)
+}
+
+fn busy_loop(us: usize) {
+    let end = get_time_us() as usize + us;
+    while (get_time_us() as usize) < end {}
+}
+
+fn test_resolution() {
+    for clk in [
+        CLOCK_REALTIME,
+        CLOCK_MONOTONIC,
+        CLOCK_PROCESS_CPUTIME_ID,
+        CLOCK_THREAD_CPUTIME_ID,
+        CLOCK_BOOTTIME,
+    ] {
+        let mut res = TimeSpec::default();
+        assert_eq!(clock_getres(clk, &mut res), 0);
+        println!("resolution of clock {}: {} ns", clk, res.nsec);
+        assert!(res.sec == 0 && res.nsec > 0 && res.nsec <= 1_000_000);
+    }
+    let mut ts = TimeSpec::default();
+    assert_eq!(clock_getres(100, &mut ts), EINVAL);
+    assert_eq!(clock_gettime(100, &mut ts), EINVAL);
+}
+
+fn test_cpu_time() {
+    let thread_start = now_us(CLOCK_THREAD_CPUTIME_ID);
+    let process_start = now_us(CLOCK_PROCESS_CPUTIME_ID);
+    busy_loop(30_000);
+    let busy = now_us(CLOCK_THREAD_CPUTIME_ID) - thread_start;
+    println!("thread CPU time of a 30ms busy loop: {} us", busy);
+    assert!(busy >= 20_000);
+
+    // sleeping does not consume CPU time
+    let thread_start = now_us(CLOCK_THREAD_CPUTIME_ID);
+    usleep(30_000);
+    let idle = now_us(CLOCK_THREAD_CPUTIME_ID) - thread_start;
+    println!("thread CPU time of a 30ms sleep: {} us", idle);
+    assert!(idle < 10_000);
+
+    // the CPU time of exited threads is counted in the process
+    let t = thread_spawn(
+        |_| {
+            busy_loop(30_000);
+            0
+        },
+        0,
+    )
+    .unwrap();
+    assert_eq!(t.join(), 0);
+    let process = now_us(CLOCK_PROCESS_CPUTIME_ID) - process_start;
+    println!("process CPU time: {} us", process);
+    assert!(process >= 40_000);
+    assert!(now_us(CLOCK_PROCESS_CPUTIME_ID) >= now_us(CLOCK_THREAD_CPUTIME_ID));
+}
+
+fn test_wall_time() {
+    // The real time is seeded from the RTC provided by QEMU.
+    let secs = time();
+    println!("seconds since the Epoch: {}", secs);
+    assert!(secs > 1_600_000_000);
+
+    let mut tv = TimeVal::default();
+    assert_eq!(gettimeofday(&mut tv), 0);
+    let real = now_us(CLOCK_REALTIME);
+    let tv_us = tv.sec * 1_000_000 + tv.usec;
+    assert!(tv_us <= real && real - tv_us < 1_000_000);
+    assert!(time() as usize >= tv.sec);
+}
+
+/// Returns the offset of the real time from the monotonic time.
+fn realtime_offset_us() -> usize {
+    // read the monotonic time first to avoid overflow if the offset is zero
+    let mono = now_us(CLOCK_MONOTONIC);
+    now_us(CLOCK_REALTIME) - mono
+}
+
+fn test_realtime() {
+    let offset = realtime_offset_us();
+    let mono = now_us(CLOCK_MONOTONIC);
+    assert!(now_us(CLOCK_BOOTTIME) >= mono);
+
+    // setting the real time does not affect the monotonic time
+    let new_offset = offset + 1000 * 1_000_000;
+    assert_eq!(
+        clock_settime(CLOCK_REALTIME, &from_us(mono + new_offset)),
+        0
+    );
+    let diff = realtime_offset_us();
+    assert!(diff >= new_offset - 1000 && diff <= new_offset + 1000);
+    assert!(now_us(CLOCK_MONOTONIC) - mono < 1_000_000);
+    assert_eq!(clock_settime(CLOCK_MONOTONIC, &from_us(mono)), EINVAL);
+
+    // absolute sleeps follow the real time
+    let deadline = now_us(CLOCK_REALTIME) + 20_000;
+    let start = get_time_us() as usize;
+    assert_eq!(
+        clock_nanosleep(CLOCK_REALTIME, TIMER_ABSTIME, &from_us(deadline)),
+        0
+    );
+    assert!(now_us(CLOCK_REALTIME) >= deadline);
+    assert!(get_time_us() as usize - start < 1_000_000);
+
+    let mono = now_us(CLOCK_MONOTONIC);
+    assert_eq!(clock_settime(CLOCK_REALTIME, &from_us(mono + offset)), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    test_resolution();
+    test_cpu_time();
+    test_wall_time();
+    test_realtime();
+    println!("clocks passed!");
+    0
+}
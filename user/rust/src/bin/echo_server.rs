@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, listen, read, socket, write, SockAddrIn, AF_INET, INADDR_ANY, SOCK_STREAM,
+};
+
+/// Forwarded from the host by QEMU, see the Makefile.
+const PORT: u16 = 5555;
+
+/// Echoes the data of a connection until it is closed by the peer.
+fn echo(conn: usize) {
+    let mut buf = [0; 1024];
+    loop {
+        let n = read(conn, &mut buf);
+        if n <= 0 {
+            break;
+        }
+        if write(conn, &buf[..n as usize]) < 0 {
+            break;
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = socket(AF_INET, SOCK_STREAM, 0);
+    assert!(fd >= 0, "failed to create a socket: {}", fd);
+    let fd = fd as usize;
+    let ret = bind(fd, &SockAddrIn::new(INADDR_ANY, PORT));
+    assert_eq!(ret, 0, "failed to bind port {}", PORT);
+    assert_eq!(listen(fd, 4), 0);
+    println!("Echo server listening on port {}", PORT);
+
+    loop {
+        let mut peer = SockAddrIn::default();
+        let conn = accept(fd, Some(&mut peer));
+        if conn < 0 {
+            println!("failed to accept: {}", conn);
+            break;
+        }
+        let [a, b, c, d] = peer.addr;
+        println!("Connection from {}.{}.{}.{}:{}", a, b, c, d, peer.port());
+        echo(conn as usize);
+        close(conn as usize);
+        println!("Connection closed");
+    }
+    close(fd);
+    1
+}
@@ -3,7 +3,7 @@
 
 #[macro_use]
 extern crate user_lib;
-use user_lib::{exit, fork, sched_yield, wait, waitpid};
+use user_lib::{exit, fork, sched_yield, wait, waitpid, wexitstatus, wifexited};
 
 const MAGIC: i32 = -0x10384;
 
@@ -21,9 +21,12 @@ pub fn main() -> i32 {
         println!("I am the parent, fork a child pid {}", pid);
     }
     println!("I am the parent, waiting now..");
+    // process groups are not supported
+    assert!(waitpid(0, None, 0) < 0);
+    assert!(waitpid(-pid, None, 0) < 0);
     let mut xstate = 0;
     assert!(waitpid(pid, Some(&mut xstate), 0) == pid);
-    assert!(xstate == MAGIC);
+    assert!(wifexited(xstate) && wexitstatus(xstate) == MAGIC & 0xff);
     assert!(waitpid(pid, None, 0) < 0);
     assert!(wait(None) <= 0);
     println!("waitpid {} ok.", pid);
@@ -0,0 +1,138 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fsync, getdents64, lseek, mkdir, open, read, rmdir, sync, unlink, write, DirEntries,
+    DT_DIR, DT_REG, O_APPEND, O_CREAT, O_DIRECTORY, O_EXCL, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY,
+    SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+const ENOENT: isize = -2;
+const EEXIST: isize = -17;
+const ENOTDIR: isize = -20;
+const EISDIR: isize = -21;
+const ENOTEMPTY: isize = -39;
+
+const DIR: &str = "/fs_test\0";
+const FILE: &str = "/fs_test/Long File Name.txt\0";
+
+fn open_ok(path: &str, flags: u32) -> usize {
+    let fd = open(path, flags);
+    assert!(fd >= 0, "failed to open {:?}: {}", path, fd);
+    fd as usize
+}
+
+fn test_read_write() {
+    let fd = open_ok(FILE, O_RDWR | O_CREAT | O_EXCL);
+    assert_eq!(open(FILE, O_RDWR | O_CREAT | O_EXCL), EEXIST);
+    assert_eq!(write(fd, b"hello, "), 7);
+    assert_eq!(write(fd, b"world!"), 6);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut buf = [0; 32];
+    assert_eq!(read(fd, &mut buf), 13);
+    assert_eq!(&buf[..13], b"hello, world!");
+    assert_eq!(read(fd, &mut buf), 0);
+
+    // overwrite in the middle of the file
+    assert_eq!(lseek(fd, -6, SEEK_END), 7);
+    assert_eq!(write(fd, b"nimbos"), 6);
+    assert_eq!(lseek(fd, -13, SEEK_CUR), 0);
+    assert_eq!(read(fd, &mut buf), 13);
+    assert_eq!(&buf[..13], b"hello, nimbos");
+    assert_eq!(fsync(fd), 0);
+    assert_eq!(close(fd), 0);
+
+    // append, then read it from another file descriptor
+    let fd = open_ok(FILE, O_WRONLY | O_APPEND);
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(close(fd), 0);
+    let fd = open_ok(FILE, O_RDONLY);
+    assert_eq!(write(fd, b"x"), -9); // EBADF
+    assert_eq!(read(fd, &mut buf), 14);
+    assert_eq!(&buf[..14], b"hello, nimbos!");
+    assert_eq!(close(fd), 0);
+}
+
+fn test_large_file() {
+    // spans multiple clusters
+    const SIZE: usize = 20000;
+    let fd = open_ok(FILE, O_RDWR | O_TRUNC);
+    let mut buf = [0u8; 1000];
+    for i in 0..SIZE / buf.len() {
+        buf.fill(i as u8);
+        assert_eq!(write(fd, &buf), buf.len() as isize);
+    }
+    assert_eq!(lseek(fd, 0, SEEK_END), SIZE as isize);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    for i in 0..SIZE / buf.len() {
+        assert_eq!(read(fd, &mut buf), buf.len() as isize);
+        assert!(buf.iter().all(|&b| b == i as u8));
+    }
+    assert_eq!(close(fd), 0);
+
+    // truncate it
+    let fd = open_ok(FILE, O_RDWR | O_TRUNC);
+    assert_eq!(lseek(fd, 0, SEEK_END), 0);
+    assert_eq!(close(fd), 0);
+}
+
+fn test_read_dir() {
+    assert_eq!(open(FILE, O_RDONLY | O_DIRECTORY), ENOTDIR);
+    assert_eq!(open(DIR, O_RDWR), EISDIR);
+    let fd = open_ok(DIR, O_RDONLY | O_DIRECTORY);
+    let mut buf = [0; 256];
+    let len = getdents64(fd, &mut buf);
+    assert!(len > 0);
+    let mut count = 0;
+    for entry in DirEntries::new(&buf[..len as usize]) {
+        println!(
+            "  {} (type {}, ino {:#x})",
+            entry.name, entry.kind, entry.ino
+        );
+        match entry.name {
+            "Long File Name.txt" => assert_eq!(entry.kind, DT_REG),
+            "subdir" => assert_eq!(entry.kind, DT_DIR),
+            name => panic!("unexpected entry {:?}", name),
+        }
+        count += 1;
+    }
+    assert_eq!(count, 2);
+    assert_eq!(getdents64(fd, &mut buf), 0);
+    assert_eq!(close(fd), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let root = open("/\0", O_RDONLY | O_DIRECTORY);
+    if root == ENOENT {
+        println!("No filesystem is mounted, skipped.");
+        return 0;
+    }
+    assert_eq!(close(root as usize), 0);
+
+    // clean up the previous run
+    unlink(FILE);
+    rmdir("/fs_test/subdir\0");
+    rmdir(DIR);
+
+    assert_eq!(mkdir(DIR), 0);
+    assert_eq!(mkdir(DIR), EEXIST);
+    assert_eq!(mkdir("/fs_test/subdir\0"), 0);
+    test_read_write();
+    test_large_file();
+    test_read_dir();
+
+    assert_eq!(rmdir(DIR), ENOTEMPTY);
+    assert_eq!(unlink("/fs_test/subdir\0"), EISDIR);
+    assert_eq!(rmdir("/fs_test/subdir\0"), 0);
+    assert_eq!(rmdir(FILE), ENOTDIR);
+    assert_eq!(unlink(FILE), 0);
+    assert_eq!(open(FILE, O_RDONLY), ENOENT);
+    assert_eq!(rmdir(DIR), 0);
+    assert_eq!(sync(), 0);
+    println!("fs_test passed!");
+    0
+}
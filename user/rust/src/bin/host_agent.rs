@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::fmt::{self, Write};
+
+use user_lib::{close, get_time_us, getpid, open, read, write, O_RDWR};
+
+/// The virtio console port connected to the host, see the Makefile.
+const CTL_PORT: &str = "/dev/org.nimbos.ctl\0";
+
+const MAX_LINE: usize = 256;
+
+/// A fixed-size buffer for a reply.
+struct Reply {
+    buf: [u8; MAX_LINE + 32],
+    len: usize,
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Answers a request from the host, returns false to quit.
+fn handle(line: &str, reply: &mut Reply) -> bool {
+    let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+    let _ = match cmd {
+        "" => return true,
+        "ping" => writeln!(reply, "pong"),
+        "uptime" => {
+            let us = get_time_us();
+            writeln!(reply, "{}.{:06}", us / 1_000_000, us % 1_000_000)
+        }
+        "pid" => writeln!(reply, "{}", getpid()),
+        "echo" => writeln!(reply, "{}", arg),
+        "quit" => {
+            let _ = writeln!(reply, "bye");
+            return false;
+        }
+        _ => writeln!(reply, "unknown command: {}", cmd),
+    };
+    true
+}
+
+/// Serves line-based requests from the host on the control port.
+#[no_mangle]
+pub fn main() -> i32 {
+    let path = CTL_PORT.trim_end_matches('\0');
+    let fd = open(CTL_PORT, O_RDWR);
+    if fd < 0 {
+        println!("failed to open {}: {}", path, fd);
+        return 1;
+    }
+    let fd = fd as usize;
+    println!("Host agent listening on {}", path);
+
+    let mut line = [0u8; MAX_LINE];
+    let mut len = 0;
+    let mut buf = [0u8; 64];
+    'serve: loop {
+        let n = read(fd, &mut buf);
+        if n <= 0 {
+            break;
+        }
+        for &c in &buf[..n as usize] {
+            match c {
+                b'\r' => {}
+                b'\n' => {
+                    let mut reply = Reply {
+                        buf: [0; MAX_LINE + 32],
+                        len: 0,
+                    };
+                    let req = core::str::from_utf8(&line[..len]).unwrap_or("");
+                    let more = handle(req.trim(), &mut reply);
+                    write(fd, &reply.buf[..reply.len]);
+                    len = 0;
+                    if !more {
+                        break 'serve;
+                    }
+                }
+                // overlong lines are truncated
+                _ if len < MAX_LINE => {
+                    line[len] = c;
+                    len += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    close(fd);
+    0
+}
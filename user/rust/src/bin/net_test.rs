@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, connect, exit, fork, getpeername, getsockname, listen, read, recv, send,
+    socket, waitpid, wexitstatus, write, SockAddrIn, AF_INET, INADDR_ANY, INADDR_LOOPBACK,
+    MSG_DONTWAIT, SOCK_NONBLOCK, SOCK_STREAM,
+};
+
+const EAGAIN: isize = -11;
+const ENOTSOCK: isize = -88;
+const EADDRINUSE: isize = -98;
+const ENOTCONN: isize = -107;
+const ECONNREFUSED: isize = -111;
+const EINPROGRESS: isize = -115;
+
+const PORT: u16 = 7777;
+const CLOSED_PORT: u16 = 7778;
+const DATA_SIZE: usize = 100_000;
+
+fn tcp_socket(flags: u32) -> usize {
+    let fd = socket(AF_INET, SOCK_STREAM | flags, 0);
+    assert!(fd >= 0, "failed to create a socket: {}", fd);
+    fd as usize
+}
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+/// Echoes everything received on one connection.
+fn server(listener: usize) -> i32 {
+    let mut peer = SockAddrIn::default();
+    let conn = accept(listener, Some(&mut peer));
+    assert!(conn >= 0, "failed to accept: {}", conn);
+    let conn = conn as usize;
+    assert_eq!(peer.addr, INADDR_LOOPBACK);
+    let mut buf = [0; 4096];
+    loop {
+        let n = read(conn, &mut buf);
+        assert!(n >= 0, "failed to read: {}", n);
+        if n == 0 {
+            break;
+        }
+        let mut sent = 0;
+        while sent < n as usize {
+            let m = write(conn, &buf[sent..n as usize]);
+            assert!(m > 0, "failed to write: {}", m);
+            sent += m as usize;
+        }
+    }
+    assert_eq!(close(conn), 0);
+    assert_eq!(close(listener), 0);
+    0
+}
+
+fn test_echo(listener: usize) {
+    let pid = fork();
+    if pid == 0 {
+        exit(server(listener));
+    }
+    assert_eq!(close(listener), 0);
+
+    let fd = tcp_socket(0);
+    let mut addr = SockAddrIn::default();
+    assert_eq!(getpeername(fd, &mut addr), ENOTCONN);
+    assert_eq!(connect(fd, &SockAddrIn::new(INADDR_LOOPBACK, PORT)), 0);
+    assert_eq!(getpeername(fd, &mut addr), 0);
+    assert_eq!((addr.addr, addr.port()), (INADDR_LOOPBACK, PORT));
+    assert_eq!(getsockname(fd, &mut addr), 0);
+    assert_eq!(addr.addr, INADDR_LOOPBACK);
+    assert_ne!(addr.port(), 0);
+
+    // larger than the socket buffers, received data is drained before
+    // sending more, so that the server is never blocked on writing
+    let mut data = [0; 4096];
+    let mut sent = 0;
+    let mut received = 0;
+    while received < DATA_SIZE {
+        if sent < DATA_SIZE {
+            let len = data.len().min(DATA_SIZE - sent);
+            for (i, b) in data[..len].iter_mut().enumerate() {
+                *b = pattern(sent + i);
+            }
+            let n = send(fd, &data[..len], 0);
+            assert!(n > 0, "failed to send: {}", n);
+            sent += n as usize;
+        }
+        loop {
+            let flags = if sent < DATA_SIZE { MSG_DONTWAIT } else { 0 };
+            let n = recv(fd, &mut data, flags);
+            if n == EAGAIN {
+                break;
+            }
+            assert!(n > 0, "failed to receive: {}", n);
+            for (i, &b) in data[..n as usize].iter().enumerate() {
+                assert_eq!(b, pattern(received + i));
+            }
+            received += n as usize;
+            if received == DATA_SIZE {
+                break;
+            }
+        }
+    }
+    assert_eq!(close(fd), 0);
+
+    let mut status = 0;
+    assert_eq!(waitpid(pid, Some(&mut status), 0), pid);
+    assert_eq!(wexitstatus(status), 0);
+}
+
+fn test_nonblocking() {
+    let fd = tcp_socket(SOCK_NONBLOCK);
+    assert_eq!(bind(fd, &SockAddrIn::new(INADDR_LOOPBACK, 0)), 0);
+    assert_eq!(listen(fd, 1), 0);
+    let mut local = SockAddrIn::default();
+    assert_eq!(getsockname(fd, &mut local), 0);
+    assert_eq!(accept(fd, None), EAGAIN);
+
+    let client = tcp_socket(SOCK_NONBLOCK);
+    let ret = connect(client, &local);
+    assert!(ret == 0 || ret == EINPROGRESS, "failed to connect: {}", ret);
+    let conn = loop {
+        match accept(fd, None) {
+            EAGAIN => continue,
+            conn => break conn,
+        }
+    };
+    assert!(conn >= 0, "failed to accept: {}", conn);
+    let mut buf = [0; 8];
+    assert_eq!(recv(conn as usize, &mut buf, MSG_DONTWAIT), EAGAIN);
+    assert_eq!(close(conn as usize), 0);
+    assert_eq!(close(client), 0);
+    assert_eq!(close(fd), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getsockname(0, &mut SockAddrIn::default()), ENOTSOCK);
+
+    let listener = tcp_socket(0);
+    assert_eq!(bind(listener, &SockAddrIn::new(INADDR_ANY, PORT)), 0);
+    let other = tcp_socket(0);
+    assert_eq!(bind(other, &SockAddrIn::new(INADDR_ANY, PORT)), EADDRINUSE);
+    assert_eq!(close(other), 0);
+    assert_eq!(listen(listener, 4), 0);
+
+    // nothing listens on this port
+    let fd = tcp_socket(0);
+    assert_eq!(
+        connect(fd, &SockAddrIn::new(INADDR_LOOPBACK, CLOSED_PORT)),
+        ECONNREFUSED
+    );
+    assert_eq!(close(fd), 0);
+
+    test_echo(listener);
+    test_nonblocking();
+    println!("net_test passed!");
+    0
+}
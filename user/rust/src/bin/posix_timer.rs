@@ -0,0 +1,132 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    alarm, clock_gettime, fork, get_time_us, setitimer, sigprocmask, sigtimedwait, sigwaitinfo,
+    sleep, timer_create, timer_delete, timer_getoverrun, timer_gettime, timer_settime, usleep,
+    waitpid, wifsignaled, wtermsig, ITimerSpec, ITimerVal, SigEvent, SigInfo, SigSet, TimeSpec,
+    TimeVal, CLOCK_MONOTONIC, CLOCK_REALTIME, ITIMER_REAL, SIGALRM, SIGEV_SIGNAL, SIGUSR1, SIGUSR2,
+    SIG_BLOCK, SI_TIMER, TIMER_ABSTIME,
+};
+
+const EINVAL: isize = -22;
+const EAGAIN: isize = -11;
+
+fn ms(millis: usize) -> TimeSpec {
+    TimeSpec {
+        sec: millis / 1000,
+        nsec: millis % 1000 * 1_000_000,
+    }
+}
+
+fn to_us(ts: &TimeSpec) -> usize {
+    ts.sec * 1_000_000 + ts.nsec / 1000
+}
+
+fn test_periodic(blocked: &SigSet) {
+    let event = SigEvent::new(SIGEV_SIGNAL, SIGUSR1 as _, 0x1234);
+    let timer = timer_create(CLOCK_MONOTONIC, Some(&event)).unwrap();
+    let setting = ITimerSpec {
+        interval: ms(10),
+        value: ms(10),
+    };
+    assert_eq!(timer_settime(timer, 0, &setting, None), 0);
+
+    let start = get_time_us();
+    for _ in 0..5 {
+        let mut info = SigInfo::default();
+        assert_eq!(sigwaitinfo(blocked, Some(&mut info)), SIGUSR1 as isize);
+        assert_eq!(info.code, SI_TIMER);
+        assert_eq!(info.timer_id, timer);
+        assert_eq!(info.value, 0x1234);
+    }
+    let elapsed = get_time_us() - start;
+    println!("5 periods of 10ms in {} us", elapsed);
+    assert!(elapsed >= 40_000);
+
+    // missed periods are reported as the overrun count
+    usleep(55_000);
+    let mut info = SigInfo::default();
+    assert_eq!(sigwaitinfo(blocked, Some(&mut info)), SIGUSR1 as isize);
+    println!("overrun after sleeping 55ms: {}", info.overrun);
+    assert!(info.overrun >= 3);
+    assert_eq!(timer_getoverrun(timer), info.overrun as isize);
+
+    let mut curr = ITimerSpec::default();
+    assert_eq!(timer_gettime(timer, &mut curr), 0);
+    assert_eq!(to_us(&curr.interval), 10_000);
+    assert!(to_us(&curr.value) <= 10_000);
+
+    let mut old = ITimerSpec::default();
+    assert_eq!(
+        timer_settime(timer, 0, &ITimerSpec::default(), Some(&mut old)),
+        0
+    );
+    assert_eq!(to_us(&old.interval), 10_000);
+    assert_eq!(timer_gettime(timer, &mut curr), 0);
+    assert_eq!(to_us(&curr.value), 0);
+
+    assert_eq!(timer_delete(timer), 0);
+    assert_eq!(timer_gettime(timer, &mut curr), EINVAL);
+}
+
+fn test_oneshot_abstime(blocked: &SigSet) {
+    let event = SigEvent::new(SIGEV_SIGNAL, SIGUSR2 as _, 0);
+    let timer = timer_create(CLOCK_REALTIME, Some(&event)).unwrap();
+    let mut now = TimeSpec::default();
+    clock_gettime(CLOCK_REALTIME, &mut now);
+    let deadline = to_us(&now) + 20_000;
+    let setting = ITimerSpec {
+        interval: TimeSpec::default(),
+        value: TimeSpec {
+            sec: deadline / 1_000_000,
+            nsec: deadline % 1_000_000 * 1000,
+        },
+    };
+    assert_eq!(timer_settime(timer, TIMER_ABSTIME, &setting, None), 0);
+    assert_eq!(sigwaitinfo(blocked, None), SIGUSR2 as isize);
+    clock_gettime(CLOCK_REALTIME, &mut now);
+    assert!(to_us(&now) >= deadline);
+
+    // no more signals from a one-shot timer
+    assert_eq!(sigtimedwait(blocked, None, Some(&ms(30))), EAGAIN);
+    assert_eq!(timer_delete(timer), 0);
+}
+
+fn test_alarm() {
+    assert_eq!(alarm(5), 0);
+    assert_eq!(alarm(0), 5);
+
+    let setting = ITimerVal {
+        interval: TimeVal::default(),
+        value: TimeVal {
+            sec: 0,
+            usec: 50_000,
+        },
+    };
+    let pid = fork();
+    if pid == 0 {
+        // SIGALRM terminates the process by default
+        setitimer(ITIMER_REAL, &setting, None);
+        loop {
+            sleep(1);
+        }
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid, Some(&mut status), 0), pid);
+    assert!(wifsignaled(status) && wtermsig(status) == SIGALRM as i32);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let blocked = SigSet::empty().add(SIGUSR1).add(SIGUSR2);
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(&blocked), None), 0);
+    test_periodic(&blocked);
+    test_oneshot_abstime(&blocked);
+    test_alarm();
+    println!("posix_timer passed!");
+    0
+}
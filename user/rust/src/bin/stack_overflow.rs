@@ -4,14 +4,44 @@
 #[macro_use]
 extern crate user_lib;
 
+use user_lib::{getrlimit, setrlimit, RLimit, RLIMIT_STACK};
+
+const PAGE_SIZE: usize = 0x1000;
+const STACK_LIMIT: usize = 0x10_0000; // 1M
+
+/// Uses about one page of stack in each level.
+fn grow(d: usize) -> usize {
+    let mut buf = [0u8; PAGE_SIZE];
+    buf[d % PAGE_SIZE] = d as u8;
+    let buf = core::hint::black_box(buf);
+    if d == 0 {
+        buf[0] as usize
+    } else {
+        grow(d - 1) + buf[d % PAGE_SIZE] as usize
+    }
+}
+
 #[allow(unconditional_recursion)]
 fn f(d: usize) {
-    println!("d = {}", d);
-    f(d + 1);
+    if d % 1000 == 0 {
+        println!("d = {}", d);
+    }
+    let buf = core::hint::black_box([d as u8; 64]);
+    f(d + buf[0] as usize / 256 + 1);
 }
 
 #[no_mangle]
 pub fn main() -> i32 {
+    let mut rlim = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_STACK, &mut rlim), 0);
+    println!("stack limit: cur={:#x}, max={:#x}", rlim.cur, rlim.max);
+
+    // The stack grows beyond the initially mapped pages on demand.
+    grow(STACK_LIMIT / PAGE_SIZE / 2);
+    println!("stack grown to {:#x} bytes", STACK_LIMIT / 2);
+
+    rlim.cur = STACK_LIMIT;
+    assert_eq!(setrlimit(RLIMIT_STACK, &rlim), 0);
     println!("It should trigger segmentation fault!");
     f(0);
     0
@@ -5,7 +5,7 @@
 extern crate user_lib;
 
 use core::sync::atomic::{AtomicUsize, Ordering};
-use user_lib::{getpid, thread_spawn, waitpid};
+use user_lib::{getpid, gettid, thread_spawn};
 
 static GLOBAL_VAR: AtomicUsize = AtomicUsize::new(0);
 
@@ -28,23 +28,22 @@ pub fn main() -> i32 {
         for _ in 0..100 {
             let value = GLOBAL_VAR.fetch_add(100, Ordering::AcqRel);
             println!(
-                "test user thread: pid = {:?}, arg = {:#x}, sp = {:#x?}, global_var = {}",
+                "test user thread: pid = {}, tid = {}, arg = {:#x}, sp = {:#x?}, global_var = {}",
                 getpid(),
+                gettid(),
                 arg,
                 get_sp(),
                 value
             );
         }
-        -getpid() as _
+        -gettid() as _
     };
 
-    let t0 = thread_spawn(test_user_thread, 0xdead);
-    let t1 = thread_spawn(test_user_thread, 0xbeef);
-    let mut exit_code = 0;
-    waitpid(t0, Some(&mut exit_code), 0);
-    println!("thread {} exited with {}.", t0, exit_code);
-    waitpid(t1, Some(&mut exit_code), 0);
-    println!("thread {} exited with {}.", t1, exit_code);
+    let t0 = thread_spawn(test_user_thread, 0xdead).unwrap();
+    let t1 = thread_spawn(test_user_thread, 0xbeef).unwrap();
+    let (tid0, tid1) = (t0.tid(), t1.tid());
+    println!("thread {} exited with {}.", tid0, t0.join());
+    println!("thread {} exited with {}.", tid1, t1.join());
     println!("main thread exited.");
     0
 }
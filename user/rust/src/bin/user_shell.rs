@@ -10,7 +10,7 @@ const DL: u8 = b'\x7f';
 const BS: u8 = b'\x08';
 
 use user_lib::console::getchar;
-use user_lib::{exec, fork, waitpid};
+use user_lib::{exec, fork, waitpid, wexitstatus, wifexited, wtermsig};
 
 const MAX_CMD_LEN: usize = 256;
 
@@ -37,10 +37,16 @@ pub fn main() -> i32 {
                         }
                         unreachable!();
                     } else {
-                        let mut exit_code = 0;
-                        let exit_pid = waitpid(pid, Some(&mut exit_code), 0);
+                        let mut status = 0;
+                        let exit_pid = waitpid(pid, Some(&mut status), 0);
                         assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        if wifexited(status) {
+                            let exit_code = wexitstatus(status) as i8;
+                            println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        } else {
+                            let sig = wtermsig(status);
+                            println!("Shell: Process {} killed by signal {}", pid, sig);
+                        }
                     }
                     cursor = 0;
                 }
@@ -17,10 +17,15 @@ static TESTS: &[&str] = &[
     "stack_overflow\0",
     "yield\0",
     "thread_simple\0",
+    "bad_address\0",
     "cyclictest\0",
+    "posix_timer\0",
+    "clocks\0",
+    "fs_test\0",
+    "net_test\0",
 ];
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{exec, fork, waitpid, wexitstatus, wifexited, wtermsig};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -28,20 +33,33 @@ pub fn main() -> i32 {
         println!("Usertests: Running '{}':", test);
         let pid = fork();
         if pid == 0 {
-            if exec(*test) == -1 {
+            if exec(*test) < 0 {
                 panic!("usertest '{}' not found!", test);
             } else {
                 panic!("unreachable!");
             }
         } else {
-            let mut exit_code = 0;
-            let wait_pid = waitpid(pid, Some(&mut exit_code), 0);
+            let mut status = 0;
+            let wait_pid = waitpid(pid, Some(&mut status), 0);
             assert_eq!(pid, wait_pid);
-            let color = if exit_code == 0 { 32 } else { 31 };
-            println!(
-                "\x1b[{}mUsertests: Test '{}' in Process {} exited with code {}.\x1b[0m",
-                color, test, pid, exit_code
-            );
+            let color = if status == 0 { 32 } else { 31 };
+            if wifexited(status) {
+                println!(
+                    "\x1b[{}mUsertests: Test '{}' in Process {} exited with code {}.\x1b[0m",
+                    color,
+                    test,
+                    pid,
+                    wexitstatus(status) as i8
+                );
+            } else {
+                println!(
+                    "\x1b[{}mUsertests: Test '{}' in Process {} killed by signal {}.\x1b[0m",
+                    color,
+                    test,
+                    pid,
+                    wtermsig(status)
+                );
+            }
         }
     }
     println!("usertests passed!");
@@ -0,0 +1,94 @@
+use super::syscall::*;
+
+pub const O_RDONLY: u32 = 0;
+pub const O_WRONLY: u32 = 1;
+pub const O_RDWR: u32 = 2;
+pub const O_CREAT: u32 = 0o100;
+pub const O_EXCL: u32 = 0o200;
+pub const O_TRUNC: u32 = 0o1000;
+pub const O_APPEND: u32 = 0o2000;
+pub const O_DIRECTORY: u32 = 0o200000;
+
+pub const SEEK_SET: u32 = 0;
+pub const SEEK_CUR: u32 = 1;
+pub const SEEK_END: u32 = 2;
+
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+
+/// Opens the file at `path`, which must be terminated by a NUL byte.
+pub fn open(path: &str, flags: u32) -> isize {
+    sys_open(path, flags, 0o644)
+}
+
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
+pub fn lseek(fd: usize, offset: isize, whence: u32) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+
+pub fn sync() -> isize {
+    sys_sync()
+}
+
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path, 0o755)
+}
+
+pub fn rmdir(path: &str) -> isize {
+    sys_rmdir(path)
+}
+
+pub fn unlink(path: &str) -> isize {
+    sys_unlink(path)
+}
+
+/// Reads entries of the directory `fd` to `buf`, returns the number of bytes
+/// read, which can be parsed by [`DirEntries`].
+pub fn getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+
+/// An entry of a directory.
+#[derive(Debug)]
+pub struct DirEntry<'a> {
+    pub ino: u64,
+    pub kind: u8,
+    pub name: &'a str,
+}
+
+/// An iterator over the `struct linux_dirent64` records in the buffer filled
+/// by [`getdents64`].
+pub struct DirEntries<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DirEntries<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    type Item = DirEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < 19 {
+            return None;
+        }
+        let ino = u64::from_ne_bytes(self.buf[0..8].try_into().unwrap());
+        let reclen = u16::from_ne_bytes(self.buf[16..18].try_into().unwrap()) as usize;
+        let kind = self.buf[18];
+        let name = &self.buf[19..reclen];
+        let name_len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        let name = core::str::from_utf8(&name[..name_len]).unwrap_or("?");
+        self.buf = &self.buf[reclen..];
+        Some(DirEntry { ino, kind, name })
+    }
+}
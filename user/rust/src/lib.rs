@@ -8,10 +8,18 @@
 pub mod console;
 
 mod arch;
+mod fs;
 mod lang_items;
+mod net;
+mod signal;
 mod syscall;
+mod thread;
 mod time;
 
+pub use fs::*;
+pub use net::*;
+pub use signal::*;
+pub use thread::*;
 pub use time::*;
 
 #[no_mangle]
@@ -37,6 +45,11 @@ pub fn write(fd: usize, buf: &[u8]) -> isize {
 }
 
 pub fn exit(exit_code: i32) -> ! {
+    sys_exit_group(exit_code)
+}
+
+/// Terminates the calling thread only.
+pub fn thread_exit(exit_code: i32) -> ! {
     sys_exit(exit_code)
 }
 
@@ -48,6 +61,47 @@ pub fn getpid() -> isize {
     sys_getpid()
 }
 
+pub fn getppid() -> isize {
+    sys_getppid()
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+pub const PROT_READ: u32 = 1 << 0;
+pub const PROT_WRITE: u32 = 1 << 1;
+pub const PROT_EXEC: u32 = 1 << 2;
+
+pub const MAP_PRIVATE: u32 = 0x02;
+pub const MAP_FIXED: u32 = 0x10;
+pub const MAP_ANONYMOUS: u32 = 0x20;
+
+pub fn mmap(addr: usize, len: usize, prot: u32, flags: u32) -> isize {
+    sys_mmap(addr, len, prot, flags, -1, 0)
+}
+
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+
+pub const RLIMIT_STACK: u32 = 3;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+pub fn getrlimit(resource: u32, rlim: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlim)
+}
+
+pub fn setrlimit(resource: u32, rlim: &RLimit) -> isize {
+    sys_setrlimit(resource, rlim)
+}
+
 pub fn fork() -> isize {
     sys_fork()
 }
@@ -56,6 +110,30 @@ pub fn exec(path: &str) -> isize {
     sys_exec(path)
 }
 
+pub const WNOHANG: u32 = 1;
+pub const WUNTRACED: u32 = 2;
+
+/// Returns true if the child terminated normally.
+pub const fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+/// Returns the exit code of the child, only valid if `wifexited()` is true.
+pub const fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+/// Returns true if the child was terminated by a signal.
+pub const fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0 && status & 0x7f != 0x7f
+}
+
+/// Returns the signal that terminated the child, only valid if
+/// `wifsignaled()` is true.
+pub const fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
 pub fn waitpid(pid: isize, exit_code: Option<&mut i32>, options: u32) -> isize {
     let exit_code_ptr = exit_code.map(|e| e as _).unwrap_or(core::ptr::null_mut());
     sys_waitpid(pid, exit_code_ptr, options)
@@ -64,16 +142,3 @@ pub fn waitpid(pid: isize, exit_code: Option<&mut i32>, options: u32) -> isize {
 pub fn wait(exit_code: Option<&mut i32>) -> isize {
     waitpid(-1, exit_code, 0)
 }
-
-pub fn thread_spawn(entry: fn(usize) -> i32, arg: usize) -> isize {
-    use core::sync::atomic::{AtomicUsize, Ordering};
-    const MAX_THREADS: usize = 16;
-    const THREAD_STACK_SIZE: usize = 4096 * 4; // 16K
-    static mut THREAD_STACKS: [[u8; THREAD_STACK_SIZE]; MAX_THREADS] =
-        [[0; THREAD_STACK_SIZE]; MAX_THREADS];
-    static THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
-
-    let thread_id = THREAD_COUNT.fetch_add(1, Ordering::AcqRel);
-    let newsp = unsafe { THREAD_STACKS[thread_id].as_ptr_range().end as usize };
-    sys_clone(entry, arg, newsp)
-}
@@ -0,0 +1,74 @@
+use super::syscall::*;
+
+pub const AF_INET: u32 = 2;
+pub const SOCK_STREAM: u32 = 1;
+pub const SOCK_NONBLOCK: u32 = 0o4000;
+
+pub const MSG_DONTWAIT: u32 = 0x40;
+
+pub const INADDR_ANY: [u8; 4] = [0, 0, 0, 0];
+pub const INADDR_LOOPBACK: [u8; 4] = [127, 0, 0, 1];
+
+/// `struct sockaddr_in`, the port and the address are in network byte order.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SockAddrIn {
+    pub family: u16,
+    pub port: u16,
+    pub addr: [u8; 4],
+    pub zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub const fn new(addr: [u8; 4], port: u16) -> Self {
+        Self {
+            family: AF_INET as u16,
+            port: port.to_be(),
+            addr,
+            zero: [0; 8],
+        }
+    }
+
+    /// The port in host byte order.
+    pub const fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+pub fn socket(domain: u32, ty: u32, protocol: u32) -> isize {
+    sys_socket(domain, ty, protocol)
+}
+
+pub fn bind(fd: usize, addr: &SockAddrIn) -> isize {
+    sys_bind(fd, addr)
+}
+
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+
+/// Waits for a connection, returns the new socket, and stores the address of
+/// the peer to `addr`.
+pub fn accept(fd: usize, addr: Option<&mut SockAddrIn>) -> isize {
+    sys_accept(fd, addr)
+}
+
+pub fn connect(fd: usize, addr: &SockAddrIn) -> isize {
+    sys_connect(fd, addr)
+}
+
+pub fn send(fd: usize, buf: &[u8], flags: u32) -> isize {
+    sys_sendto(fd, buf, flags)
+}
+
+pub fn recv(fd: usize, buf: &mut [u8], flags: u32) -> isize {
+    sys_recvfrom(fd, buf, flags)
+}
+
+pub fn getsockname(fd: usize, addr: &mut SockAddrIn) -> isize {
+    sys_getsockname(fd, addr)
+}
+
+pub fn getpeername(fd: usize, addr: &mut SockAddrIn) -> isize {
+    sys_getpeername(fd, addr)
+}
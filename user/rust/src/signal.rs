@@ -0,0 +1,67 @@
+use super::syscall::*;
+use super::time::TimeSpec;
+
+pub const SIGKILL: u32 = 9;
+pub const SIGUSR1: u32 = 10;
+pub const SIGUSR2: u32 = 12;
+pub const SIGALRM: u32 = 14;
+pub const SIGRTMIN: u32 = 34;
+
+pub const SIG_BLOCK: u32 = 0;
+pub const SIG_UNBLOCK: u32 = 1;
+pub const SIG_SETMASK: u32 = 2;
+
+/// The signal was sent by the expiration of a POSIX timer.
+pub const SI_TIMER: i32 = -2;
+
+/// A set of signals.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct SigSet(u64);
+
+impl SigSet {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn add(self, signo: u32) -> Self {
+        Self(self.0 | 1 << (signo - 1))
+    }
+
+    pub const fn contains(&self, signo: u32) -> bool {
+        self.0 & (1 << (signo - 1)) != 0
+    }
+}
+
+/// `siginfo_t`, only the fields set for timer signals are defined.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    _pad: i32,
+    pub timer_id: i32,
+    pub overrun: i32,
+    pub value: usize,
+    _rest: [u64; 12],
+}
+
+impl Default for SigInfo {
+    fn default() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+pub fn sigprocmask(how: u32, set: Option<&SigSet>, oldset: Option<&mut SigSet>) -> isize {
+    sys_rt_sigprocmask(how, set, oldset)
+}
+
+/// Waits for a signal in `set` to become pending, returns the signal number.
+pub fn sigtimedwait(set: &SigSet, info: Option<&mut SigInfo>, timeout: Option<&TimeSpec>) -> isize {
+    sys_rt_sigtimedwait(set, info, timeout)
+}
+
+pub fn sigwaitinfo(set: &SigSet, info: Option<&mut SigInfo>) -> isize {
+    sigtimedwait(set, info, None)
+}
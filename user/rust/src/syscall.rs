@@ -1,19 +1,62 @@
-use super::time::{ClockId, TimeSpec};
-use crate::arch::syscall;
+use super::net::SockAddrIn;
+use super::signal::{SigInfo, SigSet};
+use super::time::{ClockId, ITimerSpec, ITimerVal, SigEvent, TimeSpec, TimeVal};
+use super::RLimit;
+use crate::arch::{syscall, syscall6};
 
 pub use crate::arch::sys_clone;
 
 pub const SYSCALL_READ: usize = 0;
 pub const SYSCALL_WRITE: usize = 1;
+pub const SYSCALL_OPEN: usize = 2;
+pub const SYSCALL_CLOSE: usize = 3;
+pub const SYSCALL_LSEEK: usize = 8;
+pub const SYSCALL_MMAP: usize = 9;
+pub const SYSCALL_MUNMAP: usize = 11;
+pub const SYSCALL_RT_SIGPROCMASK: usize = 14;
 pub const SYSCALL_YIELD: usize = 24;
+pub const SYSCALL_GETITIMER: usize = 36;
+pub const SYSCALL_ALARM: usize = 37;
+pub const SYSCALL_SETITIMER: usize = 38;
 pub const SYSCALL_GETPID: usize = 39;
+pub const SYSCALL_SOCKET: usize = 41;
+pub const SYSCALL_CONNECT: usize = 42;
+pub const SYSCALL_ACCEPT: usize = 43;
+pub const SYSCALL_SENDTO: usize = 44;
+pub const SYSCALL_RECVFROM: usize = 45;
+pub const SYSCALL_BIND: usize = 49;
+pub const SYSCALL_LISTEN: usize = 50;
+pub const SYSCALL_GETSOCKNAME: usize = 51;
+pub const SYSCALL_GETPEERNAME: usize = 52;
 pub const SYSCALL_CLONE: usize = 56;
 pub const SYSCALL_FORK: usize = 57;
 pub const SYSCALL_EXEC: usize = 59;
 pub const SYSCALL_EXIT: usize = 60;
 pub const SYSCALL_WAITPID: usize = 61;
+pub const SYSCALL_FSYNC: usize = 74;
+pub const SYSCALL_MKDIR: usize = 83;
+pub const SYSCALL_RMDIR: usize = 84;
+pub const SYSCALL_UNLINK: usize = 87;
+pub const SYSCALL_GETTIMEOFDAY: usize = 96;
+pub const SYSCALL_GETRLIMIT: usize = 97;
+pub const SYSCALL_GETPPID: usize = 110;
+pub const SYSCALL_RT_SIGTIMEDWAIT: usize = 128;
+pub const SYSCALL_SETRLIMIT: usize = 160;
+pub const SYSCALL_SYNC: usize = 162;
+pub const SYSCALL_GETTID: usize = 186;
+pub const SYSCALL_TIME: usize = 201;
+pub const SYSCALL_FUTEX: usize = 202;
+pub const SYSCALL_GETDENTS64: usize = 217;
+pub const SYSCALL_TIMER_CREATE: usize = 222;
+pub const SYSCALL_TIMER_SETTIME: usize = 223;
+pub const SYSCALL_TIMER_GETTIME: usize = 224;
+pub const SYSCALL_TIMER_GETOVERRUN: usize = 225;
+pub const SYSCALL_TIMER_DELETE: usize = 226;
+pub const SYSCALL_CLOCK_SETTIME: usize = 227;
 pub const SYSCALL_CLOCK_GETTIME: usize = 228;
+pub const SYSCALL_CLOCK_GETRES: usize = 229;
 pub const SYSCALL_CLOCK_NANOSLEEP: usize = 230;
+pub const SYSCALL_EXIT_GROUP: usize = 231;
 
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
@@ -26,11 +69,127 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_open(path: &str, flags: u32, mode: u32) -> isize {
+    syscall(
+        SYSCALL_OPEN,
+        [path.as_ptr() as usize, flags as _, mode as _],
+    )
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: u32) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence as _])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_mkdir(path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_MKDIR, [path.as_ptr() as usize, mode as _, 0])
+}
+
+pub fn sys_rmdir(path: &str) -> isize {
+    syscall(SYSCALL_RMDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_unlink(path: &str) -> isize {
+    syscall(SYSCALL_UNLINK, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_GETDENTS64,
+        [fd, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+pub fn sys_socket(domain: u32, ty: u32, protocol: u32) -> isize {
+    syscall(SYSCALL_SOCKET, [domain as _, ty as _, protocol as _])
+}
+
+pub fn sys_bind(fd: usize, addr: &SockAddrIn) -> isize {
+    syscall(
+        SYSCALL_BIND,
+        [
+            fd,
+            addr as *const _ as usize,
+            core::mem::size_of::<SockAddrIn>(),
+        ],
+    )
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    syscall(SYSCALL_LISTEN, [fd, backlog, 0])
+}
+
+pub fn sys_accept(fd: usize, addr: Option<&mut SockAddrIn>) -> isize {
+    let mut addrlen = core::mem::size_of::<SockAddrIn>() as u32;
+    let addr_ptr = addr.map_or(0, |addr| addr as *mut _ as usize);
+    syscall(
+        SYSCALL_ACCEPT,
+        [fd, addr_ptr, &mut addrlen as *mut _ as usize],
+    )
+}
+
+pub fn sys_connect(fd: usize, addr: &SockAddrIn) -> isize {
+    syscall(
+        SYSCALL_CONNECT,
+        [
+            fd,
+            addr as *const _ as usize,
+            core::mem::size_of::<SockAddrIn>(),
+        ],
+    )
+}
+
+pub fn sys_sendto(fd: usize, buf: &[u8], flags: u32) -> isize {
+    syscall6(
+        SYSCALL_SENDTO,
+        [fd, buf.as_ptr() as usize, buf.len(), flags as _, 0, 0],
+    )
+}
+
+pub fn sys_recvfrom(fd: usize, buf: &mut [u8], flags: u32) -> isize {
+    syscall6(
+        SYSCALL_RECVFROM,
+        [fd, buf.as_mut_ptr() as usize, buf.len(), flags as _, 0, 0],
+    )
+}
+
+pub fn sys_getsockname(fd: usize, addr: &mut SockAddrIn) -> isize {
+    let mut addrlen = core::mem::size_of::<SockAddrIn>() as u32;
+    syscall(
+        SYSCALL_GETSOCKNAME,
+        [fd, addr as *mut _ as usize, &mut addrlen as *mut _ as usize],
+    )
+}
+
+pub fn sys_getpeername(fd: usize, addr: &mut SockAddrIn) -> isize {
+    let mut addrlen = core::mem::size_of::<SockAddrIn>() as u32;
+    syscall(
+        SYSCALL_GETPEERNAME,
+        [fd, addr as *mut _ as usize, &mut addrlen as *mut _ as usize],
+    )
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
 }
 
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    syscall(SYSCALL_EXIT_GROUP, [exit_code as usize, 0, 0]);
+    panic!("sys_exit_group never returns!");
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
@@ -39,6 +198,43 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
+pub fn sys_gettid() -> isize {
+    syscall(SYSCALL_GETTID, [0, 0, 0])
+}
+
+pub fn sys_mmap(addr: usize, len: usize, prot: u32, flags: u32, fd: i32, offset: usize) -> isize {
+    syscall6(
+        SYSCALL_MMAP,
+        [addr, len, prot as _, flags as _, fd as _, offset],
+    )
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0])
+}
+
+pub fn sys_getrlimit(resource: u32, rlim: &mut RLimit) -> isize {
+    syscall(
+        SYSCALL_GETRLIMIT,
+        [resource as _, rlim as *mut _ as usize, 0],
+    )
+}
+
+pub fn sys_setrlimit(resource: u32, rlim: &RLimit) -> isize {
+    syscall(
+        SYSCALL_SETRLIMIT,
+        [resource as _, rlim as *const _ as usize, 0],
+    )
+}
+
+pub fn sys_futex(uaddr: *const i32, op: u32, val: i32) -> isize {
+    syscall(SYSCALL_FUTEX, [uaddr as usize, op as _, val as _])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -54,13 +250,127 @@ pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: u32) -> isize {
     )
 }
 
+pub fn sys_gettimeofday(tv: &mut TimeVal) -> isize {
+    syscall(SYSCALL_GETTIMEOFDAY, [tv as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_time() -> isize {
+    syscall(SYSCALL_TIME, [0, 0, 0])
+}
+
+pub fn sys_clock_settime(clk: ClockId, req: &TimeSpec) -> isize {
+    syscall(
+        SYSCALL_CLOCK_SETTIME,
+        [clk as _, req as *const _ as usize, 0],
+    )
+}
+
 pub fn sys_clock_gettime(clk: ClockId, req: &mut TimeSpec) -> isize {
     syscall(SYSCALL_CLOCK_GETTIME, [clk as _, req as *mut _ as usize, 0])
 }
 
+pub fn sys_clock_getres(clk: ClockId, res: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETRES, [clk as _, res as *mut _ as usize, 0])
+}
+
 pub fn sys_clock_nanosleep(clk: ClockId, flags: u32, req: &TimeSpec) -> isize {
     syscall(
         SYSCALL_CLOCK_NANOSLEEP,
         [clk as _, flags as _, req as *const _ as usize],
     )
 }
+
+pub fn sys_rt_sigprocmask(how: u32, set: Option<&SigSet>, oldset: Option<&mut SigSet>) -> isize {
+    let set = set.map_or(0, |s| s as *const _ as usize);
+    let oldset = oldset.map_or(0, |s| s as *mut _ as usize);
+    syscall6(
+        SYSCALL_RT_SIGPROCMASK,
+        [how as _, set, oldset, core::mem::size_of::<SigSet>(), 0, 0],
+    )
+}
+
+pub fn sys_rt_sigtimedwait(
+    set: &SigSet,
+    info: Option<&mut SigInfo>,
+    timeout: Option<&TimeSpec>,
+) -> isize {
+    let info = info.map_or(0, |i| i as *mut _ as usize);
+    let timeout = timeout.map_or(0, |t| t as *const _ as usize);
+    syscall6(
+        SYSCALL_RT_SIGTIMEDWAIT,
+        [
+            set as *const _ as usize,
+            info,
+            timeout,
+            core::mem::size_of::<SigSet>(),
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_timer_create(clk: ClockId, sevp: Option<&SigEvent>, timerid: &mut i32) -> isize {
+    let sevp = sevp.map_or(0, |e| e as *const _ as usize);
+    syscall(
+        SYSCALL_TIMER_CREATE,
+        [clk as _, sevp, timerid as *mut _ as usize],
+    )
+}
+
+pub fn sys_timer_settime(
+    timerid: i32,
+    flags: u32,
+    new_value: &ITimerSpec,
+    old_value: Option<&mut ITimerSpec>,
+) -> isize {
+    let old_value = old_value.map_or(0, |v| v as *mut _ as usize);
+    syscall6(
+        SYSCALL_TIMER_SETTIME,
+        [
+            timerid as _,
+            flags as _,
+            new_value as *const _ as usize,
+            old_value,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_timer_gettime(timerid: i32, curr_value: &mut ITimerSpec) -> isize {
+    syscall(
+        SYSCALL_TIMER_GETTIME,
+        [timerid as _, curr_value as *mut _ as usize, 0],
+    )
+}
+
+pub fn sys_timer_getoverrun(timerid: i32) -> isize {
+    syscall(SYSCALL_TIMER_GETOVERRUN, [timerid as _, 0, 0])
+}
+
+pub fn sys_timer_delete(timerid: i32) -> isize {
+    syscall(SYSCALL_TIMER_DELETE, [timerid as _, 0, 0])
+}
+
+pub fn sys_setitimer(
+    which: u32,
+    new_value: &ITimerVal,
+    old_value: Option<&mut ITimerVal>,
+) -> isize {
+    let old_value = old_value.map_or(0, |v| v as *mut _ as usize);
+    syscall(
+        SYSCALL_SETITIMER,
+        [which as _, new_value as *const _ as usize, old_value],
+    )
+}
+
+pub fn sys_getitimer(which: u32, curr_value: &mut ITimerVal) -> isize {
+    syscall(
+        SYSCALL_GETITIMER,
+        [which as _, curr_value as *mut _ as usize, 0],
+    )
+}
+
+pub fn sys_alarm(seconds: u32) -> isize {
+    syscall(SYSCALL_ALARM, [seconds as _, 0, 0])
+}
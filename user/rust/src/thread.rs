@@ -0,0 +1,108 @@
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use crate::syscall::{sys_clone, sys_futex, sys_mmap, sys_munmap};
+use crate::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+
+const THREAD_STACK_SIZE: usize = 4096 * 16; // 64K
+
+const CLONE_VM: usize = 0x0000_0100;
+const CLONE_FS: usize = 0x0000_0200;
+const CLONE_FILES: usize = 0x0000_0400;
+const CLONE_SIGHAND: usize = 0x0000_0800;
+const CLONE_THREAD: usize = 0x0001_0000;
+const CLONE_SYSVSEM: usize = 0x0004_0000;
+const CLONE_CHILD_CLEARTID: usize = 0x0020_0000;
+const CLONE_CHILD_SETTID: usize = 0x0100_0000;
+
+const FUTEX_WAIT: u32 = 0;
+
+/// Placed at the top of the thread stack.
+#[repr(C)]
+struct ThreadControlBlock {
+    /// Set by the kernel on creation, and cleared on exit (`CLONE_CHILD_CLEARTID`).
+    tid: AtomicI32,
+    exit_code: i32,
+    entry: fn(usize) -> i32,
+    arg: usize,
+}
+
+/// An owned permission to join on a thread.
+pub struct JoinHandle {
+    tid: isize,
+    stack_base: usize,
+    tcb: *mut ThreadControlBlock,
+}
+
+fn thread_main(tcb: usize) -> i32 {
+    let tcb = unsafe { &mut *(tcb as *mut ThreadControlBlock) };
+    tcb.exit_code = (tcb.entry)(tcb.arg);
+    tcb.exit_code
+}
+
+/// Creates a new thread in the current process, the thread stack is
+/// allocated by `mmap()`.
+pub fn thread_spawn(entry: fn(usize) -> i32, arg: usize) -> Result<JoinHandle, isize> {
+    let stack_base = sys_mmap(
+        0,
+        THREAD_STACK_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        -1,
+        0,
+    );
+    if stack_base < 0 {
+        return Err(stack_base);
+    }
+    let stack_base = stack_base as usize;
+    let tcb = (stack_base + THREAD_STACK_SIZE - core::mem::size_of::<ThreadControlBlock>()) & !0xf;
+    let tcb = tcb as *mut ThreadControlBlock;
+    unsafe {
+        tcb.write(ThreadControlBlock {
+            tid: AtomicI32::new(0),
+            exit_code: 0,
+            entry,
+            arg,
+        });
+    }
+
+    let flags = CLONE_VM
+        | CLONE_FS
+        | CLONE_FILES
+        | CLONE_SIGHAND
+        | CLONE_THREAD
+        | CLONE_SYSVSEM
+        | CLONE_CHILD_SETTID
+        | CLONE_CHILD_CLEARTID;
+    let ctid = unsafe { &(*tcb).tid as *const AtomicI32 as *mut i32 };
+    let tid = sys_clone(thread_main, tcb as usize, tcb as usize, flags, ctid);
+    if tid < 0 {
+        sys_munmap(stack_base, THREAD_STACK_SIZE);
+        return Err(tid);
+    }
+    Ok(JoinHandle {
+        tid,
+        stack_base,
+        tcb,
+    })
+}
+
+impl JoinHandle {
+    pub fn tid(&self) -> isize {
+        self.tid
+    }
+
+    /// Waits for the thread to exit, returns its exit code.
+    pub fn join(self) -> i32 {
+        let tcb = unsafe { &*self.tcb };
+        loop {
+            let tid = tcb.tid.load(Ordering::Acquire);
+            if tid == 0 {
+                break;
+            }
+            sys_futex(&tcb.tid as *const AtomicI32 as _, FUTEX_WAIT, tid);
+        }
+        let exit_code = tcb.exit_code;
+        sys_munmap(self.stack_base, THREAD_STACK_SIZE);
+        exit_code
+    }
+}
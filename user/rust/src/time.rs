@@ -1,7 +1,7 @@
 use super::syscall::*;
 
 #[repr(C)]
-#[derive(Default)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct TimeSpec {
     /// seconds
     pub sec: usize,
@@ -13,16 +13,91 @@ pub type ClockId = u32;
 
 pub const CLOCK_REALTIME: ClockId = 0;
 pub const CLOCK_MONOTONIC: ClockId = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: ClockId = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: ClockId = 3;
+pub const CLOCK_BOOTTIME: ClockId = 7;
 
 pub const TIMER_ABSTIME: u32 = 1;
 
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeVal {
+    /// seconds
+    pub sec: usize,
+    /// micro seconds
+    pub usec: usize,
+}
+
+/// The setting of a POSIX timer, a zero `value` disarms the timer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ITimerSpec {
+    pub interval: TimeSpec,
+    pub value: TimeSpec,
+}
+
+/// The setting of an interval timer, a zero `value` disarms the timer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// How to notify the expiration of a POSIX timer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigEvent {
+    pub value: usize,
+    pub signo: i32,
+    pub notify: i32,
+    /// The target thread of `SIGEV_THREAD_ID`.
+    pub tid: i32,
+    _pad: [i32; 11],
+}
+
+impl SigEvent {
+    pub const fn new(notify: i32, signo: i32, value: usize) -> Self {
+        Self {
+            value,
+            signo,
+            notify,
+            tid: 0,
+            _pad: [0; 11],
+        }
+    }
+}
+
+pub const ITIMER_REAL: u32 = 0;
+
 pub fn clock_gettime(clk: ClockId, req: &mut TimeSpec) -> isize {
     sys_clock_gettime(clk, req)
 }
 
+pub fn gettimeofday(tv: &mut TimeVal) -> isize {
+    sys_gettimeofday(tv)
+}
+
+/// Returns the seconds since the Epoch.
+pub fn time() -> isize {
+    sys_time()
+}
+
+pub fn clock_settime(clk: ClockId, req: &TimeSpec) -> isize {
+    sys_clock_settime(clk, req)
+}
+
+pub fn clock_getres(clk: ClockId, res: &mut TimeSpec) -> isize {
+    sys_clock_getres(clk, res)
+}
+
 pub fn get_time_us() -> isize {
     let mut req = TimeSpec::default();
-    let ret = clock_gettime(CLOCK_REALTIME, &mut req);
+    let ret = clock_gettime(CLOCK_MONOTONIC, &mut req);
     if ret < 0 {
         ret
     } else {
@@ -35,7 +110,7 @@ pub fn clock_nanosleep(clk: ClockId, flags: u32, req: &TimeSpec) -> isize {
 }
 
 pub fn nanosleep(req: &TimeSpec) -> isize {
-    clock_nanosleep(CLOCK_REALTIME, 0, req)
+    clock_nanosleep(CLOCK_MONOTONIC, 0, req)
 }
 
 pub fn usleep(useconds: usize) -> isize {
@@ -56,3 +131,48 @@ pub fn sleep(seconds: usize) -> isize {
         0
     }
 }
+
+pub type TimerId = i32;
+
+pub fn timer_create(clk: ClockId, sevp: Option<&SigEvent>) -> Result<TimerId, isize> {
+    let mut timerid = 0;
+    match sys_timer_create(clk, sevp, &mut timerid) {
+        0 => Ok(timerid),
+        err => Err(err),
+    }
+}
+
+pub fn timer_settime(
+    timerid: TimerId,
+    flags: u32,
+    new_value: &ITimerSpec,
+    old_value: Option<&mut ITimerSpec>,
+) -> isize {
+    sys_timer_settime(timerid, flags, new_value, old_value)
+}
+
+pub fn timer_gettime(timerid: TimerId, curr_value: &mut ITimerSpec) -> isize {
+    sys_timer_gettime(timerid, curr_value)
+}
+
+pub fn timer_getoverrun(timerid: TimerId) -> isize {
+    sys_timer_getoverrun(timerid)
+}
+
+pub fn timer_delete(timerid: TimerId) -> isize {
+    sys_timer_delete(timerid)
+}
+
+pub fn setitimer(which: u32, new_value: &ITimerVal, old_value: Option<&mut ITimerVal>) -> isize {
+    sys_setitimer(which, new_value, old_value)
+}
+
+pub fn getitimer(which: u32, curr_value: &mut ITimerVal) -> isize {
+    sys_getitimer(which, curr_value)
+}
+
+/// Sends `SIGALRM` to the process after `seconds`, returns the remaining
+/// seconds of the previous alarm.
+pub fn alarm(seconds: u32) -> usize {
+    sys_alarm(seconds) as usize
+}